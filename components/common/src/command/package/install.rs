@@ -28,6 +28,12 @@
 //!
 //! This would install the `3.0.1` version of redis.
 //!
+//! Will install `core/redis` package from a [local depot](../../../local_depot) directory:
+//!
+//! ```bash
+//! $ hab pkg install core/redis -u file:///mnt/depot
+//! ```
+//!
 //! # Internals
 //!
 //! * Download the artifact
@@ -35,25 +41,28 @@
 //! * Unpack it
 //!
 
+use std::fs;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use ansi_term::Colour::{Blue, Green, Yellow};
-use depot_client::Client;
-use hcore::crypto::{artifact, SigKeyPair};
+use depot_client::{Client, SslSettings};
+use hcore::crypto::{artifact, KeyRevocation, SigKeyPair};
 use hcore::crypto::keys::parse_name_with_rev;
 use hcore::fs::cache_artifact_path;
 use hcore::package::{Identifiable, PackageArchive, PackageIdent, PackageInstall};
 use protocol::depotsrv;
 
 use command::ProgressBar;
-use error::Result;
+use error::{Error, Result};
+use local_depot::LocalDepot;
 
 pub fn start<P1: ?Sized, P2: ?Sized, P3: ?Sized>(url: &str,
                                                  ident_or_archive: &str,
                                                  fs_root_path: &P1,
                                                  cache_artifact_path: &P2,
-                                                 cache_key_path: &P3)
+                                                 cache_key_path: &P3,
+                                                 ssl: &SslSettings)
                                                  -> Result<()>
     where P1: AsRef<Path>,
           P2: AsRef<Path>,
@@ -64,14 +73,16 @@ pub fn start<P1: ?Sized, P2: ?Sized, P3: ?Sized>(url: &str,
                           &ident_or_archive,
                           fs_root_path,
                           cache_artifact_path,
-                          cache_key_path));
+                          cache_key_path,
+                          ssl));
     } else {
         let ident = try!(PackageIdent::from_str(ident_or_archive));
         try!(from_url(url,
                       &ident,
                       fs_root_path,
                       cache_artifact_path,
-                      cache_key_path));
+                      cache_key_path,
+                      ssl));
     }
     Ok(())
 }
@@ -86,7 +97,8 @@ pub fn from_url<P1: ?Sized, P2: ?Sized, P3: ?Sized>(url: &str,
                                                     ident: &PackageIdent,
                                                     fs_root_path: &P1,
                                                     cache_artifact_path: &P2,
-                                                    cache_key_path: &P3)
+                                                    cache_key_path: &P3,
+                                                    ssl: &SslSettings)
                                                     -> Result<depotsrv::Package>
     where P1: AsRef<Path>,
           P2: AsRef<Path>,
@@ -94,8 +106,8 @@ pub fn from_url<P1: ?Sized, P2: ?Sized, P3: ?Sized>(url: &str,
 {
     println!("{}",
              Yellow.bold().paint(format!("» Installing {}", ident)));
-    let depot_client = try!(Client::new(url, Some(fs_root_path.as_ref())));
-    let pkg_data = try!(depot_client.show_package(ident.clone()));
+    let source = try!(Source::new(url, fs_root_path.as_ref(), ssl));
+    let pkg_data = try!(source.show_package(ident));
     for dep in pkg_data.get_tdeps().into_iter() {
         let d: PackageIdent = (*dep).clone().into();
         try!(install_from_depot(url,
@@ -103,14 +115,16 @@ pub fn from_url<P1: ?Sized, P2: ?Sized, P3: ?Sized>(url: &str,
                                 &d,
                                 fs_root_path.as_ref(),
                                 cache_artifact_path.as_ref(),
-                                cache_key_path.as_ref()));
+                                cache_key_path.as_ref(),
+                                ssl));
     }
     try!(install_from_depot(url,
                             &pkg_data.get_ident().clone().into(),
                             ident,
                             fs_root_path.as_ref(),
                             cache_artifact_path.as_ref(),
-                            cache_key_path.as_ref()));
+                            cache_key_path.as_ref(),
+                            ssl));
     println!("{}",
              Blue.paint(format!("★ Install of {} complete with {} packages installed.",
                                 ident,
@@ -122,7 +136,8 @@ pub fn from_archive<P1: ?Sized, P2: ?Sized, P3: ?Sized, P4: ?Sized>(url: &str,
                                                                     path: &P1,
                                                                     fs_root_path: &P2,
                                                                     cache_artifact_path: &P3,
-                                                                    cache_key_path: &P4)
+                                                                    cache_key_path: &P4,
+                                                                    ssl: &SslSettings)
                                                                     -> Result<()>
     where P1: AsRef<Path>,
           P2: AsRef<Path>,
@@ -140,13 +155,15 @@ pub fn from_archive<P1: ?Sized, P2: ?Sized, P3: ?Sized, P4: ?Sized>(url: &str,
                                 dep.as_ref(),
                                 fs_root_path.as_ref(),
                                 cache_artifact_path.as_ref(),
-                                cache_key_path.as_ref()));
+                                cache_key_path.as_ref(),
+                                ssl));
     }
     try!(install_from_archive(url,
                               archive,
                               &ident,
                               fs_root_path.as_ref(),
-                              cache_key_path.as_ref()));
+                              cache_key_path.as_ref(),
+                              ssl));
     println!("{}",
              Blue.paint(format!("★ Install of {} complete with {} packages installed.",
                                 &ident,
@@ -159,7 +176,8 @@ fn install_from_depot(url: &str,
                       given_ident: &PackageIdent,
                       fs_root_path: &Path,
                       cache_artifact_path: &Path,
-                      cache_key_path: &Path)
+                      cache_key_path: &Path,
+                      ssl: &SslSettings)
                       -> Result<()> {
     match PackageInstall::load(ident, Some(&fs_root_path)) {
         Ok(_) => {
@@ -176,13 +194,10 @@ fn install_from_depot(url: &str,
             println!("{} {}",
                      Green.bold().paint("↓ Downloading"),
                      ident.as_ref());
-            let mut progress = ProgressBar::default();
-            let depot_client = try!(Client::new(url, Some(fs_root_path)));
-            let mut archive = try!(depot_client.fetch_package((*ident).clone(),
-                                                               cache_artifact_path,
-                                                               Some(&mut progress)));
+            let source = try!(Source::new(url, fs_root_path, ssl));
+            let mut archive = try!(source.fetch_package(ident, cache_artifact_path));
             let ident = try!(archive.ident());
-            try!(verify(url, &archive, &ident, fs_root_path, cache_key_path));
+            try!(verify(url, &archive, &ident, fs_root_path, cache_key_path, ssl));
             try!(archive.unpack(Some(fs_root_path)));
             println!("{} {}", Green.bold().paint("✓ Installed"), ident.as_ref());
         }
@@ -194,7 +209,8 @@ fn install_from_archive(url: &str,
                         archive: PackageArchive,
                         ident: &PackageIdent,
                         fs_root_path: &Path,
-                        cache_key_path: &Path)
+                        cache_key_path: &Path,
+                        ssl: &SslSettings)
                         -> Result<()> {
     match PackageInstall::load(ident.as_ref(), Some(&fs_root_path)) {
         Ok(_) => {
@@ -204,7 +220,7 @@ fn install_from_archive(url: &str,
             println!("{} {} from cache",
                      Green.bold().paint("← Extracting"),
                      ident);
-            try!(verify(url, &archive, &ident, fs_root_path, cache_key_path));
+            try!(verify(url, &archive, &ident, fs_root_path, cache_key_path, ssl));
             try!(archive.unpack(Some(fs_root_path)));
             println!("{} {}", Green.bold().paint("✓ Installed"), ident);
         }
@@ -212,29 +228,156 @@ fn install_from_archive(url: &str,
     Ok(())
 }
 
-/// get the signer for the artifact and see if we have the key locally.
-/// If we don't, attempt to download it from the depot.
+/// get the signers for the artifact and see if we have their keys locally.
+/// If we don't, attempt to download them from the depot.
 fn verify(url: &str,
           archive: &PackageArchive,
           ident: &PackageIdent,
           fs_root_path: &Path,
-          cache_key_path: &Path)
+          cache_key_path: &Path,
+          ssl: &SslSettings)
           -> Result<()> {
-    let nwr = try!(artifact::artifact_signer(&archive.path));
-    if let Err(_) = SigKeyPair::get_public_key_path(&nwr, cache_key_path) {
-        println!("{} {} public origin key",
-                 Green.bold().paint("↓ Downloading"),
-                 &nwr);
-        let (name, rev) = try!(parse_name_with_rev(&nwr));
-        let mut progress = ProgressBar::default();
-        let depot_client = try!(Client::new(url, Some(fs_root_path)));
-        try!(depot_client.fetch_origin_key(&name, &rev, cache_key_path, Some(&mut progress)));
-        println!("{} {} public origin key",
-                 Green.bold().paint("☑ Cached"),
-                 &nwr);
+    let signers = try!(artifact::artifact_signers(&archive.path));
+    let source = try!(Source::new(url, fs_root_path, ssl));
+    for nwr in signers.iter() {
+        let (name, rev) = try!(parse_name_with_rev(nwr));
+        if let Err(_) = SigKeyPair::get_public_key_path(nwr, cache_key_path) {
+            println!("{} {} public origin key",
+                     Green.bold().paint("↓ Downloading"),
+                     nwr);
+            try!(source.fetch_origin_key(&name, &rev, cache_key_path));
+            println!("{} {} public origin key",
+                     Green.bold().paint("☑ Cached"),
+                     nwr);
+        }
+        // A depot which can't be reached shouldn't prevent installing an artifact whose keys are
+        // all cached; any revocation already in the key cache is still enforced by verification.
+        match source.fetch_origin_key_revocation(&name, &rev, cache_key_path) {
+            Ok(Some(revocation)) => {
+                println!("{} {} public origin key is revoked as of {}",
+                         Yellow.bold().paint("✗ Revoked"),
+                         nwr,
+                         revocation.revoked_at());
+            }
+            Ok(None) => (),
+            Err(e) => debug!("Unable to check revocation of {}: {}", nwr, e),
+        }
     }
 
     try!(archive.verify(&cache_key_path));
-    info!("Verified {} signed by {}", &ident, &nwr);
+    info!("Verified {} signed by {}", &ident, signers.join(", "));
     Ok(())
 }
+
+/// Fetch the revocation of an origin key revision from the depot into the key cache, returning
+/// the revocation in the key cache, if there is one. A revocation already in the key cache which
+/// takes effect earlier than the fetched one is kept.
+pub fn fetch_origin_key_revocation(depot_client: &Client,
+                                   name: &str,
+                                   rev: &str,
+                                   cache_key_path: &Path)
+                                   -> Result<Option<KeyRevocation>> {
+    let nwr = format!("{}-{}", name, rev);
+    let existing = try!(KeyRevocation::get_for(&nwr, cache_key_path));
+    if try!(depot_client.fetch_origin_key_revocation(name, rev, cache_key_path, None)).is_none() {
+        return Ok(existing);
+    }
+    match KeyRevocation::get_for(&nwr, cache_key_path) {
+        Ok(Some(fetched)) => {
+            match existing {
+                Some(existing) => {
+                    if existing.revoked_at() <= fetched.revoked_at() {
+                        try!(existing.write_file(cache_key_path));
+                        Ok(Some(existing))
+                    } else {
+                        Ok(Some(fetched))
+                    }
+                }
+                None => Ok(Some(fetched)),
+            }
+        }
+        Ok(None) => Ok(existing),
+        Err(e) => {
+            // Never leave a revocation which doesn't verify in the key cache
+            match existing {
+                Some(existing) => {
+                    try!(existing.write_file(cache_key_path));
+                }
+                None => try!(fs::remove_file(KeyRevocation::get_path(&nwr, cache_key_path))),
+            }
+            Err(Error::from(e))
+        }
+    }
+}
+
+/// Where packages and public origin keys are installed from: a depot, or a local depot directory
+/// named by a `file://` URL or its path.
+enum Source {
+    Depot(Client),
+    Local(LocalDepot),
+}
+
+impl Source {
+    fn new(url: &str, fs_root_path: &Path, ssl: &SslSettings) -> Result<Self> {
+        match LocalDepot::path_from_url(url) {
+            Some(path) => Ok(Source::Local(try!(LocalDepot::open(path)))),
+            None => Ok(Source::Depot(try!(Client::new_with_ssl(url, Some(fs_root_path), ssl)))),
+        }
+    }
+
+    fn show_package(&self, ident: &PackageIdent) -> Result<depotsrv::Package> {
+        match *self {
+            Source::Depot(ref client) => Ok(try!(client.show_package(ident.clone()))),
+            Source::Local(ref depot) => depot.show_package(ident),
+        }
+    }
+
+    fn fetch_package(&self,
+                     ident: &PackageIdent,
+                     cache_artifact_path: &Path)
+                     -> Result<PackageArchive> {
+        match *self {
+            Source::Depot(ref client) => {
+                let mut progress = ProgressBar::default();
+                Ok(try!(client.fetch_package(ident.clone(),
+                                             cache_artifact_path,
+                                             Some(&mut progress))))
+            }
+            Source::Local(ref depot) => depot.fetch_package(ident, cache_artifact_path),
+        }
+    }
+
+    fn fetch_origin_key(&self, name: &str, rev: &str, cache_key_path: &Path) -> Result<PathBuf> {
+        match *self {
+            Source::Depot(ref client) => {
+                let mut progress = ProgressBar::default();
+                Ok(try!(client.fetch_origin_key(name, rev, cache_key_path, Some(&mut progress))))
+            }
+            Source::Local(ref depot) => depot.fetch_origin_key(name, rev, cache_key_path),
+        }
+    }
+
+    fn fetch_origin_key_revocation(&self,
+                                   name: &str,
+                                   rev: &str,
+                                   cache_key_path: &Path)
+                                   -> Result<Option<KeyRevocation>> {
+        match *self {
+            Source::Depot(ref client) => {
+                fetch_origin_key_revocation(client, name, rev, cache_key_path)
+            }
+            Source::Local(ref depot) => {
+                match try!(depot.origin_key_revocation(name, rev)) {
+                    Some(content) => {
+                        Ok(Some(try!(KeyRevocation::write_file_from_str(&content,
+                                                                        cache_key_path))))
+                    }
+                    None => {
+                        let nwr = format!("{}-{}", name, rev);
+                        Ok(try!(KeyRevocation::get_for(&nwr, cache_key_path)))
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,373 @@
+// Copyright (c) 2016 Chef Software Inc. and/or applicable contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Partial configuration updates for a service group.
+//!
+//! Rather than replacing the whole gossiped configuration, a patch sets or unsets individual
+//! keys, named by their dotted path (ex: `redis.port`). Every key keeps the version number it was
+//! last changed with, and patches are merged key by key: the higher version wins, and for equal
+//! versions an unset loses to a set and two sets are ordered by their rendered value. The outcome
+//! is the same whatever order patches arrive in, so two operators changing different keys at the
+//! same time both see their change survive. When a patch is applied, a change to a key is
+//! skipped if a later version unset or replaced one of the tables holding it.
+
+use std::collections::BTreeMap;
+
+use toml;
+
+use error::{Error, Result};
+
+/// The name a merged patch is gossiped and written under.
+pub const GOSSIP_PATCH_TOML: &'static str = "gossip.patch.toml";
+
+/// The latest change to a single key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PatchEntry {
+    pub version: u64,
+    /// The key's new value, or `None` if the key was unset.
+    pub value: Option<toml::Value>,
+}
+
+impl PatchEntry {
+    /// Returns true if this entry should replace `other` for the same key.
+    fn wins_over(&self, other: &PatchEntry) -> bool {
+        if self.version != other.version {
+            return self.version > other.version;
+        }
+        match (&self.value, &other.value) {
+            (&Some(ref mine), &Some(ref theirs)) => mine.to_string() > theirs.to_string(),
+            (&Some(_), &None) => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfigPatch {
+    entries: BTreeMap<String, PatchEntry>,
+}
+
+impl ConfigPatch {
+    pub fn new() -> ConfigPatch {
+        ConfigPatch { entries: BTreeMap::new() }
+    }
+
+    /// Sets the key at `key_path` to `value`.
+    pub fn set(&mut self, key_path: &str, value: toml::Value, version: u64) -> Result<()> {
+        try!(validate_key_path(key_path));
+        self.update(key_path,
+                    PatchEntry {
+                        version: version,
+                        value: Some(value),
+                    });
+        Ok(())
+    }
+
+    /// Removes the key at `key_path`.
+    pub fn unset(&mut self, key_path: &str, version: u64) -> Result<()> {
+        try!(validate_key_path(key_path));
+        self.update(key_path,
+                    PatchEntry {
+                        version: version,
+                        value: None,
+                    });
+        Ok(())
+    }
+
+    /// Merges another patch into this one, returning true if anything changed.
+    pub fn merge(&mut self, other: &ConfigPatch) -> bool {
+        let mut changed = false;
+        for (key_path, entry) in other.entries.iter() {
+            changed |= self.update(key_path, entry.clone());
+        }
+        changed
+    }
+
+    /// The changed keys and their latest change, ordered by key path so a parent key is always
+    /// visited before its children.
+    pub fn entries(&self) -> &BTreeMap<String, PatchEntry> {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The highest version of any change in the patch.
+    pub fn version(&self) -> u64 {
+        self.entries.values().map(|e| e.version).max().unwrap_or(0)
+    }
+
+    /// Parses a patch from the TOML document produced by `to_toml_string`.
+    pub fn from_toml_str(body: &str) -> Result<ConfigPatch> {
+        let mut parser = toml::Parser::new(body);
+        let table = try!(parser.parse()
+            .ok_or(Error::InvalidTomlError(format!("{:?}", parser.errors))));
+        let mut patch = ConfigPatch::new();
+        let entries = match table.get("entry") {
+            Some(&toml::Value::Array(ref entries)) => entries.clone(),
+            Some(_) => return Err(Error::InvalidConfigPatch("entry must be an array".to_string())),
+            None => vec![],
+        };
+        for entry in entries.iter() {
+            let key = match entry.lookup("key").and_then(|k| k.as_str()) {
+                Some(key) => key,
+                None => return Err(Error::InvalidConfigPatch("entry has no key".to_string())),
+            };
+            let version = match entry.lookup("version").and_then(|v| v.as_integer()) {
+                Some(version) if version >= 0 => version as u64,
+                _ => return Err(Error::InvalidConfigPatch(format!("{} has no version", key))),
+            };
+            match entry.lookup("value") {
+                Some(value) => try!(patch.set(key, value.clone(), version)),
+                None => try!(patch.unset(key, version)),
+            }
+        }
+        Ok(patch)
+    }
+
+    /// Renders the patch as a TOML document with one `[[entry]]` per changed key; an unset key
+    /// is an entry without a `value`.
+    pub fn to_toml_string(&self) -> String {
+        let entries = self.entries
+            .iter()
+            .map(|(key_path, entry)| {
+                let mut table = toml::Table::new();
+                table.insert("key".to_string(), toml::Value::String(key_path.clone()));
+                table.insert("version".to_string(),
+                             toml::Value::Integer(entry.version as i64));
+                if let Some(ref value) = entry.value {
+                    table.insert("value".to_string(), value.clone());
+                }
+                toml::Value::Table(table)
+            })
+            .collect();
+        let mut root = toml::Table::new();
+        root.insert("entry".to_string(), toml::Value::Array(entries));
+        toml::Value::Table(root).to_string()
+    }
+
+    /// Applies the patch to a configuration table. Each change replaces the key in the table
+    /// holding it, creating any missing tables along the way. A change older than a change to
+    /// one of the tables holding its key is skipped, as that table has since been removed or
+    /// replaced.
+    pub fn apply(&self, table: &toml::Table) -> toml::Table {
+        let mut patched = table.clone();
+        for (key_path, entry) in self.entries.iter() {
+            let keys: Vec<&str> = key_path.split('.').collect();
+            let superseded = (1..keys.len()).any(|n| {
+                self.entries
+                    .get(&keys[..n].join("."))
+                    .map_or(false, |ancestor| ancestor.version > entry.version)
+            });
+            if superseded {
+                continue;
+            }
+            patched = patch_key(&patched, &keys, entry.value.as_ref());
+        }
+        patched
+    }
+
+    fn update(&mut self, key_path: &str, entry: PatchEntry) -> bool {
+        let replace = match self.entries.get(key_path) {
+            Some(current) => entry.wins_over(current),
+            None => true,
+        };
+        if replace {
+            self.entries.insert(key_path.to_string(), entry);
+        }
+        replace
+    }
+}
+
+fn patch_key(table: &toml::Table, keys: &[&str], value: Option<&toml::Value>) -> toml::Table {
+    let (key, rest) = (keys[0], &keys[1..]);
+    let mut patched = table.clone();
+    if rest.is_empty() {
+        match value {
+            Some(value) => {
+                patched.insert(key.to_string(), value.clone());
+            }
+            None => {
+                patched.remove(key);
+            }
+        }
+    } else {
+        let child = match table.get(key) {
+            Some(&toml::Value::Table(ref child)) => child.clone(),
+            _ if value.is_none() => return patched,
+            _ => toml::Table::new(),
+        };
+        patched.insert(key.to_string(),
+                       toml::Value::Table(patch_key(&child, rest, value)));
+    }
+    patched
+}
+
+/// Parses a `KEY=VALUE` argument into a key path and a TOML value. A value which isn't valid
+/// TOML is taken as a string, so `redis.host=db1` needs no quoting.
+pub fn parse_assignment(assignment: &str) -> Result<(String, toml::Value)> {
+    let mut parts = assignment.splitn(2, '=');
+    let key_path = parts.next().unwrap_or("").trim();
+    let raw = match parts.next() {
+        Some(raw) => raw.trim(),
+        None => return Err(Error::InvalidConfigPatch(format!("{} is not KEY=VALUE", assignment))),
+    };
+    try!(validate_key_path(key_path));
+    let document = format!("value = {}", raw);
+    let value = toml::Parser::new(&document)
+        .parse()
+        .and_then(|mut t| t.remove("value"))
+        .unwrap_or(toml::Value::String(raw.to_string()));
+    Ok((key_path.to_string(), value))
+}
+
+fn validate_key_path(key_path: &str) -> Result<()> {
+    let valid = !key_path.is_empty() &&
+                key_path.split('.').all(|part| {
+        !part.is_empty() &&
+        part.chars().all(|c| match c {
+            'a'...'z' | 'A'...'Z' | '0'...'9' | '_' | '-' => true,
+            _ => false,
+        })
+    });
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::InvalidConfigPatch(format!("{} is not a valid key path", key_path)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use toml;
+
+    use super::{parse_assignment, ConfigPatch};
+
+    #[test]
+    fn concurrent_patches_to_different_keys_both_survive() {
+        let mut a = ConfigPatch::new();
+        a.set("redis.port", toml::Value::Integer(6380), 2).unwrap();
+        let mut b = ConfigPatch::new();
+        b.unset("redis.tcp-backlog", 2).unwrap();
+
+        let mut ab = a.clone();
+        assert!(ab.merge(&b));
+        let mut ba = b.clone();
+        assert!(ba.merge(&a));
+        assert_eq!(ab, ba);
+        assert_eq!(ab.entries().len(), 2);
+    }
+
+    #[test]
+    fn conflicting_patches_merge_deterministically() {
+        let mut a = ConfigPatch::new();
+        a.set("port", toml::Value::Integer(1), 3).unwrap();
+        let mut b = ConfigPatch::new();
+        b.set("port", toml::Value::Integer(2), 3).unwrap();
+        let mut c = ConfigPatch::new();
+        c.unset("port", 3).unwrap();
+
+        let mut abc = a.clone();
+        abc.merge(&b);
+        abc.merge(&c);
+        let mut cba = c.clone();
+        cba.merge(&b);
+        cba.merge(&a);
+        assert_eq!(abc, cba);
+        assert_eq!(abc.entries()["port"].value, Some(toml::Value::Integer(2)));
+
+        let mut newer = ConfigPatch::new();
+        newer.unset("port", 4).unwrap();
+        assert!(abc.merge(&newer));
+        assert_eq!(abc.entries()["port"].value, None);
+        assert!(!abc.merge(&a));
+    }
+
+    #[test]
+    fn round_trips_through_toml() {
+        let mut patch = ConfigPatch::new();
+        patch.set("redis.port", toml::Value::Integer(6380), 7).unwrap();
+        patch.set("name", toml::Value::String("db".to_string()), 3).unwrap();
+        patch.unset("redis.loglevel", 5).unwrap();
+        let parsed = ConfigPatch::from_toml_str(&patch.to_toml_string()).unwrap();
+        assert_eq!(parsed, patch);
+        assert_eq!(parsed.version(), 7);
+    }
+
+    #[test]
+    fn apply_sets_and_unsets_nested_keys() {
+        let gossip = toml::Parser::new("name = \"db\"\n[redis]\nport = 6379\nloglevel = \
+                                        \"notice\"\n")
+            .parse()
+            .unwrap();
+        let mut patch = ConfigPatch::new();
+        patch.set("redis.port", toml::Value::Integer(6380), 2).unwrap();
+        patch.set("redis.tls.enabled", toml::Value::Boolean(true), 2).unwrap();
+        patch.unset("redis.loglevel", 3).unwrap();
+        patch.unset("missing.key", 3).unwrap();
+        let patched = toml::Value::Table(patch.apply(&gossip));
+        assert_eq!(patched.lookup("name").unwrap().as_str(), Some("db"));
+        assert_eq!(patched.lookup("redis.port").unwrap().as_integer(), Some(6380));
+        assert_eq!(patched.lookup("redis.tls.enabled").unwrap().as_bool(), Some(true));
+        assert!(patched.lookup("redis.loglevel").is_none());
+        assert!(patched.lookup("missing").is_none());
+    }
+
+    #[test]
+    fn apply_skips_changes_older_than_a_removed_table() {
+        let gossip = toml::Parser::new("[redis]\nport = 6379\n[redis.tls]\nenabled = false\n")
+            .parse()
+            .unwrap();
+        let mut patch = ConfigPatch::new();
+        patch.unset("redis", 5).unwrap();
+        patch.set("redis.port", toml::Value::Integer(6380), 3).unwrap();
+        patch.set("redis.tls.enabled", toml::Value::Boolean(true), 4).unwrap();
+        let patched = toml::Value::Table(patch.apply(&gossip));
+        assert!(patched.lookup("redis").is_none());
+    }
+
+    #[test]
+    fn apply_skips_changes_older_than_a_replaced_table() {
+        let gossip = toml::Parser::new("[redis]\nport = 6379\n").parse().unwrap();
+        let mut patch = ConfigPatch::new();
+        patch.set("redis", toml::Value::String("disabled".to_string()), 5).unwrap();
+        patch.set("redis.port", toml::Value::Integer(6380), 3).unwrap();
+        let patched = toml::Value::Table(patch.apply(&gossip));
+        assert_eq!(patched.lookup("redis").unwrap().as_str(), Some("disabled"));
+    }
+
+    #[test]
+    fn apply_applies_changes_newer_than_a_removed_table() {
+        let gossip = toml::Parser::new("[redis]\nport = 6379\nloglevel = \"notice\"\n")
+            .parse()
+            .unwrap();
+        let mut patch = ConfigPatch::new();
+        patch.unset("redis", 3).unwrap();
+        patch.set("redis.port", toml::Value::Integer(6380), 5).unwrap();
+        let patched = toml::Value::Table(patch.apply(&gossip));
+        assert_eq!(patched.lookup("redis.port").unwrap().as_integer(), Some(6380));
+        assert!(patched.lookup("redis.loglevel").is_none());
+    }
+
+    #[test]
+    fn parses_assignments() {
+        assert_eq!(parse_assignment("redis.port=6380").unwrap(),
+                   ("redis.port".to_string(), toml::Value::Integer(6380)));
+        assert_eq!(parse_assignment("redis.host=db1").unwrap(),
+                   ("redis.host".to_string(), toml::Value::String("db1".to_string())));
+        assert!(parse_assignment("redis.port").is_err());
+        assert!(parse_assignment("redis..port=1").is_err());
+    }
+}
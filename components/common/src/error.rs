@@ -33,11 +33,15 @@ pub enum Error {
     DepotClient(depot_client::Error),
     FileNameError,
     HabitatCore(hcore::Error),
+    InvalidConfigPatch(String),
     InvalidTomlError(String),
     /// Occurs when making lower level IO calls.
     IO(io::Error),
     JsonDecode(json::DecoderError),
     JsonEncode(json::EncoderError),
+    LocalDepotIndexInvalid(String),
+    LocalDepotKeyNotFound(String),
+    LocalDepotPackageNotFound(String),
     StrFromUtf8Error(str::Utf8Error),
     StringFromUtf8Error(string::FromUtf8Error),
     WireDecode(String),
@@ -57,10 +61,22 @@ impl fmt::Display for Error {
             Error::DepotClient(ref err) => format!("{}", err),
             Error::FileNameError => format!("Failed to extract a filename"),
             Error::HabitatCore(ref e) => format!("{}", e),
+            Error::InvalidConfigPatch(ref e) => format!("Invalid configuration patch: {}", e),
             Error::InvalidTomlError(ref e) => format!("Invalid TOML: {}", e),
             Error::IO(ref err) => format!("{}", err),
             Error::JsonDecode(ref e) => format!("JSON decoding error: {}", e),
             Error::JsonEncode(ref e) => format!("JSON encoding error: {}", e),
+            Error::LocalDepotIndexInvalid(ref p) => {
+                format!("Local depot index is missing or invalid: {}. Generate it with `hab pkg \
+                         index`.",
+                        p)
+            }
+            Error::LocalDepotKeyNotFound(ref k) => {
+                format!("Public origin key not found in local depot or key cache: {}", k)
+            }
+            Error::LocalDepotPackageNotFound(ref p) => {
+                format!("Package not found in local depot: {}", p)
+            }
             Error::StrFromUtf8Error(ref e) => format!("{}", e),
             Error::StringFromUtf8Error(ref e) => format!("{}", e),
             Error::WireDecode(ref m) => format!("Failed to decode wire message: {}", m),
@@ -80,10 +96,14 @@ impl error::Error for Error {
             Error::DepotClient(ref err) => err.description(),
             Error::FileNameError => "Failed to extract a filename from a path",
             Error::HabitatCore(ref err) => err.description(),
+            Error::InvalidConfigPatch(_) => "Invalid configuration patch",
             Error::InvalidTomlError(_) => "Invalid TOML",
             Error::IO(ref err) => err.description(),
             Error::JsonDecode(_) => "JSON decoding error: {:?}",
             Error::JsonEncode(_) => "JSON encoding error",
+            Error::LocalDepotIndexInvalid(_) => "Local depot index is missing or invalid",
+            Error::LocalDepotKeyNotFound(_) => "Public origin key not found in local depot",
+            Error::LocalDepotPackageNotFound(_) => "Package not found in local depot",
             Error::StrFromUtf8Error(_) => "Failed to convert a string as UTF-8",
             Error::StringFromUtf8Error(_) => "Failed to convert a string as UTF-8",
             Error::WireDecode(_) => "Failed to decode wire message",
@@ -13,7 +13,7 @@
 // limitations under the License.
 
 use std;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::fmt;
 use std::fs::File;
 use std::io::prelude::*;
@@ -26,12 +26,16 @@ use hcore::service::ServiceGroup;
 use openssl::crypto::hash as openssl_hash;
 use rustc_serialize::{Encodable, Encoder};
 use rustc_serialize::hex::ToHex;
-use time::{SteadyTime, Duration};
+use time::{self, SteadyTime, Duration};
 use toml;
+use config_patch::{ConfigPatch, GOSSIP_PATCH_TOML};
 use error::{Error, Result};
 
 const IDEMPOTENCY_INTERVAL_MINUTES: i64 = 5;
 
+/// How many applied configuration versions a `GossipFileList` remembers.
+pub const CONFIG_HISTORY_SIZE: usize = 20;
+
 pub const GOSSIP_TOML: &'static str = "gossip.toml";
 
 /// The gossip file struct.
@@ -44,6 +48,9 @@ pub struct GossipFile {
     version_number: u64,
     written: bool,
     encrypted: bool,
+    // The signed message sealed from a user's key to the service's, vouching for who applied a
+    // file that isn't encrypted.
+    signature: Option<Vec<u8>>,
 }
 
 impl GossipFile {
@@ -68,16 +75,17 @@ impl GossipFile {
 
         let toml_body = try!(String::from_utf8(body.clone()));
         try!(is_data_toml(toml_body));
-        let checksum = openssl_hash::hash(openssl_hash::Type::SHA256, &body);
+        let checksum = sha256sum(&body);
 
         let cf = GossipFile {
             service_group: service_group,
             file_name: GOSSIP_TOML.to_string(),
             body: body,
-            checksum: checksum.as_slice().to_hex(),
+            checksum: checksum,
             version_number: version_number,
             written: false,
             encrypted: false,
+            signature: None,
         };
         Ok(cf)
     }
@@ -110,15 +118,16 @@ impl GossipFile {
         let encrypted_body = try!(user_pair.encrypt(&body, service_pair));
 
         let file_name = try!(path.file_name().ok_or(Error::FileNameError));
-        let checksum = openssl_hash::hash(openssl_hash::Type::SHA256, &body);
+        let checksum = sha256sum(&body);
         let cf = GossipFile {
             service_group: try!(ServiceGroup::from_str(&service_pair.name)),
             file_name: file_name.to_string_lossy().to_string(),
             body: encrypted_body,
-            checksum: checksum.as_slice().to_hex(),
+            checksum: checksum,
             version_number: version_number,
             written: false,
             encrypted: true,
+            signature: None,
         };
         Ok(cf)
     }
@@ -130,23 +139,86 @@ impl GossipFile {
                      body: Vec<u8>,
                      version_number: u64)
                      -> Result<GossipFile> {
-        let checksum = openssl_hash::hash(openssl_hash::Type::SHA256, &body);
-
         let toml_body = try!(String::from_utf8(body.clone()));
         try!(is_data_toml(toml_body));
+        let checksum = sha256sum(&body);
 
         let cf = GossipFile {
             service_group: service_group,
             file_name: GOSSIP_TOML.to_string(),
             body: body,
-            checksum: checksum.as_slice().to_hex(),
+            checksum: checksum,
             version_number: version_number,
             written: false,
             encrypted: false,
+            signature: None,
         };
         Ok(cf)
     }
 
+    /// Creates a GossipFile with a Vec<u8> containing valid TOML, encrypted for a service. The
+    /// GossipFile.file_name is automatically assigned to `gossip.toml`, and only Supervisors
+    /// holding the service's secret key will apply it.
+    pub fn from_body_encrypt(user_pair: &BoxKeyPair,
+                             service_pair: &BoxKeyPair,
+                             body: Vec<u8>,
+                             version_number: u64)
+                             -> Result<GossipFile> {
+        let toml_body = try!(String::from_utf8(body.clone()));
+        try!(is_data_toml(toml_body));
+        let checksum = sha256sum(&body);
+        let encrypted_body = try!(user_pair.encrypt(&body, service_pair));
+
+        Ok(GossipFile {
+            service_group: try!(ServiceGroup::from_str(&service_pair.name)),
+            file_name: GOSSIP_TOML.to_string(),
+            body: encrypted_body,
+            checksum: checksum,
+            version_number: version_number,
+            written: false,
+            encrypted: true,
+            signature: None,
+        })
+    }
+
+    /// Re-issues an already encrypted configuration, such as an earlier version being rolled
+    /// back to, under a new version number. The payload is gossiped as is, so it keeps the
+    /// signer it was encrypted by.
+    pub fn from_encrypted_body(service_group: ServiceGroup,
+                               body: Vec<u8>,
+                               checksum: String,
+                               version_number: u64)
+                               -> GossipFile {
+        GossipFile {
+            service_group: service_group,
+            file_name: GOSSIP_TOML.to_string(),
+            body: body,
+            checksum: checksum,
+            version_number: version_number,
+            written: false,
+            encrypted: true,
+            signature: None,
+        }
+    }
+
+    /// Creates a GossipFile carrying a partial configuration update. The
+    /// GossipFile.file_name is automatically assigned to `gossip.patch.toml`,
+    /// and its version number is the highest version of any change in it.
+    pub fn from_patch(service_group: ServiceGroup, patch: &ConfigPatch) -> Result<GossipFile> {
+        let body = patch.to_toml_string().into_bytes();
+        let checksum = sha256sum(&body);
+        Ok(GossipFile {
+            service_group: service_group,
+            file_name: GOSSIP_PATCH_TOML.to_string(),
+            body: body,
+            checksum: checksum,
+            version_number: patch.version(),
+            written: false,
+            encrypted: false,
+            signature: None,
+        })
+    }
+
     /// Updates this struct against another `GossipFile`. If true is returned, we have changed the gossip file
     /// and the rumour should stay hot. If false is returned, nothing has changed and the rumour
     /// can start to go cold. The algorithm is as follows:
@@ -155,29 +227,143 @@ impl GossipFile {
     /// * If the version numbers are identical but the data differs, loudly warn and return false
     ///   to cool the rumour as it is irreconcilable
     /// * Otherwise our version number is higher and we return false as no updates should occur
+    ///
+    /// Configuration patches are the exception: rather than one replacing the other, the two are
+    /// merged key by key (see `config_patch`), and we return true if the merge changed anything.
     pub fn update_via(&mut self, other: GossipFile) -> bool {
+        if self.file_name == GOSSIP_PATCH_TOML {
+            return self.merge_patch(&other);
+        }
         if self.version_number < other.version_number {
             *self = other;
             true
         } else if self.version_number == other.version_number && *self != other {
             // We have a big problem: this means that the 2 gossip files are *not* the same but
             // they have the same `version_number`. This is probably irreconcilable at present.
-            println!("This gossip file has the same version number ({}) as \
-                  the other GossipFile but our data is different, meaning \
-                  that we can't pick a winner. We will trust our data and \
-                  hope a higher version is published later. \
-                  (My data: {}, other data: {})",
-                     self.version_number,
-                     self,
-                     &other);
+            warn!("This gossip file has the same version number ({}) as the other GossipFile but \
+                   our data is different, meaning that we can't pick a winner. We will trust \
+                   our data and hope a higher version is published later. (My data: {}, other \
+                   data: {})",
+                  self.version_number,
+                  self,
+                  &other);
             false
         } else {
             false
         }
     }
 
+    fn merge_patch(&mut self, other: &GossipFile) -> bool {
+        let patches = String::from_utf8(self.body.clone())
+            .map_err(Error::from)
+            .and_then(|body| ConfigPatch::from_toml_str(&body))
+            .and_then(|mine| {
+                String::from_utf8(other.body.clone())
+                    .map_err(Error::from)
+                    .and_then(|body| ConfigPatch::from_toml_str(&body))
+                    .map(|theirs| (mine, theirs))
+            });
+        let (mut mine, theirs) = match patches {
+            Ok(patches) => patches,
+            Err(e) => {
+                warn!("Ignoring configuration patch for {} which can't be merged: {}",
+                      self.service_group,
+                      e);
+                return false;
+            }
+        };
+        if !mine.merge(&theirs) {
+            return false;
+        }
+        self.body = mine.to_toml_string().into_bytes();
+        self.checksum = sha256sum(&self.body);
+        self.version_number = mine.version();
+        self.written = false;
+        self.signature = None;
+        true
+    }
+
+    pub fn version_number(&self) -> u64 {
+        self.version_number
+    }
+
+    /// The SHA-256 checksum of the file's contents, before any encryption.
+    pub fn checksum(&self) -> &str {
+        &self.checksum
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.encrypted
+    }
+
+    /// The file's contents as they are gossiped, which for an encrypted file is the encrypted
+    /// payload.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Signs a file that isn't encrypted by sealing its signed message from the user's key to the
+    /// service's, so the Supervisors holding the service key can tell who applied it.
+    pub fn sign(&mut self, user_pair: &BoxKeyPair, service_pair: &BoxKeyPair) -> Result<()> {
+        self.signature = Some(try!(user_pair.encrypt(&self.signed_message(), service_pair)));
+        Ok(())
+    }
+
+    /// The message a signature vouches for: the service group, file name and version number the
+    /// file is gossiped under as well as its checksum, so that a signature can't be moved to
+    /// another file, or to a newer version of the same one.
+    fn signed_message(&self) -> Vec<u8> {
+        format!("{}\n{}\n{}\n{}",
+                self.service_group,
+                self.file_name,
+                self.version_number,
+                self.checksum)
+            .into_bytes()
+    }
+
+    /// The name and revision of the user key which vouches for who applied the file, once the
+    /// file has been verified with the keys in the cache. An encrypted file is vouched for by
+    /// the key it was sealed with and a signed one by its signature; a file which is neither, or
+    /// which doesn't verify, has no signer.
+    pub fn verified_signer<P: AsRef<Path> + ?Sized>(&self, cache_key_path: &P) -> Option<String> {
+        let payload = if self.encrypted {
+            &self.body
+        } else {
+            match self.signature {
+                Some(ref signature) => signature,
+                None => return None,
+            }
+        };
+        let verified = match BoxKeyPair::decrypt(payload, cache_key_path) {
+            Ok(ref plain) if self.encrypted => sha256sum(plain) == self.checksum,
+            Ok(ref plain) => *plain == self.signed_message(),
+            Err(_) => false,
+        };
+        if !verified {
+            return None;
+        }
+        std::str::from_utf8(payload)
+            .ok()
+            .and_then(|payload| payload.lines().nth(1))
+            .map(|signer| signer.to_string())
+    }
+
+    /// Returns true if the file isn't encrypted, or if the secret key of the service it was
+    /// encrypted for is in the key cache.
+    pub fn can_decrypt<P: AsRef<Path> + ?Sized>(&self, cache_key_path: &P) -> bool {
+        if !self.encrypted {
+            return true;
+        }
+        std::str::from_utf8(&self.body)
+            .ok()
+            .and_then(|payload| payload.lines().nth(2))
+            .map_or(false, |recipient| {
+                BoxKeyPair::get_secret_key_path(recipient, cache_key_path).is_ok()
+            })
+    }
+
     pub fn on_disk_path(&self) -> PathBuf {
-        if &self.file_name == GOSSIP_TOML {
+        if &self.file_name == GOSSIP_TOML || &self.file_name == GOSSIP_PATCH_TOML {
             fs::svc_path(&self.service_group.service).join(&self.file_name)
         } else {
             fs::svc_files_path(&self.service_group.service).join(&self.file_name)
@@ -209,8 +395,8 @@ impl GossipFile {
             Ok(false)
         } else {
             let filename = self.on_disk_path();
-            println!("Writing new file from gossip: {}",
-                     filename.to_string_lossy());
+            info!("Writing new file from gossip: {}",
+                  filename.to_string_lossy());
             let new_filename = format!("{}.write", filename.to_string_lossy());
             {
                 if self.encrypted {
@@ -219,10 +405,10 @@ impl GossipFile {
                     // checks before calling this function.
                     // However, if decrypt() can't find user/service keys,
                     // this write will fail.
-                    println!("Attempting to decrypt {}", &self.file_name);
+                    debug!("Attempting to decrypt {}", &self.file_name);
                     let decrypted_bytes = try!(BoxKeyPair::decrypt(&self.body,
                                                                    &default_cache_key_path(None)));
-                    println!("Successfully decrypted {}", &self.file_name);
+                    debug!("Successfully decrypted {}", &self.file_name);
                     try!(new_file.write_all(&decrypted_bytes));
                 } else {
                     let mut new_file = try!(File::create(&new_filename));
@@ -254,6 +440,11 @@ impl PartialEq for GossipFile {
 }
 
 
+/// Returns the checksum of a gossip file's contents, as a hex string.
+pub fn sha256sum(body: &[u8]) -> String {
+    openssl_hash::hash(openssl_hash::Type::SHA256, body).as_slice().to_hex()
+}
+
 /// try to parse the string as Toml
 fn is_data_toml(body: String) -> Result<()> {
     let mut parser = toml::Parser::new(&body);
@@ -319,6 +510,35 @@ impl Encodable for FileWriteRetry {
     }
 }
 
+/// A version of the gossiped configuration that was applied to my service group: either a whole
+/// configuration document, or a configuration patch along with every change merged before it.
+#[derive(Clone, Debug, RustcEncodable)]
+pub struct ConfigRevision {
+    pub version_number: u64,
+    /// `gossip.toml` for a configuration document, or `gossip.patch.toml` for a patch.
+    pub file_name: String,
+    pub checksum: String,
+    pub signer: Option<String>,
+    /// When this Supervisor applied the version, in RFC 3339 format.
+    pub applied_at: String,
+    pub encrypted: bool,
+    pub body: Vec<u8>,
+}
+
+impl ConfigRevision {
+    fn new(gf: &GossipFile) -> ConfigRevision {
+        ConfigRevision {
+            version_number: gf.version_number,
+            file_name: gf.file_name.clone(),
+            checksum: gf.checksum.clone(),
+            signer: gf.verified_signer(&default_cache_key_path(None)),
+            applied_at: time::now_utc().rfc3339().to_string(),
+            encrypted: gf.encrypted,
+            body: gf.body.clone(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct GossipFileList {
     my_service_group: ServiceGroup,
@@ -326,6 +546,8 @@ pub struct GossipFileList {
     gossip_files: HashMap<(ServiceGroup, String), GossipFile>,
     next_idempotency_check: SteadyTime,
     pub file_write_retries: HashMap<String, FileWriteRetry>,
+    // The configuration versions applied to my service group, newest first.
+    history: VecDeque<ConfigRevision>,
 }
 
 impl GossipFileList {
@@ -336,6 +558,7 @@ impl GossipFileList {
             next_idempotency_check: SteadyTime::now() +
                                     Duration::minutes(IDEMPOTENCY_INTERVAL_MINUTES),
             file_write_retries: HashMap::new(),
+            history: VecDeque::new(),
         }
     }
 
@@ -373,6 +596,14 @@ impl GossipFileList {
                 continue;
             }
 
+            // An encrypted file is only for the Supervisors holding the secret key of the service
+            // it was encrypted for. Everyone else passes it along without ever decrypting it.
+            if !gf.can_decrypt(&default_cache_key_path(None)) {
+                debug!("Skipping {}, which we don't hold the service key to decrypt",
+                       &gf.file_name);
+                continue;
+            }
+
             // see if we need to skip this write if it's a retry but
             // it's not quite time yet
             let needs_retry = self.file_write_retries.contains_key(&gf.file_name);
@@ -400,9 +631,9 @@ impl GossipFileList {
                 }
                 Err(e) => {
                     // the write failed, let's log the error and retry again later
-                    println!("Failed to write file {}:{}",
-                             gf.on_disk_path().to_string_lossy(),
-                             e);
+                    warn!("Failed to write file {}:{}",
+                          gf.on_disk_path().to_string_lossy(),
+                          e);
 
                     if needs_retry {
                         // if needs_retry == true, then we know this file is already
@@ -411,15 +642,15 @@ impl GossipFileList {
                         let next_retries = fwr.total_retries + 1;
                         let exp_backoff_seconds = 2i64.pow(next_retries);
                         let next = SteadyTime::now() + Duration::seconds(exp_backoff_seconds);
-                        println!("Retrying write to {} in {} seconds",
-                                 &gf.file_name,
-                                 exp_backoff_seconds);
+                        warn!("Retrying write to {} in {} seconds",
+                              &gf.file_name,
+                              exp_backoff_seconds);
                         fwr.next_retry = next;
                         fwr.total_retries = next_retries;
                         fwr.last_failure_reason = e.to_string();
                     } else {
                         let next = SteadyTime::now() + Duration::seconds(1);
-                        println!("Retrying write for {} in 1 second", &gf.file_name);
+                        warn!("Retrying write for {} in 1 second", &gf.file_name);
                         let fwr = FileWriteRetry::new(gf.file_name.clone(), 0, next, e.to_string());
                         self.file_write_retries.insert(gf.file_name.clone(), fwr);
                     };
@@ -430,20 +661,85 @@ impl GossipFileList {
             if needs_file_updated == false && written == true {
                 needs_file_updated = true;
             }
-            if gf.file_name == GOSSIP_TOML {
+            if gf.file_name == GOSSIP_TOML || gf.file_name == GOSSIP_PATCH_TOML {
                 needs_reconfigure = true;
             }
         }
         Ok((needs_file_updated, needs_reconfigure))
     }
 
+    pub fn service_group(&self) -> &ServiceGroup {
+        &self.my_service_group
+    }
+
+    /// Returns the files held for my service group, ordered by file name.
+    pub fn files(&self) -> Vec<&GossipFile> {
+        let mut files: Vec<&GossipFile> = self.gossip_files
+            .iter()
+            .filter(|&(&(ref sg, _), _)| sg == &self.my_service_group)
+            .map(|(_, gf)| gf)
+            .collect();
+        files.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+        files
+    }
+
+    /// Returns the file with the given name held for my service group.
+    pub fn get(&self, file_name: &str) -> Option<&GossipFile> {
+        self.gossip_files.get(&(self.my_service_group.clone(), file_name.to_string()))
+    }
+
+    /// Returns the file with the given name held for any service group.
+    pub fn get_for(&self, service_group: &ServiceGroup, file_name: &str) -> Option<&GossipFile> {
+        self.gossip_files.get(&(service_group.clone(), file_name.to_string()))
+    }
+
+    /// Returns the configuration versions applied to my service group, newest first.
+    pub fn history(&self) -> Vec<&ConfigRevision> {
+        self.history.iter().collect()
+    }
+
+    /// Returns an applied configuration version, if it is still remembered.
+    pub fn revision(&self, version_number: u64) -> Option<&ConfigRevision> {
+        self.history.iter().find(|r| r.version_number == version_number)
+    }
+
     pub fn process(&mut self, remote_gf: GossipFile) -> bool {
-        if let Some(mut current_gf) = self.get_mut(&remote_gf.service_group, &remote_gf.file_name) {
-            return current_gf.update_via(remote_gf);
+        let key = (remote_gf.service_group.clone(), remote_gf.file_name.clone());
+        let updated = match self.get_mut(&key.0, &key.1) {
+            Some(current_gf) => Some(current_gf.update_via(remote_gf.clone())),
+            None => None,
+        };
+        let processed = match updated {
+            Some(updated) => updated,
+            None => {
+                self.gossip_files.insert(key.clone(), remote_gf);
+                true
+            }
+        };
+        // Only a configuration that was accepted is verified, so a stale rumor costs no more
+        // than comparing its version number. A patch is remembered as merged.
+        if processed && key.0 == self.my_service_group &&
+           (key.1 == GOSSIP_TOML || key.1 == GOSSIP_PATCH_TOML) {
+            let revision = ConfigRevision::new(&self.gossip_files[&key]);
+            self.record_revision(revision);
+        }
+        processed
+    }
+
+    fn record_revision(&mut self, revision: ConfigRevision) {
+        if let Some(current) = self.history
+            .iter_mut()
+            .find(|r| r.version_number == revision.version_number) {
+            // Merging an older change into a patch leaves its version number as it was
+            if current.file_name == revision.file_name {
+                *current = revision;
+            }
+            return;
+        }
+        self.history.push_front(revision);
+        while self.history.len() > CONFIG_HISTORY_SIZE {
+            self.history.pop_back();
         }
-        self.gossip_files.insert((remote_gf.service_group.clone(), remote_gf.file_name.clone()),
-                                 remote_gf);
-        true
     }
 
     fn get_mut(&mut self,
@@ -465,10 +761,13 @@ mod test {
     use rustc_serialize::json;
     use tempdir::TempDir;
     use time::SteadyTime;
+    use toml;
 
     use hcore::crypto::BoxKeyPair;
     use hcore::service::ServiceGroup;
-    use gossip_file::{GossipFile, FileWriteRetry, GOSSIP_TOML};
+    use config_patch::{ConfigPatch, GOSSIP_PATCH_TOML};
+    use gossip_file::{sha256sum, GossipFile, GossipFileList, FileWriteRetry, CONFIG_HISTORY_SIZE,
+                      GOSSIP_TOML};
 
     fn fixture(name: &str) -> PathBuf {
         env::current_exe()
@@ -649,6 +948,177 @@ mod test {
         assert_eq!(me == other, true);
     }
 
+    #[test]
+    fn new_from_body_encrypt() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let other_cache = TempDir::new("other_key_cache").unwrap();
+        let user_pair = BoxKeyPair::generate_pair_for_user("testuser", cache.path()).unwrap();
+        let service_pair =
+            BoxKeyPair::generate_pair_for_service("someorg", "petty.gunslingers", cache.path())
+                .unwrap();
+        let body = "password = \"hunter2\"\n".as_bytes().to_vec();
+        let gf = GossipFile::from_body_encrypt(&user_pair, &service_pair, body.clone(), 3)
+            .unwrap();
+        assert_eq!(gf.file_name, GOSSIP_TOML);
+        assert_eq!(gf.service_group,
+                   ServiceGroup::from_str("petty.gunslingers@someorg").unwrap());
+        assert_eq!(gf.checksum, sha256sum(&body));
+        assert!(gf.body != body);
+        assert_eq!(gf.verified_signer(cache.path()), Some(user_pair.name_with_rev()));
+        assert_eq!(gf.verified_signer(other_cache.path()), None);
+        assert!(gf.can_decrypt(cache.path()));
+        assert!(!gf.can_decrypt(other_cache.path()));
+        assert_eq!(BoxKeyPair::decrypt(&gf.body, cache.path()).unwrap(), body);
+
+        assert!(GossipFile::from_body_encrypt(&user_pair,
+                                              &service_pair,
+                                              "not toml".as_bytes().to_vec(),
+                                              3)
+            .is_err());
+    }
+
+    #[test]
+    fn verified_signer_of_signed_body() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let other_cache = TempDir::new("other_key_cache").unwrap();
+        let user_pair = BoxKeyPair::generate_pair_for_user("testuser", cache.path()).unwrap();
+        let service_pair =
+            BoxKeyPair::generate_pair_for_service("someorg", "petty.gunslingers", cache.path())
+                .unwrap();
+        let sg = ServiceGroup::from_str("petty.gunslingers@someorg").unwrap();
+        let mut gf = GossipFile::from_body(sg.clone(), "woot=1\n".as_bytes().to_vec(), 3)
+            .unwrap();
+        assert_eq!(gf.verified_signer(cache.path()), None);
+
+        gf.sign(&user_pair, &service_pair).unwrap();
+        assert_eq!(gf.verified_signer(cache.path()), Some(user_pair.name_with_rev()));
+        assert_eq!(gf.verified_signer(other_cache.path()), None);
+
+        // A signature made for another file doesn't vouch for this one.
+        let mut other = GossipFile::from_body(sg.clone(), "woot=2\n".as_bytes().to_vec(), 3)
+            .unwrap();
+        other.signature = gf.signature.clone();
+        assert_eq!(other.verified_signer(cache.path()), None);
+
+        // Nor for the same file gossiped under another version, file name or service group.
+        let mut newer = gf.clone();
+        newer.version_number = 4;
+        assert_eq!(newer.verified_signer(cache.path()), None);
+        let mut renamed = gf.clone();
+        renamed.file_name = "other.toml".to_string();
+        assert_eq!(renamed.verified_signer(cache.path()), None);
+        let mut moved = gf.clone();
+        moved.service_group = ServiceGroup::from_str("petty.outlaws@someorg").unwrap();
+        assert_eq!(moved.verified_signer(cache.path()), None);
+    }
+
+    #[test]
+    fn verified_signer_of_encrypted_body_with_wrong_checksum() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let user_pair = BoxKeyPair::generate_pair_for_user("testuser", cache.path()).unwrap();
+        let service_pair =
+            BoxKeyPair::generate_pair_for_service("someorg", "petty.gunslingers", cache.path())
+                .unwrap();
+        let body = user_pair.encrypt("woot=1\n".as_bytes(), &service_pair).unwrap();
+        let gf = GossipFile::from_encrypted_body(ServiceGroup::from_str("petty.gunslingers@someorg")
+                                                     .unwrap(),
+                                                 body,
+                                                 sha256sum("woot=2\n".as_bytes()),
+                                                 3);
+        assert_eq!(gf.verified_signer(cache.path()), None);
+    }
+
+    #[test]
+    fn update_via_merges_config_patches() {
+        let sg = ServiceGroup::from_str("petty.gunslingers").unwrap();
+        let mut mine = ConfigPatch::new();
+        mine.set("port", toml::Value::Integer(6380), 2).unwrap();
+        let mut theirs = ConfigPatch::new();
+        theirs.unset("loglevel", 1).unwrap();
+
+        let mut gf = GossipFile::from_patch(sg.clone(), &mine).unwrap();
+        assert_eq!(gf.file_name, GOSSIP_PATCH_TOML);
+        assert!(gf.update_via(GossipFile::from_patch(sg.clone(), &theirs).unwrap()));
+        assert_eq!(gf.version_number, 2);
+        let merged = ConfigPatch::from_toml_str(&String::from_utf8(gf.body.clone()).unwrap())
+            .unwrap();
+        assert_eq!(merged.entries().len(), 2);
+        assert!(!gf.update_via(GossipFile::from_patch(sg, &theirs).unwrap()));
+    }
+
+    #[test]
+    fn gossip_file_list_files_for_my_service_group() {
+        let mut list = GossipFileList::new(ServiceGroup::from_str("heart.barracuda").unwrap());
+        list.process(GossipFile::from_body(ServiceGroup::from_str("heart.barracuda").unwrap(),
+                                           "woot=1\n".as_bytes().to_vec(),
+                                           20)
+            .unwrap());
+        list.process(GossipFile::from_body(ServiceGroup::from_str("heart.oops").unwrap(),
+                                           "woot=2\n".as_bytes().to_vec(),
+                                           30)
+            .unwrap());
+
+        let files = list.files();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].version_number(), 20);
+        assert_eq!(files[0].checksum(), sha256sum("woot=1\n".as_bytes()));
+        assert!(list.get(GOSSIP_TOML).is_some());
+        assert!(list.get("nope.toml").is_none());
+    }
+
+    #[test]
+    fn gossip_file_list_keeps_bounded_config_history() {
+        let sg = ServiceGroup::from_str("heart.barracuda").unwrap();
+        let mut list = GossipFileList::new(sg.clone());
+        for version in 1..(CONFIG_HISTORY_SIZE as u64 + 3) {
+            let body = format!("woot={}\n", version);
+            list.process(GossipFile::from_body(sg.clone(), body.into_bytes(), version).unwrap());
+        }
+        // Older versions are rejected and don't enter the history.
+        list.process(GossipFile::from_body(sg.clone(), "woot=0\n".as_bytes().to_vec(), 1)
+            .unwrap());
+
+        let history = list.history();
+        assert_eq!(history.len(), CONFIG_HISTORY_SIZE);
+        assert_eq!(history[0].version_number, CONFIG_HISTORY_SIZE as u64 + 2);
+        assert_eq!(history[0].signer, None);
+        assert!(list.revision(2).is_none());
+        assert_eq!(list.revision(3).unwrap().body, "woot=3\n".as_bytes().to_vec());
+    }
+
+    #[test]
+    fn gossip_file_list_remembers_merged_config_patches() {
+        let sg = ServiceGroup::from_str("heart.barracuda").unwrap();
+        let mut list = GossipFileList::new(sg.clone());
+        list.process(GossipFile::from_body(sg.clone(), "woot=1\n".as_bytes().to_vec(), 1)
+            .unwrap());
+        let mut first = ConfigPatch::new();
+        first.set("port", toml::Value::Integer(6380), 2).unwrap();
+        list.process(GossipFile::from_patch(sg.clone(), &first).unwrap());
+        let mut second = ConfigPatch::new();
+        second.unset("loglevel", 3).unwrap();
+        list.process(GossipFile::from_patch(sg.clone(), &second).unwrap());
+        // A late change merges into the latest version rather than adding one of its own.
+        let mut late = ConfigPatch::new();
+        late.set("name", toml::Value::String("db".to_string()), 2).unwrap();
+        list.process(GossipFile::from_patch(sg.clone(), &late).unwrap());
+
+        let history = list.history();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].version_number, 3);
+        assert_eq!(history[0].file_name, GOSSIP_PATCH_TOML);
+        let merged = ConfigPatch::from_toml_str(&String::from_utf8(history[0].body.clone())
+                .unwrap())
+            .unwrap();
+        assert_eq!(merged.entries().len(), 3);
+        let earlier = list.revision(2).unwrap();
+        assert_eq!(earlier.file_name, GOSSIP_PATCH_TOML);
+        assert_eq!(ConfigPatch::from_toml_str(&String::from_utf8(earlier.body.clone()).unwrap())
+                       .unwrap(),
+                   first);
+        assert_eq!(list.revision(1).unwrap().file_name, GOSSIP_TOML);
+    }
+
     #[test]
     fn file_write_retry_encode() {
         let fwr = FileWriteRetry::new("foo".to_string(),
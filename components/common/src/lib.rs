@@ -30,6 +30,8 @@ extern crate toml;
 pub use self::error::{Error, Result};
 
 pub mod command;
+pub mod config_patch;
 pub mod gossip_file;
 pub mod error;
+pub mod local_depot;
 pub mod wire_message;
@@ -0,0 +1,427 @@
+// Copyright (c) 2016 Chef Software Inc. and/or applicable contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A depot backed by a plain directory, such as one shared over NFS.
+//!
+//! A local depot directory holds Habitat Artifacts anywhere beneath it, a `keys/` directory of
+//! public origin keys (and any key revocations) named as they are in the key cache, and an
+//! `INDEX` file mapping each package identifier to its artifact. The index is generated with
+//! [`LocalDepot::generate_index()`](struct.LocalDepot.html#method.generate_index) and looks like:
+//!
+//! ```text
+//! HAB-INDEX-1
+//! core/glibc/2.22/20160612063629 core-glibc-2.22-20160612063629-x86_64-linux.hart
+//! core/redis/3.0.7/20160614023044 redis/core-redis-3.0.7-20160614023044-x86_64-linux.hart
+//! ```
+//!
+//! A local depot is named by a `file://` URL or by the path of its directory wherever a depot
+//! URL is accepted.
+
+use std::cmp::Ordering;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+use hcore::crypto::{artifact, KeyRevocation, SigKeyPair};
+use hcore::package::{FromArchive, Identifiable, PackageArchive, PackageIdent};
+use protocol::depotsrv;
+
+use error::{Error, Result};
+
+/// The name of the index file at the root of a local depot.
+pub const INDEX_FILE: &'static str = "INDEX";
+/// The directory of a local depot holding public origin keys and key revocations.
+pub const KEYS_DIR: &'static str = "keys";
+const INDEX_VERSION: &'static str = "HAB-INDEX-1";
+const FILE_URL_PREFIX: &'static str = "file://";
+const ARTIFACT_EXTENSION: &'static str = "hart";
+
+pub struct LocalDepot {
+    path: PathBuf,
+    packages: Vec<(PackageIdent, PathBuf)>,
+}
+
+impl LocalDepot {
+    /// Returns the directory of the local depot named by `url`, if it names one. A `file://` URL
+    /// always names a local depot, while any other value does when it is the path of a directory
+    /// containing an index.
+    pub fn path_from_url(url: &str) -> Option<PathBuf> {
+        if url.starts_with(FILE_URL_PREFIX) {
+            Some(PathBuf::from(&url[FILE_URL_PREFIX.len()..]))
+        } else if Path::new(url).join(INDEX_FILE).is_file() {
+            Some(PathBuf::from(url))
+        } else {
+            None
+        }
+    }
+
+    /// Opens the local depot in the given directory by reading its index.
+    ///
+    /// # Failures
+    ///
+    /// * If the index is missing or cannot be read
+    /// * If the index is malformed
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let index = path.join(INDEX_FILE);
+        let mut content = String::new();
+        match File::open(&index) {
+            Ok(mut file) => try!(file.read_to_string(&mut content)),
+            Err(_) => return Err(Error::LocalDepotIndexInvalid(index.display().to_string())),
+        };
+        let packages = try!(parse_index(&content)
+            .ok_or(Error::LocalDepotIndexInvalid(index.display().to_string())));
+        Ok(LocalDepot {
+            path: path,
+            packages: packages,
+        })
+    }
+
+    /// Writes the index of the local depot in the given directory, returning the number of
+    /// artifacts indexed. Every artifact beneath the directory is indexed, and the public origin
+    /// keys (and any key revocations) of their signers are copied from the key cache into the
+    /// `keys/` directory if they aren't there already.
+    ///
+    /// # Failures
+    ///
+    /// * If an artifact cannot be read
+    /// * If the public key of a signer is in neither the local depot nor the key cache
+    /// * If the index cannot be written
+    pub fn generate_index<P1: AsRef<Path> + ?Sized, P2: AsRef<Path> + ?Sized>(path: &P1,
+                                                                              cache_key_path: &P2)
+                                                                              -> Result<usize> {
+        let path = path.as_ref();
+        let keys_path = path.join(KEYS_DIR);
+        let mut artifacts = Vec::new();
+        try!(find_artifacts(path, &mut artifacts));
+        let mut packages = Vec::new();
+        for artifact_path in artifacts {
+            let mut archive = PackageArchive::new(artifact_path.clone());
+            let ident = try!(archive.ident());
+            for nwr in try!(artifact::artifact_signers(&artifact_path)) {
+                if SigKeyPair::get_public_key_path(&nwr, &keys_path).is_err() {
+                    let cached = match SigKeyPair::get_public_key_path(&nwr,
+                                                                       cache_key_path.as_ref()) {
+                        Ok(cached) => cached,
+                        Err(_) => return Err(Error::LocalDepotKeyNotFound(nwr)),
+                    };
+                    try!(fs::create_dir_all(&keys_path));
+                    try!(fs::copy(&cached, keys_path.join(cached.file_name().unwrap())));
+                }
+                let cached = KeyRevocation::get_path(&nwr, cache_key_path.as_ref());
+                if cached.is_file() {
+                    try!(fs::create_dir_all(&keys_path));
+                    try!(fs::copy(&cached, keys_path.join(cached.file_name().unwrap())));
+                }
+            }
+            let relative = artifact_path.strip_prefix(path).unwrap().to_path_buf();
+            packages.push((ident, relative));
+        }
+        packages.sort_by(|a, b| a.0.to_string().cmp(&b.0.to_string()));
+
+        let mut content = format!("{}\n", INDEX_VERSION);
+        for &(ref ident, ref relative) in packages.iter() {
+            content.push_str(&format!("{} {}\n", ident, relative.display()));
+        }
+        let index = path.join(INDEX_FILE);
+        let tmp = path.join(format!(".{}.tmp", INDEX_FILE));
+        {
+            let mut file = try!(File::create(&tmp));
+            try!(file.write_all(content.as_bytes()));
+            try!(file.sync_all());
+        }
+        try!(fs::rename(&tmp, &index));
+        Ok(packages.len())
+    }
+
+    /// Returns every indexed package along with the path of its artifact, relative to the local
+    /// depot.
+    pub fn packages(&self) -> &[(PackageIdent, PathBuf)] {
+        &self.packages
+    }
+
+    /// Returns the latest indexed package which satisfies the given identifier and the path of
+    /// its artifact.
+    pub fn latest(&self, ident: &PackageIdent) -> Result<(PackageIdent, PathBuf)> {
+        let latest = self.packages
+            .iter()
+            .filter(|&&(ref p, _)| p.satisfies(ident))
+            .fold(None, |winner: Option<&(PackageIdent, PathBuf)>, b| {
+                match winner {
+                    Some(a) => {
+                        match a.0.partial_cmp(&b.0) {
+                            Some(Ordering::Less) => Some(b),
+                            _ => Some(a),
+                        }
+                    }
+                    None => Some(b),
+                }
+            });
+        match latest {
+            Some(&(ref id, ref relative)) => Ok((id.clone(), self.path.join(relative))),
+            None => Err(Error::LocalDepotPackageNotFound(ident.to_string())),
+        }
+    }
+
+    /// Returns the package metadata of the latest package satisfying the given identifier, as a
+    /// depot would.
+    pub fn show_package(&self, ident: &PackageIdent) -> Result<depotsrv::Package> {
+        let (_, path) = try!(self.latest(ident));
+        let mut archive = PackageArchive::new(path);
+        Ok(try!(depotsrv::Package::from_archive(&mut archive)))
+    }
+
+    /// Copies the artifact of the latest package satisfying the given identifier into
+    /// `dst_path`.
+    pub fn fetch_package<P: AsRef<Path> + ?Sized>(&self,
+                                                  ident: &PackageIdent,
+                                                  dst_path: &P)
+                                                  -> Result<PackageArchive> {
+        let (_, src) = try!(self.latest(ident));
+        let dst = try!(copy_into(&src, dst_path.as_ref()));
+        Ok(PackageArchive::new(dst))
+    }
+
+    /// Copies the public origin key of the given revision into `dst_path`.
+    pub fn fetch_origin_key<P: AsRef<Path> + ?Sized>(&self,
+                                                     origin: &str,
+                                                     revision: &str,
+                                                     dst_path: &P)
+                                                     -> Result<PathBuf> {
+        let nwr = format!("{}-{}", origin, revision);
+        match SigKeyPair::get_public_key_path(&nwr, &self.path.join(KEYS_DIR)) {
+            Ok(src) => copy_into(&src, dst_path.as_ref()),
+            Err(_) => Err(Error::LocalDepotKeyNotFound(nwr)),
+        }
+    }
+
+    /// Returns the content of the revocation of the given origin key revision, if the local
+    /// depot has one.
+    pub fn origin_key_revocation(&self, origin: &str, revision: &str) -> Result<Option<String>> {
+        let nwr = format!("{}-{}", origin, revision);
+        let path = KeyRevocation::get_path(&nwr, &self.path.join(KEYS_DIR));
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let mut content = String::new();
+        try!(try!(File::open(&path)).read_to_string(&mut content));
+        Ok(Some(content))
+    }
+}
+
+fn parse_index(content: &str) -> Option<Vec<(PackageIdent, PathBuf)>> {
+    let mut lines = content.lines();
+    if lines.next() != Some(INDEX_VERSION) {
+        return None;
+    }
+    let mut packages = Vec::new();
+    for line in lines {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut parts = line.splitn(2, ' ');
+        let ident = match parts.next().map(PackageIdent::from_str) {
+            Some(Ok(ident)) => ident,
+            _ => return None,
+        };
+        match parts.next() {
+            Some(relative) if is_within_depot(relative) => {
+                packages.push((ident, PathBuf::from(relative)))
+            }
+            _ => return None,
+        }
+    }
+    Some(packages)
+}
+
+/// Returns whether a path from the index names a file beneath the local depot directory, being
+/// neither absolute nor containing `..`.
+fn is_within_depot(relative: &str) -> bool {
+    !relative.is_empty() &&
+    Path::new(relative).components().all(|c| match c {
+        Component::Normal(_) | Component::CurDir => true,
+        _ => false,
+    })
+}
+
+fn find_artifacts(dir: &Path, artifacts: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries = Vec::new();
+    for entry in try!(fs::read_dir(dir)) {
+        entries.push(try!(entry).path());
+    }
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            try!(find_artifacts(&path, artifacts));
+        } else if path.extension().and_then(|e| e.to_str()) == Some(ARTIFACT_EXTENSION) {
+            artifacts.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Copies a file into a directory, by way of a temporary file so a partial copy is never left
+/// under the final name.
+fn copy_into(src: &Path, dst_dir: &Path) -> Result<PathBuf> {
+    let file_name = match src.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => return Err(Error::FileNameError),
+    };
+    try!(fs::create_dir_all(dst_dir));
+    let dst = dst_dir.join(&file_name);
+    let tmp = dst_dir.join(format!("{}.tmp", file_name));
+    try!(fs::copy(src, &tmp));
+    try!(fs::rename(&tmp, &dst));
+    Ok(dst)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::{self, File};
+    use std::io::{Read, Write};
+    use std::path::PathBuf;
+    use std::str::FromStr;
+
+    use hcore::package::PackageIdent;
+    use tempdir::TempDir;
+
+    use error::Error;
+    use super::*;
+
+    const POSSUMS: &'static str = "happyhumans/possums/8.1.4/20160427165340";
+    const POSSUMS_HART: &'static str = "happyhumans-possums-8.1.4-20160427165340-x86_64-linux.hart";
+    const POSSUMS_KEY: &'static str = "happyhumans-20160424223347.pub";
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("../core/tests/fixtures")
+            .join(name)
+    }
+
+    fn write_index(dir: &TempDir, content: &str) {
+        let mut file = File::create(dir.path().join(INDEX_FILE)).unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn path_from_url() {
+        let dir = TempDir::new("local-depot").unwrap();
+        let dir_str = dir.path().to_str().unwrap();
+
+        assert_eq!(LocalDepot::path_from_url(&format!("file://{}", dir_str)),
+                   Some(dir.path().to_path_buf()));
+        assert_eq!(LocalDepot::path_from_url(dir_str), None);
+        write_index(&dir, "HAB-INDEX-1\n");
+        assert_eq!(LocalDepot::path_from_url(dir_str),
+                   Some(dir.path().to_path_buf()));
+        assert_eq!(LocalDepot::path_from_url("https://app.habitat.sh/v1/depot"),
+                   None);
+    }
+
+    #[test]
+    fn latest_resolves_partial_idents() {
+        let dir = TempDir::new("local-depot").unwrap();
+        write_index(&dir,
+                    "HAB-INDEX-1\n\
+                     core/redis/3.0.7/20160614023044 core-redis-3.0.7-20160614023044.hart\n\
+                     core/redis/3.2.0/20160701010101 redis/core-redis-3.2.0-20160701010101.hart\n\
+                     core/redis/3.0.7/20160615000000 core-redis-3.0.7-20160615000000.hart\n\
+                     core/glibc/2.22/20160612063629 core-glibc-2.22-20160612063629.hart\n");
+        let depot = LocalDepot::open(dir.path()).unwrap();
+
+        let (ident, path) = depot.latest(&PackageIdent::from_str("core/redis").unwrap()).unwrap();
+        assert_eq!(ident.to_string(), "core/redis/3.2.0/20160701010101");
+        assert_eq!(path,
+                   dir.path().join("redis/core-redis-3.2.0-20160701010101.hart"));
+
+        let (ident, _) = depot.latest(&PackageIdent::from_str("core/redis/3.0.7").unwrap())
+            .unwrap();
+        assert_eq!(ident.to_string(), "core/redis/3.0.7/20160615000000");
+
+        let (ident, _) =
+            depot.latest(&PackageIdent::from_str("core/redis/3.0.7/20160614023044").unwrap())
+                .unwrap();
+        assert_eq!(ident.to_string(), "core/redis/3.0.7/20160614023044");
+
+        assert!(depot.latest(&PackageIdent::from_str("core/nginx").unwrap()).is_err());
+    }
+
+    #[test]
+    fn open_rejects_invalid_index() {
+        let dir = TempDir::new("local-depot").unwrap();
+        assert!(LocalDepot::open(dir.path()).is_err());
+
+        write_index(&dir, "core/redis/3.0.7/20160614023044 redis.hart\n");
+        assert!(LocalDepot::open(dir.path()).is_err());
+
+        write_index(&dir, "HAB-INDEX-1\ncore/redis/3.0.7/20160614023044 /tmp/redis.hart\n");
+        assert!(LocalDepot::open(dir.path()).is_err());
+
+        write_index(&dir, "HAB-INDEX-1\ncore/redis/3.0.7/20160614023044 ../redis.hart\n");
+        assert!(LocalDepot::open(dir.path()).is_err());
+
+        write_index(&dir,
+                    "HAB-INDEX-1\ncore/redis/3.0.7/20160614023044 redis/../../redis.hart\n");
+        assert!(LocalDepot::open(dir.path()).is_err());
+    }
+
+    #[test]
+    fn generate_index_indexes_artifacts_and_copies_keys() {
+        let dir = TempDir::new("local-depot").unwrap();
+        let cache = TempDir::new("key-cache").unwrap();
+        fs::create_dir_all(dir.path().join("possums")).unwrap();
+        fs::copy(fixture(POSSUMS_HART),
+                 dir.path().join("possums").join(POSSUMS_HART))
+            .unwrap();
+        fs::copy(fixture(POSSUMS_KEY), cache.path().join(POSSUMS_KEY)).unwrap();
+
+        assert_eq!(LocalDepot::generate_index(dir.path(), cache.path()).unwrap(),
+                   1);
+        let mut index = String::new();
+        File::open(dir.path().join(INDEX_FILE)).unwrap().read_to_string(&mut index).unwrap();
+        assert_eq!(index,
+                   format!("HAB-INDEX-1\n{} possums/{}\n", POSSUMS, POSSUMS_HART));
+        assert!(dir.path().join(KEYS_DIR).join(POSSUMS_KEY).is_file());
+
+        let depot = LocalDepot::open(dir.path()).unwrap();
+        let (ident, path) = depot.latest(&PackageIdent::from_str("happyhumans/possums").unwrap())
+            .unwrap();
+        assert_eq!(ident.to_string(), POSSUMS);
+        assert_eq!(path, dir.path().join("possums").join(POSSUMS_HART));
+    }
+
+    #[test]
+    fn generate_index_requires_signer_keys() {
+        let dir = TempDir::new("local-depot").unwrap();
+        let cache = TempDir::new("key-cache").unwrap();
+        fs::copy(fixture(POSSUMS_HART), dir.path().join(POSSUMS_HART)).unwrap();
+
+        match LocalDepot::generate_index(dir.path(), cache.path()) {
+            Err(Error::LocalDepotKeyNotFound(nwr)) => assert_eq!(nwr, "happyhumans-20160424223347"),
+            r => panic!("Expected LocalDepotKeyNotFound, got {:?}", r),
+        }
+        assert!(!dir.path().join(INDEX_FILE).exists());
+    }
+
+    #[test]
+    fn origin_key_revocation_is_optional() {
+        let dir = TempDir::new("local-depot").unwrap();
+        write_index(&dir, "HAB-INDEX-1\n");
+        let depot = LocalDepot::open(dir.path()).unwrap();
+
+        assert_eq!(depot.origin_key_revocation("core", "20160810182414").unwrap(),
+                   None);
+    }
+}
@@ -12,78 +12,539 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::fmt;
 use std::fs::File;
 use std::io;
 use std::io::prelude::*;
 use std::io::{BufReader, BufWriter};
 use std::path::Path;
+use std::str::FromStr;
 
 use rustc_serialize::base64::{STANDARD, ToBase64, FromBase64};
 use sodiumoxide::crypto::sign;
+use time;
+use xz2::read::XzDecoder;
+use xz2::write::XzEncoder;
+use zstd::stream::{Decoder as ZstdDecoder, Encoder as ZstdEncoder};
 
 use error::{Error, Result};
-use super::{HART_FORMAT_VERSION, SIG_HASH_TYPE, SigKeyPair};
+use super::{HART_FORMAT_VERSION, HART2_FORMAT_VERSION, HART3_FORMAT_VERSION, SigKeyPair};
+use super::hash::{self, HashType};
 use super::keys::parse_name_with_rev;
+use super::keys::revocation::KeyRevocation;
+
+/// The compression level used when writing `xz` payloads. This matches the default used by
+/// `xz(1)` and therefore by `hab-plan-build`.
+const XZ_COMPRESSION_LEVEL: u32 = 6;
+
+/// The compression level used when writing `zstd` payloads.
+const ZSTD_COMPRESSION_LEVEL: i32 = 19;
+
+const XZ_MAGIC: &'static [u8] = &[0xFD, 0x37, 0x7A, 0x58, 0x5A, 0x00];
+const ZSTD_MAGIC: &'static [u8] = &[0x28, 0xB5, 0x2F, 0xFD];
+
+/// The compression algorithm of the tarball payload in a Habitat artifact.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    Xz,
+    Zstd,
+}
+
+impl Compression {
+    /// Detects the compression of a file by inspecting its leading magic bytes, returning `None`
+    /// if the file is not compressed with a supported algorithm.
+    pub fn detect<P: ?Sized + AsRef<Path>>(src: &P) -> Result<Option<Compression>> {
+        let mut f = try!(File::open(src));
+        let mut magic = [0u8; 6];
+        let mut read = 0;
+        while read < magic.len() {
+            match try!(f.read(&mut magic[read..])) {
+                0 => break,
+                n => read += n,
+            }
+        }
+        Ok(Self::from_magic(&magic[..read]))
+    }
+
+    /// Returns the compression whose magic bytes the given bytes start with, if any.
+    fn from_magic(magic: &[u8]) -> Option<Compression> {
+        if magic.starts_with(XZ_MAGIC) {
+            Some(Compression::Xz)
+        } else if magic.starts_with(ZSTD_MAGIC) {
+            Some(Compression::Zstd)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let value = match *self {
+            Compression::Xz => "xz",
+            Compression::Zstd => "zstd",
+        };
+        write!(f, "{}", value)
+    }
+}
+
+impl FromStr for Compression {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "xz" => Ok(Compression::Xz),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(Error::CryptoError(format!("Unsupported compression type: {}", value))),
+        }
+    }
+}
+
+/// A signature over an artifact's payload hash, along with the name of the key which made it.
+#[derive(Clone, Debug)]
+pub struct ArtifactSignature {
+    pub key_name: String,
+    pub signature: Vec<u8>,
+}
+
+/// The plaintext header of a Habitat artifact.
+#[derive(Debug)]
+pub struct ArtifactHeader {
+    pub format_version: String,
+    pub hash_type: HashType,
+    pub compression: Compression,
+    /// The signatures on the artifact, the first being the one made by the original signer.
+    /// `HART-1` and `HART-2` artifacts carry exactly one signature.
+    pub signatures: Vec<ArtifactSignature>,
+}
+
+impl ArtifactHeader {
+    /// The name with revision of the key which originally signed the artifact.
+    pub fn key_name(&self) -> &str {
+        &self.signatures[0].key_name
+    }
+
+    /// Returns the oldest format version able to represent a header with a single signature.
+    /// Older verifiers assume every `HART-1` artifact is an `xz` payload hashed with BLAKE2b, so
+    /// any other payload is written as `HART-2`, which records both.
+    fn single_signature_format_version(compression: Compression,
+                                       hash_type: HashType)
+                                       -> &'static str {
+        match (compression, hash_type) {
+            (Compression::Xz, HashType::Blake2b) => HART_FORMAT_VERSION,
+            _ => HART2_FORMAT_VERSION,
+        }
+    }
+
+    /// Writes the header, choosing the oldest format version able to represent it.
+    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        if self.signatures.len() > 1 {
+            let () = try!(write!(writer,
+                                 "{}\n{}\n{}\n",
+                                 HART3_FORMAT_VERSION,
+                                 self.hash_type,
+                                 self.compression));
+            for sig in self.signatures.iter() {
+                let () = try!(write!(writer,
+                                     "{} {}\n",
+                                     sig.key_name,
+                                     sig.signature.to_base64(STANDARD)));
+            }
+            let () = try!(write!(writer, "\n"));
+        } else {
+            let format_version = Self::single_signature_format_version(self.compression,
+                                                                       self.hash_type);
+            let () = try!(write!(writer,
+                                 "{}\n{}\n{}\n",
+                                 format_version,
+                                 self.key_name(),
+                                 self.hash_type));
+            if format_version == HART2_FORMAT_VERSION {
+                let () = try!(write!(writer, "{}\n", self.compression));
+            }
+            let () = try!(write!(writer,
+                                 "{}\n\n",
+                                 self.signatures[0].signature.to_base64(STANDARD)));
+        }
+        Ok(())
+    }
+}
+
+/// A policy describing which keys must have signed an artifact for it to be trusted.
+///
+/// A policy is made up of requirements, each of which is a set of alternative keys. Every
+/// requirement must be met by at least one valid signature. A key may be given as a bare name,
+/// which matches any revision of that key, or as a name with revision.
+#[derive(Clone, Debug, Default)]
+pub struct SignaturePolicy {
+    requirements: Vec<Vec<String>>,
+}
+
+impl SignaturePolicy {
+    pub fn new() -> Self {
+        SignaturePolicy::default()
+    }
+
+    /// Adds a requirement that the artifact is signed by at least one of the given keys.
+    pub fn require_one_of<I, S>(mut self, keys: I) -> Self
+        where I: IntoIterator<Item = S>,
+              S: Into<String>
+    {
+        self.requirements.push(keys.into_iter().map(|k| k.into()).collect());
+        self
+    }
+
+    /// Returns the first requirement which is not met by the given signers, if any.
+    fn unmet(&self, signers: &[String]) -> Option<&Vec<String>> {
+        self.requirements.iter().find(|requirement| {
+            !requirement.iter().any(|key| {
+                signers.iter().any(|signer| {
+                    match parse_name_with_rev(signer) {
+                        Ok((ref name, _)) if name == key => true,
+                        _ => signer == key,
+                    }
+                })
+            })
+        })
+    }
+}
 
 /// Generate and sign a package
+///
+/// The compression of the source payload is detected and recorded in the artifact header. A
+/// payload which is neither `xz` nor `zstd` compressed is refused.
 pub fn sign<P1: ?Sized, P2: ?Sized>(src: &P1, dst: &P2, pair: &SigKeyPair) -> Result<()>
     where P1: AsRef<Path>,
           P2: AsRef<Path>
 {
-    let hash = try!(super::hash::hash_file(&src));
-    debug!("File hash for {} = {}", src.as_ref().display(), &hash);
+    sign_with_hash_type(src, dst, pair, HashType::default())
+}
+
+/// Generate and sign a package, hashing its payload with the given algorithm. The algorithm is
+/// recorded in the artifact header and used again when the artifact is verified.
+pub fn sign_with_hash_type<P1: ?Sized, P2: ?Sized>(src: &P1,
+                                                   dst: &P2,
+                                                   pair: &SigKeyPair,
+                                                   hash_type: HashType)
+                                                   -> Result<()>
+    where P1: AsRef<Path>,
+          P2: AsRef<Path>
+{
+    sign_at(src, dst, pair, hash_type, time::now_utc().to_timespec().sec)
+}
+
+fn sign_at<P1: ?Sized, P2: ?Sized>(src: &P1,
+                                   dst: &P2,
+                                   pair: &SigKeyPair,
+                                   hash_type: HashType,
+                                   signed_at: i64)
+                                   -> Result<()>
+    where P1: AsRef<Path>,
+          P2: AsRef<Path>
+{
+    let compression = match try!(Compression::detect(src)) {
+        Some(compression) => compression,
+        None => {
+            let msg = format!("Unsupported payload compression in {}, must be one of (xz, zstd)",
+                              src.as_ref().display());
+            return Err(Error::CryptoError(msg));
+        }
+    };
+    let hash = try!(hash::hash_file_with_type(&src, hash_type));
+    debug!("File {} hash for {} = {}", hash_type, src.as_ref().display(), &hash);
 
-    let signature = sign::sign(&hash.as_bytes(), try!(pair.secret()));
+    let format_version = ArtifactHeader::single_signature_format_version(compression, hash_type);
+    let signed_at = if format_version == HART_FORMAT_VERSION {
+        None
+    } else {
+        Some(signed_at)
+    };
+    let message = signed_message(&hash, signed_at);
+    let header = ArtifactHeader {
+        format_version: format_version.to_string(),
+        hash_type: hash_type,
+        compression: compression,
+        signatures: vec![ArtifactSignature {
+                             key_name: pair.name_with_rev(),
+                             signature: sign::sign(message.as_bytes(), try!(pair.secret())),
+                         }],
+    };
     let output_file = try!(File::create(dst));
     let mut writer = BufWriter::new(&output_file);
-    let () = try!(write!(writer,
-                         "{}\n{}\n{}\n{}\n\n",
-                         HART_FORMAT_VERSION,
-                         pair.name_with_rev(),
-                         SIG_HASH_TYPE,
-                         signature.to_base64(STANDARD)));
+    try!(header.write(&mut writer));
     let mut file = try!(File::open(src));
     try!(io::copy(&mut file, &mut writer));
     Ok(())
 }
 
+/// Adds a signature made with the given key to an existing artifact, writing the result as a
+/// `HART-3` artifact to `dst`, which must not be the same file as `src`. The payload is left
+/// untouched so all existing signatures remain valid.
+///
+/// The existing signatures are verified first, as with `verify`, so the public keys of all
+/// signers must be present in the key cache. A tampered artifact is never countersigned.
+pub fn countersign<P1: ?Sized, P2: ?Sized, P3: ?Sized>(src: &P1,
+                                                       dst: &P2,
+                                                       pair: &SigKeyPair,
+                                                       cache_key_path: &P3)
+                                                       -> Result<()>
+    where P1: AsRef<Path>,
+          P2: AsRef<Path>,
+          P3: AsRef<Path>
+{
+    countersign_at(src,
+                   dst,
+                   pair,
+                   cache_key_path,
+                   time::now_utc().to_timespec().sec)
+}
+
+fn countersign_at<P1: ?Sized, P2: ?Sized, P3: ?Sized>(src: &P1,
+                                                      dst: &P2,
+                                                      pair: &SigKeyPair,
+                                                      cache_key_path: &P3,
+                                                      signed_at: i64)
+                                                      -> Result<()>
+    where P1: AsRef<Path>,
+          P2: AsRef<Path>,
+          P3: AsRef<Path>
+{
+    let f = try!(File::open(src));
+    let mut reader = BufReader::new(f);
+    let mut header = try!(read_header(&mut reader));
+    if header.signatures.iter().any(|s| s.key_name == pair.name_with_rev()) {
+        let msg = format!("Artifact is already signed with {}", pair.name_with_rev());
+        return Err(Error::CryptoError(msg));
+    }
+    let (_, verified_hash) = try!(verify(src, cache_key_path));
+    let hash = try!(hash::hash_reader_with_type(&mut reader, header.hash_type));
+    debug!("Payload hash for {} = {}", src.as_ref().display(), &hash);
+    if hash != verified_hash {
+        let msg = format!("Artifact {} changed while it was being countersigned",
+                          src.as_ref().display());
+        return Err(Error::CryptoError(msg));
+    }
+    header.signatures.push(ArtifactSignature {
+        key_name: pair.name_with_rev(),
+        signature: sign::sign(signed_message(&hash, Some(signed_at)).as_bytes(),
+                              try!(pair.secret())),
+    });
+    header.format_version = HART3_FORMAT_VERSION.to_string();
+
+    let mut payload = try!(get_archive_reader(&src));
+    let output_file = try!(File::create(dst));
+    let mut writer = BufWriter::new(&output_file);
+    try!(header.write(&mut writer));
+    try!(io::copy(&mut payload, &mut writer));
+    Ok(())
+}
+
+/// Re-encode a compressed tarball using the given compression, writing the result to `dst`. The
+/// source may be `xz` or `zstd` compressed, or an uncompressed tarball.
+pub fn recompress<P1: ?Sized, P2: ?Sized>(src: &P1,
+                                          dst: &P2,
+                                          compression: Compression)
+                                          -> Result<()>
+    where P1: AsRef<Path>,
+          P2: AsRef<Path>
+{
+    let src_file = try!(File::open(src));
+    let mut reader: Box<Read> = match try!(Compression::detect(src)) {
+        Some(Compression::Xz) => Box::new(XzDecoder::new(BufReader::new(src_file))),
+        Some(Compression::Zstd) => Box::new(try!(ZstdDecoder::new(src_file))),
+        None => Box::new(BufReader::new(src_file)),
+    };
+    let writer = BufWriter::new(try!(File::create(dst)));
+    match compression {
+        Compression::Xz => {
+            let mut encoder = XzEncoder::new(writer, XZ_COMPRESSION_LEVEL);
+            try!(io::copy(&mut reader, &mut encoder));
+            try!(encoder.finish());
+        }
+        Compression::Zstd => {
+            let mut encoder = try!(ZstdEncoder::new(writer, ZSTD_COMPRESSION_LEVEL));
+            try!(io::copy(&mut reader, &mut encoder));
+            try!(encoder.finish());
+        }
+    }
+    Ok(())
+}
+
+/// return the header of a .hart file
+pub fn get_artifact_header<P: ?Sized + AsRef<Path>>(src: &P) -> Result<ArtifactHeader> {
+    let f = try!(File::open(src));
+    let mut reader = BufReader::new(f);
+    read_header(&mut reader)
+}
+
 /// return a BufReader to the .tar bytestream, skipping the signed header
 pub fn get_archive_reader<P: AsRef<Path>>(src: &P) -> Result<BufReader<File>> {
     let f = try!(File::open(src));
-    let mut your_format_version = String::new();
-    let mut your_key_name = String::new();
-    let mut your_hash_type = String::new();
-    let mut your_signature_raw = String::new();
-    let mut empty_line = String::new();
-
     let mut reader = BufReader::new(f);
-    if try!(reader.read_line(&mut your_format_version)) <= 0 {
-        return Err(Error::CryptoError("Can't read format version".to_string()));
+    let _ = try!(read_header(&mut reader));
+    Ok(reader)
+}
+
+/// return a reader over the decompressed payload of a signed file, skipping the signed header
+///
+/// The compression is detected from the payload itself. A payload which isn't compressed, such
+/// as that of a `FILES` metafile signed before payloads had to be compressed, is read as is.
+pub fn get_payload_reader<P: AsRef<Path>>(src: &P) -> Result<Box<Read>> {
+    let mut reader = try!(get_archive_reader(src));
+    let compression = Compression::from_magic(try!(reader.fill_buf()));
+    match compression {
+        Some(Compression::Xz) => Ok(Box::new(XzDecoder::new(reader))),
+        Some(Compression::Zstd) => Ok(Box::new(try!(ZstdDecoder::new(reader)))),
+        None => Ok(Box::new(reader)),
     }
-    if try!(reader.read_line(&mut your_key_name)) <= 0 {
-        return Err(Error::CryptoError("Can't read keyname".to_string()));
+}
+
+/// verify the crypto signature of a .hart file
+///
+/// Every signature on the artifact is checked, so the public keys of all signers must be present
+/// in the key cache. The name with revision of the original signer is returned along with the
+/// payload hash. Once a revocation in the key cache has taken effect, every signature made by the
+/// key it revokes fails verification, whenever the signature claims to have been made.
+pub fn verify<P1: ?Sized, P2: ?Sized>(src: &P1, cache_key_path: &P2) -> Result<(String, String)>
+    where P1: AsRef<Path>,
+          P2: AsRef<Path>
+{
+    let (mut signers, hash) = try!(verify_signatures(src, cache_key_path, false));
+    Ok((signers.remove(0), hash))
+}
+
+/// verify the crypto signatures of a .hart file against a signature policy
+///
+/// Signatures made by keys which are not present in the key cache are ignored, but any other
+/// signature must be valid and not made by a revoked key. The names with revision of all keys
+/// with a valid signature are returned along with the payload hash.
+pub fn verify_with_policy<P1: ?Sized, P2: ?Sized>(src: &P1,
+                                                  cache_key_path: &P2,
+                                                  policy: &SignaturePolicy)
+                                                  -> Result<(Vec<String>, String)>
+    where P1: AsRef<Path>,
+          P2: AsRef<Path>
+{
+    let (signers, hash) = try!(verify_signatures(src, cache_key_path, true));
+    if signers.is_empty() {
+        return Err(Error::CryptoError("Artifact has no signature from a known key".to_string()));
     }
-    if try!(reader.read_line(&mut your_hash_type)) <= 0 {
-        return Err(Error::CryptoError("Can't read hash type".to_string()));
+    if let Some(requirement) = policy.unmet(&signers) {
+        let msg = format!("Signature policy not met, artifact is not signed by any of: {}",
+                          requirement.join(", "));
+        return Err(Error::CryptoError(msg));
     }
-    if try!(reader.read_line(&mut your_signature_raw)) <= 0 {
-        return Err(Error::CryptoError("Can't read signature".to_string()));
+    Ok((signers, hash))
+}
+
+/// Returns the name with revision of the key which originally signed the artifact.
+pub fn artifact_signer<P: AsRef<Path>>(src: &P) -> Result<String> {
+    let header = try!(get_artifact_header(src));
+    Ok(header.key_name().to_string())
+}
+
+/// Returns the names with revision of every key which signed the artifact.
+pub fn artifact_signers<P: AsRef<Path>>(src: &P) -> Result<Vec<String>> {
+    let header = try!(get_artifact_header(src));
+    Ok(header.signatures.into_iter().map(|s| s.key_name).collect())
+}
+
+/// Returns true if `candidate` is a copy of the `original` artifact with further signatures added,
+/// as made by `countersign`: the payloads are identical and every signature on the original is
+/// kept, in order, ahead of at least one new one. No signature is verified.
+pub fn adds_signatures<P1: ?Sized, P2: ?Sized>(original: &P1, candidate: &P2) -> Result<bool>
+    where P1: AsRef<Path>,
+          P2: AsRef<Path>
+{
+    let mut original_reader = BufReader::new(try!(File::open(original)));
+    let original_header = try!(read_header(&mut original_reader));
+    let mut candidate_reader = BufReader::new(try!(File::open(candidate)));
+    let candidate_header = try!(read_header(&mut candidate_reader));
+    if candidate_header.hash_type != original_header.hash_type ||
+       candidate_header.signatures.len() <= original_header.signatures.len() {
+        return Ok(false);
     }
-    if try!(reader.read_line(&mut empty_line)) <= 0 {
-        return Err(Error::CryptoError("Can't end of header".to_string()));
+    let kept = original_header.signatures
+        .iter()
+        .zip(candidate_header.signatures.iter())
+        .all(|(o, c)| o.key_name == c.key_name && o.signature == c.signature);
+    if !kept {
+        return Ok(false);
     }
-    Ok(reader)
+    let original_hash = try!(hash::hash_reader_with_type(&mut original_reader,
+                                                         original_header.hash_type));
+    let candidate_hash = try!(hash::hash_reader_with_type(&mut candidate_reader,
+                                                          candidate_header.hash_type));
+    Ok(original_hash == candidate_hash)
 }
 
-/// verify the crypto signature of a .hart file
-pub fn verify<P1: ?Sized, P2: ?Sized>(src: &P1, cache_key_path: &P2) -> Result<(String, String)>
+fn verify_signatures<P1: ?Sized, P2: ?Sized>(src: &P1,
+                                             cache_key_path: &P2,
+                                             skip_unknown_keys: bool)
+                                             -> Result<(Vec<String>, String)>
     where P1: AsRef<Path>,
           P2: AsRef<Path>
 {
     let f = try!(File::open(src));
     let mut reader = BufReader::new(f);
+    let header = try!(read_header(&mut reader));
+    let mut signed_hashes = Vec::with_capacity(header.signatures.len());
+    for sig in header.signatures.iter() {
+        let pair = match SigKeyPair::get_pair_for(&sig.key_name, cache_key_path) {
+            Ok(pair) => pair,
+            Err(e) => {
+                if skip_unknown_keys {
+                    debug!("Skipping signature by unknown key {}: {}", &sig.key_name, e);
+                    continue;
+                }
+                return Err(e);
+            }
+        };
+        if skip_unknown_keys && pair.public().is_err() {
+            debug!("Skipping signature by {}, public key not present", &sig.key_name);
+            continue;
+        }
+        // The signing time is the signer's own claim, so anyone holding a leaked key could
+        // backdate it; it is never trusted to let a signature by a revoked key through.
+        let (expected_hash, _) =
+            match sign::verify(sig.signature.as_slice(), try!(pair.public())) {
+                Ok(signed_data) => try!(parse_signed_message(signed_data)),
+                Err(_) => return Err(Error::CryptoError("Verification failed".to_string())),
+            };
+        if let Some(revocation) = try!(KeyRevocation::get_for(&sig.key_name, cache_key_path)) {
+            if revocation.in_effect() {
+                let msg = format!("Artifact is signed with revoked key {} (revoked at {})",
+                                  &sig.key_name,
+                                  revocation.revoked_at());
+                return Err(Error::CryptoError(msg));
+            }
+        }
+        signed_hashes.push((pair.name_with_rev(), expected_hash));
+    }
+    debug!("VERIFIED, checking signed hash against mine");
 
-    let _ = {
+    let computed_hash = try!(hash::hash_reader_with_type(&mut reader, header.hash_type));
+    debug!("My hash {}", computed_hash);
+
+    let mut signers = Vec::with_capacity(signed_hashes.len());
+    for (signer, expected_hash) in signed_hashes.into_iter() {
+        debug!("Expected hash {} signed by {}", expected_hash, signer);
+        if computed_hash != expected_hash {
+            let msg = format!("Habitat artifact is invalid, \
+                              hashes don't match (expected: {}, computed: {})",
+                              expected_hash,
+                              computed_hash);
+            return Err(Error::CryptoError(msg));
+        }
+        signers.push(signer);
+    }
+    Ok((signers, computed_hash))
+}
+
+/// Reads and validates the plaintext header of a .hart file, leaving the reader positioned at the
+/// start of the compressed payload.
+fn read_header(reader: &mut BufReader<File>) -> Result<ArtifactHeader> {
+    let format_version = {
         let mut buffer = String::new();
         match reader.read_line(&mut buffer) {
             Ok(0) => {
@@ -91,38 +552,67 @@ pub fn verify<P1: ?Sized, P2: ?Sized>(src: &P1, cache_key_path: &P2) -> Result<(
                     .to_string()))
             }
             Ok(_) => {
-                if buffer.trim() != HART_FORMAT_VERSION {
-                    let msg = format!("Unsupported format version: {}", &buffer.trim());
+                let version = buffer.trim();
+                if version != HART_FORMAT_VERSION && version != HART2_FORMAT_VERSION &&
+                   version != HART3_FORMAT_VERSION {
+                    let msg = format!("Unsupported format version: {}", version);
                     return Err(Error::CryptoError(msg));
                 }
             }
             Err(e) => return Err(Error::from(e)),
-
         };
         buffer.trim().to_string()
     };
-    let pair = {
+    if format_version == HART3_FORMAT_VERSION {
+        let hash_type = try!(read_hash_type(reader));
+        let compression = try!(read_compression(reader));
+        let mut signatures = Vec::new();
+        loop {
+            let mut buffer = String::new();
+            if try!(reader.read_line(&mut buffer)) <= 0 {
+                if signatures.is_empty() {
+                    return Err(Error::CryptoError("Corrupt payload, can't read signature"
+                        .to_string()));
+                }
+                return Err(Error::CryptoError("Corrupt payload, can't find end of header"
+                    .to_string()));
+            }
+            if buffer.trim().is_empty() {
+                break;
+            }
+            let mut parts = buffer.trim().splitn(2, ' ');
+            let key_name = parts.next().unwrap_or("");
+            try!(parse_name_with_rev(key_name));
+            let signature = try!(decode_signature(parts.next().unwrap_or("")));
+            signatures.push(ArtifactSignature {
+                key_name: key_name.to_string(),
+                signature: signature,
+            });
+        }
+        if signatures.is_empty() {
+            return Err(Error::CryptoError("Corrupt payload, can't read signature".to_string()));
+        }
+        return Ok(ArtifactHeader {
+            format_version: format_version,
+            hash_type: hash_type,
+            compression: compression,
+            signatures: signatures,
+        });
+    }
+    let key_name = {
         let mut buffer = String::new();
         if try!(reader.read_line(&mut buffer)) <= 0 {
             return Err(Error::CryptoError("Corrupt payload, can't read origin key name"
                 .to_string()));
         }
-        try!(SigKeyPair::get_pair_for(buffer.trim(), cache_key_path))
+        try!(parse_name_with_rev(buffer.trim()));
+        buffer.trim().to_string()
     };
-    let _ = {
-        let mut buffer = String::new();
-        match reader.read_line(&mut buffer) {
-            Ok(0) => {
-                return Err(Error::CryptoError("Corrupt payload, can't read hash type".to_string()))
-            }
-            Ok(_) => {
-                if buffer.trim() != SIG_HASH_TYPE {
-                    let msg = format!("Unsupported signature type: {}", &buffer.trim());
-                    return Err(Error::CryptoError(msg));
-                }
-            }
-            Err(e) => return Err(Error::from(e)),
-        };
+    let hash_type = try!(read_hash_type(reader));
+    let compression = if format_version == HART_FORMAT_VERSION {
+        Compression::Xz
+    } else {
+        try!(read_compression(reader))
     };
     let signature = {
         let mut buffer = String::new();
@@ -130,14 +620,7 @@ pub fn verify<P1: ?Sized, P2: ?Sized>(src: &P1, cache_key_path: &P2) -> Result<(
             Ok(0) => {
                 return Err(Error::CryptoError("Corrupt payload, can't read signature".to_string()))
             }
-            Ok(_) => {
-                match buffer.trim().as_bytes().from_base64() {
-                    Ok(sig) => sig,
-                    Err(e) => {
-                        return Err(Error::CryptoError(format!("Can't decode signature: {}", e)));
-                    }
-                }
-            }
+            Ok(_) => try!(decode_signature(buffer.trim())),
             Err(e) => return Err(Error::from(e)),
         }
     };
@@ -147,66 +630,81 @@ pub fn verify<P1: ?Sized, P2: ?Sized>(src: &P1, cache_key_path: &P2) -> Result<(
             return Err(Error::CryptoError("Corrupt payload, can't find end of header".to_string()));
         }
     };
-    let expected_hash = match sign::verify(signature.as_slice(), try!(pair.public())) {
-        Ok(signed_data) => {
-            match String::from_utf8(signed_data) {
-                Ok(hash) => hash,
+    Ok(ArtifactHeader {
+        format_version: format_version,
+        hash_type: hash_type,
+        compression: compression,
+        signatures: vec![ArtifactSignature {
+                             key_name: key_name,
+                             signature: signature,
+                         }],
+    })
+}
+
+fn read_hash_type(reader: &mut BufReader<File>) -> Result<HashType> {
+    let mut buffer = String::new();
+    match reader.read_line(&mut buffer) {
+        Ok(0) => Err(Error::CryptoError("Corrupt payload, can't read hash type".to_string())),
+        Ok(_) => {
+            match HashType::from_str(buffer.trim()) {
+                Ok(hash_type) => Ok(hash_type),
                 Err(_) => {
-                    return Err(Error::CryptoError("Error parsing artifact signature".to_string()))
+                    let msg = format!("Unsupported signature type: {}", &buffer.trim());
+                    Err(Error::CryptoError(msg))
                 }
             }
         }
-        Err(_) => return Err(Error::CryptoError("Verification failed".to_string())),
-    };
-    debug!("VERIFIED, checking signed hash against mine");
-
-    let computed_hash = try!(super::hash::hash_reader(&mut reader));
+        Err(e) => Err(Error::from(e)),
+    }
+}
 
-    debug!("Expected hash {}", expected_hash);
-    debug!("My hash {}", computed_hash);
-    if computed_hash == expected_hash {
-        Ok((pair.name_with_rev(), expected_hash))
-    } else {
-        let msg = format!("Habitat artifact is invalid, \
-                          hashes don't match (expected: {}, computed: {})",
-                          expected_hash,
-                          computed_hash);
-        Err(Error::CryptoError(msg))
+fn read_compression(reader: &mut BufReader<File>) -> Result<Compression> {
+    let mut buffer = String::new();
+    match reader.read_line(&mut buffer) {
+        Ok(0) => {
+            Err(Error::CryptoError("Corrupt payload, can't read compression type".to_string()))
+        }
+        Ok(_) => Compression::from_str(buffer.trim()),
+        Err(e) => Err(Error::from(e)),
     }
 }
 
-pub fn artifact_signer<P: AsRef<Path>>(src: &P) -> Result<String> {
-    let f = try!(File::open(src));
-    let mut reader = BufReader::new(f);
+/// Returns the message signed for a payload hash: the hash followed by the signing time, in
+/// seconds since the Unix epoch, on a line of its own. `HART-1` signatures are made over the bare
+/// hash, as older verifiers expect, and so record no signing time.
+fn signed_message(hash: &str, signed_at: Option<i64>) -> String {
+    match signed_at {
+        Some(signed_at) => format!("{}\n{}", hash, signed_at),
+        None => hash.to_string(),
+    }
+}
 
-    let _ = {
-        let mut buffer = String::new();
-        match reader.read_line(&mut buffer) {
-            Ok(0) => {
-                return Err(Error::CryptoError("Corrupt payload, can't read format version"
-                    .to_string()))
-            }
-            Ok(_) => {
-                if buffer.trim() != HART_FORMAT_VERSION {
-                    let msg = format!("Unsupported format version: {}", &buffer.trim());
-                    return Err(Error::CryptoError(msg));
+/// Splits a verified signed message into the payload hash and the signing time, if any.
+fn parse_signed_message(signed_data: Vec<u8>) -> Result<(String, Option<i64>)> {
+    let message = match String::from_utf8(signed_data) {
+        Ok(message) => message,
+        Err(_) => return Err(Error::CryptoError("Error parsing artifact signature".to_string())),
+    };
+    let mut lines = message.splitn(2, '\n');
+    let hash = lines.next().unwrap_or("").to_string();
+    match lines.next() {
+        Some(signed_at) => {
+            match signed_at.parse::<i64>() {
+                Ok(signed_at) => Ok((hash, Some(signed_at))),
+                Err(_) => {
+                    Err(Error::CryptoError("Error parsing artifact signing time".to_string()))
                 }
             }
-            Err(e) => return Err(Error::from(e)),
-
-        };
-        buffer.trim().to_string()
-    };
-    let name_with_rev = {
-        let mut buffer = String::new();
-        if try!(reader.read_line(&mut buffer)) <= 0 {
-            return Err(Error::CryptoError("Corrupt payload, can't read origin key name"
-                .to_string()));
         }
-        try!(parse_name_with_rev(buffer.trim()));
-        buffer.trim().to_string()
-    };
-    Ok(name_with_rev)
+        None => Ok((hash, None)),
+    }
+}
+
+fn decode_signature(encoded: &str) -> Result<Vec<u8>> {
+    match encoded.as_bytes().from_base64() {
+        Ok(sig) => Ok(sig),
+        Err(e) => Err(Error::CryptoError(format!("Can't decode signature: {}", e))),
+    }
 }
 
 #[cfg(test)]
@@ -217,7 +715,8 @@ mod test {
     use tempdir::TempDir;
 
     use super::*;
-    use super::super::SigKeyPair;
+    use super::super::{KeyRevocation, SigKeyPair};
+    use super::super::hash::HashType;
     use super::super::test_support::*;
 
     #[test]
@@ -226,11 +725,27 @@ mod test {
         let pair = SigKeyPair::generate_pair_for_origin("unicorn", cache.path()).unwrap();
         let dst = cache.path().join("signed.dat");
 
-        sign(&fixture("signme.dat"), &dst, &pair).unwrap();
+        sign(&fixture("signme.dat.xz"), &dst, &pair).unwrap();
         verify(&dst, cache.path()).unwrap();
         assert!(true);
     }
 
+    #[test]
+    fn sign_and_verify_sha256() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let pair = SigKeyPair::generate_pair_for_origin("unicorn", cache.path()).unwrap();
+        let dst = cache.path().join("signed.dat");
+
+        sign_with_hash_type(&fixture("signme.dat.xz"), &dst, &pair, HashType::Sha256).unwrap();
+        let header = get_artifact_header(&dst).unwrap();
+        assert_eq!(header.hash_type, HashType::Sha256);
+        assert_eq!(header.format_version, "HART-2");
+        assert_eq!(header.compression, Compression::Xz);
+        let (_, hash) = verify(&dst, cache.path()).unwrap();
+        assert_eq!(hash,
+                   "541ee40baf9150189b09e18aab9ba0354357af07e1b27a4a02771c1c89579025");
+    }
+
     #[test]
     #[should_panic(expected = "Secret key is required but not present for")]
     fn sign_missing_private_key() {
@@ -245,7 +760,7 @@ mod test {
         // Now reload the key pair which will be missing the secret key
         let pair = SigKeyPair::get_latest_pair_for("unicorn", cache.path()).unwrap();
 
-        sign(&fixture("signme.dat"), &dst, &pair).unwrap();
+        sign(&fixture("signme.dat.xz"), &dst, &pair).unwrap();
     }
 
     #[test]
@@ -254,7 +769,7 @@ mod test {
         let cache = TempDir::new("key_cache").unwrap();
         let pair = SigKeyPair::generate_pair_for_origin("unicorn", cache.path()).unwrap();
         let dst = cache.path().join("signed.dat");
-        sign(&fixture("signme.dat"), &dst, &pair).unwrap();
+        sign(&fixture("signme.dat.xz"), &dst, &pair).unwrap();
 
         // Delete the public key
         fs::remove_file(SigKeyPair::get_public_key_path(&pair.name_with_rev(), cache.path())
@@ -382,7 +897,7 @@ mod test {
         let dst = cache.path().join("signed.dat");
         let dst_corrupted = cache.path().join("corrupted.dat");
 
-        sign(&fixture("signme.dat"), &dst, &pair).unwrap();
+        sign(&fixture("signme.dat.xz"), &dst, &pair).unwrap();
         let mut corrupted = File::create(&dst_corrupted).unwrap();
         let f = File::open(&dst).unwrap();
         let f = BufReader::new(f);
@@ -405,14 +920,311 @@ mod test {
         let cache = TempDir::new("key_cache").unwrap();
         let pair = SigKeyPair::generate_pair_for_origin("unicorn", cache.path()).unwrap();
         let src = cache.path().join("src.in");
+        let xz = cache.path().join("src.tar.xz");
         let dst = cache.path().join("src.signed");
         let mut f = File::create(&src).unwrap();
         f.write_all("harty goodness".as_bytes()).unwrap();
-        sign(&src, &dst, &pair).unwrap();
+        recompress(&src, &xz, Compression::Xz).unwrap();
+        sign(&xz, &dst, &pair).unwrap();
 
-        let mut buffer = String::new();
+        let mut expected = Vec::new();
+        File::open(&xz).unwrap().read_to_end(&mut expected).unwrap();
+        let mut buffer = Vec::new();
         let mut reader = get_archive_reader(&dst).unwrap();
-        reader.read_to_string(&mut buffer).unwrap();
-        assert_eq!(buffer.as_bytes(), "harty goodness".as_bytes());
+        reader.read_to_end(&mut buffer).unwrap();
+        assert_eq!(buffer, expected);
+    }
+
+    #[test]
+    fn get_payload_reader_decompresses() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let pair = SigKeyPair::generate_pair_for_origin("unicorn", cache.path()).unwrap();
+        let src = cache.path().join("src.in");
+        File::create(&src).unwrap().write_all("harty goodness".as_bytes()).unwrap();
+        for compression in vec![Compression::Xz, Compression::Zstd] {
+            let compressed = cache.path().join(format!("src.{}", compression));
+            let dst = cache.path().join(format!("src.{}.signed", compression));
+            recompress(&src, &compressed, compression).unwrap();
+            sign(&compressed, &dst, &pair).unwrap();
+
+            let mut buffer = String::new();
+            get_payload_reader(&dst).unwrap().read_to_string(&mut buffer).unwrap();
+            assert_eq!(buffer, "harty goodness");
+        }
+    }
+
+    #[test]
+    fn get_payload_reader_uncompressed_legacy_payload() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let dst = cache.path().join("FILES");
+        File::create(&dst)
+            .unwrap()
+            .write_all("HART-1\nunicorn-20160101000000\nBLAKE2b\nc2lnbmF0dXJl\n\nsums".as_bytes())
+            .unwrap();
+
+        let mut buffer = String::new();
+        get_payload_reader(&dst).unwrap().read_to_string(&mut buffer).unwrap();
+        assert_eq!(buffer, "sums");
+    }
+
+    #[test]
+    #[should_panic(expected = "Unsupported payload compression")]
+    fn sign_uncompressed_payload() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let pair = SigKeyPair::generate_pair_for_origin("unicorn", cache.path()).unwrap();
+        let dst = cache.path().join("signed.dat");
+
+        sign(&fixture("signme.dat"), &dst, &pair).unwrap();
+    }
+
+    #[test]
+    fn sign_records_xz_compression_as_hart_1() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let pair = SigKeyPair::generate_pair_for_origin("unicorn", cache.path()).unwrap();
+        let src = cache.path().join("src.in");
+        let xz = cache.path().join("src.tar.xz");
+        let dst = cache.path().join("src.signed");
+        let mut f = File::create(&src).unwrap();
+        f.write_all("harty goodness".as_bytes()).unwrap();
+        recompress(&src, &xz, Compression::Xz).unwrap();
+        sign(&xz, &dst, &pair).unwrap();
+
+        let header = get_artifact_header(&dst).unwrap();
+        assert_eq!(header.format_version, "HART-1");
+        assert_eq!(header.compression, Compression::Xz);
+        verify(&dst, cache.path()).unwrap();
+    }
+
+    #[test]
+    fn sign_and_verify_zstd_payload() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let pair = SigKeyPair::generate_pair_for_origin("unicorn", cache.path()).unwrap();
+        let src = cache.path().join("src.in");
+        let zst = cache.path().join("src.tar.zst");
+        let dst = cache.path().join("src.signed");
+        let mut f = File::create(&src).unwrap();
+        f.write_all("harty goodness".as_bytes()).unwrap();
+        recompress(&src, &zst, Compression::Zstd).unwrap();
+        sign(&zst, &dst, &pair).unwrap();
+
+        let header = get_artifact_header(&dst).unwrap();
+        assert_eq!(header.format_version, "HART-2");
+        assert_eq!(header.compression, Compression::Zstd);
+        verify(&dst, cache.path()).unwrap();
+
+        let mut expected = Vec::new();
+        File::open(&zst).unwrap().read_to_end(&mut expected).unwrap();
+        let mut payload = Vec::new();
+        get_archive_reader(&dst).unwrap().read_to_end(&mut payload).unwrap();
+        assert_eq!(payload, expected);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unsupported compression type: lzma")]
+    fn verify_invalid_compression_type() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let pair = SigKeyPair::generate_pair_for_origin("unicorn", cache.path()).unwrap();
+        let dst = cache.path().join("signed.dat");
+        let mut f = File::create(&dst).unwrap();
+        f.write_all(format!("HART-2\n{}\nBLAKE2b\nlzma\nuhoh", pair.name_with_rev()).as_bytes())
+            .unwrap();
+
+        verify(&dst, cache.path()).unwrap();
+    }
+
+    #[test]
+    fn recompress_round_trip() {
+        let dir = TempDir::new("recompress").unwrap();
+        let src = dir.path().join("src.in");
+        let zst = dir.path().join("src.zst");
+        let xz = dir.path().join("src.xz");
+        let mut f = File::create(&src).unwrap();
+        f.write_all("harty goodness".as_bytes()).unwrap();
+
+        recompress(&src, &zst, Compression::Zstd).unwrap();
+        assert_eq!(Compression::detect(&zst).unwrap(), Some(Compression::Zstd));
+        recompress(&zst, &xz, Compression::Xz).unwrap();
+        assert_eq!(Compression::detect(&xz).unwrap(), Some(Compression::Xz));
+        assert_eq!(Compression::detect(&src).unwrap(), None);
+    }
+
+    #[test]
+    fn countersigned_artifact_adds_signatures() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let builder = SigKeyPair::generate_pair_for_origin("unicorn", cache.path()).unwrap();
+        let release = SigKeyPair::generate_pair_for_origin("release", cache.path()).unwrap();
+        let signed = cache.path().join("signed.dat");
+        let countersigned = cache.path().join("countersigned.dat");
+        let resigned = cache.path().join("resigned.dat");
+
+        sign(&fixture("signme.dat.xz"), &signed, &builder).unwrap();
+        countersign(&signed, &countersigned, &release, cache.path()).unwrap();
+        sign(&fixture("signme.dat.xz"), &resigned, &release).unwrap();
+
+        assert!(adds_signatures(&signed, &countersigned).unwrap());
+        assert!(!adds_signatures(&countersigned, &signed).unwrap());
+        assert!(!adds_signatures(&signed, &signed).unwrap());
+        assert!(!adds_signatures(&signed, &resigned).unwrap());
+    }
+
+    #[test]
+    fn countersign_and_verify() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let builder = SigKeyPair::generate_pair_for_origin("unicorn", cache.path()).unwrap();
+        let release = SigKeyPair::generate_pair_for_origin("release", cache.path()).unwrap();
+        let signed = cache.path().join("signed.dat");
+        let countersigned = cache.path().join("countersigned.dat");
+
+        sign(&fixture("signme.dat.xz"), &signed, &builder).unwrap();
+        countersign(&signed, &countersigned, &release, cache.path()).unwrap();
+
+        let header = get_artifact_header(&countersigned).unwrap();
+        assert_eq!(header.format_version, "HART-3");
+        assert_eq!(header.key_name(), builder.name_with_rev());
+        assert_eq!(artifact_signers(&countersigned).unwrap(),
+                   vec![builder.name_with_rev(), release.name_with_rev()]);
+        let (signer, _) = verify(&countersigned, cache.path()).unwrap();
+        assert_eq!(signer, builder.name_with_rev());
+
+        let mut expected = Vec::new();
+        File::open(fixture("signme.dat.xz")).unwrap().read_to_end(&mut expected).unwrap();
+        let mut payload = Vec::new();
+        get_archive_reader(&countersigned).unwrap().read_to_end(&mut payload).unwrap();
+        assert_eq!(payload, expected);
+    }
+
+    #[test]
+    #[should_panic(expected = "Habitat artifact is invalid")]
+    fn countersign_tampered_artifact() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let builder = SigKeyPair::generate_pair_for_origin("unicorn", cache.path()).unwrap();
+        let release = SigKeyPair::generate_pair_for_origin("release", cache.path()).unwrap();
+        let signed = cache.path().join("signed.dat");
+        let tampered = cache.path().join("tampered.dat");
+        let countersigned = cache.path().join("countersigned.dat");
+        sign(&fixture("signme.dat.xz"), &signed, &builder).unwrap();
+        {
+            let header = get_artifact_header(&signed).unwrap();
+            let mut f = File::create(&tampered).unwrap();
+            header.write(&mut f).unwrap();
+            f.write_all("payload-wont-match-signature".as_bytes()).unwrap();
+        }
+
+        countersign(&tampered, &countersigned, &release, cache.path()).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "Artifact is already signed with")]
+    fn countersign_with_same_key() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let pair = SigKeyPair::generate_pair_for_origin("unicorn", cache.path()).unwrap();
+        let signed = cache.path().join("signed.dat");
+        let countersigned = cache.path().join("countersigned.dat");
+
+        sign(&fixture("signme.dat.xz"), &signed, &pair).unwrap();
+        countersign(&signed, &countersigned, &pair, cache.path()).unwrap();
+    }
+
+    #[test]
+    fn verify_with_policy_met() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let builder = SigKeyPair::generate_pair_for_origin("unicorn", cache.path()).unwrap();
+        let release = SigKeyPair::generate_pair_for_origin("release", cache.path()).unwrap();
+        let signed = cache.path().join("signed.dat");
+        let countersigned = cache.path().join("countersigned.dat");
+        sign(&fixture("signme.dat.xz"), &signed, &builder).unwrap();
+        countersign(&signed, &countersigned, &release, cache.path()).unwrap();
+
+        let policy = SignaturePolicy::new()
+            .require_one_of(vec!["unicorn"])
+            .require_one_of(vec!["other".to_string(), release.name_with_rev()]);
+        let (signers, _) = verify_with_policy(&countersigned, cache.path(), &policy).unwrap();
+        assert_eq!(signers.len(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "artifact is not signed by any of: release, other")]
+    fn verify_with_policy_unmet() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let builder = SigKeyPair::generate_pair_for_origin("unicorn", cache.path()).unwrap();
+        let signed = cache.path().join("signed.dat");
+        sign(&fixture("signme.dat.xz"), &signed, &builder).unwrap();
+
+        let policy = SignaturePolicy::new()
+            .require_one_of(vec!["unicorn"])
+            .require_one_of(vec!["release", "other"]);
+        verify_with_policy(&signed, cache.path(), &policy).unwrap();
+    }
+
+    #[test]
+    fn verify_with_policy_ignores_unknown_keys() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let other_cache = TempDir::new("other_key_cache").unwrap();
+        let builder = SigKeyPair::generate_pair_for_origin("unicorn", cache.path()).unwrap();
+        let release = SigKeyPair::generate_pair_for_origin("release", other_cache.path())
+            .unwrap();
+        let signed = cache.path().join("signed.dat");
+        let countersigned = cache.path().join("countersigned.dat");
+        sign(&fixture("signme.dat.xz"), &signed, &builder).unwrap();
+        countersign(&signed, &countersigned, &release, cache.path()).unwrap();
+
+        let policy = SignaturePolicy::new().require_one_of(vec!["unicorn"]);
+        let (signers, _) = verify_with_policy(&countersigned, cache.path(), &policy).unwrap();
+        assert_eq!(signers, vec![builder.name_with_rev()]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Artifact is signed with revoked key")]
+    fn verify_revoked_key() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let pair = SigKeyPair::generate_pair_for_origin("unicorn", cache.path()).unwrap();
+        let dst = cache.path().join("signed.dat");
+
+        sign(&fixture("signme.dat.xz"), &dst, &pair).unwrap();
+        KeyRevocation::generate_now(&pair).unwrap().write_file(cache.path()).unwrap();
+        verify(&dst, cache.path()).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "Artifact is signed with revoked key")]
+    fn verify_backdated_signature_of_revoked_key() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let builder = SigKeyPair::generate_pair_for_origin("unicorn", cache.path()).unwrap();
+        let release = SigKeyPair::generate_pair_for_origin("release", cache.path()).unwrap();
+        let signed = cache.path().join("signed.dat");
+        let countersigned = cache.path().join("countersigned.dat");
+
+        // Whoever holds the leaked key claims to have signed long before it was revoked.
+        KeyRevocation::generate_now(&release).unwrap().write_file(cache.path()).unwrap();
+        sign(&fixture("signme.dat.xz"), &signed, &builder).unwrap();
+        countersign_at(&signed, &countersigned, &release, cache.path(), 1000).unwrap();
+        verify(&countersigned, cache.path()).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "Artifact is signed with revoked key")]
+    fn verify_key_revoked_before_signing() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let builder = SigKeyPair::generate_pair_for_origin("unicorn", cache.path()).unwrap();
+        let release = SigKeyPair::generate_pair_for_origin("release", cache.path()).unwrap();
+        let signed = cache.path().join("signed.dat");
+        let countersigned = cache.path().join("countersigned.dat");
+
+        sign(&fixture("signme.dat.xz"), &signed, &builder).unwrap();
+        countersign_at(&signed, &countersigned, &release, cache.path(), 3000).unwrap();
+        KeyRevocation::generate(&release, 2000).unwrap().write_file(cache.path()).unwrap();
+        verify(&countersigned, cache.path()).unwrap();
+    }
+
+    #[test]
+    fn verify_key_with_future_revocation() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let pair = SigKeyPair::generate_pair_for_origin("unicorn", cache.path()).unwrap();
+        let dst = cache.path().join("signed.dat");
+
+        sign(&fixture("signme.dat.xz"), &dst, &pair).unwrap();
+        let revocation = KeyRevocation::generate(&pair, i64::max_value()).unwrap();
+        revocation.write_file(cache.path()).unwrap();
+        verify(&dst, cache.path()).unwrap();
     }
 }
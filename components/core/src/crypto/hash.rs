@@ -12,68 +12,200 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::fmt;
 use std::fs::File;
 use std::io::{BufReader, Read};
 use std::mem;
 use std::path::Path;
 use std::ptr;
+use std::str::FromStr;
 
 use libsodium_sys;
 use rustc_serialize::hex::ToHex;
 
-use error::Result;
+use error::{Error, Result};
 
 const BUF_SIZE: usize = 1024;
 
+/// A hashing algorithm used for file hashes, artifact signatures, and package checksums. Both
+/// algorithms produce a digest of 32 bytes, written as a hex string.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HashType {
+    /// BLAKE2b, via libsodium's generic hash. This is the default.
+    Blake2b,
+    /// SHA-256
+    Sha256,
+}
+
+impl Default for HashType {
+    fn default() -> HashType {
+        HashType::Blake2b
+    }
+}
+
+impl fmt::Display for HashType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let value = match *self {
+            HashType::Blake2b => "BLAKE2b",
+            HashType::Sha256 => "SHA256",
+        };
+        write!(f, "{}", value)
+    }
+}
+
+impl FromStr for HashType {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_ref() {
+            "blake2b" => Ok(HashType::Blake2b),
+            "sha256" => Ok(HashType::Sha256),
+            _ => Err(Error::CryptoError(format!("Unsupported hash type: {}", value))),
+        }
+    }
+}
+
 /// Calculate the BLAKE2b hash of a file, return as a hex string
 /// digest size = 32 BYTES
 /// NOTE: the hashing is keyless
 pub fn hash_file<P: AsRef<Path>>(filename: &P) -> Result<String> {
+    hash_file_with_type(filename, HashType::default())
+}
+
+/// Calculate the hash of a file with the given algorithm, return as a hex string
+pub fn hash_file_with_type<P: AsRef<Path>>(filename: &P, hash_type: HashType) -> Result<String> {
     let file = try!(File::open(filename.as_ref()));
     let mut reader = BufReader::new(file);
-    hash_reader(&mut reader)
+    hash_reader_with_type(&mut reader, hash_type)
 }
 
 pub fn hash_string(data: &str) -> Result<String> {
-    let mut out = [0u8; libsodium_sys::crypto_generichash_BYTES];
-    let mut st = vec![0u8; (unsafe { libsodium_sys::crypto_generichash_statebytes() })];
-    let pst = unsafe {
-        mem::transmute::<*mut u8, *mut libsodium_sys::crypto_generichash_state>(st.as_mut_ptr())
-    };
-    unsafe {
-        libsodium_sys::crypto_generichash_init(pst, ptr::null_mut(), 0, out.len());
-        libsodium_sys::crypto_generichash_update(pst, data[..].as_ptr(), data.len() as u64);
-        libsodium_sys::crypto_generichash_final(pst, out.as_mut_ptr(), out.len());
-    }
-    Ok(out.to_hex())
+    let mut hasher = Blake2bHasher::new();
+    hasher.update(data.as_bytes());
+    Ok(hasher.finish())
 }
 
 pub fn hash_reader(reader: &mut BufReader<File>) -> Result<String> {
-    let mut out = [0u8; libsodium_sys::crypto_generichash_BYTES];
-    let mut st = vec![0u8; (unsafe { libsodium_sys::crypto_generichash_statebytes() })];
-    let pst = unsafe {
-        mem::transmute::<*mut u8, *mut libsodium_sys::crypto_generichash_state>(st.as_mut_ptr())
-    };
-
-    unsafe {
-        libsodium_sys::crypto_generichash_init(pst, ptr::null_mut(), 0, out.len());
-    }
+    hash_reader_with_type(reader, HashType::default())
+}
 
+pub fn hash_reader_with_type(reader: &mut BufReader<File>, hash_type: HashType) -> Result<String> {
+    let mut hasher = Hasher::new(hash_type);
     let mut buf = [0u8; BUF_SIZE];
     loop {
         let bytes_read = try!(reader.read(&mut buf));
         if bytes_read == 0 {
             break;
         }
-        let chunk = &buf[0..bytes_read];
+        hasher.update(&buf[0..bytes_read]);
+    }
+    Ok(hasher.finish())
+}
+
+/// An incremental hasher for any of the supported hash types.
+pub enum Hasher {
+    Blake2b(Blake2bHasher),
+    Sha256(Sha256Hasher),
+}
+
+impl Hasher {
+    pub fn new(hash_type: HashType) -> Self {
+        match hash_type {
+            HashType::Blake2b => Hasher::Blake2b(Blake2bHasher::new()),
+            HashType::Sha256 => Hasher::Sha256(Sha256Hasher::new()),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match *self {
+            Hasher::Blake2b(ref mut hasher) => hasher.update(data),
+            Hasher::Sha256(ref mut hasher) => hasher.update(data),
+        }
+    }
+
+    /// Consumes the hasher and returns the digest as a hex string.
+    pub fn finish(self) -> String {
+        match self {
+            Hasher::Blake2b(hasher) => hasher.finish(),
+            Hasher::Sha256(hasher) => hasher.finish(),
+        }
+    }
+}
+
+/// An incremental BLAKE2b hasher, for data which arrives in chunks such as the entries of a
+/// package archive. The resulting digest is identical to `hash_file` and `hash_reader`.
+pub struct Blake2bHasher {
+    state: Vec<u8>,
+}
+
+impl Blake2bHasher {
+    pub fn new() -> Self {
+        let mut st = vec![0u8; (unsafe { libsodium_sys::crypto_generichash_statebytes() })];
+        unsafe {
+            libsodium_sys::crypto_generichash_init(Self::state_ptr(&mut st),
+                                                   ptr::null_mut(),
+                                                   0,
+                                                   libsodium_sys::crypto_generichash_BYTES);
+        }
+        Blake2bHasher { state: st }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
         unsafe {
-            libsodium_sys::crypto_generichash_update(pst, chunk.as_ptr(), chunk.len() as u64);
+            libsodium_sys::crypto_generichash_update(Self::state_ptr(&mut self.state),
+                                                     data.as_ptr(),
+                                                     data.len() as u64);
         }
     }
-    unsafe {
-        libsodium_sys::crypto_generichash_final(pst, out.as_mut_ptr(), out.len());
+
+    /// Consumes the hasher and returns the digest as a hex string.
+    pub fn finish(mut self) -> String {
+        let mut out = [0u8; libsodium_sys::crypto_generichash_BYTES];
+        unsafe {
+            libsodium_sys::crypto_generichash_final(Self::state_ptr(&mut self.state),
+                                                    out.as_mut_ptr(),
+                                                    out.len());
+        }
+        out.to_hex()
+    }
+
+    fn state_ptr(st: &mut Vec<u8>) -> *mut libsodium_sys::crypto_generichash_state {
+        unsafe {
+            mem::transmute::<*mut u8, *mut libsodium_sys::crypto_generichash_state>(st.as_mut_ptr())
+        }
+    }
+}
+
+/// An incremental SHA-256 hasher. The resulting digest matches `sha256sum(1)`.
+pub struct Sha256Hasher {
+    state: libsodium_sys::crypto_hash_sha256_state,
+}
+
+impl Sha256Hasher {
+    pub fn new() -> Self {
+        let mut state: libsodium_sys::crypto_hash_sha256_state = unsafe { mem::zeroed() };
+        unsafe {
+            libsodium_sys::crypto_hash_sha256_init(&mut state);
+        }
+        Sha256Hasher { state: state }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        unsafe {
+            libsodium_sys::crypto_hash_sha256_update(&mut self.state,
+                                                     data.as_ptr(),
+                                                     data.len() as u64);
+        }
+    }
+
+    /// Consumes the hasher and returns the digest as a hex string.
+    pub fn finish(mut self) -> String {
+        let mut out = [0u8; libsodium_sys::crypto_hash_sha256_BYTES];
+        unsafe {
+            libsodium_sys::crypto_hash_sha256_final(&mut self.state, &mut out);
+        }
+        out.to_hex()
     }
-    Ok(out.to_hex())
 }
 
 #[cfg(test)]
@@ -82,7 +214,7 @@ mod test {
     #[allow(unused_imports)]
     use std::fs::{self, File};
     #[allow(unused_imports)]
-    use std::io;
+    use std::io::{self, Read};
     use std::path::PathBuf;
 
     #[cfg(feature = "functional")]
@@ -128,6 +260,33 @@ mod test {
         assert_eq!(computed, expected);
     }
 
+    #[test]
+    fn hasher_matches_hash_file() {
+        let mut content = Vec::new();
+        File::open(fixture("signme.dat")).unwrap().read_to_end(&mut content).unwrap();
+        let mut hasher = Blake2bHasher::new();
+        for chunk in content.chunks(7) {
+            hasher.update(chunk);
+        }
+        assert_eq!(hasher.finish(), hash_file(&fixture("signme.dat")).unwrap());
+    }
+
+    #[test]
+    fn hash_file_sha256() {
+        // The expected value was computed using `sha256sum signme.dat`
+        let computed = hash_file_with_type(&fixture("signme.dat"), HashType::Sha256).unwrap();
+        let expected = "b5bb9d8014a0f9b1d61e21e796d78dccdf1352f23cd32812f4850b878ae4944c";
+        assert_eq!(computed, expected);
+    }
+
+    #[test]
+    fn hash_type_from_str() {
+        assert_eq!(HashType::Blake2b, "BLAKE2b".parse::<HashType>().unwrap());
+        assert_eq!(HashType::Sha256, "sha256".parse::<HashType>().unwrap());
+        assert!("md5".parse::<HashType>().is_err());
+        assert_eq!("SHA256", HashType::Sha256.to_string());
+    }
+
     #[test]
     #[cfg(feature = "functional")]
     fn hash_file_large_binary() {
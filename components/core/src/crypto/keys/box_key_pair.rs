@@ -23,8 +23,9 @@ use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::SecretKey as BoxSecre
 use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::{Nonce, gen_nonce};
 
 use error::{Error, Result};
-use super::{get_key_revisions, mk_key_filename, mk_revision_string, parse_name_with_rev,
-            read_key_bytes, write_keypair_files, KeyPair, KeyType};
+use super::{encrypt_key_bytes, get_key_revisions, has_passphrase_for, mk_key_filename,
+            mk_revision_string, parse_name_with_rev, read_key_bytes, write_keypair_files, KeyPair,
+            KeyType};
 use super::super::{BOX_FORMAT_VERSION, PUBLIC_KEY_SUFFIX, SECRET_BOX_KEY_SUFFIX};
 
 pub type BoxKeyPair = KeyPair<BoxPublicKey, BoxSecretKey>;
@@ -38,6 +39,7 @@ impl BoxKeyPair {
         let keyname = Self::mk_key_name_for_service(org, service_group, &revision);
         debug!("new service box key name = {}", &keyname);
         let (public_key, secret_key) = try!(Self::generate_pair_files(&keyname,
+                                                                      None,
                                                                       cache_key_path.as_ref()));
         let (name, _) = try!(parse_name_with_rev(&keyname));
         Ok(Self::new(name, revision, Some(public_key), Some(secret_key)))
@@ -46,11 +48,27 @@ impl BoxKeyPair {
     pub fn generate_pair_for_user<P: AsRef<Path> + ?Sized>(user: &str,
                                                            cache_key_path: &P)
                                                            -> Result<Self> {
+        Self::generate_user_pair(user, None, cache_key_path.as_ref())
+    }
+
+    /// Generates a user key pair whose secret key is written encrypted with a passphrase.
+    pub fn generate_encrypted_pair_for_user<P: AsRef<Path> + ?Sized>(user: &str,
+                                                                     passphrase: &str,
+                                                                     cache_key_path: &P)
+                                                                     -> Result<Self> {
+        Self::generate_user_pair(user, Some(passphrase), cache_key_path.as_ref())
+    }
+
+    fn generate_user_pair(user: &str,
+                          passphrase: Option<&str>,
+                          cache_key_path: &Path)
+                          -> Result<Self> {
         let revision = try!(mk_revision_string());
         let keyname = Self::mk_key_name_for_user(user, &revision);
         debug!("new user sig key name = {}", &keyname);
         let (public_key, secret_key) = try!(Self::generate_pair_files(&keyname,
-                                                                      cache_key_path.as_ref()));
+                                                                      passphrase,
+                                                                      cache_key_path));
         let (name, _) = try!(parse_name_with_rev(&keyname));
         Ok(Self::new(name, revision, Some(public_key), Some(secret_key)))
     }
@@ -73,6 +91,15 @@ impl BoxKeyPair {
     pub fn get_pair_for<P: AsRef<Path> + ?Sized>(name_with_rev: &str,
                                                  cache_key_path: &P)
                                                  -> Result<Self> {
+        Self::get_pair_for_with_passphrase(name_with_rev, None, cache_key_path)
+    }
+
+    /// Returns the key pair for a name with revision, decrypting an encrypted secret key with
+    /// the given passphrase.
+    pub fn get_pair_for_with_passphrase<P: AsRef<Path> + ?Sized>(name_with_rev: &str,
+                                                                 passphrase: Option<&str>,
+                                                                 cache_key_path: &P)
+                                                                 -> Result<Self> {
         let (name, rev) = try!(parse_name_with_rev(&name_with_rev));
         let pk = match Self::get_public_key(name_with_rev, cache_key_path.as_ref()) {
             Ok(k) => Some(k),
@@ -84,9 +111,14 @@ impl BoxKeyPair {
                 None
             }
         };
-        let sk = match Self::get_secret_key(name_with_rev, cache_key_path.as_ref()) {
+        let sk = match Self::get_secret_key(name_with_rev, passphrase, cache_key_path.as_ref()) {
             Ok(k) => Some(k),
             Err(e) => {
+                if let Ok(path) = Self::get_secret_key_path(name_with_rev, cache_key_path) {
+                    if has_passphrase_for(&path, passphrase) {
+                        return Err(e);
+                    }
+                }
                 // Not an error, just continue
                 debug!("Can't find secret key for name_with_rev {}: {}",
                        name_with_rev,
@@ -229,9 +261,14 @@ impl BoxKeyPair {
     }
 
     fn generate_pair_files(name_with_rev: &str,
+                           passphrase: Option<&str>,
                            cache_key_path: &Path)
                            -> Result<(BoxPublicKey, BoxSecretKey)> {
         let (pk, sk) = box_::gen_keypair();
+        let secret_content = match passphrase {
+            Some(passphrase) => try!(encrypt_key_bytes(&sk[..], passphrase)),
+            None => sk[..].to_vec(),
+        };
 
         let public_keyfile = mk_key_filename(cache_key_path, name_with_rev, PUBLIC_KEY_SUFFIX);
         let secret_keyfile = mk_key_filename(cache_key_path, name_with_rev, SECRET_BOX_KEY_SUFFIX);
@@ -243,13 +280,14 @@ impl BoxKeyPair {
                                  Some(&public_keyfile),
                                  Some(&pk[..].to_base64(STANDARD).into_bytes()),
                                  Some(&secret_keyfile),
-                                 Some(&sk[..].to_base64(STANDARD).into_bytes())));
+                                 Some(&secret_content.to_base64(STANDARD).into_bytes()),
+                                 passphrase.is_some()));
         Ok((pk, sk))
     }
 
     fn get_public_key(key_with_rev: &str, cache_key_path: &Path) -> Result<BoxPublicKey> {
         let public_keyfile = mk_key_filename(cache_key_path, key_with_rev, PUBLIC_KEY_SUFFIX);
-        let bytes = try!(read_key_bytes(&public_keyfile));
+        let bytes = try!(read_key_bytes(&public_keyfile, None));
         match BoxPublicKey::from_slice(&bytes) {
             Some(sk) => Ok(sk),
             None => {
@@ -259,9 +297,12 @@ impl BoxKeyPair {
         }
     }
 
-    fn get_secret_key(key_with_rev: &str, cache_key_path: &Path) -> Result<BoxSecretKey> {
+    fn get_secret_key(key_with_rev: &str,
+                      passphrase: Option<&str>,
+                      cache_key_path: &Path)
+                      -> Result<BoxSecretKey> {
         let secret_keyfile = mk_key_filename(cache_key_path, key_with_rev, SECRET_BOX_KEY_SUFFIX);
-        let bytes = try!(read_key_bytes(&secret_keyfile));
+        let bytes = try!(read_key_bytes(&secret_keyfile, passphrase));
         match BoxSecretKey::from_slice(&bytes) {
             Some(sk) => Ok(sk),
             None => {
@@ -282,8 +323,8 @@ impl BoxKeyPair {
 
 #[cfg(test)]
 mod test {
-    use std::fs;
-    use std::io::BufRead;
+    use std::fs::{self, File};
+    use std::io::{BufRead, Read};
     use std::str;
 
     use tempdir::TempDir;
@@ -352,6 +393,49 @@ mod test {
         assert!(cache.path().join(format!("{}.box.key", pair.name_with_rev())).exists());
     }
 
+    #[test]
+    fn generated_encrypted_user_pair() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let pair = BoxKeyPair::generate_encrypted_pair_for_user("wecoyote",
+                                                                "correct horse",
+                                                                cache.path())
+            .unwrap();
+        let secret_keyfile = BoxKeyPair::get_secret_key_path(&pair.name_with_rev(), cache.path())
+            .unwrap();
+        let mut content = String::new();
+        File::open(&secret_keyfile).unwrap().read_to_string(&mut content).unwrap();
+        assert!(content.starts_with("BOX-SEC-ENC-1\n"));
+
+        let loaded = BoxKeyPair::get_pair_for_with_passphrase(&pair.name_with_rev(),
+                                                              Some("correct horse"),
+                                                              cache.path())
+            .unwrap();
+        assert!(loaded.secret().unwrap() == pair.secret().unwrap());
+
+        let service = BoxKeyPair::generate_pair_for_service("acme", "tnt.default", cache.path())
+            .unwrap();
+        let ciphertext = loaded.encrypt("I wish to buy more rockets".as_bytes(), &service)
+            .unwrap();
+        let message = BoxKeyPair::decrypt(&ciphertext, cache.path()).unwrap();
+        assert_eq!(message, "I wish to buy more rockets".as_bytes());
+    }
+
+    #[test]
+    #[should_panic(expected = "is the passphrase correct?")]
+    fn get_encrypted_pair_for_wrong_passphrase() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let pair = BoxKeyPair::generate_encrypted_pair_for_user("wecoyote",
+                                                                "correct horse",
+                                                                cache.path())
+            .unwrap();
+
+        // The public key alone must not pass for the pair
+        BoxKeyPair::get_pair_for_with_passphrase(&pair.name_with_rev(),
+                                                 Some("battery staple"),
+                                                 cache.path())
+            .unwrap();
+    }
+
     #[test]
     fn get_pairs_for() {
         let cache = TempDir::new("key_cache").unwrap();
@@ -23,16 +23,20 @@ use std::result;
 use std::str::FromStr;
 
 use regex::Regex;
-use rustc_serialize::base64::FromBase64;
+use rustc_serialize::base64::{STANDARD, ToBase64, FromBase64};
+use sodiumoxide::crypto::pwhash;
+use sodiumoxide::crypto::secretbox;
 use time;
 
+use env as henv;
 use error::{Error, Result};
 use util::perm;
 
 use super::{PUBLIC_BOX_KEY_VERSION, PUBLIC_KEY_PERMISSIONS, PUBLIC_KEY_SUFFIX,
-            PUBLIC_SIG_KEY_VERSION, SECRET_BOX_KEY_SUFFIX, SECRET_BOX_KEY_VERSION,
-            SECRET_KEY_PERMISSIONS, SECRET_SIG_KEY_SUFFIX, SECRET_SIG_KEY_VERSION,
-            SECRET_SYM_KEY_SUFFIX, SECRET_SYM_KEY_VERSION};
+            PUBLIC_SIG_KEY_VERSION, SECRET_BOX_KEY_ENCRYPTED_VERSION, SECRET_BOX_KEY_SUFFIX,
+            SECRET_BOX_KEY_VERSION, SECRET_KEY_PASSPHRASE_ENV_VAR, SECRET_KEY_PERMISSIONS,
+            SECRET_SIG_KEY_ENCRYPTED_VERSION, SECRET_SIG_KEY_SUFFIX, SECRET_SIG_KEY_VERSION,
+            SECRET_SYM_KEY_ENCRYPTED_VERSION, SECRET_SYM_KEY_SUFFIX, SECRET_SYM_KEY_VERSION};
 
 lazy_static! {
     static ref NAME_WITH_REV_RE: Regex = Regex::new(r"\A(?P<name>.+)-(?P<rev>\d{14})\z").unwrap();
@@ -41,6 +45,7 @@ lazy_static! {
 }
 
 pub mod box_key_pair;
+pub mod revocation;
 pub mod sym_key;
 pub mod sig_key_pair;
 
@@ -307,9 +312,214 @@ pub fn is_valid_origin_name(name: &str) -> bool {
     name.chars().count() <= 255 && ORIGIN_NAME_RE.is_match(name)
 }
 
+/// Returns the passphrase for encrypted secret keys from the environment, if one is set.
+pub fn secret_key_passphrase() -> Option<String> {
+    match henv::var(SECRET_KEY_PASSPHRASE_ENV_VAR) {
+        Ok(ref val) if !val.is_empty() => Some(val.to_string()),
+        _ => None,
+    }
+}
+
+/// Is the string slice the contents of a passphrase-encrypted secret key?
+pub fn is_encrypted_key_str(content: &str) -> bool {
+    content.lines().next().and_then(plain_key_version).is_some()
+}
+
+/// Is the file a passphrase-encrypted secret key?
+pub fn is_encrypted_key_file(keyfile: &Path) -> Result<bool> {
+    let mut content = String::new();
+    try!(try!(File::open(keyfile)).read_to_string(&mut content));
+    Ok(is_encrypted_key_str(&content))
+}
+
+/// Encrypts the contents of an unencrypted secret sig, box, or sym key with a passphrase,
+/// returning the contents of the encrypted secret key.
+///
+/// # Errors
+///
+/// * If the content is not an unencrypted secret key
+/// * If the key value (the Base64 payload) is missing or cannot be decoded
+pub fn encrypt_key_str(content: &str, passphrase: &str) -> Result<String> {
+    let (version, name_with_rev, body) = try!(split_key_str(content));
+    let encrypted_version = match encrypted_key_version(version) {
+        Some(val) => val,
+        None => {
+            let msg = if plain_key_version(version).is_some() {
+                format!("Secret key {} is already encrypted", name_with_rev)
+            } else {
+                format!("Only secret keys can be encrypted, found key version: {}", version)
+            };
+            return Err(Error::CryptoError(msg));
+        }
+    };
+    let keybytes = try!(decode_key_body(name_with_rev, body));
+    let encrypted = try!(encrypt_key_bytes(&keybytes, passphrase));
+    Ok(format!("{}\n{}\n\n{}",
+               encrypted_version,
+               name_with_rev,
+               encrypted.to_base64(STANDARD)))
+}
+
+/// Decrypts the contents of a passphrase-encrypted secret key, returning the contents of the
+/// unencrypted secret key.
+///
+/// # Errors
+///
+/// * If the content is not an encrypted secret key
+/// * If the key value (the Base64 payload) is missing or cannot be decoded
+/// * If the passphrase is incorrect
+pub fn decrypt_key_str(content: &str, passphrase: &str) -> Result<String> {
+    let (version, name_with_rev, body) = try!(split_key_str(content));
+    let plain_version = match plain_key_version(version) {
+        Some(val) => val,
+        None => {
+            return Err(Error::CryptoError(format!("Key {} is not an encrypted secret key",
+                                                  name_with_rev)))
+        }
+    };
+    let encrypted = try!(decode_key_body(name_with_rev, body));
+    let keybytes = try!(decrypt_key_bytes(name_with_rev, &encrypted, passphrase));
+    Ok(format!("{}\n{}\n\n{}",
+               plain_version,
+               name_with_rev,
+               keybytes.to_base64(STANDARD)))
+}
+
+/// Checks that a passphrase decrypts an encrypted secret key file.
+pub fn check_key_passphrase(keyfile: &Path, passphrase: &str) -> Result<()> {
+    let mut content = String::new();
+    try!(try!(File::open(keyfile)).read_to_string(&mut content));
+    try!(decrypt_key_str(&content, passphrase));
+    Ok(())
+}
+
+/// Checks that the contents of a secret key can be loaded once written to the key cache,
+/// returning whether it is encrypted. An encrypted secret key must decrypt with the given
+/// passphrase, or with the one from the environment when none is given.
+fn check_encrypted_key_str(content: &str, passphrase: Option<&str>) -> Result<bool> {
+    if !is_encrypted_key_str(content) {
+        return Ok(false);
+    }
+    match resolve_passphrase(passphrase) {
+        Some(passphrase) => {
+            try!(decrypt_key_str(content, &passphrase));
+            Ok(true)
+        }
+        None => {
+            Err(Error::CryptoError(format!("Secret key is encrypted, set {} to its passphrase",
+                                           SECRET_KEY_PASSPHRASE_ENV_VAR)))
+        }
+    }
+}
+
+/// Is the file an encrypted secret key with a passphrase to decrypt it, given by the caller or
+/// from the environment? Failing to read such a key means the passphrase is wrong, which unlike
+/// a missing secret key is an error even for callers content with the public key.
+fn has_passphrase_for(keyfile: &Path, passphrase: Option<&str>) -> bool {
+    resolve_passphrase(passphrase).is_some() && is_encrypted_key_file(keyfile).unwrap_or(false)
+}
+
+/// Returns the passphrase given by the caller, falling back to the one from the environment.
+fn resolve_passphrase(passphrase: Option<&str>) -> Option<String> {
+    match passphrase {
+        Some(passphrase) => Some(passphrase.to_string()),
+        None => secret_key_passphrase(),
+    }
+}
+
+fn encrypted_key_version(version: &str) -> Option<&'static str> {
+    match version {
+        SECRET_SIG_KEY_VERSION => Some(SECRET_SIG_KEY_ENCRYPTED_VERSION),
+        SECRET_BOX_KEY_VERSION => Some(SECRET_BOX_KEY_ENCRYPTED_VERSION),
+        SECRET_SYM_KEY_VERSION => Some(SECRET_SYM_KEY_ENCRYPTED_VERSION),
+        _ => None,
+    }
+}
+
+fn plain_key_version(version: &str) -> Option<&'static str> {
+    match version {
+        SECRET_SIG_KEY_ENCRYPTED_VERSION => Some(SECRET_SIG_KEY_VERSION),
+        SECRET_BOX_KEY_ENCRYPTED_VERSION => Some(SECRET_BOX_KEY_VERSION),
+        SECRET_SYM_KEY_ENCRYPTED_VERSION => Some(SECRET_SYM_KEY_VERSION),
+        _ => None,
+    }
+}
+
+/// Splits key contents into its version, name with revision, and key value. The contents are
+/// never included in error messages as they may be a secret key.
+fn split_key_str(content: &str) -> Result<(&str, &str, &str)> {
+    let mut lines = content.lines();
+    match (lines.next(), lines.next(), lines.nth(1)) {
+        (Some(version), Some(name_with_rev), Some(body)) => Ok((version, name_with_rev, body)),
+        _ => Err(Error::CryptoError("Malformed key contents".to_string())),
+    }
+}
+
+fn decode_key_body(name_with_rev: &str, body: &str) -> Result<Vec<u8>> {
+    match body.trim().as_bytes().from_base64() {
+        Ok(keybytes) => Ok(keybytes),
+        Err(e) => {
+            Err(Error::CryptoError(format!("Can't read raw key for {}: {}", name_with_rev, e)))
+        }
+    }
+}
+
+/// Derives a symmetric key from a passphrase. The cost parameters are fixed so that the same
+/// passphrase and salt always derive the same key.
+fn derive_passphrase_key(passphrase: &str, salt: &pwhash::Salt) -> Result<secretbox::Key> {
+    let mut key = secretbox::Key([0; secretbox::KEYBYTES]);
+    {
+        let secretbox::Key(ref mut kb) = key;
+        if pwhash::derive_key(kb,
+                              passphrase.as_bytes(),
+                              salt,
+                              pwhash::OPSLIMIT_INTERACTIVE,
+                              pwhash::MEMLIMIT_INTERACTIVE)
+            .is_err() {
+            return Err(Error::CryptoError("Can't derive a key from the passphrase".to_string()));
+        }
+    }
+    Ok(key)
+}
+
+/// Encrypts raw key bytes, returning the salt, the nonce and the ciphertext concatenated.
+fn encrypt_key_bytes(keybytes: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    let salt = pwhash::gen_salt();
+    let key = try!(derive_passphrase_key(passphrase, &salt));
+    let nonce = secretbox::gen_nonce();
+    let mut encrypted = Vec::new();
+    encrypted.extend_from_slice(&salt[..]);
+    encrypted.extend_from_slice(&nonce[..]);
+    encrypted.extend_from_slice(&secretbox::seal(keybytes, &nonce, &key));
+    Ok(encrypted)
+}
+
+fn decrypt_key_bytes(name_with_rev: &str, encrypted: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    if encrypted.len() < pwhash::SALTBYTES + secretbox::NONCEBYTES + secretbox::MACBYTES {
+        return Err(Error::CryptoError(format!("Encrypted secret key {} is truncated",
+                                              name_with_rev)));
+    }
+    let (salt, rest) = encrypted.split_at(pwhash::SALTBYTES);
+    let (nonce, ciphertext) = rest.split_at(secretbox::NONCEBYTES);
+    // Both slices have exactly the right length, so these can't fail
+    let salt = pwhash::Salt::from_slice(salt).unwrap();
+    let nonce = secretbox::Nonce::from_slice(nonce).unwrap();
+    let key = try!(derive_passphrase_key(passphrase, &salt));
+    match secretbox::open(ciphertext, &nonce, &key) {
+        Ok(keybytes) => Ok(keybytes),
+        Err(_) => {
+            Err(Error::CryptoError(format!("Can't decrypt secret key {}, is the passphrase \
+                                            correct?",
+                                           name_with_rev)))
+        }
+    }
+}
 
 /// Read a file into a Vec<u8>
-fn read_key_bytes(keyfile: &Path) -> Result<Vec<u8>> {
+///
+/// An encrypted secret key is decrypted with the given passphrase, or with the one from the
+/// environment when none is given.
+fn read_key_bytes(keyfile: &Path, passphrase: Option<&str>) -> Result<Vec<u8>> {
     let mut f = try!(File::open(keyfile));
     let mut s = String::new();
     if try!(f.read_to_string(&mut s)) <= 0 {
@@ -323,22 +533,38 @@ fn read_key_bytes(keyfile: &Path) -> Result<Vec<u8>> {
         }
     };
 
-    match s[start_index..].as_bytes().from_base64() {
-        Ok(keybytes) => Ok(keybytes),
+    let keybytes = match s[start_index..].as_bytes().from_base64() {
+        Ok(keybytes) => keybytes,
         Err(e) => {
             return Err(Error::CryptoError(format!("Can't read raw key from {}: {}",
                                                   keyfile.display(),
                                                   e)))
         }
+    };
+    if !is_encrypted_key_str(&s) {
+        return Ok(keybytes);
+    }
+    match resolve_passphrase(passphrase) {
+        Some(passphrase) => {
+            decrypt_key_bytes(&keyfile.display().to_string(), &keybytes, &passphrase)
+        }
+        None => {
+            Err(Error::CryptoError(format!("Secret key {} is encrypted, set {} to its passphrase",
+                                           keyfile.display(),
+                                           SECRET_KEY_PASSPHRASE_ENV_VAR)))
+        }
     }
 }
 
+/// Writes the public and/or secret key files. When `secret_encrypted` is `true` the secret
+/// content is an encrypted key value and the file is written with the encrypted key version.
 fn write_keypair_files(key_type: KeyType,
                        keyname: &str,
                        public_keyfile: Option<&Path>,
                        public_content: Option<&Vec<u8>>,
                        secret_keyfile: Option<&Path>,
-                       secret_content: Option<&Vec<u8>>)
+                       secret_content: Option<&Vec<u8>>,
+                       secret_encrypted: bool)
                        -> Result<()> {
     if let Some(public_keyfile) = public_keyfile {
         let public_version = match key_type {
@@ -370,10 +596,13 @@ fn write_keypair_files(key_type: KeyType,
     }
 
     if let Some(secret_keyfile) = secret_keyfile {
-        let secret_version = match key_type {
-            KeyType::Sig => SECRET_SIG_KEY_VERSION,
-            KeyType::Box => SECRET_BOX_KEY_VERSION,
-            KeyType::Sym => SECRET_SYM_KEY_VERSION,
+        let secret_version = match (key_type, secret_encrypted) {
+            (KeyType::Sig, false) => SECRET_SIG_KEY_VERSION,
+            (KeyType::Box, false) => SECRET_BOX_KEY_VERSION,
+            (KeyType::Sym, false) => SECRET_SYM_KEY_VERSION,
+            (KeyType::Sig, true) => SECRET_SIG_KEY_ENCRYPTED_VERSION,
+            (KeyType::Box, true) => SECRET_BOX_KEY_ENCRYPTED_VERSION,
+            (KeyType::Sym, true) => SECRET_SYM_KEY_ENCRYPTED_VERSION,
         };
 
         let secret_content = match secret_content {
@@ -470,7 +699,7 @@ mod test {
         let keyfile = cache.path().join(VALID_KEY);
         fs::copy(fixture(&format!("keys/{}", VALID_KEY)), &keyfile).unwrap();
 
-        let result = super::read_key_bytes(keyfile.as_path()).unwrap();
+        let result = super::read_key_bytes(keyfile.as_path(), None).unwrap();
         assert_eq!(result.as_slice().to_hex(), VALID_KEY_AS_HEX);
 
     }
@@ -482,7 +711,7 @@ mod test {
         let keyfile = cache.path().join("not-much-here");
         let _ = File::create(&keyfile).unwrap();
 
-        super::read_key_bytes(keyfile.as_path()).unwrap();
+        super::read_key_bytes(keyfile.as_path(), None).unwrap();
     }
 
     #[test]
@@ -493,7 +722,7 @@ mod test {
         let mut f = File::create(&keyfile).unwrap();
         f.write_all("SOMETHING\nELSE\n".as_bytes()).unwrap();
 
-        super::read_key_bytes(keyfile.as_path()).unwrap();
+        super::read_key_bytes(keyfile.as_path(), None).unwrap();
     }
 
     #[test]
@@ -504,7 +733,7 @@ mod test {
         let mut f = File::create(&keyfile).unwrap();
         f.write_all("something\n\nI am not base64 content".as_bytes()).unwrap();
 
-        super::read_key_bytes(keyfile.as_path()).unwrap();
+        super::read_key_bytes(keyfile.as_path(), None).unwrap();
     }
 
 
@@ -637,4 +866,39 @@ mod test {
         assert!(!super::is_valid_origin_name("foo bar"));
         assert!(!super::is_valid_origin_name("0xDEADBEEF"));
     }
+
+    #[test]
+    fn encrypt_and_decrypt_key_str() {
+        let content = fixture_as_string(&format!("keys/{}", VALID_KEY));
+        let encrypted = super::encrypt_key_str(&content, "correct horse").unwrap();
+        assert!(encrypted.starts_with("SYM-SEC-ENC-1\nring-key-valid-20160504220722\n\n"));
+        assert!(super::is_encrypted_key_str(&encrypted));
+        assert!(!super::is_encrypted_key_str(&content));
+
+        let decrypted = super::decrypt_key_str(&encrypted, "correct horse").unwrap();
+        assert_eq!(decrypted.trim(), content.trim());
+    }
+
+    #[test]
+    #[should_panic(expected = "is the passphrase correct?")]
+    fn decrypt_key_str_wrong_passphrase() {
+        let content = fixture_as_string(&format!("keys/{}", VALID_KEY));
+        let encrypted = super::encrypt_key_str(&content, "correct horse").unwrap();
+        super::decrypt_key_str(&encrypted, "battery staple").unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "Only secret keys can be encrypted")]
+    fn encrypt_key_str_public_key() {
+        let content = fixture_as_string("keys/origin-key-valid-20160509190508.pub");
+        super::encrypt_key_str(&content, "correct horse").unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "is already encrypted")]
+    fn encrypt_key_str_already_encrypted() {
+        let content = fixture_as_string(&format!("keys/{}", VALID_KEY));
+        let encrypted = super::encrypt_key_str(&content, "correct horse").unwrap();
+        super::encrypt_key_str(&encrypted, "correct horse").unwrap();
+    }
 }
@@ -0,0 +1,389 @@
+// Copyright (c) 2016 Chef Software Inc. and/or applicable contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use rustc_serialize::base64::{STANDARD, ToBase64, FromBase64};
+use rustc_serialize::hex::ToHex;
+use sodiumoxide::crypto::sign;
+use sodiumoxide::crypto::sign::ed25519::PublicKey as SigPublicKey;
+use sodiumoxide::randombytes::randombytes;
+use time;
+
+use error::{Error, Result};
+use super::{mk_key_filename, parse_name_with_rev, PairType, TmpKeyfile};
+use super::sig_key_pair::SigKeyPair;
+use super::super::{KEY_REVOCATION_SUFFIX, KEY_REVOCATION_VERSION};
+
+/// A signed statement that an origin key revision must no longer be trusted from a given point
+/// in time onwards.
+///
+/// A revocation is signed with the secret key of the revision it revokes, so anyone holding the
+/// public key can check it, and the holder of a leaked secret key can only ever revoke that key,
+/// never un-revoke it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyRevocation {
+    name_with_rev: String,
+    revoked_at: i64,
+    signature: Vec<u8>,
+}
+
+impl KeyRevocation {
+    /// Creates a revocation for the given sig key pair, taking effect at `revoked_at` seconds
+    /// since the Unix epoch.
+    ///
+    /// # Errors
+    ///
+    /// * If the secret key of the pair is not present
+    pub fn generate(pair: &SigKeyPair, revoked_at: i64) -> Result<Self> {
+        let name_with_rev = pair.name_with_rev();
+        let payload = Self::signed_payload(&name_with_rev, revoked_at);
+        let signature = sign::sign(payload.as_bytes(), try!(pair.secret()));
+        Ok(KeyRevocation {
+            name_with_rev: name_with_rev,
+            revoked_at: revoked_at,
+            signature: signature,
+        })
+    }
+
+    /// Creates a revocation for the given sig key pair which takes effect immediately.
+    pub fn generate_now(pair: &SigKeyPair) -> Result<Self> {
+        Self::generate(pair, time::now_utc().to_timespec().sec)
+    }
+
+    pub fn name_with_rev(&self) -> &str {
+        &self.name_with_rev
+    }
+
+    /// Returns the time, in seconds since the Unix epoch, from which the key is revoked.
+    pub fn revoked_at(&self) -> i64 {
+        self.revoked_at
+    }
+
+    /// Returns `true` if the revocation has taken effect at the current time.
+    pub fn in_effect(&self) -> bool {
+        self.in_effect_at(time::now_utc().to_timespec().sec)
+    }
+
+    pub fn in_effect_at(&self, at: i64) -> bool {
+        at >= self.revoked_at
+    }
+
+    /// Checks that the revocation was signed by the public key of the revision it revokes.
+    ///
+    /// # Errors
+    ///
+    /// * If the pair is for a different key revision
+    /// * If the public key of the pair is not present
+    /// * If the signature does not verify or does not match the revocation's contents
+    pub fn verify(&self, pair: &SigKeyPair) -> Result<()> {
+        if pair.name_with_rev() != self.name_with_rev {
+            return Err(Error::CryptoError(format!("Revocation for {} cannot be verified with {}",
+                                                  self.name_with_rev,
+                                                  pair.name_with_rev())));
+        }
+        let signed = match sign::verify(&self.signature, try!(pair.public())) {
+            Ok(signed) => signed,
+            Err(_) => {
+                return Err(Error::CryptoError(format!("Revocation for {} failed verification",
+                                                      self.name_with_rev)))
+            }
+        };
+        if signed != Self::signed_payload(&self.name_with_rev, self.revoked_at).into_bytes() {
+            return Err(Error::CryptoError(format!("Revocation for {} does not match its signed \
+                                                   contents",
+                                                  self.name_with_rev)));
+        }
+        Ok(())
+    }
+
+    /// Checks the revocation against the contents of a public sig key, as written in a `.pub` key
+    /// file.
+    ///
+    /// # Errors
+    ///
+    /// * If the key content cannot be parsed or is not a public key
+    /// * If the revocation does not verify against the key
+    pub fn verify_with_key_str(&self, content: &str) -> Result<()> {
+        let (pair_type, name_with_rev, key_body) = try!(SigKeyPair::parse_key_str(content));
+        if pair_type != PairType::Public {
+            return Err(Error::CryptoError(format!("Revocation for {} must be verified with a \
+                                                   public key",
+                                                  self.name_with_rev)));
+        }
+        let public_key = match key_body.trim().from_base64() {
+            Ok(bytes) => {
+                match SigPublicKey::from_slice(&bytes) {
+                    Some(pk) => pk,
+                    None => {
+                        return Err(Error::CryptoError(format!("Can't read sig public key for {}",
+                                                              name_with_rev)))
+                    }
+                }
+            }
+            Err(e) => {
+                return Err(Error::CryptoError(format!("Can't decode sig public key for {}: {}",
+                                                      name_with_rev,
+                                                      e)))
+            }
+        };
+        let (name, rev) = try!(parse_name_with_rev(&name_with_rev));
+        self.verify(&SigKeyPair::new(name, rev, Some(public_key), None))
+    }
+
+    /// Returns the verified revocation for a key revision from the key cache, if one exists.
+    ///
+    /// # Errors
+    ///
+    /// * If a revocation file exists but cannot be read or parsed
+    /// * If a revocation file exists but the public key to verify it is not in the cache
+    /// * If a revocation file exists but does not verify
+    pub fn get_for<P: AsRef<Path> + ?Sized>(name_with_rev: &str,
+                                            cache_key_path: &P)
+                                            -> Result<Option<Self>> {
+        let path = Self::get_path(name_with_rev, cache_key_path);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let mut content = String::new();
+        try!(try!(File::open(&path)).read_to_string(&mut content));
+        let revocation = try!(Self::parse_str(&content));
+        if revocation.name_with_rev != name_with_rev {
+            return Err(Error::CryptoError(format!("Revocation file {} is for key {}",
+                                                  path.display(),
+                                                  revocation.name_with_rev)));
+        }
+        let pair = try!(SigKeyPair::get_pair_for(name_with_rev, cache_key_path));
+        try!(revocation.verify(&pair));
+        Ok(Some(revocation))
+    }
+
+    /// Returns the path of the revocation file for a key revision in the key cache, whether or not
+    /// it exists.
+    pub fn get_path<P: AsRef<Path> + ?Sized>(name_with_rev: &str, cache_key_path: &P) -> PathBuf {
+        mk_key_filename(cache_key_path.as_ref(), name_with_rev, KEY_REVOCATION_SUFFIX)
+    }
+
+    /// Parses and verifies a revocation from a string slice, and writes it to the key cache. The
+    /// public key of the revoked revision must already be in the key cache.
+    ///
+    /// If the key cache already holds a revocation for the same revision which took effect no
+    /// later than the new one, the existing revocation is kept and returned instead, so a later
+    /// revocation can never widen the window in which a key is trusted.
+    ///
+    /// # Errors
+    ///
+    /// * If the revocation cannot be parsed
+    /// * If the public key of the revoked revision is not in the key cache
+    /// * If the revocation does not verify
+    /// * If the revocation file cannot be written to disk
+    pub fn write_file_from_str<P: AsRef<Path> + ?Sized>(content: &str,
+                                                        cache_key_path: &P)
+                                                        -> Result<Self> {
+        let revocation = try!(Self::parse_str(content));
+        let pair = try!(SigKeyPair::get_pair_for(&revocation.name_with_rev, cache_key_path));
+        try!(revocation.verify(&pair));
+        if let Some(existing) = try!(Self::get_for(&revocation.name_with_rev, cache_key_path)) {
+            if existing.revoked_at <= revocation.revoked_at {
+                debug!("Keeping existing revocation for {}, revoked at {}",
+                       existing.name_with_rev,
+                       existing.revoked_at);
+                return Ok(existing);
+            }
+        }
+        try!(revocation.write_file(cache_key_path));
+        Ok(revocation)
+    }
+
+    /// Writes the revocation to the key cache, replacing any existing revocation of the same key
+    /// revision.
+    pub fn write_file<P: AsRef<Path> + ?Sized>(&self, cache_key_path: &P) -> Result<PathBuf> {
+        let path = Self::get_path(&self.name_with_rev, cache_key_path);
+        let tmpfile = {
+            let mut t = path.clone();
+            t.set_file_name(format!("{}.{}",
+                                    &path.file_name().unwrap().to_str().unwrap(),
+                                    &randombytes(6).as_slice().to_hex()));
+            TmpKeyfile { path: t }
+        };
+        debug!("Writing temp revocation file {}", tmpfile.path.display());
+        {
+            let mut f = try!(File::create(&tmpfile.path));
+            try!(f.write_all(self.to_string().as_bytes()));
+        }
+        debug!("Moving {} to {}", tmpfile.path.display(), path.display());
+        try!(fs::rename(&tmpfile.path, &path));
+        Ok(path)
+    }
+
+    /// Parses a revocation from a string slice without verifying its signature.
+    ///
+    /// # Errors
+    ///
+    /// * If the revocation version is missing or unsupported
+    /// * If the key name with revision is missing or invalid
+    /// * If the revocation time is missing or not a number
+    /// * If the signature is missing or is not valid Base64
+    pub fn parse_str(content: &str) -> Result<Self> {
+        let mut lines = content.lines();
+        match lines.next() {
+            Some(val) if val == KEY_REVOCATION_VERSION => (),
+            Some(val) => {
+                return Err(Error::CryptoError(format!("Unsupported revocation version: {}", val)))
+            }
+            None => {
+                return Err(Error::CryptoError("Malformed revocation: missing version".to_string()))
+            }
+        }
+        let name_with_rev = match lines.next() {
+            Some(val) => {
+                try!(parse_name_with_rev(val));
+                val.to_string()
+            }
+            None => {
+                return Err(Error::CryptoError("Malformed revocation: missing key name"
+                    .to_string()))
+            }
+        };
+        let revoked_at = match lines.next().map(|val| val.parse::<i64>()) {
+            Some(Ok(val)) => val,
+            _ => {
+                return Err(Error::CryptoError("Malformed revocation: missing or invalid \
+                                               revocation time"
+                    .to_string()))
+            }
+        };
+        let signature = match lines.nth(1).map(|val| val.trim().from_base64()) {
+            Some(Ok(val)) => val,
+            _ => {
+                return Err(Error::CryptoError("Malformed revocation: missing or invalid \
+                                               signature"
+                    .to_string()))
+            }
+        };
+        Ok(KeyRevocation {
+            name_with_rev: name_with_rev,
+            revoked_at: revoked_at,
+            signature: signature,
+        })
+    }
+
+    fn signed_payload(name_with_rev: &str, revoked_at: i64) -> String {
+        format!("{}\n{}", name_with_rev, revoked_at)
+    }
+}
+
+impl fmt::Display for KeyRevocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "{}\n{}\n{}\n\n{}",
+               KEY_REVOCATION_VERSION,
+               self.name_with_rev,
+               self.revoked_at,
+               self.signature.to_base64(STANDARD))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::File;
+    use std::io::Read;
+
+    use tempdir::TempDir;
+
+    use super::KeyRevocation;
+    use super::super::sig_key_pair::SigKeyPair;
+
+    #[test]
+    fn generate_and_verify() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let pair = SigKeyPair::generate_pair_for_origin("unicorn", cache.path()).unwrap();
+        let revocation = KeyRevocation::generate(&pair, 1000).unwrap();
+        revocation.verify(&pair).unwrap();
+        assert!(revocation.in_effect_at(1000));
+        assert!(!revocation.in_effect_at(999));
+    }
+
+    #[test]
+    fn round_trip_through_key_cache() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let pair = SigKeyPair::generate_pair_for_origin("unicorn", cache.path()).unwrap();
+        assert_eq!(KeyRevocation::get_for(&pair.name_with_rev(), cache.path()).unwrap(),
+                   None);
+        let revocation = KeyRevocation::generate_now(&pair).unwrap();
+        let written =
+            KeyRevocation::write_file_from_str(&revocation.to_string(), cache.path()).unwrap();
+        assert_eq!(written, revocation);
+        let loaded = KeyRevocation::get_for(&pair.name_with_rev(), cache.path()).unwrap();
+        assert_eq!(loaded, Some(revocation));
+    }
+
+    #[test]
+    fn verify_with_key_str() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let pair = SigKeyPair::generate_pair_for_origin("unicorn", cache.path()).unwrap();
+        let revocation = KeyRevocation::generate(&pair, 1000).unwrap();
+        let mut key_content = String::new();
+        File::open(SigKeyPair::get_public_key_path(&pair.name_with_rev(), cache.path()).unwrap())
+            .unwrap()
+            .read_to_string(&mut key_content)
+            .unwrap();
+        revocation.verify_with_key_str(&key_content).unwrap();
+    }
+
+    #[test]
+    fn keep_earliest_revocation() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let pair = SigKeyPair::generate_pair_for_origin("unicorn", cache.path()).unwrap();
+        let early = KeyRevocation::generate(&pair, 1000).unwrap();
+        let late = KeyRevocation::generate(&pair, 2000).unwrap();
+        KeyRevocation::write_file_from_str(&early.to_string(), cache.path()).unwrap();
+        let kept = KeyRevocation::write_file_from_str(&late.to_string(), cache.path()).unwrap();
+        assert_eq!(kept, early);
+        let loaded = KeyRevocation::get_for(&pair.name_with_rev(), cache.path()).unwrap();
+        assert_eq!(loaded, Some(early));
+    }
+
+    #[test]
+    #[should_panic(expected = "failed verification")]
+    fn reject_revocation_signed_by_another_key() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let pair = SigKeyPair::generate_pair_for_origin("unicorn", cache.path()).unwrap();
+        let other_cache = TempDir::new("other_key_cache").unwrap();
+        let other = SigKeyPair::generate_pair_for_origin("unicorn", other_cache.path()).unwrap();
+        let forged = KeyRevocation::generate(&other, 1000).unwrap().to_string();
+        // Point the forged revocation at the key revision in the first cache
+        let forged = forged.replace(&other.name_with_rev(), &pair.name_with_rev());
+        KeyRevocation::write_file_from_str(&forged, cache.path()).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "does not match its signed contents")]
+    fn reject_tampered_revocation_time() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let pair = SigKeyPair::generate_pair_for_origin("unicorn", cache.path()).unwrap();
+        let revocation = KeyRevocation::generate(&pair, 1000).unwrap().to_string();
+        let tampered = revocation.replace("\n1000\n", "\n9999999999\n");
+        KeyRevocation::write_file_from_str(&tampered, cache.path()).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "Unsupported revocation version")]
+    fn parse_invalid_version() {
+        KeyRevocation::parse_str("SIG-PUB-1\nunicorn-20160517220007\n1000\n\nabc=").unwrap();
+    }
+}
@@ -23,10 +23,11 @@ use sodiumoxide::crypto::sign::ed25519::PublicKey as SigPublicKey;
 use sodiumoxide::randombytes::randombytes;
 
 use error::{Error, Result};
-use super::{get_key_revisions, mk_key_filename, mk_revision_string, parse_name_with_rev,
-            read_key_bytes, write_keypair_files, KeyPair, KeyType, PairType, TmpKeyfile};
-use super::super::{PUBLIC_KEY_SUFFIX, PUBLIC_SIG_KEY_VERSION, SECRET_SIG_KEY_SUFFIX,
-                   SECRET_SIG_KEY_VERSION, hash};
+use super::{check_encrypted_key_str, encrypt_key_bytes, get_key_revisions, has_passphrase_for,
+            mk_key_filename, mk_revision_string, parse_name_with_rev, read_key_bytes,
+            write_keypair_files, KeyPair, KeyType, PairType, TmpKeyfile};
+use super::super::{PUBLIC_KEY_SUFFIX, PUBLIC_SIG_KEY_VERSION, SECRET_SIG_KEY_ENCRYPTED_VERSION,
+                   SECRET_SIG_KEY_SUFFIX, SECRET_SIG_KEY_VERSION, hash};
 
 pub type SigKeyPair = KeyPair<SigPublicKey, SigSecretKey>;
 
@@ -34,11 +35,24 @@ impl SigKeyPair {
     pub fn generate_pair_for_origin<P: AsRef<Path> + ?Sized>(name: &str,
                                                              cache_key_path: &P)
                                                              -> Result<Self> {
+        Self::generate_pair(name, None, cache_key_path.as_ref())
+    }
+
+    /// Generates an origin key pair whose secret key is written encrypted with a passphrase.
+    pub fn generate_encrypted_pair_for_origin<P: AsRef<Path> + ?Sized>(name: &str,
+                                                                       passphrase: &str,
+                                                                       cache_key_path: &P)
+                                                                       -> Result<Self> {
+        Self::generate_pair(name, Some(passphrase), cache_key_path.as_ref())
+    }
+
+    fn generate_pair(name: &str, passphrase: Option<&str>, cache_key_path: &Path) -> Result<Self> {
         let revision = try!(mk_revision_string());
         let keyname = Self::mk_key_name(name, &revision);
         debug!("new sig key name = {}", &keyname);
         let (public_key, secret_key) = try!(Self::generate_pair_files(&keyname,
-                                                                      cache_key_path.as_ref()));
+                                                                      passphrase,
+                                                                      cache_key_path));
         Ok(Self::new(name.to_string(),
                      revision,
                      Some(public_key),
@@ -50,9 +64,14 @@ impl SigKeyPair {
     }
 
     fn generate_pair_files(name_with_rev: &str,
+                           passphrase: Option<&str>,
                            cache_key_path: &Path)
                            -> Result<(SigPublicKey, SigSecretKey)> {
         let (pk, sk) = sign::gen_keypair();
+        let secret_content = match passphrase {
+            Some(passphrase) => try!(encrypt_key_bytes(&sk[..], passphrase)),
+            None => sk[..].to_vec(),
+        };
 
         let public_keyfile = mk_key_filename(cache_key_path, name_with_rev, PUBLIC_KEY_SUFFIX);
         let secret_keyfile = mk_key_filename(cache_key_path, name_with_rev, SECRET_SIG_KEY_SUFFIX);
@@ -64,7 +83,8 @@ impl SigKeyPair {
                                  Some(&public_keyfile),
                                  Some(&pk[..].to_base64(STANDARD).into_bytes()),
                                  Some(&secret_keyfile),
-                                 Some(&sk[..].to_base64(STANDARD).into_bytes())));
+                                 Some(&secret_content.to_base64(STANDARD).into_bytes()),
+                                 passphrase.is_some()));
         Ok((pk, sk))
     }
 
@@ -90,6 +110,15 @@ impl SigKeyPair {
     pub fn get_pair_for<P: AsRef<Path> + ?Sized>(name_with_rev: &str,
                                                  cache_key_path: &P)
                                                  -> Result<Self> {
+        Self::get_pair_for_with_passphrase(name_with_rev, None, cache_key_path)
+    }
+
+    /// Returns the key pair for a name with revision, decrypting an encrypted secret key with
+    /// the given passphrase.
+    pub fn get_pair_for_with_passphrase<P: AsRef<Path> + ?Sized>(name_with_rev: &str,
+                                                                 passphrase: Option<&str>,
+                                                                 cache_key_path: &P)
+                                                                 -> Result<Self> {
         let (name, rev) = try!(parse_name_with_rev(&name_with_rev));
         let pk = match Self::get_public_key(name_with_rev, cache_key_path.as_ref()) {
             Ok(k) => Some(k),
@@ -101,9 +130,14 @@ impl SigKeyPair {
                 None
             }
         };
-        let sk = match Self::get_secret_key(name_with_rev, cache_key_path.as_ref()) {
+        let sk = match Self::get_secret_key(name_with_rev, passphrase, cache_key_path.as_ref()) {
             Ok(k) => Some(k),
             Err(e) => {
+                if let Ok(path) = Self::get_secret_key_path(name_with_rev, cache_key_path) {
+                    if has_passphrase_for(&path, passphrase) {
+                        return Err(e);
+                    }
+                }
                 // Not an error, just continue
                 debug!("Can't find secret key for name_with_rev {}: {}",
                        name_with_rev,
@@ -232,7 +266,18 @@ impl SigKeyPair {
     pub fn write_file_from_str<P: AsRef<Path> + ?Sized>(content: &str,
                                                         cache_key_path: &P)
                                                         -> Result<(Self, PairType)> {
+        Self::write_file_from_str_with_passphrase(content, None, cache_key_path)
+    }
+
+    /// Writes a sig key to the key cache like `write_file_from_str`, checking that an encrypted
+    /// secret key decrypts with the given passphrase.
+    pub fn write_file_from_str_with_passphrase<P: AsRef<Path> + ?Sized>(content: &str,
+                                                                        passphrase: Option<&str>,
+                                                                        cache_key_path: &P)
+                                                                        -> Result<(Self,
+                                                                                   PairType)> {
         let (pair_type, name_with_rev, key_body) = try!(Self::parse_key_str(content));
+        let encrypted = try!(check_encrypted_key_str(content, passphrase));
         let name_with_rev = &name_with_rev;
         let key_body = &key_body;
 
@@ -257,7 +302,8 @@ impl SigKeyPair {
                                          Some(&tmpfile.path),
                                          Some(&key_body.as_bytes().to_vec()),
                                          None,
-                                         None));
+                                         None,
+                                         false));
             }
             PairType::Secret => {
                 try!(write_keypair_files(KeyType::Sig,
@@ -265,7 +311,8 @@ impl SigKeyPair {
                                          None,
                                          None,
                                          Some(&tmpfile.path),
-                                         Some(&key_body.as_bytes().to_vec())));
+                                         Some(&key_body.as_bytes().to_vec()),
+                                         encrypted));
             }
         }
 
@@ -359,6 +406,7 @@ impl SigKeyPair {
                 match val {
                     PUBLIC_SIG_KEY_VERSION => PairType::Public,
                     SECRET_SIG_KEY_VERSION => PairType::Secret,
+                    SECRET_SIG_KEY_ENCRYPTED_VERSION => PairType::Secret,
                     _ => {
                         return Err(Error::CryptoError(format!("Unsupported key version: {}", val)))
                     }
@@ -391,7 +439,7 @@ impl SigKeyPair {
 
     fn get_public_key(key_with_rev: &str, cache_key_path: &Path) -> Result<SigPublicKey> {
         let public_keyfile = mk_key_filename(cache_key_path, key_with_rev, PUBLIC_KEY_SUFFIX);
-        let bytes = try!(read_key_bytes(&public_keyfile));
+        let bytes = try!(read_key_bytes(&public_keyfile, None));
         match SigPublicKey::from_slice(&bytes) {
             Some(sk) => Ok(sk),
             None => {
@@ -401,9 +449,12 @@ impl SigKeyPair {
         }
     }
 
-    fn get_secret_key(key_with_rev: &str, cache_key_path: &Path) -> Result<SigSecretKey> {
+    fn get_secret_key(key_with_rev: &str,
+                      passphrase: Option<&str>,
+                      cache_key_path: &Path)
+                      -> Result<SigSecretKey> {
         let secret_keyfile = mk_key_filename(cache_key_path, key_with_rev, SECRET_SIG_KEY_SUFFIX);
-        let bytes = try!(read_key_bytes(&secret_keyfile));
+        let bytes = try!(read_key_bytes(&secret_keyfile, passphrase));
         match SigSecretKey::from_slice(&bytes) {
             Some(sk) => Ok(sk),
             None => {
@@ -743,4 +794,64 @@ mod test {
                                         cache.path())
             .unwrap();
     }
+
+    #[test]
+    fn generate_encrypted_pair_and_load_with_passphrase() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let pair = SigKeyPair::generate_encrypted_pair_for_origin("unicorn",
+                                                                  "correct horse",
+                                                                  cache.path())
+            .unwrap();
+        let secret_keyfile = SigKeyPair::get_secret_key_path(&pair.name_with_rev(), cache.path())
+            .unwrap();
+        let mut content = String::new();
+        File::open(&secret_keyfile).unwrap().read_to_string(&mut content).unwrap();
+        assert!(content.starts_with("SIG-SEC-ENC-1\n"));
+
+        let loaded = SigKeyPair::get_pair_for_with_passphrase(&pair.name_with_rev(),
+                                                              Some("correct horse"),
+                                                              cache.path())
+            .unwrap();
+        assert!(loaded.secret().unwrap() == pair.secret().unwrap());
+    }
+
+    #[test]
+    fn write_encrypted_secret_from_str_with_passphrase() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let pair = SigKeyPair::generate_encrypted_pair_for_origin("unicorn",
+                                                                  "correct horse",
+                                                                  cache.path())
+            .unwrap();
+        let secret_keyfile = SigKeyPair::get_secret_key_path(&pair.name_with_rev(), cache.path())
+            .unwrap();
+        let mut content = String::new();
+        File::open(&secret_keyfile).unwrap().read_to_string(&mut content).unwrap();
+
+        let new_cache = TempDir::new("key_cache").unwrap();
+        SigKeyPair::write_file_from_str_with_passphrase(&content,
+                                                        Some("correct horse"),
+                                                        new_cache.path())
+            .unwrap();
+        assert!(new_cache.path().join(secret_keyfile.file_name().unwrap()).is_file());
+    }
+
+    #[test]
+    #[should_panic(expected = "is the passphrase correct?")]
+    fn write_encrypted_secret_from_str_wrong_passphrase() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let pair = SigKeyPair::generate_encrypted_pair_for_origin("unicorn",
+                                                                  "correct horse",
+                                                                  cache.path())
+            .unwrap();
+        let secret_keyfile = SigKeyPair::get_secret_key_path(&pair.name_with_rev(), cache.path())
+            .unwrap();
+        let mut content = String::new();
+        File::open(&secret_keyfile).unwrap().read_to_string(&mut content).unwrap();
+
+        let new_cache = TempDir::new("key_cache").unwrap();
+        SigKeyPair::write_file_from_str_with_passphrase(&content,
+                                                        Some("battery staple"),
+                                                        new_cache.path())
+            .unwrap();
+    }
 }
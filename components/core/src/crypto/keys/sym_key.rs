@@ -22,9 +22,11 @@ use sodiumoxide::crypto::secretbox::Key as SymSecretKey;
 use sodiumoxide::randombytes::randombytes;
 
 use error::{Error, Result};
-use super::{get_key_revisions, mk_key_filename, mk_revision_string, parse_name_with_rev,
-            read_key_bytes, write_keypair_files, KeyPair, KeyType, PairType, TmpKeyfile};
-use super::super::{SECRET_SYM_KEY_SUFFIX, SECRET_SYM_KEY_VERSION, hash};
+use super::{check_encrypted_key_str, get_key_revisions, has_passphrase_for, mk_key_filename,
+            mk_revision_string, parse_name_with_rev, read_key_bytes, write_keypair_files, KeyPair,
+            KeyType, PairType, TmpKeyfile};
+use super::super::{SECRET_SYM_KEY_ENCRYPTED_VERSION, SECRET_SYM_KEY_SUFFIX,
+                   SECRET_SYM_KEY_VERSION, hash};
 
 pub type SymKey = KeyPair<(), SymSecretKey>;
 
@@ -61,6 +63,15 @@ impl SymKey {
     pub fn get_pair_for<P: AsRef<Path> + ?Sized>(name_with_rev: &str,
                                                  cache_key_path: &P)
                                                  -> Result<Self> {
+        Self::get_pair_for_with_passphrase(name_with_rev, None, cache_key_path)
+    }
+
+    /// Returns the key for a name with revision, decrypting an encrypted secret key with the
+    /// given passphrase.
+    pub fn get_pair_for_with_passphrase<P: AsRef<Path> + ?Sized>(name_with_rev: &str,
+                                                                 passphrase: Option<&str>,
+                                                                 cache_key_path: &P)
+                                                                 -> Result<Self> {
         let (name, rev) = try!(parse_name_with_rev(&name_with_rev));
         let pk = match Self::get_public_key(name_with_rev, cache_key_path.as_ref()) {
             Ok(k) => Some(k),
@@ -72,9 +83,14 @@ impl SymKey {
                 None
             }
         };
-        let sk = match Self::get_secret_key(name_with_rev, cache_key_path.as_ref()) {
+        let sk = match Self::get_secret_key(name_with_rev, passphrase, cache_key_path.as_ref()) {
             Ok(k) => Some(k),
             Err(e) => {
+                if let Ok(path) = Self::get_secret_key_path(name_with_rev, cache_key_path) {
+                    if has_passphrase_for(&path, passphrase) {
+                        return Err(e);
+                    }
+                }
                 // Not an error, just continue
                 debug!("Can't find secret key for name_with_rev {}: {}",
                        name_with_rev,
@@ -228,9 +244,12 @@ impl SymKey {
         Err(Error::CryptoError("SymKey never contains a public key".to_string()))
     }
 
-    fn get_secret_key(key_with_rev: &str, cache_key_path: &Path) -> Result<SymSecretKey> {
+    fn get_secret_key(key_with_rev: &str,
+                      passphrase: Option<&str>,
+                      cache_key_path: &Path)
+                      -> Result<SymSecretKey> {
         let secret_keyfile = mk_key_filename(cache_key_path, key_with_rev, SECRET_SYM_KEY_SUFFIX);
-        let bytes = try!(read_key_bytes(&secret_keyfile));
+        let bytes = try!(read_key_bytes(&secret_keyfile, passphrase));
         match SymSecretKey::from_slice(&bytes) {
             Some(sk) => Ok(sk),
             None => {
@@ -282,10 +301,20 @@ impl SymKey {
     pub fn write_file_from_str<P: AsRef<Path> + ?Sized>(content: &str,
                                                         cache_key_path: &P)
                                                         -> Result<(Self, PairType)> {
+        Self::write_file_from_str_with_passphrase(content, None, cache_key_path)
+    }
+
+    /// Writes a sym key to the key cache like `write_file_from_str`, checking that an encrypted
+    /// secret key decrypts with the given passphrase.
+    pub fn write_file_from_str_with_passphrase<P: AsRef<Path> + ?Sized>(content: &str,
+                                                                        passphrase: Option<&str>,
+                                                                        cache_key_path: &P)
+                                                                        -> Result<(Self,
+                                                                                   PairType)> {
         let mut lines = content.lines();
         let _ = match lines.next() {
             Some(val) => {
-                if val != SECRET_SYM_KEY_VERSION {
+                if val != SECRET_SYM_KEY_VERSION && val != SECRET_SYM_KEY_ENCRYPTED_VERSION {
                     return Err(Error::CryptoError(format!("Unsupported key version: {}", val)));
                 }
                 ()
@@ -312,6 +341,7 @@ impl SymKey {
                 return Err(Error::CryptoError(msg));
             }
         };
+        let encrypted = try!(check_encrypted_key_str(content, passphrase));
         let secret_keyfile = mk_key_filename(cache_key_path.as_ref(),
                                              &name_with_rev,
                                              SECRET_SYM_KEY_SUFFIX);
@@ -329,7 +359,8 @@ impl SymKey {
                                  None,
                                  None,
                                  Some(&tmpfile.path),
-                                 Some(&sk.as_bytes().to_vec())));
+                                 Some(&sk.as_bytes().to_vec()),
+                                 encrypted));
 
         if Path::new(&secret_keyfile).is_file() {
             let existing_hash = try!(hash::hash_file(&secret_keyfile));
@@ -380,7 +411,8 @@ impl SymKey {
                                  None,
                                  None,
                                  Some(&secret_keyfile),
-                                 Some(&sk[..].to_base64(STANDARD).into_bytes())));
+                                 Some(&sk[..].to_base64(STANDARD).into_bytes()),
+                                 false));
         Ok((pk, sk))
     }
 }
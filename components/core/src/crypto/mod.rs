@@ -127,19 +127,53 @@
 //!
 //! 1. The artifact format version
 //! 1. The name with revision of the origin key which was used to sign the artifact
-//! 1. The hashing algorithm used, which at present is only `BLAKE2b`, but may expand in the future
+//! 1. The hashing algorithm used, either `BLAKE2b` (the default) or `SHA256`
 //! 1. A Base64 *signed* value of the binary blob's Base64 file hash
 //! 1. The last line is left empty, meaning that 2 newline characters (`\n`) separate the header
 //!    from the payload
 //!
-//! The remainder of the file is a compressed tarball of the contents to be extracted on disk. At
-//! present, the tarball is compressed using `xz` but is considered an implementation detail. Also
-//! note unlike the format of keys, the compressed tarball is **not** Base64 encoded--it is the
-//! compressed tarball itself.
+//! The remainder of the file is a compressed tarball of the contents to be extracted on disk. A
+//! `HART-1` tarball is always compressed using `xz`. Also note unlike the format of keys, the
+//! compressed tarball is **not** Base64 encoded--it is the compressed tarball itself.
+//!
+//! A `HART-2` artifact records the compression of its tarball in an extra line directly after the
+//! hashing algorithm. The supported values are `xz` and `zstd`:
+//!
+//! ```text
+//! HART-2
+//! habitat-20160405144945
+//! BLAKE2b
+//! zstd
+//! signed BLAKE2b signature
+//!
+//! <binary-blob>
+//! ```
+//!
+//! Artifacts with an `xz` compressed tarball hashed with `BLAKE2b` are always written as `HART-1`
+//! so that they remain readable by older releases of Habitat, which assume both for a `HART-1`
+//! artifact. Any other artifact with a single signature is written as `HART-2`.
+//!
+//! A `HART-3` artifact carries more than one signature, for example a builder's signature and a
+//! release manager's countersignature. The hashing algorithm and compression follow the format
+//! version, then each signature is written on its own line as the name with revision of the
+//! signing key and the Base64 signature, separated by a space. The first signature is always the
+//! one made by the original signer. All signatures are made over the same payload hash, so adding
+//! a countersignature never invalidates an existing signature:
+//!
+//! ```text
+//! HART-3
+//! BLAKE2b
+//! xz
+//! habitat-20160405144945 signed BLAKE2b signature
+//! release-20160601100000 signed BLAKE2b signature
+//!
+//! <binary-blob>
+//! ```
 //!
 //! Note that the BLAKE2b hash functions use a digest length of 32 bytes (256 bits!). More details
 //! about the hashing strategy can be found in the [libsodium hashing
-//! documentation](https://download.libsodium.org/doc/hashing/generic_hashing.html).
+//! documentation](https://download.libsodium.org/doc/hashing/generic_hashing.html). Artifacts
+//! signed with `SHA256` are hashed with libsodium's SHA-256, which has the same digest length.
 //!
 //! Signing uses a secret origin key, while verifying uses the public origin key. Thus, it it safe
 //! to distribute public origin keys.
@@ -176,7 +210,7 @@
 //! ```
 //!
 //! The above command starts streaming the file to standard out at line 6, skipping the first 5
-//! plaintext lines.
+//! plaintext lines. For a `HART-2` artifact the payload starts at line 7.
 //!
 //! If the Habitat artifact needs to be extracted on disk without verifying its integrity or
 //! authenticity, this can be accomplised with:
@@ -189,6 +223,65 @@
 //! is **not** a supported workflow for working with Habitat artifacts--they are signed for very
 //! important reasons.
 //!
+//! ## Encrypted secret keys
+//!
+//! A secret sig, box, or sym key can be protected at rest with a passphrase. An encrypted secret
+//! key has the same layout as an unencrypted one, but uses its own key version (`SIG-SEC-ENC-1`,
+//! `BOX-SEC-ENC-1`, or `SYM-SEC-ENC-1`) and its Base64 value is the concatenation of:
+//!
+//! 1. A random salt for deriving a key from the passphrase with `scrypt`
+//! 1. A random nonce
+//! 1. The secret key, encrypted with the derived key using `secretbox`
+//!
+//! ```text
+//! SIG-SEC-ENC-1
+//! habitat-20160405144945
+//!
+//! <salt_nonce_ciphertext_base64>
+//! ```
+//!
+//! Encrypted secret keys are decrypted when they are loaded using the passphrase given to the
+//! `*_with_passphrase` functions, or else the one in the `HAB_KEY_PASSPHRASE` environment
+//! variable. Unencrypted secret keys are loaded as before.
+//!
+//! ## Origin key revocations
+//!
+//! An origin key revision that must no longer be trusted, for example because its secret key has
+//! leaked, is revoked with a revocation record. The record is signed with the secret key of the
+//! revision it revokes and is stored in the key cache next to the public key, for example
+//! `habitat-20160405144945.revoked`. The lines are as follows:
+//!
+//! 1. The revocation format version
+//! 1. The name with revision of the revoked origin key
+//! 1. The time from which the key is revoked, in seconds since the Unix epoch
+//! 1. A Base64 *signed* value of the key name with revision and the revocation time, separated by
+//!    a newline character (`\n`)
+//!
+//! ```text
+//! REV-1
+//! habitat-20160405144945
+//! 1465000000
+//!
+//! <signature_base64>
+//! ```
+//!
+//! Once the revocation time has passed, every signature made with the revoked key fails
+//! verification. Except in `HART-1` artifacts, every signature is made over the payload hash
+//! followed by the signing time, in seconds since the Unix epoch, separated by a newline character
+//! (`\n`). A `HART-1` signature is made over the bare hash so that older releases of Habitat can
+//! verify it, and records no signing time.
+//!
+//! The signing time is chosen by the signer, so it is never used to decide whether a signature
+//! made with a revoked key is trusted: someone holding a leaked secret key could backdate it. This
+//! leaves some limits:
+//!
+//! * Artifacts signed before the revocation are rejected too, and must be signed again with
+//!   another key revision. Countersigning isn't enough, as every signature must verify.
+//! * A revocation only protects a host once it is in that host's key cache, for example after
+//!   `hab origin key download`, and takes effect by that host's clock.
+//! * A revocation with a time in the future takes no effect until then, so a leaked key is trusted
+//!   in the meantime.
+//!
 //! ## Encrypted payloads
 //!
 //! The first 4 lines of an encrypted payload are as follows:
@@ -243,7 +336,10 @@ pub static SECRET_BOX_KEY_SUFFIX: &'static str = "box.key";
 /// The suffix on the end of a secret symmetric key file
 pub static SECRET_SYM_KEY_SUFFIX: &'static str = "sym.key";
 
-/// The hashing function we're using during sign/verify
+/// The suffix on the end of an origin key revocation file
+pub static KEY_REVOCATION_SUFFIX: &'static str = "revoked";
+
+/// The hashing function we use by default during sign/verify, see `hash::HashType`
 /// See also: https://download.libsodium.org/doc/hashing/generic_hashing.html
 pub static SIG_HASH_TYPE: &'static str = "BLAKE2b";
 
@@ -251,11 +347,16 @@ pub static SIG_HASH_TYPE: &'static str = "BLAKE2b";
 /// at runtime. This is useful for testing.
 pub static CACHE_KEY_PATH_ENV_VAR: &'static str = "HAB_CACHE_KEY_PATH";
 
+/// This environment variable holds the passphrase used to decrypt encrypted secret keys
+pub static SECRET_KEY_PASSPHRASE_ENV_VAR: &'static str = "HAB_KEY_PASSPHRASE";
+
 /// Create secret key files with these permissions
 static PUBLIC_KEY_PERMISSIONS: &'static str = "0400";
 static SECRET_KEY_PERMISSIONS: &'static str = "0400";
 
 pub static HART_FORMAT_VERSION: &'static str = "HART-1";
+pub static HART2_FORMAT_VERSION: &'static str = "HART-2";
+pub static HART3_FORMAT_VERSION: &'static str = "HART-3";
 pub static BOX_FORMAT_VERSION: &'static str = "BOX-1";
 
 pub const PUBLIC_SIG_KEY_VERSION: &'static str = "SIG-PUB-1";
@@ -263,8 +364,13 @@ pub const SECRET_SIG_KEY_VERSION: &'static str = "SIG-SEC-1";
 pub const PUBLIC_BOX_KEY_VERSION: &'static str = "BOX-PUB-1";
 pub const SECRET_BOX_KEY_VERSION: &'static str = "BOX-SEC-1";
 pub const SECRET_SYM_KEY_VERSION: &'static str = "SYM-SEC-1";
+pub const SECRET_SIG_KEY_ENCRYPTED_VERSION: &'static str = "SIG-SEC-ENC-1";
+pub const SECRET_BOX_KEY_ENCRYPTED_VERSION: &'static str = "BOX-SEC-ENC-1";
+pub const SECRET_SYM_KEY_ENCRYPTED_VERSION: &'static str = "SYM-SEC-ENC-1";
+pub const KEY_REVOCATION_VERSION: &'static str = "REV-1";
 
 pub use self::keys::box_key_pair::BoxKeyPair;
+pub use self::keys::revocation::KeyRevocation;
 pub use self::keys::sym_key::SymKey;
 pub use self::keys::sig_key_pair::SigKeyPair;
 
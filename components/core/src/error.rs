@@ -69,6 +69,8 @@ pub enum Error {
     NoOutboundAddr,
     /// Occurs when a suitable installed pacakge cannot be found.
     PackageNotFound(package::PackageIdent),
+    /// Occurs when an unpacked package doesn't match the files it was built with.
+    PackageUnpackFailed(String),
     /// When an error occurs parsing an integer.
     ParseIntError(num::ParseIntError),
     /// Occurs when setting ownership or permissions on a file or directory fails.
@@ -140,6 +142,7 @@ impl fmt::Display for Error {
                     format!("Cannot find a release of package: {}", pkg)
                 }
             }
+            Error::PackageUnpackFailed(ref e) => format!("Package could not be unpacked: {}", e),
             Error::ParseIntError(ref e) => format!("{}", e),
             Error::PermissionFailed => format!("Failed to set permissions"),
             Error::RegexParse(ref e) => format!("{}", e),
@@ -186,6 +189,7 @@ impl error::Error for Error {
             Error::MetaFileIO(_) => "MetaFile could not be read or written to",
             Error::NoOutboundAddr => "Failed to discover the outbound IP address",
             Error::PackageNotFound(_) => "Cannot find a package",
+            Error::PackageUnpackFailed(_) => "Package could not be unpacked",
             Error::ParseIntError(_) => "Failed to parse an integer from a string!",
             Error::PermissionFailed => "Failed to set permissions",
             Error::RegexParse(_) => "Failed to parse a regular expression",
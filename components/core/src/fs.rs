@@ -35,8 +35,14 @@ pub const CACHE_SRC_PATH: &'static str = "hab/cache/src";
 pub const CACHE_SSL_PATH: &'static str = "hab/cache/ssl";
 /// The root path containing all locally installed packages
 pub const PKG_PATH: &'static str = "hab/pkgs";
+/// The path where packages are unpacked before being moved into the package path
+pub const PKG_STAGING_PATH: &'static str = "hab/pkgs/.staging";
 /// The root path containing all runtime service directories and files
-const SVC_PATH: &'static str = "hab/svc";
+pub const SVC_PATH: &'static str = "hab/svc";
+/// The file in a service's directory containing the PID of its running process
+pub const SVC_PID_FILE: &'static str = "PID";
+/// The file in a service's directory containing the identifier of the package it is running
+pub const SVC_IDENT_FILE: &'static str = "IDENT";
 
 lazy_static! {
     static ref EUID: u32 = users::get_effective_uid();
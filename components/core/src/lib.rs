@@ -19,6 +19,7 @@ extern crate hyper;
 extern crate lazy_static;
 extern crate libc;
 extern crate libarchive;
+extern crate libarchive3_sys;
 #[macro_use]
 extern crate log;
 extern crate regex;
@@ -31,6 +32,8 @@ extern crate time;
 extern crate toml;
 extern crate url as extern_url;
 extern crate users;
+extern crate xz2;
+extern crate zstd;
 
 pub use self::error::{Error, Result};
 
@@ -14,18 +14,30 @@
 
 use std::collections::HashMap;
 use std::error;
+use std::fs::{self, File};
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::result;
 use std::str::{self, FromStr};
+use std::sync::atomic::{AtomicUsize, ATOMIC_USIZE_INIT, Ordering};
 
 use libarchive::writer;
-use libarchive::reader::{self, Reader};
-use libarchive::archive::{Entry, ReadFilter, ReadFormat};
+use libarchive::reader::{self, Reader, StreamReader};
+use libarchive::archive::{Entry, FileType, ReadFilter, ReadFormat};
+use libarchive3_sys::ffi;
+use libc;
 use regex::Regex;
+use zstd::stream::Decoder as ZstdDecoder;
 
 use error::{Error, Result};
 use crypto::{artifact, hash};
+use crypto::artifact::{Compression, SignaturePolicy};
+use crypto::hash::Blake2bHasher;
+use fs::{PKG_PATH, PKG_STAGING_PATH};
 use package::{Identifiable, PackageIdent, MetaFile};
+use package::install::{self, INSTALL_COMPLETE_FILE};
+
+static STAGING_COUNT: AtomicUsize = ATOMIC_USIZE_INIT;
 
 lazy_static! {
     static ref METAFILE_REGXS: HashMap<MetaFile, Regex> = {
@@ -46,6 +58,32 @@ lazy_static! {
 
 type Metadata = HashMap<MetaFile, String>;
 
+/// The kind of a file contained in a package archive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ArchiveEntryKind {
+    File,
+    Directory,
+    /// A symbolic link and the path it points to.
+    Symlink(String),
+    /// A hard link and the path of the file it links to.
+    Hardlink(String),
+    Other,
+}
+
+/// A single file contained in a package archive.
+#[derive(Clone, Debug)]
+pub struct ArchiveEntry {
+    /// The absolute path the file would be unpacked to, relative to the filesystem root.
+    pub path: String,
+    pub kind: ArchiveEntryKind,
+    /// The permission bits of the file.
+    pub mode: u32,
+    /// The size of the file's contents in bytes.
+    pub size: u64,
+    /// The BLAKE2b hash of the file's contents. Only present for regular files.
+    pub hash: Option<String>,
+}
+
 #[derive(Debug)]
 pub struct PackageArchive {
     pub path: PathBuf,
@@ -60,13 +98,16 @@ impl PackageArchive {
         }
     }
 
-    /// Calculate and return the checksum of the package archive in base64 format.
+    /// Calculate and return the checksum of the package archive in base64 format. The checksum
+    /// is made with the hash type recorded in the artifact header, so an artifact signed with
+    /// `SHA256` has a SHA-256 checksum.
     ///
     /// # Failures
     ///
     /// * If the archive cannot be read
     pub fn checksum(&self) -> Result<String> {
-        hash::hash_file(&self.path)
+        let header = try!(artifact::get_artifact_header(&self.path));
+        hash::hash_file_with_type(&self.path, header.hash_type)
     }
 
     pub fn cflags(&mut self) -> Result<Option<String>> {
@@ -157,6 +198,16 @@ impl PackageArchive {
         }
     }
 
+    /// Returns the compression of the tarball payload as recorded in the artifact header.
+    ///
+    /// # Failures
+    ///
+    /// * If the archive header cannot be read or is malformed
+    pub fn compression(&self) -> Result<Compression> {
+        let header = try!(artifact::get_artifact_header(&self.path));
+        Ok(header.compression)
+    }
+
     /// A plain string representation of the archive's file name.
     pub fn file_name(&self) -> String {
         self.path.file_name().unwrap().to_string_lossy().into_owned()
@@ -172,26 +223,210 @@ impl PackageArchive {
         artifact::verify(&self.path, cache_key_path)
     }
 
+    /// Verify the archive's signatures against a signature policy, returning the names with
+    /// revision of all keys with a valid signature and the archive's checksum.
+    ///
+    /// # Failures
+    ///
+    /// * Fails if any signature made by a known key is invalid
+    /// * Fails if the policy is not met by the valid signatures
+    pub fn verify_with_policy<P: AsRef<Path>>(&self,
+                                              cache_key_path: &P,
+                                              policy: &SignaturePolicy)
+                                              -> Result<(Vec<String>, String)> {
+        artifact::verify_with_policy(&self.path, cache_key_path, policy)
+    }
+
+    /// Returns every file contained in the archive along with its mode, size, and content hash,
+    /// without unpacking the archive. The archive's signature is verified first.
+    ///
+    /// # Failures
+    ///
+    /// * If the archive cannot be verified
+    /// * If the archive cannot be read
+    pub fn entries<P: AsRef<Path>>(&self, cache_key_path: &P) -> Result<Vec<ArchiveEntry>> {
+        try!(self.verify(cache_key_path));
+        let mut entries = Vec::new();
+        let mut reader = try!(self.open_payload());
+        loop {
+            let mut entry = match reader.next_header() {
+                Some(header) => {
+                    let kind = match header.filetype() {
+                        FileType::RegularFile => {
+                            match header.hardlink() {
+                                Some(target) => ArchiveEntryKind::Hardlink(entry_path(target)),
+                                None => ArchiveEntryKind::File,
+                            }
+                        }
+                        FileType::Directory => ArchiveEntryKind::Directory,
+                        FileType::SymbolicLink => {
+                            ArchiveEntryKind::Symlink(header.symlink().to_string())
+                        }
+                        _ => ArchiveEntryKind::Other,
+                    };
+                    ArchiveEntry {
+                        path: entry_path(header.pathname()),
+                        kind: kind,
+                        mode: unsafe { ffi::archive_entry_perm(header.entry()) } as u32,
+                        size: header.size() as u64,
+                        hash: None,
+                    }
+                }
+                None => break,
+            };
+            if entry.kind == ArchiveEntryKind::File {
+                let mut hasher = Blake2bHasher::new();
+                loop {
+                    match try!(reader.read_block()) {
+                        Some(bytes) => hasher.update(bytes),
+                        None => break,
+                    }
+                }
+                entry.hash = Some(hasher.finish());
+            }
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    /// Writes the contents of a single file in the archive to the given writer, without
+    /// unpacking the rest of the archive. The archive's signature is verified first.
+    ///
+    /// The path is absolute and relative to the filesystem root, as returned by `entries`.
+    ///
+    /// # Failures
+    ///
+    /// * If the archive cannot be verified
+    /// * If the archive cannot be read
+    /// * If the path is not a regular file in the archive
+    pub fn extract_file<P, W>(&self, path: &str, writer: &mut W, cache_key_path: &P) -> Result<()>
+        where P: AsRef<Path>,
+              W: Write
+    {
+        try!(self.verify(cache_key_path));
+        let wanted = entry_path(path);
+        let mut reader = try!(self.open_payload());
+        loop {
+            match reader.next_header() {
+                Some(header) => {
+                    if entry_path(header.pathname()) != wanted {
+                        continue;
+                    }
+                    let is_file = match header.filetype() {
+                        FileType::RegularFile => header.hardlink().is_none(),
+                        _ => false,
+                    };
+                    if !is_file {
+                        return Err(Error::FileNotFound(format!("{} in {}",
+                                                               wanted,
+                                                               self.path.display())));
+                    }
+                }
+                None => {
+                    return Err(Error::FileNotFound(format!("{} in {}",
+                                                           wanted,
+                                                           self.path.display())))
+                }
+            }
+            loop {
+                match try!(reader.read_block()) {
+                    Some(bytes) => try!(writer.write_all(bytes)),
+                    None => break,
+                }
+            }
+            return Ok(());
+        }
+    }
+
     /// Given a package name and a path to a file as an `&str`, unpack
     /// the package.
     ///
+    /// The package is first unpacked into a staging directory of its own on the same filesystem
+    /// as the package path, so concurrent unpacks of the same package don't collide. There its files are checked against the hashes in its `FILES` metafile, or
+    /// for a package built without one, its `MANIFEST` must be present. The package directory is
+    /// then marked as complete and renamed into the package path, so a package which fails to
+    /// unpack part way is never seen as installed.
+    ///
     /// # Failures
     ///
     /// * If the package cannot be unpacked
+    /// * If the unpacked files don't match the package's `FILES` metafile
     pub fn unpack(&self, fs_root_path: Option<&Path>) -> Result<()> {
         let root = fs_root_path.unwrap_or(Path::new("/"));
-        let tar_reader = try!(artifact::get_archive_reader(&self.path));
-        let mut builder = reader::Builder::new();
-        try!(builder.support_format(ReadFormat::Gnutar));
-        try!(builder.support_filter(ReadFilter::Xz));
-        let mut reader = try!(builder.open_stream(tar_reader));
+        let staging_path = self.staging_path(root);
+        if staging_path.exists() {
+            debug!("Removing staging directory {} left by an earlier unpack",
+                   staging_path.display());
+            try!(fs::remove_dir_all(&staging_path));
+        }
+        try!(fs::create_dir_all(&staging_path));
+        let result = self.unpack_staged(root, &staging_path);
+        if let Err(e) = fs::remove_dir_all(&staging_path) {
+            debug!("Error removing staging directory {}: {}, continuing",
+                   staging_path.display(),
+                   e);
+        }
+        result
+    }
+
+    /// Returns a staging directory for the package which is unique to this process and call.
+    fn staging_path(&self, root: &Path) -> PathBuf {
+        let count = STAGING_COUNT.fetch_add(1, Ordering::SeqCst);
+        root.join(PKG_STAGING_PATH)
+            .join(format!("{}-{}-{}", self.file_name(), unsafe { libc::getpid() }, count))
+    }
+
+    fn unpack_staged(&self, root: &Path, staging_path: &Path) -> Result<()> {
+        let mut reader = try!(self.open_payload());
         let writer = writer::Disk::new();
         try!(writer.set_standard_lookup());
-        try!(writer.write(&mut reader, Some(root.to_string_lossy().as_ref())));
+        try!(writer.write(&mut reader, Some(staging_path.to_string_lossy().as_ref())));
         try!(writer.close());
+
+        let release_path = try!(staged_release_path(staging_path));
+        let staged = staging_path.join(&release_path);
+        try!(verify_staged(staging_path, &staged));
+        try!(try!(File::create(staged.join(INSTALL_COMPLETE_FILE))).sync_all());
+
+        let installed_path = root.join(&release_path);
+        if installed_path.exists() {
+            // An install without a marker is incomplete, or was installed before installs were
+            // staged and can't be told apart from an incomplete one without checking every file
+            if installed_path.join(INSTALL_COMPLETE_FILE).is_file() {
+                debug!("Package is already installed at {}", installed_path.display());
+                return Ok(());
+            }
+            debug!("Replacing unmarked install at {}", installed_path.display());
+            try!(fs::remove_dir_all(&installed_path));
+        }
+        let version_path = installed_path.parent().unwrap();
+        try!(fs::create_dir_all(version_path));
+        try!(fs::rename(&staged, &installed_path));
+        try!(try!(File::open(version_path)).sync_all());
         Ok(())
     }
 
+    /// Opens a tar stream over the archive's payload, decompressing it according to the
+    /// compression recorded in the artifact header. `xz` is handled natively by libarchive while
+    /// `zstd` payloads are decompressed before being handed over.
+    fn open_payload(&self) -> Result<StreamReader> {
+        let compression = try!(self.compression());
+        let tar_reader = try!(artifact::get_archive_reader(&self.path));
+        let mut builder = reader::Builder::new();
+        try!(builder.support_format(ReadFormat::Gnutar));
+        let reader = match compression {
+            Compression::Xz => {
+                try!(builder.support_filter(ReadFilter::Xz));
+                try!(builder.open_stream(tar_reader))
+            }
+            Compression::Zstd => {
+                try!(builder.support_filter(ReadFilter::None));
+                try!(builder.open_stream(try!(ZstdDecoder::new(tar_reader))))
+            }
+        };
+        Ok(reader)
+    }
+
     fn read_deps(&mut self, file: MetaFile) -> Result<Vec<PackageIdent>> {
         let mut deps: Vec<PackageIdent> = vec![];
         match self.read_metadata(file) {
@@ -221,11 +456,7 @@ impl PackageArchive {
         }
         let mut metadata = Metadata::new();
         let mut matched_count = 0u8;
-        let tar_reader = try!(artifact::get_archive_reader(&self.path));
-        let mut builder = reader::Builder::new();
-        try!(builder.support_format(ReadFormat::Gnutar));
-        try!(builder.support_filter(ReadFilter::Xz));
-        let mut reader = try!(builder.open_stream(tar_reader));
+        let mut reader = try!(self.open_payload());
         loop {
             let mut matched_type: Option<MetaFile> = None;
             if let Some(entry) = reader.next_header() {
@@ -266,6 +497,59 @@ impl PackageArchive {
     }
 }
 
+/// Returns the release directory of the single package unpacked into a staging directory, as a
+/// path relative to the staging directory.
+fn staged_release_path(staging_path: &Path) -> Result<PathBuf> {
+    let mut release_path = PathBuf::from(PKG_PATH);
+    // Descend through the origin, name, version, and release directories
+    for _ in 0..4 {
+        let mut entries = try!(fs::read_dir(staging_path.join(&release_path)));
+        let entry = match (entries.next(), entries.next()) {
+            (Some(entry), None) => try!(entry),
+            _ => {
+                return Err(Error::PackageUnpackFailed(format!("archive does not contain a \
+                                                               single package under {}",
+                                                              PKG_PATH)))
+            }
+        };
+        release_path.push(entry.file_name());
+    }
+    Ok(release_path)
+}
+
+/// Checks the files of a staged package against the hashes in its `FILES` metafile, flushing each
+/// to disk on the way. A package without a `FILES` metafile must have a `MANIFEST`.
+fn verify_staged(staging_path: &Path, staged: &Path) -> Result<()> {
+    let files_path = staged.join(MetaFile::Files.to_string());
+    if !files_path.is_file() {
+        if staged.join(MetaFile::Manifest.to_string()).is_file() {
+            return Ok(());
+        }
+        return Err(Error::PackageUnpackFailed(format!("{} not found",
+                                                      MetaFile::Manifest)));
+    }
+    for (expected, file) in try!(install::read_files_metafile(&files_path)) {
+        let path = staging_path.join(file.trim_left_matches('/'));
+        let actual = match hash::hash_file(&path) {
+            Ok(actual) => actual,
+            Err(_) => return Err(Error::PackageUnpackFailed(format!("{} is missing", file))),
+        };
+        if actual != expected {
+            return Err(Error::PackageUnpackFailed(format!("{} has hash {}, expected {}",
+                                                          file,
+                                                          actual,
+                                                          expected)));
+        }
+        try!(try!(File::open(&path)).sync_all());
+    }
+    Ok(())
+}
+
+/// Normalizes a path from a tarball entry into an absolute path.
+fn entry_path(path: &str) -> String {
+    format!("/{}", path.trim_left_matches("./").trim_left_matches('/'))
+}
+
 pub trait FromArchive: Sized {
     type Error: error::Error;
 
@@ -275,7 +559,15 @@ pub trait FromArchive: Sized {
 #[cfg(test)]
 mod test {
     use std::env;
+    use std::fs::{self, File};
     use std::path::PathBuf;
+    use std::str::FromStr;
+
+    use tempdir::TempDir;
+
+    use fs::{PKG_PATH, PKG_STAGING_PATH};
+    use package::{PackageIdent, PackageInstall};
+    use package::install::INSTALL_COMPLETE_FILE;
     use super::*;
 
     #[test]
@@ -289,6 +581,115 @@ mod test {
         assert_eq!(ident.release, Some("20160427165340".to_string()));
     }
 
+    #[test]
+    fn listing_artifact_entries() {
+        let hart = PackageArchive::new(fixtures()
+            .join("happyhumans-possums-8.1.4-20160427165340-x86_64-linux.hart"));
+        let entries = hart.entries(&fixtures()).unwrap();
+        assert_eq!(entries.len(), 6);
+        let file = entries.iter()
+            .find(|e| {
+                e.path == "/hab/pkgs/happyhumans/possums/8.1.4/20160427165340/share/signme.dat"
+            })
+            .unwrap();
+        assert_eq!(file.kind, ArchiveEntryKind::File);
+        assert_eq!(file.mode, 0o755);
+        assert_eq!(file.size, 4);
+        assert_eq!(file.hash,
+                   Some("20590a52c4f00588c500328b16d466c982a26fabaa5fa4dcc83052dd0a84f233"
+                       .to_string()));
+        let dir = entries.iter()
+            .find(|e| e.path == "/hab/pkgs/happyhumans/possums/8.1.4/20160427165340/share/")
+            .unwrap();
+        assert_eq!(dir.kind, ArchiveEntryKind::Directory);
+        assert_eq!(dir.hash, None);
+    }
+
+    #[test]
+    fn extracting_a_single_file() {
+        let hart = PackageArchive::new(fixtures()
+            .join("happyhumans-possums-8.1.4-20160427165340-x86_64-linux.hart"));
+        let mut out = Vec::new();
+        hart.extract_file("/hab/pkgs/happyhumans/possums/8.1.4/20160427165340/share/signme.dat",
+                          &mut out,
+                          &fixtures())
+            .unwrap();
+        assert_eq!(out, b"foo\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "File not found")]
+    fn extracting_a_missing_file() {
+        let hart = PackageArchive::new(fixtures()
+            .join("happyhumans-possums-8.1.4-20160427165340-x86_64-linux.hart"));
+        let mut out = Vec::new();
+        hart.extract_file("/etc/passwd", &mut out, &fixtures()).unwrap();
+    }
+
+    #[test]
+    fn unpacking_is_staged() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        let hart = PackageArchive::new(fixtures()
+            .join("happyhumans-possums-8.1.4-20160427165340-x86_64-linux.hart"));
+        hart.unpack(Some(fs_root.path())).unwrap();
+
+        let installed_path = fs_root.path()
+            .join(PKG_PATH)
+            .join("happyhumans/possums/8.1.4/20160427165340");
+        assert!(installed_path.join(INSTALL_COMPLETE_FILE).is_file());
+        assert!(installed_path.join("share/signme.dat").is_file());
+        assert_eq!(0,
+                   fs::read_dir(fs_root.path().join(PKG_STAGING_PATH)).unwrap().count());
+        let pkg_install = PackageInstall::load(&PackageIdent::from_str("happyhumans/possums")
+                                                   .unwrap(),
+                                               Some(fs_root.path()))
+            .unwrap();
+        assert_eq!(&installed_path, pkg_install.installed_path());
+    }
+
+    #[test]
+    fn staging_paths_are_unique() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        let hart = PackageArchive::new(fixtures()
+            .join("happyhumans-possums-8.1.4-20160427165340-x86_64-linux.hart"));
+        let first = hart.staging_path(fs_root.path());
+        let second = hart.staging_path(fs_root.path());
+        assert!(first != second);
+        assert_eq!(first.parent(), second.parent());
+    }
+
+    #[test]
+    fn unpacking_replaces_an_incomplete_install() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        let installed_path = fs_root.path()
+            .join(PKG_PATH)
+            .join("happyhumans/possums/8.1.4/20160427165340");
+        fs::create_dir_all(installed_path.join("share")).unwrap();
+        File::create(installed_path.join("share/partial")).unwrap();
+        let hart = PackageArchive::new(fixtures()
+            .join("happyhumans-possums-8.1.4-20160427165340-x86_64-linux.hart"));
+        hart.unpack(Some(fs_root.path())).unwrap();
+
+        assert!(installed_path.join(INSTALL_COMPLETE_FILE).is_file());
+        assert!(!installed_path.join("share/partial").exists());
+    }
+
+    #[test]
+    fn unpacking_replaces_an_unmarked_legacy_install() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        let installed_path = fs_root.path()
+            .join(PKG_PATH)
+            .join("happyhumans/possums/8.1.4/20160427165340");
+        fs::create_dir_all(&installed_path).unwrap();
+        File::create(installed_path.join("MANIFEST")).unwrap();
+        let hart = PackageArchive::new(fixtures()
+            .join("happyhumans-possums-8.1.4-20160427165340-x86_64-linux.hart"));
+        hart.unpack(Some(fs_root.path())).unwrap();
+
+        assert!(installed_path.join(INSTALL_COMPLETE_FILE).is_file());
+        assert!(installed_path.join("share/signme.dat").is_file());
+    }
+
     pub fn exe_path() -> PathBuf {
         env::current_exe().unwrap()
     }
@@ -21,10 +21,58 @@ use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
+use crypto::{artifact, hash};
+use crypto::keys::parse_name_with_rev;
 use error::{Error, Result};
 use fs::{self, PKG_PATH};
 use package::{Identifiable, MetaFile, PackageIdent};
 
+/// The file written into an installed package's directory once the package has been completely
+/// unpacked and verified. A release directory without it is an incomplete install and is
+/// ignored.
+pub const INSTALL_COMPLETE_FILE: &'static str = "INSTALLED";
+
+/// Reads a `FILES` metafile, returning the BLAKE2b hash and absolute path of every file it
+/// lists. The metafile is signed like an artifact, so its header is skipped.
+///
+/// # Failures
+///
+/// * The metafile could not be read or is malformed
+pub fn read_files_metafile(path: &Path) -> Result<Vec<(String, String)>> {
+    let mut body = String::new();
+    if try!(artifact::get_payload_reader(&path)).read_to_string(&mut body).is_err() {
+        return Err(Error::MetaFileMalformed(MetaFile::Files));
+    }
+    let mut files = Vec::new();
+    for line in body.lines().filter(|l| !l.trim().is_empty()) {
+        let mut parts = line.splitn(2, "  ");
+        match (parts.next(), parts.next()) {
+            (Some(hash), Some(file)) => files.push((hash.to_string(), file.to_string())),
+            _ => return Err(Error::MetaFileMalformed(MetaFile::Files)),
+        }
+    }
+    Ok(files)
+}
+
+/// The differences found between an installed package's files and the hashes in its `FILES`
+/// metafile. Paths are absolute, as they are listed in `FILES`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PackageVerification {
+    /// Files whose contents no longer match their hash.
+    pub modified: Vec<String>,
+    /// Files which are listed but no longer exist.
+    pub missing: Vec<String>,
+    /// Files which exist under the installed path but aren't listed.
+    pub extra: Vec<String>,
+}
+
+impl PackageVerification {
+    /// Returns true if the installed files match exactly.
+    pub fn is_ok(&self) -> bool {
+        self.modified.is_empty() && self.missing.is_empty() && self.extra.is_empty()
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct PackageInstall {
     ident: PackageIdent,
@@ -103,6 +151,121 @@ impl PackageInstall {
         }
     }
 
+    /// Returns every package installed under the package path, ordered by origin, name, version,
+    /// and release.
+    ///
+    /// An optional `fs_root` path may be provided to list the packages on a filesystem not
+    /// currently rooted at `/`.
+    pub fn installed(fs_root_path: Option<&Path>) -> Result<Vec<PackageInstall>> {
+        let fs_root_path = fs_root_path.unwrap_or(Path::new("/"));
+        let package_root_path = fs_root_path.join(PKG_PATH);
+        if !package_root_path.exists() {
+            return Ok(vec![]);
+        }
+        let mut pl = try!(Self::package_list(&package_root_path));
+        pl.sort_by(|a, b| {
+            match (&a.origin, &a.name).cmp(&(&b.origin, &b.name)) {
+                Ordering::Equal => a.partial_cmp(b).unwrap_or(Ordering::Equal),
+                ordering => ordering,
+            }
+        });
+        let mut installs = Vec::with_capacity(pl.len());
+        for ident in pl.into_iter() {
+            let installed_path = try!(Self::calc_installed_path(&ident, &package_root_path));
+            installs.push(PackageInstall {
+                ident: ident,
+                fs_root_path: PathBuf::from(fs_root_path),
+                package_root_path: package_root_path.clone(),
+                installed_path: installed_path,
+            });
+        }
+        Ok(installs)
+    }
+
+    /// Removes this package installation from disk. Any version, name, and origin directories
+    /// left empty by the removal are removed as well.
+    ///
+    /// # Failures
+    ///
+    /// * The installed package directory could not be removed
+    pub fn remove(&self) -> Result<()> {
+        // Without its marker a partially removed package is ignored rather than half-loaded
+        match std::fs::remove_file(self.installed_path.join(INSTALL_COMPLETE_FILE)) {
+            Ok(_) => {}
+            Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(Error::IO(e)),
+        }
+        try!(std::fs::remove_dir_all(&self.installed_path));
+        let mut dir = self.installed_path.parent();
+        while let Some(path) = dir {
+            if path == self.package_root_path.as_path() {
+                break;
+            }
+            // A directory which still has other releases, versions, or names in it won't be
+            // removed, which is where pruning stops
+            if std::fs::remove_dir(path).is_err() {
+                break;
+            }
+            dir = path.parent();
+        }
+        Ok(())
+    }
+
+    /// Compares the files under the installed path against the hashes in the package's `FILES`
+    /// metafile, reporting the files which were modified, are missing, or were added. The
+    /// `MANIFEST` only describes a package, so `FILES` is where the hashes of its files are
+    /// kept. `FILES` itself and the install marker are never reported as extra.
+    ///
+    /// `FILES` is signed when a package is built, and no hash in it is trusted until its
+    /// signature is verified against a key of the package's origin in the key cache.
+    ///
+    /// # Failures
+    ///
+    /// * The package has no `FILES` metafile or it could not be read
+    /// * The signature on `FILES` is invalid or isn't made by a key of the package's origin
+    /// * The installed files could not be read
+    pub fn verify<P: AsRef<Path> + ?Sized>(&self,
+                                           cache_key_path: &P)
+                                           -> Result<PackageVerification> {
+        let files_path = self.installed_path.join(MetaFile::Files.to_string());
+        if !files_path.is_file() {
+            return Err(Error::MetaFileNotFound(MetaFile::Files));
+        }
+        let (signer, _) = try!(artifact::verify(&files_path, cache_key_path.as_ref()));
+        let (signer_origin, _) = try!(parse_name_with_rev(&signer));
+        if signer_origin != self.ident.origin {
+            return Err(Error::CryptoError(format!("{} of {} is signed with {}, which is not a \
+                                                   key of origin {}",
+                                                  MetaFile::Files,
+                                                  self.ident,
+                                                  signer,
+                                                  self.ident.origin)));
+        }
+        let mut verification = PackageVerification::default();
+        let mut listed = HashSet::new();
+        for (expected, file) in try!(read_files_metafile(&files_path)) {
+            let path = self.fs_root_path.join(file.trim_left_matches('/'));
+            if !path.is_file() {
+                verification.missing.push(file);
+            } else if try!(hash::hash_file(&path)) != expected {
+                verification.modified.push(file);
+            }
+            listed.insert(path);
+        }
+        let mut on_disk = Vec::new();
+        try!(Self::walk_files(&self.installed_path, &mut on_disk));
+        for path in on_disk.into_iter() {
+            if listed.contains(&path) || path == files_path ||
+               path == self.installed_path.join(INSTALL_COMPLETE_FILE) {
+                continue;
+            }
+            let relative = path.strip_prefix(&self.fs_root_path).unwrap_or(&path).to_path_buf();
+            verification.extra.push(format!("/{}", relative.display()));
+        }
+        verification.extra.sort();
+        Ok(verification)
+    }
+
     pub fn deps(&self) -> Result<Vec<PackageIdent>> {
         self.read_deps(MetaFile::Deps)
     }
@@ -321,6 +484,32 @@ impl PackageInstall {
         }
     }
 
+    /// Returns true if the release directory at the given path holds a completely installed
+    /// package.
+    ///
+    /// Packages installed before installs were staged have no marker file. These are taken to be
+    /// complete when they have a `MANIFEST`, without checking their files, as this runs for every
+    /// installed release whenever packages are loaded. `PackageInstall::verify` checks the files.
+    pub fn is_complete(installed_path: &Path) -> bool {
+        installed_path.join(INSTALL_COMPLETE_FILE).is_file() ||
+        installed_path.join(MetaFile::Manifest.to_string()).is_file()
+    }
+
+    /// Collects the paths of all regular files under the given directory. Symbolic links are
+    /// not followed.
+    fn walk_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+        for entry in try!(std::fs::read_dir(path)) {
+            let entry = try!(entry);
+            let file_type = try!(entry.file_type());
+            if file_type.is_dir() {
+                try!(Self::walk_files(&entry.path(), files));
+            } else if file_type.is_file() {
+                files.push(entry.path());
+            }
+        }
+        Ok(())
+    }
+
     /// Returns a list of package structs built from the contents of the given directory.
     fn package_list(path: &Path) -> Result<Vec<PackageIdent>> {
         let mut package_list: Vec<PackageIdent> = vec![];
@@ -336,6 +525,10 @@ impl PackageInstall {
     fn walk_origins(path: &Path, packages: &mut Vec<PackageIdent>) -> Result<()> {
         for entry in try!(std::fs::read_dir(path)) {
             let origin = try!(entry);
+            // Hidden directories such as the staging directory don't contain installed packages
+            if origin.file_name().to_string_lossy().starts_with(".") {
+                continue;
+            }
             if try!(std::fs::metadata(origin.path())).is_dir() {
                 try!(Self::walk_names(&origin, packages));
             }
@@ -374,14 +567,21 @@ impl PackageInstall {
 
     /// Helper function for walk_versions. Walks the given release DirEntry for directories and recurses
     /// into them to find version directories. Finally, a Package struct is built and concatenated onto
-    /// the given packages vector with the origin, name, version, and release of each.
+    /// the given packages vector with the origin, name, version, and release of each. Incomplete
+    /// installs are skipped.
     fn walk_releases(origin: &String,
                      name: &String,
                      version: &DirEntry,
                      packages: &mut Vec<PackageIdent>)
                      -> Result<()> {
         for release in try!(std::fs::read_dir(version.path())) {
-            let release = try!(release).file_name().to_string_lossy().into_owned().to_string();
+            let release = try!(release);
+            if !Self::is_complete(&release.path()) {
+                debug!("Ignoring incomplete package install at {}",
+                       release.path().display());
+                continue;
+            }
+            let release = release.file_name().to_string_lossy().into_owned().to_string();
             let version = version.file_name().to_string_lossy().into_owned().to_string();
             let ident =
                 PackageIdent::new(origin.clone(), name.clone(), Some(version), Some(release));
@@ -390,3 +590,246 @@ impl PackageInstall {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::fs::{self, File};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::str::FromStr;
+
+    use tempdir::TempDir;
+
+    use crypto::{artifact, hash, SigKeyPair};
+    use crypto::artifact::Compression;
+    use fs::PKG_PATH;
+    use package::PackageIdent;
+    use super::{INSTALL_COMPLETE_FILE, PackageInstall};
+
+    fn fake_install(fs_root: &TempDir, ident: &str, tdeps: &[&str]) {
+        let ident = PackageIdent::from_str(ident).unwrap();
+        let path = fs_root.path()
+            .join(PKG_PATH)
+            .join(&ident.origin)
+            .join(&ident.name)
+            .join(ident.version.as_ref().unwrap())
+            .join(ident.release.as_ref().unwrap());
+        fs::create_dir_all(&path).unwrap();
+        File::create(path.join(INSTALL_COMPLETE_FILE)).unwrap();
+        if !tdeps.is_empty() {
+            let mut f = File::create(path.join("TDEPS")).unwrap();
+            f.write_all(tdeps.join("\n").as_bytes()).unwrap();
+        }
+    }
+
+    #[test]
+    fn installed_is_ordered() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        fake_install(&fs_root, "core/redis/3.0.10/20160101000000", &[]);
+        fake_install(&fs_root, "core/redis/3.0.9/20160101000000", &[]);
+        fake_install(&fs_root,
+                     "acme/app/1.0.0/20160101000000",
+                     &["core/redis/3.0.9/20160101000000"]);
+
+        let installed: Vec<String> = PackageInstall::installed(Some(fs_root.path()))
+            .unwrap()
+            .iter()
+            .map(|p| p.ident().to_string())
+            .collect();
+        assert_eq!(vec!["acme/app/1.0.0/20160101000000",
+                        "core/redis/3.0.9/20160101000000",
+                        "core/redis/3.0.10/20160101000000"],
+                   installed);
+    }
+
+    #[test]
+    fn installed_without_package_path() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        assert!(PackageInstall::installed(Some(fs_root.path())).unwrap().is_empty());
+    }
+
+    #[test]
+    fn load_ignores_incomplete_installs() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        fake_install(&fs_root, "core/redis/3.0.9/20160101000000", &[]);
+        fake_install(&fs_root, "core/redis/3.0.10/20160101000000", &[]);
+        let partial = fs_root.path().join(PKG_PATH).join("core/redis/3.0.10/20160101000000");
+        fs::remove_file(partial.join(INSTALL_COMPLETE_FILE)).unwrap();
+
+        let latest = PackageInstall::load(&PackageIdent::from_str("core/redis").unwrap(),
+                                          Some(fs_root.path()))
+            .unwrap();
+        assert_eq!("core/redis/3.0.9/20160101000000", latest.ident().to_string());
+    }
+
+    #[test]
+    fn load_accepts_installs_without_marker_with_manifest() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        fake_install(&fs_root, "core/redis/3.0.9/20160101000000", &[]);
+        let legacy = fs_root.path().join(PKG_PATH).join("core/redis/3.0.9/20160101000000");
+        fs::remove_file(legacy.join(INSTALL_COMPLETE_FILE)).unwrap();
+        File::create(legacy.join("MANIFEST")).unwrap();
+
+        assert!(PackageInstall::load(&PackageIdent::from_str("core/redis").unwrap(),
+                                     Some(fs_root.path()))
+            .is_ok());
+    }
+
+    fn write_files_metafile(fs_root: &TempDir, cache: &TempDir, ident: &str, files: &[&str]) {
+        let origin = ident.split('/').next().unwrap();
+        let pair = SigKeyPair::generate_pair_for_origin(origin, cache.path()).unwrap();
+        let installed_path = fs_root.path().join(PKG_PATH).join(ident);
+        let mut sums = String::new();
+        for file in files.iter() {
+            let path = installed_path.join(file);
+            sums.push_str(&format!("{}  /{}/{}/{}\n",
+                                   hash::hash_file(&path).unwrap(),
+                                   PKG_PATH,
+                                   ident,
+                                   file));
+        }
+        let sums_path = cache.path().join("blake2bsums");
+        File::create(&sums_path).unwrap().write_all(sums.as_bytes()).unwrap();
+        let xz_path = cache.path().join("blake2bsums.xz");
+        artifact::recompress(&sums_path, &xz_path, Compression::Xz).unwrap();
+        artifact::sign(&xz_path, &installed_path.join("FILES"), &pair).unwrap();
+    }
+
+    #[test]
+    fn verify_unchanged_install() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        let cache = TempDir::new("key_cache").unwrap();
+        fake_install(&fs_root, "core/redis/3.0.9/20160101000000", &[]);
+        let installed_path = fs_root.path()
+            .join(PKG_PATH)
+            .join("core/redis/3.0.9/20160101000000");
+        File::create(installed_path.join("MANIFEST")).unwrap().write_all(b"redis").unwrap();
+        write_files_metafile(&fs_root, &cache, "core/redis/3.0.9/20160101000000", &["MANIFEST"]);
+
+        let pkg_install = PackageInstall::load(&PackageIdent::from_str("core/redis").unwrap(),
+                                               Some(fs_root.path()))
+            .unwrap();
+        assert!(pkg_install.verify(cache.path()).unwrap().is_ok());
+    }
+
+    #[test]
+    fn verify_reports_modified_missing_and_extra_files() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        let cache = TempDir::new("key_cache").unwrap();
+        fake_install(&fs_root, "core/redis/3.0.9/20160101000000", &[]);
+        let installed_path = fs_root.path()
+            .join(PKG_PATH)
+            .join("core/redis/3.0.9/20160101000000");
+        fs::create_dir_all(installed_path.join("bin")).unwrap();
+        File::create(installed_path.join("MANIFEST")).unwrap().write_all(b"redis").unwrap();
+        File::create(installed_path.join("bin/redis-server")).unwrap().write_all(b"#!").unwrap();
+        write_files_metafile(&fs_root,
+                             &cache,
+                             "core/redis/3.0.9/20160101000000",
+                             &["MANIFEST", "bin/redis-server"]);
+        File::create(installed_path.join("MANIFEST")).unwrap().write_all(b"evil").unwrap();
+        fs::remove_file(installed_path.join("bin/redis-server")).unwrap();
+        File::create(installed_path.join("bin/backdoor")).unwrap();
+
+        let pkg_install = PackageInstall::load(&PackageIdent::from_str("core/redis").unwrap(),
+                                               Some(fs_root.path()))
+            .unwrap();
+        let verification = pkg_install.verify(cache.path()).unwrap();
+        assert_eq!(vec!["/hab/pkgs/core/redis/3.0.9/20160101000000/MANIFEST"],
+                   verification.modified);
+        assert_eq!(vec!["/hab/pkgs/core/redis/3.0.9/20160101000000/bin/redis-server"],
+                   verification.missing);
+        assert_eq!(vec!["/hab/pkgs/core/redis/3.0.9/20160101000000/bin/backdoor"],
+                   verification.extra);
+    }
+
+    #[test]
+    #[should_panic(expected = "Habitat artifact is invalid")]
+    fn verify_rejects_tampered_files_metafile() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        let cache = TempDir::new("key_cache").unwrap();
+        fake_install(&fs_root, "core/redis/3.0.9/20160101000000", &[]);
+        let installed_path = fs_root.path()
+            .join(PKG_PATH)
+            .join("core/redis/3.0.9/20160101000000");
+        File::create(installed_path.join("MANIFEST")).unwrap().write_all(b"redis").unwrap();
+        write_files_metafile(&fs_root, &cache, "core/redis/3.0.9/20160101000000", &["MANIFEST"]);
+
+        // Replace the payload with checksums matching a tampered MANIFEST, keeping the header
+        File::create(installed_path.join("MANIFEST")).unwrap().write_all(b"evil").unwrap();
+        let files_path = installed_path.join("FILES");
+        let mut header = String::new();
+        {
+            let mut reader = BufReader::new(File::open(&files_path).unwrap());
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                header.push_str(&line);
+                if line == "\n" {
+                    break;
+                }
+            }
+        }
+        let sums = format!("{}  /{}/core/redis/3.0.9/20160101000000/MANIFEST\n",
+                           hash::hash_file(&installed_path.join("MANIFEST")).unwrap(),
+                           PKG_PATH);
+        let sums_path = cache.path().join("tampered");
+        let xz_path = cache.path().join("tampered.xz");
+        File::create(&sums_path).unwrap().write_all(sums.as_bytes()).unwrap();
+        artifact::recompress(&sums_path, &xz_path, Compression::Xz).unwrap();
+        let mut payload = Vec::new();
+        File::open(&xz_path).unwrap().read_to_end(&mut payload).unwrap();
+        let mut f = File::create(&files_path).unwrap();
+        f.write_all(header.as_bytes()).unwrap();
+        f.write_all(&payload).unwrap();
+
+        let pkg_install = PackageInstall::load(&PackageIdent::from_str("core/redis").unwrap(),
+                                               Some(fs_root.path()))
+            .unwrap();
+        pkg_install.verify(cache.path()).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "which is not a key of origin core")]
+    fn verify_rejects_files_metafile_signed_by_another_origin() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        let cache = TempDir::new("key_cache").unwrap();
+        fake_install(&fs_root, "core/redis/3.0.9/20160101000000", &[]);
+        fake_install(&fs_root, "acme/redis/3.0.9/20160101000000", &[]);
+        let installed_path = fs_root.path()
+            .join(PKG_PATH)
+            .join("acme/redis/3.0.9/20160101000000");
+        File::create(installed_path.join("MANIFEST")).unwrap().write_all(b"redis").unwrap();
+        write_files_metafile(&fs_root, &cache, "acme/redis/3.0.9/20160101000000", &["MANIFEST"]);
+        fs::copy(installed_path.join("FILES"),
+                 fs_root.path().join(PKG_PATH).join("core/redis/3.0.9/20160101000000/FILES"))
+            .unwrap();
+
+        let pkg_install = PackageInstall::load(&PackageIdent::from_str("core/redis").unwrap(),
+                                               Some(fs_root.path()))
+            .unwrap();
+        pkg_install.verify(cache.path()).unwrap();
+    }
+
+    #[test]
+    fn remove_prunes_empty_directories() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        fake_install(&fs_root, "core/redis/3.0.9/20160101000000", &[]);
+        fake_install(&fs_root, "core/redis/3.0.10/20160101000000", &[]);
+        let pkgs = fs_root.path().join(PKG_PATH);
+
+        let old = PackageInstall::load(&PackageIdent::from_str("core/redis/3.0.9/20160101000000")
+                                           .unwrap(),
+                                       Some(fs_root.path()))
+            .unwrap();
+        old.remove().unwrap();
+        assert!(!pkgs.join("core/redis/3.0.9").exists());
+        assert!(pkgs.join("core/redis/3.0.10/20160101000000").is_dir());
+
+        let new = PackageInstall::load(&PackageIdent::from_str("core/redis").unwrap(),
+                                       Some(fs_root.path()))
+            .unwrap();
+        new.remove().unwrap();
+        assert!(!pkgs.join("core").exists());
+        assert!(pkgs.is_dir());
+    }
+}
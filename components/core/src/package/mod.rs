@@ -16,9 +16,9 @@ pub mod archive;
 pub mod ident;
 pub mod install;
 
-pub use self::archive::{FromArchive, PackageArchive};
+pub use self::archive::{ArchiveEntry, ArchiveEntryKind, FromArchive, PackageArchive};
 pub use self::ident::{Identifiable, PackageIdent};
-pub use self::install::PackageInstall;
+pub use self::install::{PackageInstall, PackageVerification};
 
 use std::fmt;
 
@@ -29,6 +29,7 @@ pub enum MetaFile {
     Deps,
     TDeps,
     Exposes,
+    Files,
     Ident,
     LdRunPath,
     LdFlags,
@@ -44,6 +45,7 @@ impl fmt::Display for MetaFile {
             MetaFile::Deps => "DEPS",
             MetaFile::TDeps => "TDEPS",
             MetaFile::Exposes => "EXPOSES",
+            MetaFile::Files => "FILES",
             MetaFile::Ident => "IDENT",
             MetaFile::LdRunPath => "LD_RUN_PATH",
             MetaFile::LdFlags => "LDFLAGS",
@@ -18,6 +18,7 @@ use std::fmt;
 use std::result;
 
 use hyper;
+use rustc_serialize::json;
 use url;
 
 use hab_core::{self, package};
@@ -31,6 +32,7 @@ pub enum Error {
     HTTP(hyper::status::StatusCode),
     HyperError(hyper::error::Error),
     IO(io::Error),
+    JsonDecode(json::DecoderError),
     NoFilePart,
     NoXFilename,
     RemoteOriginKeyNotFound(String),
@@ -49,6 +51,7 @@ impl fmt::Display for Error {
             Error::HTTP(ref e) => format!("{}", e),
             Error::HyperError(ref err) => format!("{}", err),
             Error::IO(ref e) => format!("{}", e),
+            Error::JsonDecode(ref e) => format!("Invalid response from a Depot: {}", e),
             Error::NoFilePart => {
                 format!("An invalid path was passed - we needed a filename, and this path does \
                          not have one")
@@ -81,6 +84,7 @@ impl error::Error for Error {
             Error::HTTP(_) => "Received an HTTP error",
             Error::HyperError(ref err) => err.description(),
             Error::IO(ref err) => err.description(),
+            Error::JsonDecode(_) => "Invalid JSON response from a Depot",
             Error::NoFilePart => {
                 "An invalid path was passed - we needed a filename, and this path does not have one"
             }
@@ -113,6 +117,12 @@ impl From<hyper::error::Error> for Error {
     }
 }
 
+impl From<json::DecoderError> for Error {
+    fn from(err: json::DecoderError) -> Error {
+        Error::JsonDecode(err)
+    }
+}
+
 impl From<io::Error> for Error {
     fn from(err: io::Error) -> Error {
         Error::IO(err)
@@ -28,14 +28,16 @@ extern crate url;
 pub mod error;
 
 pub use error::{Error, Result};
+pub use hab_http::SslSettings;
 
 use std::fs::{self, File};
 use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
 
 use broadcast::BroadcastWriter;
-use hab_core::package::{Identifiable, PackageArchive};
-use hab_http::new_hyper_client;
+use hab_core::crypto::keys::parse_name_with_rev;
+use hab_core::package::{Identifiable, PackageArchive, PackageIdent};
+use hab_http::new_hyper_client_with_ssl;
 use hyper::client::{Body, IntoUrl, Response};
 use hyper::status::StatusCode;
 use hyper::header::{Headers, Authorization, Bearer};
@@ -47,6 +49,9 @@ use tee::TeeReader;
 header! { (XFileName, "X-Filename") => [String] }
 header! { (ETag, "ETag") => [String] }
 
+/// The number of packages a Depot returns in one page of a listing or search.
+pub const PAGE_SIZE: usize = 50;
+
 pub trait DisplayProgress: Write {
     fn size(&mut self, size: u64);
 }
@@ -58,10 +63,18 @@ pub struct Client {
 
 impl Client {
     pub fn new<U: IntoUrl>(hab_depot_url: U, fs_root_path: Option<&Path>) -> Result<Self> {
+        Self::new_with_ssl(hab_depot_url, fs_root_path, &SslSettings::from_env())
+    }
+
+    /// Creates a client which uses the given SSL settings instead of the ones in the environment.
+    pub fn new_with_ssl<U: IntoUrl>(hab_depot_url: U,
+                                    fs_root_path: Option<&Path>,
+                                    ssl: &SslSettings)
+                                    -> Result<Self> {
         let url = try!(hab_depot_url.into_url());
         Ok(Client {
             depot_url: url.clone(),
-            client: try!(new_hyper_client(Some(&url), fs_root_path)),
+            client: try!(new_hyper_client_with_ssl(Some(&url), fs_root_path, ssl)),
         })
     }
 
@@ -82,6 +95,29 @@ impl Client {
         self.download(url, dst_path.as_ref(), progress)
     }
 
+    /// Download the revocation of a public key from a remote Depot to the given filepath.
+    ///
+    /// Returns `None` if the key has not been revoked.
+    ///
+    /// # Failures
+    ///
+    /// * Remote Depot is not available
+    /// * File cannot be created and written to
+    pub fn fetch_origin_key_revocation<P: AsRef<Path> + ?Sized>
+        (&self,
+         origin: &str,
+         revision: &str,
+         dst_path: &P,
+         progress: Option<&mut DisplayProgress>)
+         -> Result<Option<PathBuf>> {
+        let url = try!(self.url_join(&format!("origins/{}/keys/{}/revocation", origin, revision)));
+        match self.download(url, dst_path.as_ref(), progress) {
+            Ok(path) => Ok(Some(path)),
+            Err(Error::HTTP(StatusCode::NotFound)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
     pub fn show_origin_keys(&self, origin: &str) -> Result<Vec<depotsrv::OriginKeyIdent>> {
         let url = try!(self.url_join(&format!("origins/{}/keys", origin)));
         debug!("GET {} with {:?}", &url, &self.client);
@@ -144,6 +180,104 @@ impl Client {
         }
     }
 
+    /// Upload the revocation of a public origin key to a remote Depot.
+    ///
+    /// # Failures
+    ///
+    /// * Remote Depot is not available
+    /// * File cannot be read
+    ///
+    /// # Panics
+    ///
+    /// * Authorization token was not set on client
+    pub fn put_origin_key_revocation(&self,
+                                     origin: &str,
+                                     revision: &str,
+                                     src_path: &Path,
+                                     token: &str,
+                                     progress: Option<&mut DisplayProgress>)
+                                     -> Result<()> {
+        let mut headers = Headers::new();
+        headers.set(Authorization(Bearer { token: token.to_string() }));
+        let url = try!(self.url_join(&format!("origins/{}/keys/{}/revocation",
+                                              &origin,
+                                              &revision)));
+        let mut file = try!(File::open(src_path));
+        let file_size = try!(file.metadata()).len();
+        let result = if let Some(progress) = progress {
+            progress.size(file_size);
+            let mut reader = TeeReader::new(file, progress);
+            self.client
+                .post(url)
+                .headers(headers)
+                .body(Body::SizedBody(&mut reader, file_size))
+                .send()
+        } else {
+            self.client
+                .post(url)
+                .headers(headers)
+                .body(Body::SizedBody(&mut file, file_size))
+                .send()
+        };
+        match result {
+            Ok(Response { status: StatusCode::Created, .. }) => Ok(()),
+            Ok(Response { status: code, .. }) => Err(Error::HTTP(code)),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    /// Returns the revision of an origin key which a remote Depot holds as the current one, and
+    /// serves as the origin's latest key.
+    ///
+    /// # Failures
+    ///
+    /// * The origin has no key which has not been revoked
+    /// * Remote Depot is not available
+    pub fn current_origin_key_revision(&self, origin: &str) -> Result<String> {
+        let url = try!(self.url_join(&format!("origins/{}/keys/latest", origin)));
+        debug!("GET {} with {:?}", &url, &self.client);
+        let res = try!(self.client.get(url).send());
+        debug!("Response: {:?}", res);
+
+        match res.status {
+            StatusCode::Ok => (),
+            StatusCode::NotFound => {
+                return Err(Error::RemoteOriginKeyNotFound(origin.to_string()))
+            }
+            code => return Err(Error::HTTP(code)),
+        }
+        let file_name = match res.headers.get::<XFileName>() {
+            Some(filename) => format!("{}", filename),
+            None => return Err(Error::NoXFilename),
+        };
+        let (_, revision) = try!(parse_name_with_rev(file_name.trim_right_matches(".pub")));
+        Ok(revision)
+    }
+
+    /// Mark a revision of an origin key, already uploaded to a remote Depot, as the current one.
+    /// The Depot serves the current revision as the origin's latest key until another revision
+    /// is made current or it is revoked.
+    ///
+    /// # Failures
+    ///
+    /// * Key cannot be found
+    /// * Key has been revoked
+    /// * Remote Depot is not available
+    ///
+    /// # Panics
+    ///
+    /// * Authorization token was not set on client
+    pub fn set_current_origin_key(&self, origin: &str, revision: &str, token: &str) -> Result<()> {
+        let mut headers = Headers::new();
+        headers.set(Authorization(Bearer { token: token.to_string() }));
+        let url = try!(self.url_join(&format!("origins/{}/keys/{}/current", &origin, &revision)));
+        debug!("POST {} with {:?}", &url, &self.client);
+        match self.client.post(url).headers(headers).send() {
+            Ok(Response { status: StatusCode::Ok, .. }) => Ok(()),
+            Ok(Response { status: code, .. }) => Err(Error::HTTP(code)),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
 
     /// Upload a secret origin key to a remote Depot.
     ///
@@ -245,8 +379,103 @@ impl Client {
         Ok(package)
     }
 
+    /// Returns one page of the packages whose identifiers start with the given query, beginning
+    /// at the given offset into the results, along with the offset of the next page if there are
+    /// more results.
+    ///
+    /// # Failures
+    ///
+    /// * Remote Depot is not available
+    pub fn search_package(&self,
+                          query: &str,
+                          offset: usize)
+                          -> Result<(Vec<depotsrv::PackageIdent>, Option<usize>)> {
+        let url = try!(self.url_join(&format!("pkgs/search/{}", query)));
+        self.package_page(url, offset)
+    }
+
+    /// Returns every release of a package known to the Depot, following the pagination of the
+    /// listing. The version and release of the given identifier, if any, narrow the listing.
+    ///
+    /// # Failures
+    ///
+    /// * Package cannot be found
+    /// * Remote Depot is not available
+    pub fn list_package_versions<I: Identifiable>(&self,
+                                                  ident: &I)
+                                                  -> Result<Vec<depotsrv::PackageIdent>> {
+        let url = try!(self.url_join(&format!("pkgs/{}", ident)));
+        match self.all_pages(url) {
+            Err(Error::HTTP(StatusCode::NotFound)) => {
+                let ident = PackageIdent::new(ident.origin(),
+                                              ident.name(),
+                                              ident.version(),
+                                              ident.release());
+                Err(Error::RemotePackageNotFound(ident))
+            }
+            result => result,
+        }
+    }
+
+    /// Returns every release of every package in an origin known to the Depot, following the
+    /// pagination of the listing.
+    ///
+    /// # Failures
+    ///
+    /// * Remote Depot is not available
+    pub fn list_origin_packages(&self, origin: &str) -> Result<Vec<depotsrv::PackageIdent>> {
+        let url = try!(self.url_join(&format!("pkgs/{}", origin)));
+        match self.all_pages(url) {
+            Err(Error::HTTP(StatusCode::NotFound)) => Ok(Vec::new()),
+            result => result,
+        }
+    }
+
+    /// Returns the names of every view in the Depot.
+    ///
+    /// # Failures
+    ///
+    /// * Remote Depot is not available
+    pub fn list_views(&self) -> Result<Vec<String>> {
+        let url = try!(self.url_join("views"));
+        debug!("GET {} with {:?}", &url, &self.client);
+        let mut res = try!(self.client.get(url).send());
+
+        if res.status != hyper::status::StatusCode::Ok {
+            return Err(Error::HTTP(res.status));
+        }
+
+        let mut encoded = String::new();
+        try!(res.read_to_string(&mut encoded));
+        debug!("Body: {:?}", encoded);
+        Ok(try!(json::decode(&encoded)))
+    }
+
+    /// Returns the names of the views containing the given fully qualified package.
+    ///
+    /// # Failures
+    ///
+    /// * Remote Depot is not available
+    pub fn package_views<I: Identifiable>(&self, ident: &I) -> Result<Vec<String>> {
+        let mut views = Vec::new();
+        for view in try!(self.list_views()) {
+            let url = try!(self.url_join(&format!("views/{}/pkgs/{}", view, ident)));
+            debug!("GET {} with {:?}", &url, &self.client);
+            let res = try!(self.client.get(url).send());
+            match res.status {
+                StatusCode::Ok => views.push(view),
+                StatusCode::NotFound => (),
+                code => return Err(Error::HTTP(code)),
+            }
+        }
+        Ok(views)
+    }
+
     /// Upload a package to a remote Depot.
     ///
+    /// A release already in the Depot is only replaced by a copy of it with more signatures, such
+    /// as one countersigned with a new origin key.
+    ///
     /// # Failures
     ///
     /// * Remote Depot is not available
@@ -299,6 +528,49 @@ impl Client {
         }
     }
 
+    fn all_pages(&self, url: Url) -> Result<Vec<depotsrv::PackageIdent>> {
+        let mut packages: Vec<depotsrv::PackageIdent> = Vec::new();
+        let mut offset = Some(0);
+        while let Some(current) = offset {
+            let (page, next) = try!(self.package_page(url.clone(), current));
+            packages.extend(page);
+            offset = next.and_then(|n| if n > current { Some(n) } else { None });
+        }
+        Ok(packages)
+    }
+
+    fn package_page(&self,
+                    url: Url,
+                    offset: usize)
+                    -> Result<(Vec<depotsrv::PackageIdent>, Option<usize>)> {
+        debug!("GET {} from {} with {:?}", &url, offset, &self.client);
+        // The Depot reads a non-zero range as a 1-based offset and returns the inclusive range of
+        // entries from there, giving the 0-based offset following it as the next range
+        let range = if offset > 0 { offset + 1 } else { 0 };
+        let mut headers = Headers::new();
+        headers.set_raw("Range", vec![range.to_string().into_bytes()]);
+        let mut res = try!(self.client.get(url).headers(headers).send());
+        debug!("Response: {:?}", res);
+
+        let more = match res.status {
+            StatusCode::Ok => false,
+            StatusCode::PartialContent => true,
+            code => return Err(Error::HTTP(code)),
+        };
+        let next = if more {
+            hab_http::header(&res, "Next-Range")
+                .and_then(|value| value.trim().parse::<usize>().ok())
+        } else {
+            None
+        };
+
+        let mut encoded = String::new();
+        try!(res.read_to_string(&mut encoded));
+        debug!("Body: {:?}", encoded);
+        let packages: Vec<depotsrv::PackageIdent> = try!(json::decode(&encoded));
+        Ok((packages, next))
+    }
+
     fn download(&self,
                 url: Url,
                 dst_path: &Path,
@@ -342,3 +614,113 @@ impl Client {
         Ok(try!(self.depot_url.join(&format!("{}/{}", self.depot_url.path(), path))))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::cmp;
+    use std::net::SocketAddr;
+
+    use hab_http::testing;
+    use hyper::server::{Request, Response};
+    use hyper::status::StatusCode;
+    use protocol::depotsrv;
+    use rustc_serialize::json;
+
+    use super::{Client, Error, PAGE_SIZE};
+
+    fn ident(n: usize) -> depotsrv::PackageIdent {
+        let mut ident = depotsrv::PackageIdent::new();
+        ident.set_origin("core".to_string());
+        ident.set_name(format!("pkg{:03}", n));
+        ident.set_version("1.0.0".to_string());
+        ident.set_release("20160101000000".to_string());
+        ident
+    }
+
+    /// Serves a listing of `count` packages paginated as the Depot does, returning its address.
+    fn serve_packages(count: usize) -> SocketAddr {
+        testing::serve(move |req: Request, mut res: Response| {
+            let range = req.headers
+                .get_raw("Range")
+                .and_then(|values| values.first())
+                .and_then(|value| String::from_utf8(value.clone()).ok())
+                .and_then(|value| value.parse::<usize>().ok())
+                .unwrap_or(0);
+            let offset = if range > 0 { range - 1 } else { 0 };
+            let num = offset + PAGE_SIZE;
+            let page: Vec<depotsrv::PackageIdent> =
+                (offset..cmp::min(num + 1, count)).map(ident).collect();
+            if count >= num + 1 {
+                *res.status_mut() = StatusCode::PartialContent;
+                let next_range = vec![(num + 1).to_string().into_bytes()];
+                res.headers_mut().set_raw("Next-Range", next_range);
+            }
+            res.send(json::encode(&page).unwrap().as_bytes()).unwrap();
+        })
+    }
+
+    /// Serves the given revision of the core origin key as the latest one, or nothing at all.
+    fn serve_latest_key(revision: Option<&'static str>) -> SocketAddr {
+        testing::serve(move |req: Request, mut res: Response| {
+            match (revision, req.uri.to_string().ends_with("/origins/core/keys/latest")) {
+                (Some(revision), true) => {
+                    let file_name = format!("core-{}.pub", revision).into_bytes();
+                    res.headers_mut().set_raw("X-Filename", vec![file_name]);
+                    res.send(b"SIG-PUB-1").unwrap();
+                }
+                _ => *res.status_mut() = StatusCode::NotFound,
+            }
+        })
+    }
+
+    #[test]
+    fn current_origin_key_revision_is_the_latest_key_served() {
+        let addr = serve_latest_key(Some("20160614023044"));
+        let client = Client::new(&format!("http://{}/v1/depot", addr)[..], None).unwrap();
+        assert_eq!(client.current_origin_key_revision("core").unwrap(),
+                   "20160614023044");
+    }
+
+    #[test]
+    fn current_origin_key_revision_of_an_origin_without_keys() {
+        let addr = serve_latest_key(None);
+        let client = Client::new(&format!("http://{}/v1/depot", addr)[..], None).unwrap();
+        match client.current_origin_key_revision("core") {
+            Err(Error::RemoteOriginKeyNotFound(ref origin)) => assert_eq!(origin, "core"),
+            other => panic!("Expected RemoteOriginKeyNotFound, got {:?}", other),
+        }
+    }
+
+    fn names(packages: &[depotsrv::PackageIdent]) -> Vec<String> {
+        packages.iter().map(|p| p.get_name().to_string()).collect()
+    }
+
+    #[test]
+    fn search_package_pages_do_not_overlap() {
+        let addr = serve_packages(120);
+        let client = Client::new(&format!("http://{}/v1/depot", addr)[..], None).unwrap();
+
+        let (first, next) = client.search_package("core", 0).unwrap();
+        assert_eq!(first.len(), PAGE_SIZE + 1);
+        assert_eq!(next, Some(PAGE_SIZE + 1));
+
+        let (second, next) = client.search_package("core", next.unwrap()).unwrap();
+        assert_eq!(second[0].get_name(), format!("pkg{:03}", PAGE_SIZE + 1));
+        assert!(names(&second).iter().all(|name| !names(&first).contains(name)));
+        assert_eq!(next, Some(2 * (PAGE_SIZE + 1)));
+
+        let (third, next) = client.search_package("core", next.unwrap()).unwrap();
+        assert_eq!(third.len(), 120 - 2 * (PAGE_SIZE + 1));
+        assert_eq!(next, None);
+    }
+
+    #[test]
+    fn list_origin_packages_follows_every_page_once() {
+        let addr = serve_packages(120);
+        let client = Client::new(&format!("http://{}/v1/depot", addr)[..], None).unwrap();
+
+        let packages = client.list_origin_packages("core").unwrap();
+        let expected: Vec<String> = (0..120).map(|n| format!("pkg{:03}", n)).collect();
+        assert_eq!(names(&packages), expected);
+    }
+}
@@ -458,28 +458,68 @@ impl OriginKeysTable {
         Ok(())
     }
 
-    /// return the latest revision for a given origin key
+    /// record that a revision of an origin key has been revoked
+    pub fn revoke(&self, origin: &str, revision: &str) -> Result<()> {
+        let conn = self.pool().get().unwrap();
+        try!(conn.sadd(OriginKeysTable::revoked_key(origin), revision));
+        Ok(())
+    }
+
+    pub fn is_revoked(&self, origin: &str, revision: &str) -> Result<bool> {
+        let conn = self.pool().get().unwrap();
+        let revoked = try!(conn.sismember(OriginKeysTable::revoked_key(origin), revision));
+        Ok(revoked)
+    }
+
+    /// return the latest revision for a given origin key which has not been revoked
     pub fn latest(&self, origin: &str) -> Result<String> {
         let conn = self.pool().get().unwrap();
         let key = OriginKeysTable::key(&origin.to_string());
 
         match redis::cmd("SORT")
             .arg(key)
-            .arg("LIMIT")
-            .arg(0)
-            .arg(1)
             .arg("ALPHA")
             .arg("DESC")
             .query::<Vec<String>>(conn.deref()) {
             Ok(ids) => {
-                if ids.is_empty() {
-                    return Err(Error::DataStore(dbcache::Error::EntityNotFound));
+                let revoked: Vec<String> =
+                    try!(conn.smembers(OriginKeysTable::revoked_key(origin)));
+                match ids.into_iter().find(|id| !revoked.contains(id)) {
+                    Some(id) => Ok(id),
+                    None => Err(Error::DataStore(dbcache::Error::EntityNotFound)),
                 }
-                Ok(ids[0].to_string())
             }
             Err(e) => Err(Error::from(e)),
         }
     }
+
+    /// record which revision of an origin key is the current one, as chosen by a key rotation
+    pub fn set_current(&self, origin: &str, revision: &str) -> Result<()> {
+        let conn = self.pool().get().unwrap();
+        try!(conn.set(OriginKeysTable::current_key(origin), revision));
+        Ok(())
+    }
+
+    /// return the current revision of an origin key, falling back to the latest revision which
+    /// has not been revoked if no current revision was recorded or it has since been revoked
+    pub fn current(&self, origin: &str) -> Result<String> {
+        let conn = self.pool().get().unwrap();
+        let current: Option<String> = try!(conn.get(OriginKeysTable::current_key(origin)));
+        if let Some(revision) = current {
+            if !try!(self.is_revoked(origin, &revision)) {
+                return Ok(revision);
+            }
+        }
+        self.latest(origin)
+    }
+
+    fn revoked_key(origin: &str) -> String {
+        format!("{}:revoked", OriginKeysTable::key(&origin.to_string()))
+    }
+
+    fn current_key(origin: &str) -> String {
+        format!("{}:current", OriginKeysTable::key(&origin.to_string()))
+    }
 }
 
 impl Bucket for OriginKeysTable {
@@ -37,6 +37,7 @@ pub enum Error {
     NoXFilename,
     NoFilePart,
     NulError(ffi::NulError),
+    OriginKeyNotFound(String),
     RemotePackageNotFound(package::PackageIdent),
     WriteSyncFailed,
 }
@@ -65,6 +66,7 @@ impl fmt::Display for Error {
                          not have one")
             }
             Error::NulError(ref e) => format!("{}", e),
+            Error::OriginKeyNotFound(ref e) => format!("The Depot has no origin key {}", e),
             Error::RemotePackageNotFound(ref pkg) => {
                 if pkg.fully_qualified() {
                     format!("Cannot find package in any sources: {}", pkg)
@@ -95,6 +97,7 @@ impl error::Error for Error {
             Error::NulError(_) => {
                 "An attempt was made to build a CString with a null byte inside it"
             }
+            Error::OriginKeyNotFound(_) => "The Depot has no such origin key",
             Error::RemotePackageNotFound(_) => "Cannot find a package in any sources",
             Error::NoXFilename => "Invalid download from a Depot - missing X-Filename header",
             Error::NoFilePart => {
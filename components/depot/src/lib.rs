@@ -53,11 +53,13 @@ pub use self::config::Config;
 pub use self::error::{Error, Result};
 
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
 use std::fs;
 use std::path::{Path, PathBuf};
 
 use crypto::sha2::Sha256;
 use crypto::digest::Digest;
+use hab_core::crypto::KEY_REVOCATION_SUFFIX;
 use hab_core::package::{Identifiable, PackageArchive};
 use data_store::DataStore;
 
@@ -65,6 +67,8 @@ use hab_net::oauth::github::GitHubClient;
 use hab_net::server::NetIdent;
 use hab_net::routing::BrokerContext;
 
+static STAGING_COUNT: AtomicUsize = ATOMIC_USIZE_INIT;
+
 pub struct Depot {
     pub config: Config,
     pub datastore: DataStore,
@@ -123,6 +127,19 @@ impl Depot {
             .join(format!("{}-{}.pub", key, rev))
     }
 
+    fn key_revocation_path(&self, key: &str, rev: &str) -> PathBuf {
+        self.key_path(key, rev).with_extension(KEY_REVOCATION_SUFFIX)
+    }
+
+    // Return a new directory path, unique to this process and call, under which an upload can
+    // be staged without colliding with any other upload.
+    fn staging_path(&self) -> PathBuf {
+        let count = STAGING_COUNT.fetch_add(1, Ordering::SeqCst);
+        Path::new(&self.config.path)
+            .join("staging")
+            .join(format!("{}-{}", unsafe { libc::getpid() }, count))
+    }
+
     fn keys_path(&self) -> PathBuf {
         Path::new(&self.config.path).join("keys")
     }
@@ -23,7 +23,7 @@ use bodyparser;
 use dbcache::{self, BasicSet, IndexSet};
 use hab_core::package::{Identifiable, FromArchive, PackageArchive};
 use hab_core::crypto::keys::{self, PairType};
-use hab_core::crypto::SigKeyPair;
+use hab_core::crypto::{artifact, KeyRevocation, SigKeyPair};
 use hab_net;
 use hab_net::config::RouteAddrs;
 use hab_net::routing::{Broker, BrokerContext};
@@ -619,6 +619,144 @@ fn upload_origin_key(depot: &Depot, req: &mut Request) -> IronResult<Response> {
     Ok(response)
 }
 
+fn upload_origin_key_revocation(depot: &Depot, req: &mut Request) -> IronResult<Response> {
+    debug!("Upload Origin Key Revocation {:?}", req);
+
+    // this lets us get around ownership/mutability issues
+    fn get_origin_and_revision(req: &mut Request) -> Option<(String, String)> {
+        let params = req.extensions.get::<Router>().unwrap();
+        let origin = params.find("origin").map(|s| s.to_string());
+        let revision = params.find("revision").map(|s| s.to_string());
+        match (origin, revision) {
+            (None, _) => None,
+            (_, None) => None,
+            (Some(origin), Some(revision)) => Some((origin, revision)),
+        }
+    }
+
+    let (origin, revision) = match get_origin_and_revision(req) {
+        Some((origin, revision)) => (origin, revision),
+        None => return Ok(Response::with(status::BadRequest)),
+    };
+
+    if !depot.config.insecure {
+        let session = match authenticate(depot, req) {
+            Ok(session) => session,
+            Err(response) => return Ok(response),
+        };
+
+        if !check_origin_access(&depot, session.get_id(), &origin) {
+            return Ok(Response::with(status::Forbidden));
+        }
+    }
+
+    let mut content = String::new();
+    if let Err(e) = req.body.read_to_string(&mut content) {
+        debug!("Can't read key revocation upload content: {}", e);
+        return Ok(Response::with(status::BadRequest));
+    }
+
+    let revocation = match KeyRevocation::parse_str(&content) {
+        Ok(revocation) => revocation,
+        Err(e) => {
+            debug!("Invalid key revocation content: {}", e);
+            return Ok(Response::with(status::BadRequest));
+        }
+    };
+    if revocation.name_with_rev() != format!("{}-{}", &origin, &revision) {
+        debug!("Key revocation is for {}, not {}-{}",
+               revocation.name_with_rev(),
+               &origin,
+               &revision);
+        return Ok(Response::with(status::BadRequest));
+    }
+
+    let origin_keyfile = depot.key_path(&origin, &revision);
+    let mut key_content = String::new();
+    if let Err(e) = File::open(&origin_keyfile)
+        .and_then(|mut f| f.read_to_string(&mut key_content)) {
+        debug!("Can't read key file {}: {}",
+               &origin_keyfile.to_string_lossy(),
+               e);
+        return Ok(Response::with(status::NotFound));
+    }
+    if let Err(e) = revocation.verify_with_key_str(&key_content) {
+        debug!("Key revocation failed verification: {}", e);
+        return Ok(Response::with(status::UnprocessableEntity));
+    }
+
+    // An existing revocation is only ever replaced by one which takes effect earlier
+    let revocation_file = depot.key_revocation_path(&origin, &revision);
+    let mut existing = String::new();
+    if File::open(&revocation_file).and_then(|mut f| f.read_to_string(&mut existing)).is_ok() {
+        match KeyRevocation::parse_str(&existing) {
+            Ok(ref e) if e.revoked_at() <= revocation.revoked_at() => {
+                return Ok(Response::with(status::Conflict));
+            }
+            _ => (),
+        }
+    }
+    debug!("Writing key revocation file {}",
+           revocation_file.to_string_lossy());
+    try!(write_string_to_file(&revocation_file, content));
+
+    // don't write to Redis if the file wasn't written
+    depot.datastore.origin_keys.revoke(&origin, &revision).unwrap();
+
+    Ok(Response::with((status::Created,
+                       format!("/origins/{}/keys/{}/revocation", &origin, &revision))))
+}
+
+fn set_current_origin_key(depot: &Depot, req: &mut Request) -> IronResult<Response> {
+    debug!("Set Current Origin Key {:?}", req);
+
+    // this lets us get around ownership/mutability issues
+    fn get_origin_and_revision(req: &mut Request) -> Option<(String, String)> {
+        let params = req.extensions.get::<Router>().unwrap();
+        let origin = params.find("origin").map(|s| s.to_string());
+        let revision = params.find("revision").map(|s| s.to_string());
+        match (origin, revision) {
+            (None, _) => None,
+            (_, None) => None,
+            (Some(origin), Some(revision)) => Some((origin, revision)),
+        }
+    }
+
+    let (origin, revision) = match get_origin_and_revision(req) {
+        Some((origin, revision)) => (origin, revision),
+        None => return Ok(Response::with(status::BadRequest)),
+    };
+
+    if !depot.config.insecure {
+        let session = match authenticate(depot, req) {
+            Ok(session) => session,
+            Err(response) => return Ok(response),
+        };
+
+        if !check_origin_access(&depot, session.get_id(), &origin) {
+            return Ok(Response::with(status::Forbidden));
+        }
+    }
+
+    if !depot.key_path(&origin, &revision).is_file() {
+        return Ok(Response::with(status::NotFound));
+    }
+    match depot.datastore.origin_keys.is_revoked(&origin, &revision) {
+        Ok(false) => (),
+        Ok(true) => {
+            debug!("Origin key {}-{} is revoked", &origin, &revision);
+            return Ok(Response::with(status::UnprocessableEntity));
+        }
+        Err(e) => {
+            error!("set_current_origin_key:1, err={:?}", e);
+            return Ok(Response::with(status::InternalServerError));
+        }
+    }
+    depot.datastore.origin_keys.set_current(&origin, &revision).unwrap();
+
+    Ok(Response::with((status::Ok, format!("/origins/{}/keys/{}", &origin, &revision))))
+}
+
 fn upload_origin_secret_key(depot: &Depot, req: &mut Request) -> IronResult<Response> {
     debug!("Upload Origin Secret Key {:?}", req);
     let session = match authenticate(depot, req) {
@@ -726,58 +864,138 @@ fn upload_package(depot: &Depot, req: &mut Request) -> IronResult<Response> {
     }
 
 
-    match depot.datastore.packages.find(&ident) {
+    let replacing = match depot.datastore.packages.find(&ident) {
         Ok(_) |
-        Err(dbcache::Error::EntityNotFound) => {
-            if let Some(_) = depot.archive(&ident) {
-                return Ok(Response::with((status::Conflict)));
-            }
-        }
+        Err(dbcache::Error::EntityNotFound) => depot.archive(&ident).is_some(),
         Err(e) => {
             error!("upload_package:1, err={:?}", e);
             return Ok(Response::with(status::InternalServerError));
         }
-    }
+    };
 
     let filename = depot.archive_path(&ident);
+    if replacing {
+        // The upload is staged in a directory of its own, so that concurrent uploads don't
+        // collide, and the directory is removed whatever the outcome
+        let staging = depot.staging_path();
+        let response =
+            replace_package(depot, req, &ident, &checksum_from_param, &filename, &staging);
+        if let Err(e) = fs::remove_dir_all(&staging) {
+            debug!("Could not remove staging directory {:?}: {:?}", staging, e);
+        }
+        return response;
+    }
     try!(write_file(&filename, &mut req.body));
-    let mut archive = PackageArchive::new(filename);
+    match checked_package(&ident, &checksum_from_param, &filename) {
+        Ok(object) => Ok(package_created(depot, req, object)),
+        Err(response) => Ok(response),
+    }
+}
+
+// An existing release is only replaced by a copy of it with more signatures, every one of which
+// must verify against the origin keys held by the Depot.
+fn replace_package(depot: &Depot,
+                   req: &mut Request,
+                   ident: &depotsrv::PackageIdent,
+                   checksum_from_param: &str,
+                   filename: &PathBuf,
+                   staging: &PathBuf)
+                   -> IronResult<Response> {
+    let upload_filename = staging.join(filename.file_name().unwrap());
+    try!(write_file(&upload_filename, &mut req.body));
+    match artifact::adds_signatures(filename, &upload_filename) {
+        Ok(true) => (),
+        Ok(false) => return Ok(Response::with(status::Conflict)),
+        Err(e) => {
+            info!("Could not compare {:?} with the existing archive: {:?}",
+                  upload_filename,
+                  e);
+            return Ok(Response::with(status::UnprocessableEntity));
+        }
+    }
+    let key_cache = staging.join("keys");
+    if let Err(e) = verify_with_depot_keys(depot, &upload_filename, &key_cache) {
+        info!("Could not verify the signatures of {:?}: {:?}", upload_filename, e);
+        return Ok(Response::with(status::UnprocessableEntity));
+    }
+    let object = match checked_package(ident, checksum_from_param, &upload_filename) {
+        Ok(object) => object,
+        Err(response) => return Ok(response),
+    };
+    try!(fs::rename(&upload_filename, filename));
+    Ok(package_created(depot, req, object))
+}
+
+// Verifies every signature of an artifact against the public keys and revocations held by the
+// Depot, copying those of its signers into a key cache which is only used for this check.
+fn verify_with_depot_keys(depot: &Depot,
+                          artifact_path: &PathBuf,
+                          key_cache: &PathBuf)
+                          -> Result<()> {
+    try!(fs::create_dir_all(key_cache));
+    for signer in try!(artifact::artifact_signers(artifact_path)) {
+        let (origin, revision) = try!(keys::parse_name_with_rev(&signer));
+        let key_path = depot.key_path(&origin, &revision);
+        if !key_path.is_file() {
+            return Err(Error::OriginKeyNotFound(signer));
+        }
+        try!(fs::copy(&key_path, key_cache.join(key_path.file_name().unwrap())));
+        let revocation_path = depot.key_revocation_path(&origin, &revision);
+        if revocation_path.is_file() {
+            try!(fs::copy(&revocation_path,
+                          key_cache.join(revocation_path.file_name().unwrap())));
+        }
+    }
+    try!(artifact::verify(artifact_path, key_cache));
+    Ok(())
+}
+
+// Returns the package described by an uploaded artifact, or the response to give if the artifact
+// doesn't match the checksum and identifier it was uploaded with.
+fn checked_package(ident: &depotsrv::PackageIdent,
+                   checksum_from_param: &str,
+                   filename: &PathBuf)
+                   -> result::Result<depotsrv::Package, Response> {
+    let mut archive = PackageArchive::new(filename.clone());
     debug!("Package Archive: {:#?}", archive);
     let checksum_from_artifact = match archive.checksum() {
         Ok(cksum) => cksum,
         Err(e) => {
             info!("Could not compute a checksum for {:#?}: {:#?}", archive, e);
-            return Ok(Response::with(status::UnprocessableEntity));
+            return Err(Response::with(status::UnprocessableEntity));
         }
     };
     if checksum_from_param != checksum_from_artifact {
         info!("Checksums did not match: from_param={:?}, from_artifact={:?}",
               checksum_from_param,
               checksum_from_artifact);
-        return Ok(Response::with(status::UnprocessableEntity));
+        return Err(Response::with(status::UnprocessableEntity));
     }
     let object = match depotsrv::Package::from_archive(&mut archive) {
         Ok(object) => object,
         Err(e) => {
             info!("Error building package from archive: {:#?}", e);
-            return Ok(Response::with(status::UnprocessableEntity));
+            return Err(Response::with(status::UnprocessableEntity));
         }
     };
-    if ident.satisfies(object.get_ident()) {
-        depot.datastore.packages.write(&object).unwrap();
-        let mut response = Response::with((status::Created,
-                                           format!("/pkgs/{}/download", object.get_ident())));
-        let mut base_url = req.url.clone();
-        base_url.path =
-            vec![String::from("pkgs"), object.get_ident().to_string(), String::from("download")];
-        response.headers.set(headers::Location(format!("{}", base_url)));
-        Ok(response)
-    } else {
+    if !ident.satisfies(object.get_ident()) {
         info!("Ident mismatch, expected={:?}, got={:?}",
               ident,
               object.get_ident());
-        Ok(Response::with(status::UnprocessableEntity))
+        return Err(Response::with(status::UnprocessableEntity));
     }
+    Ok(object)
+}
+
+fn package_created(depot: &Depot, req: &Request, object: depotsrv::Package) -> Response {
+    depot.datastore.packages.write(&object).unwrap();
+    let mut response = Response::with((status::Created,
+                                       format!("/pkgs/{}/download", object.get_ident())));
+    let mut base_url = req.url.clone();
+    base_url.path =
+        vec![String::from("pkgs"), object.get_ident().to_string(), String::from("download")];
+    response.headers.set(headers::Location(format!("{}", base_url)));
+    response
 }
 
 fn download_origin_key(depot: &Depot, req: &mut Request) -> IronResult<Response> {
@@ -823,6 +1041,42 @@ fn download_origin_key(depot: &Depot, req: &mut Request) -> IronResult<Response>
     Ok(response)
 }
 
+fn download_origin_key_revocation(depot: &Depot, req: &mut Request) -> IronResult<Response> {
+    debug!("Download origin key revocation {:?}", req);
+    let params = req.extensions.get::<Router>().unwrap();
+
+    let origin = match params.find("origin") {
+        Some(origin) => origin,
+        None => return Ok(Response::with(status::BadRequest)),
+    };
+
+    let revision = match params.find("revision") {
+        Some(revision) => revision,
+        None => return Ok(Response::with(status::BadRequest)),
+    };
+    debug!("Trying to retreive origin key revocation {}-{}",
+           &origin,
+           &revision);
+    let revocation_file = depot.key_revocation_path(&origin, &revision);
+    debug!("Looking for {}", &revocation_file.to_string_lossy());
+    if !revocation_file.is_file() {
+        return Ok(Response::with(status::NotFound));
+    }
+
+    let xfilename = revocation_file.file_name().unwrap().to_string_lossy().into_owned();
+    let mut response = Response::with((status::Ok, revocation_file));
+    // use set_raw because we're having problems with Iron's Hyper 0.8.x
+    // and the newer Hyper 0.9.4. TODO: change back to set() once
+    // Iron updates to Hyper 0.9.x.
+    response.headers.set_raw("X-Filename", vec![xfilename.clone().into_bytes()]);
+    response.headers.set_raw("content-disposition",
+                             vec![format!("attachment; filename=\"{}\"", xfilename.clone())
+                                      .into_bytes()]);
+    // a revocation can be replaced by an earlier one, so it must not be cached
+    dont_cache_response(&mut response);
+    Ok(response)
+}
+
 fn download_latest_origin_key(depot: &Depot, req: &mut Request) -> IronResult<Response> {
     debug!("Download latest origin key {:?}", req);
     let params = req.extensions.get::<Router>().unwrap();
@@ -832,7 +1086,13 @@ fn download_latest_origin_key(depot: &Depot, req: &mut Request) -> IronResult<Re
         None => return Ok(Response::with(status::BadRequest)),
     };
     debug!("Trying to retreive latest origin key for {}", &origin);
-    let latest_rev = depot.datastore.origin_keys.latest(&origin).unwrap();
+    let latest_rev = match depot.datastore.origin_keys.current(&origin) {
+        Ok(rev) => rev,
+        Err(e) => {
+            debug!("No unrevoked origin key for {}: {:?}", &origin, e);
+            return Ok(Response::with(status::NotFound));
+        }
+    };
     let origin_keyfile = depot.key_path(&origin, &latest_rev);
     debug!("Looking for {}", &origin_keyfile.to_string_lossy());
     match origin_keyfile.metadata() {
@@ -1266,6 +1526,9 @@ pub fn router(depot: Arc<Depot>) -> Result<Chain> {
     let depot25 = depot.clone();
     let depot26 = depot.clone();
     let depot27 = depot.clone();
+    let depot28 = depot.clone();
+    let depot29 = depot.clone();
+    let depot30 = depot.clone();
 
     let router = router!(
         get "/views" => move |r: &mut Request| list_views(&depot1, r),
@@ -1334,6 +1597,15 @@ pub fn router(depot: Arc<Depot>) -> Result<Chain> {
         get "/origins/:origin/users" => {
             move |r: &mut Request| list_origin_members(&depot27, r)
         },
+        get "/origins/:origin/keys/:revision/revocation" => {
+            move |r: &mut Request| download_origin_key_revocation(&depot28, r)
+        },
+        post "/origins/:origin/keys/:revision/revocation" => {
+            move |r: &mut Request| upload_origin_key_revocation(&depot29, r)
+        },
+        post "/origins/:origin/keys/:revision/current" => {
+            move |r: &mut Request| set_current_origin_key(&depot30, r)
+        },
     );
     let mut chain = Chain::new(router);
     chain.link_after(Cors);
@@ -197,6 +197,7 @@ use http_client;
 use url::percent_encoding::{utf8_percent_encode, PATH_SEGMENT_ENCODE_SET};
 use uuid::Uuid;
 
+use config;
 use error::Result;
 
 /// The Google Analytics [Tracking
@@ -493,15 +494,17 @@ fn send_event(payload: &str) -> bool {
     // Create a new Hyper HTTP client, using a helper from the `habitat_http_client` crate. This
     // function is reponsible for setting up the SSL context, finding suitable SSL root certificate
     // files, etc. The `None` references are for more advanced use cases which is the Rust way of
-    // saying: "I'm giving you nothing for this value, as opposed to something".
+    // saying: "I'm giving you nothing for this value, as opposed to something". Any additional CA
+    // certificates and client certificate come from the environment or the CLI config.
     //
-    // The `new_hyper_client` function returns a `Result` structure which can either be `Ok`, or
+    // The `new_hyper_client_with_ssl` function returns a `Result` structure which can either be `Ok`, or
     // can contain an error (`Err`). The `Ok` pattern matching arm will return the actual
     // "unwraped" client from the expresssion and setting the client variable binding. The `Err`
     // matching arm is when something (or anything) goes wrong. In this case we absolutely do not
     // want to crash, panic this thread, or otherwise impact the real operation potentially running
     // concurrently. So, the strategy here is to report and early return.
-    let client = match http_client::new_hyper_client(None, None) {
+    let ssl = config::ssl_settings();
+    let client = match http_client::new_hyper_client_with_ssl(None, None, &ssl) {
         Ok(c) => c,
         Err(e) => {
             debug!("Error create HTTP client: {}", e);
@@ -16,7 +16,10 @@ use std::path::Path;
 use std::result;
 use std::str::FromStr;
 
-use clap::{App, AppSettings, Arg};
+use clap::{App, AppSettings, Arg, SubCommand};
+use common::local_depot::{INDEX_FILE, LocalDepot};
+use hcore::crypto::artifact::Compression;
+use hcore::crypto::hash::HashType;
 use hcore::crypto::keys::PairType;
 use regex::Regex;
 use url::Url;
@@ -42,6 +45,9 @@ pub fn get() -> App<'static, 'static> {
         (author: "\nAuthors: The Habitat Maintainers <humans@habitat.sh>\n")
         (@setting VersionlessSubcommands)
         (@setting ArgRequiredElseHelp)
+        (@arg OUTPUT_FORMAT: --format +takes_value +global {valid_output_format}
+            "Output format; json writes one JSON document describing the result, dot writes a \
+            Graphviz digraph from pkg list, deps and rdeps (ex: text, json, dot, default: text)")
         (@subcommand cli =>
             (about: "Commands relating to Habitat runtime config")
             (aliases: &["cl"])
@@ -53,11 +59,59 @@ pub fn get() -> App<'static, 'static> {
             (aliases: &["co", "con", "conf", "confi"])
             (@setting ArgRequiredElseHelp)
             (subcommand: sub_config_apply().aliases(&["a", "ap", "app", "appl"]))
+            (@subcommand history =>
+                (about: "Lists the configuration versions a Supervisor has applied")
+                (aliases: &["h", "hi", "his", "hist", "histo", "histor"])
+                (@arg SERVICE_GROUP: +required {valid_service_group}
+                    "Target service group (ex: redis.default)")
+                (@arg SUPERVISOR: -s --supervisor +takes_value
+                    "The Habitat Supervisor to query (default: 127.0.0.1:9631)")
+                (@arg ORG: --org +takes_value "Name of service organization")
+            )
+            (@subcommand rollback =>
+                (about: "Re-applies an earlier configuration version under a new version number")
+                (aliases: &["r", "ro", "rol", "roll", "rollb", "rollba", "rollbac"])
+                (@arg SERVICE_GROUP: +required {valid_service_group}
+                    "Target service group (ex: redis.default)")
+                (@arg VERSION_NUMBER: +required
+                    "The configuration version to roll back to (ex: 41)")
+                (@arg NEW_VERSION_NUMBER: +required
+                    "A version number (positive integer) to re-apply it under, higher than the \
+                    ring's current version (ex: 45)")
+                (@arg SUPERVISOR: -s --supervisor +takes_value
+                    "The Habitat Supervisor whose history holds the version \
+                    (default: 127.0.0.1:9631)")
+                (@arg PEER: -p --peer +takes_value
+                    "A comma-delimited list of one or more Habitat Supervisor peers to infect \
+                    (default: 127.0.0.1:9634)")
+                (@arg RING: -r --ring +takes_value
+                    "Ring key name, which will encrypt communication messages")
+                (@arg ORG: --org +takes_value "Name of service organization")
+            )
         )
         (@subcommand file =>
             (about: "Commands relating to Habitat files")
             (aliases: &["f", "fi", "fil"])
             (@setting ArgRequiredElseHelp)
+            (@subcommand download =>
+                (about: "Download a file gossiped to a Supervisor's service group")
+                (aliases: &["d", "do", "dow", "down", "downl", "downlo", "downloa"])
+                (@arg SERVICE_GROUP: +required {valid_service_group}
+                    "Service group the file was gossiped to (ex: redis.default)")
+                (@arg FILE: +required +takes_value "Name of the gossiped file (ex: app.conf)")
+                (@arg DEST: +takes_value
+                    "Path to write the file to (default: the file's name in the current directory)")
+                (@arg PEER: -p --peer +takes_value
+                    "The Habitat Supervisor to download from (default: 127.0.0.1:9631)")
+            )
+            (@subcommand list =>
+                (about: "List the files gossiped to a Supervisor's service group")
+                (aliases: &["l", "li", "lis"])
+                (@arg SERVICE_GROUP: +required {valid_service_group}
+                    "Target service group (ex: redis.default)")
+                (@arg PEER: -p --peer +takes_value
+                    "The Habitat Supervisor to query (default: 127.0.0.1:9631)")
+            )
             (@subcommand upload =>
                 (about: "Upload a file to the supervisor ring.")
                 (aliases: &["u", "up", "upl", "uplo", "uploa"])
@@ -96,16 +150,46 @@ pub fn get() -> App<'static, 'static> {
                     (@arg ORIGIN: +required +takes_value)
                     (@arg PAIR_TYPE: -t --type +takes_value +required {valid_pair_type}
                     "Export either the `public' or `secret' key")
+                    (@arg ENCRYPT: -e --encrypt conflicts_with[DECRYPT]
+                        "Encrypt the exported secret key with a passphrase")
+                    (@arg DECRYPT: -d --decrypt conflicts_with[ENCRYPT]
+                        "Export the secret key unencrypted")
                 )
                 (@subcommand generate =>
                     (about: "Generates a Habitat origin key")
                     (aliases: &["g", "ge", "gen", "gene", "gener", "genera", "generat"])
                     (@arg ORIGIN: "The origin name")
+                    (@arg ENCRYPT: -e --encrypt "Encrypt the secret key with a passphrase")
                 )
                 (@subcommand import =>
                     (about: "Reads a stdin stream containing a public or secret origin key \
                         contents and writes the key to disk")
                     (aliases: &["i", "im", "imp", "impo", "impor"])
+                    (@arg ENCRYPT: -e --encrypt
+                        "Encrypt an imported secret key with a passphrase")
+                )
+                (@subcommand revoke =>
+                    (about: "Revokes an origin key revision and uploads the revocation to the \
+                        depot")
+                    (aliases: &["r", "re", "rev", "revo", "revok"])
+                    (@arg ORIGIN: +required "The origin name")
+                    (@arg REVISION: +required "The key revision")
+                    (@arg DEPOT_URL: -u --url +takes_value {valid_url} "Use a specific Depot URL")
+                    (@arg AUTH_TOKEN: -z --auth +takes_value "Authentication token for the Depot")
+                )
+                (@subcommand rotate =>
+                    (about: "Generates a new origin key revision, uploads it and makes it the \
+                        depot's current key")
+                    (aliases: &["ro", "rot", "rota", "rotat"])
+                    (@arg ORIGIN: +required "The origin name")
+                    (@arg ENCRYPT: -e --encrypt "Encrypt the secret key with a passphrase")
+                    (@arg WITH_SECRET: -s --secret
+                        "Upload secret key in addition to the public key")
+                    (@arg RESIGN: --resign
+                        "Countersign the latest release of each package in the origin with the \
+                        new key and upload it again")
+                    (@arg DEPOT_URL: -u --url +takes_value {valid_url} "Use a specific Depot URL")
+                    (@arg AUTH_TOKEN: -z --auth +takes_value "Authentication token for the Depot")
                 )
                 (@subcommand upload =>
                     (@group upload =>
@@ -140,6 +224,46 @@ pub fn get() -> App<'static, 'static> {
                     "Sets the destination directory (default: /bin)")
             )
             (subcommand: sub_pkg_build())
+            (@subcommand bundle =>
+                (about: "Commands relating to bundles of packages for hosts without a Depot")
+                (aliases: &["bun", "bund", "bundl"])
+                (@setting ArgRequiredElseHelp)
+                (@subcommand create =>
+                    (about: "Writes a bundle of a package, its dependencies and their origin keys")
+                    (aliases: &["c", "cr", "cre", "crea", "creat"])
+                    (@arg PKG_IDENT: +required +takes_value
+                        "A package identifier (ex: core/redis, core/busybox-static/1.42.2)")
+                    (@arg DEPOT_URL: -u --url +takes_value {valid_depot_url}
+                        "Use a specific Depot URL, or a local depot directory \
+                        (ex: file:///mnt/depot)")
+                )
+                (@subcommand install =>
+                    (about: "Verifies and installs every package in a bundle without a Depot")
+                    (aliases: &["i", "in", "ins", "inst", "insta", "instal"])
+                    (@arg SOURCE: +required {file_exists}
+                        "A path to a bundle \
+                        (ex: /home/acme-redis-3.0.7-21120102031201-bundle.tar)")
+                )
+            )
+            (@subcommand deps =>
+                (about: "Prints the dependency tree of an installed package")
+                (aliases: &["d", "de", "dep"])
+                (@arg PKG_IDENT: +required +takes_value
+                    "A package identifier (ex: core/redis, core/busybox-static/1.42.2)")
+                (@arg FORMAT: -f +takes_value {valid_output_format}
+                    "Same as --format (ex: text, json, dot, default: text)")
+            )
+            (@subcommand diff =>
+                (about: "Compares two releases of a package, installed or as Habitat Artifacts")
+                (aliases: &["di", "dif"])
+                (@arg PKG_A: +required +takes_value
+                    "A package identifier or a path to a Habitat Artifact \
+                    (ex: core/redis/3.0.7/21120102031201, \
+                    /home/core-redis-3.0.7-21120102031201-x86_64-linux.hart)")
+                (@arg PKG_B: +required +takes_value
+                    "A package identifier or a path to a Habitat Artifact to compare with")
+                (@arg PATCH: -p --patch "Shows a unified diff of each changed text file")
+            )
             (@subcommand exec =>
                 (about: "Executes a command using the 'PATH' context of an installed package")
                 (aliases: &["exe"])
@@ -150,30 +274,94 @@ pub fn get() -> App<'static, 'static> {
                 (@arg ARGS: +takes_value +multiple
                     "Arguments to the command (ex: -l /tmp)")
             )
-            (@subcommand export =>
-                (about: "Exports the package to the specified format")
-                (aliases: &["exp"])
-                (@arg FORMAT: +required +takes_value "The export format (ex: docker, aci)")
-                (@arg PKG_IDENT: +required +takes_value
-                    "A package identifier (ex: core/redis, core/busybox-static/1.42.2)")
+            (@subcommand countersign =>
+                (about: "Adds a signature made with an origin key to a Habitat Artifact")
+                (aliases: &["cou", "coun", "count", "counte", "counter"])
+                (@arg ORIGIN: --origin +takes_value "Origin key used to create signature")
+                (@arg DEPOT_URL: -u --url +takes_value {valid_url}
+                    "Use the current origin key of a specific Depot URL")
+                (@arg SOURCE: +required {file_exists}
+                    "A path to a signed Habitat Artifact \
+                    (ex: /home/acme-redis-3.0.7-21120102031201-x86_64-linux.hart)")
+                (@arg DEST: +required
+                    "The destination path to the countersigned Habitat Artifact \
+                    (ex: /home/release/acme-redis-3.0.7-21120102031201-x86_64-linux.hart)")
             )
+            (subcommand: sub_pkg_export())
+            (subcommand: sub_pkg_gc())
             (@subcommand hash =>
-                (about: "Generates a blake2b hashsum from a target at any given filepath")
+                (about: "Generates a hashsum from a target at any given filepath")
                 (aliases: &["ha", "has"])
+                (@arg HASH_TYPE: -a --algorithm +takes_value {valid_hash_type}
+                    "Hash algorithm to use (ex: blake2b, sha256, default: blake2b)")
                 (@arg SOURCE: +required {file_exists} "A filepath of the target")
             )
+            (@subcommand info =>
+                (about: "Shows the Depot's metadata for a package and its available versions")
+                (aliases: &["inf"])
+                (@arg PKG_IDENT: +required +takes_value
+                    "A package identifier (ex: core/redis, core/busybox-static/1.42.2)")
+                (@arg DEPOT_URL: -u --url +takes_value {valid_url} "Use a specific Depot URL")
+            )
+            (@subcommand inspect =>
+                (about: "Lists the files in a Habitat Artifact or writes one of them to stdout")
+                (aliases: &["insp", "inspe", "inspec"])
+                (@arg SOURCE: +required {file_exists}
+                    "A path to a Habitat Artifact \
+                    (ex: /home/acme-redis-3.0.7-21120102031201-x86_64-linux.hart)")
+                (@arg FILE: +takes_value
+                    "The path of a file in the artifact to write to stdout \
+                    (ex: /hab/pkgs/acme/redis/3.0.7/21120102031201/MANIFEST)")
+            )
+            (@subcommand index =>
+                (about: "Indexes a directory of Habitat Artifacts for use as a local depot")
+                (aliases: &["ind", "inde"])
+                (@arg DIR: +required {dir_exists}
+                    "A path to a directory of Habitat Artifacts (ex: /mnt/depot)")
+            )
             (subcommand: sub_pkg_install().aliases(
                 &["i", "in", "ins", "inst", "insta", "instal"]))
+            (@subcommand list =>
+                (about: "Lists installed packages")
+                (aliases: &["l", "li", "lis"])
+                (@arg ORIGIN: -o --origin +takes_value "Only list packages of this origin")
+                (@arg NAME: -n --name +takes_value "Only list packages with this name")
+                (@arg FORMAT: -f +takes_value {valid_output_format}
+                    "Same as --format (ex: text, json, dot, default: text)")
+            )
             (@subcommand path =>
                 (about: "Prints the path to a specific installed release of a package")
                 (aliases: &["p", "pa", "pat"])
                 (@arg PKG_IDENT: +required +takes_value
                     "A package identifier (ex: core/redis, core/busybox-static/1.42.2)")
             )
+            (@subcommand rdeps =>
+                (about: "Prints the tree of installed packages which depend on a package")
+                (aliases: &["r", "rd", "rde", "rdep"])
+                (@arg PKG_IDENT: +required +takes_value
+                    "A package identifier (ex: core/redis, core/busybox-static/1.42.2)")
+                (@arg FORMAT: -f +takes_value {valid_output_format}
+                    "Same as --format (ex: text, json, dot, default: text)")
+            )
+            (@subcommand search =>
+                (about: "Searches a Depot for packages whose identifiers start with a term")
+                (aliases: &["se", "sea", "sear", "searc"])
+                (@arg SEARCH_TERM: +required +takes_value "A search term (ex: core/red)")
+                (@arg OFFSET: -o --offset +takes_value {valid_numeric}
+                    "Skip this many results, to list a later page (default: 0)")
+                (@arg DEPOT_URL: -u --url +takes_value {valid_url} "Use a specific Depot URL")
+            )
             (@subcommand sign =>
                 (about: "Signs an archive with an origin key, generating a Habitat Artifact")
                 (aliases: &["s", "si", "sig"])
                 (@arg ORIGIN: --origin +takes_value "Origin key used to create signature")
+                (@arg DEPOT_URL: -u --url +takes_value {valid_url}
+                    "Use the current origin key of a specific Depot URL")
+                (@arg COMPRESSION: -c --compression +takes_value {valid_compression}
+                    "Compression of the artifact payload, recompressing the source archive \
+                    if needed (ex: xz, zstd, default: compression of the source archive)")
+                (@arg HASH_TYPE: -a --algorithm +takes_value {valid_hash_type}
+                    "Hash algorithm used for the signature (ex: blake2b, sha256, default: blake2b)")
                 (@arg SOURCE: +required {file_exists}
                     "A path to a source archive file \
                     (ex: /home/acme-redis-3.0.7-21120102031201.tar.xz)")
@@ -181,6 +369,14 @@ pub fn get() -> App<'static, 'static> {
                     "The destination path to the signed Habitat Artifact \
                     (ex: /home/acme-redis-3.0.7-21120102031201-x86_64-linux.hart)")
             )
+            (@subcommand uninstall =>
+                (about: "Removes an installed package which no other installed package requires")
+                (aliases: &["un", "uni", "unin", "unins", "uninst", "uninsta", "uninstal"])
+                (@arg PKG_IDENT: +required +takes_value
+                    "A package identifier (ex: core/redis, core/busybox-static/1.42.2)")
+                (@arg FORCE: -f --force
+                    "Uninstall the package even if other installed packages require it")
+            )
             (@subcommand upload =>
                 (about: "Uploads a local Habitat Artifact to a Depot")
                 (aliases: &["u", "up", "upl", "uplo", "uploa"])
@@ -196,7 +392,11 @@ pub fn get() -> App<'static, 'static> {
                 (@arg SOURCE: +required {file_exists}
                     "A path to a Habitat Artifact \
                     (ex: /home/acme-redis-3.0.7-21120102031201-x86_64-linux.hart)")
+                (@arg REQUIRE: -r --require +takes_value +multiple number_of_values(1)
+                    "Require a valid signature from one of a comma-delimited list of origin \
+                    keys, which may be given more than once (ex: -r core -r rel-a,rel-b)")
             )
+            (subcommand: sub_pkg_verify_installed())
         )
         (@subcommand ring =>
             (about: "Commands relating to Habitat rings")
@@ -247,6 +447,56 @@ pub fn get() -> App<'static, 'static> {
             (about: "Commands relating to the Habitat Supervisor")
             (aliases: &["su"])
         )
+        (@subcommand svc =>
+            (about: "Commands relating to the services of running Habitat Supervisors")
+            (aliases: &["sv"])
+            (@setting ArgRequiredElseHelp)
+            (@subcommand census =>
+                (about: "Shows the members of each service group in the Supervisors' census")
+                (aliases: &["c", "ce", "cen", "cens", "censu"])
+                (@arg SERVICE_GROUP: +required {valid_service_group}
+                    "Service group to ask about (ex: redis.default)")
+                (@arg PEER: -p --peer +takes_value
+                    "A comma-delimited list of one or more Habitat Supervisor HTTP APIs to query \
+                    (default: 127.0.0.1:9631)")
+            )
+            (@subcommand config =>
+                (about: "Shows the configuration the Supervisors' services are running with")
+                (aliases: &["co", "con", "conf", "confi"])
+                (@arg SERVICE_GROUP: +required {valid_service_group}
+                    "Service group to ask about (ex: redis.default)")
+                (@arg PEER: -p --peer +takes_value
+                    "A comma-delimited list of one or more Habitat Supervisor HTTP APIs to query \
+                    (default: 127.0.0.1:9631)")
+            )
+            (@subcommand election =>
+                (about: "Shows the leader elections known to the Supervisors")
+                (aliases: &["e", "el", "ele", "elec", "elect", "electi", "electio"])
+                (@arg SERVICE_GROUP: +required {valid_service_group}
+                    "Service group to ask about (ex: redis.default)")
+                (@arg PEER: -p --peer +takes_value
+                    "A comma-delimited list of one or more Habitat Supervisor HTTP APIs to query \
+                    (default: 127.0.0.1:9631)")
+            )
+            (@subcommand health =>
+                (about: "Runs the health checks of the Supervisors' services")
+                (aliases: &["h", "he", "hea", "heal", "healt"])
+                (@arg SERVICE_GROUP: +required {valid_service_group}
+                    "Service group to ask about (ex: redis.default)")
+                (@arg PEER: -p --peer +takes_value
+                    "A comma-delimited list of one or more Habitat Supervisor HTTP APIs to query \
+                    (default: 127.0.0.1:9631)")
+            )
+            (@subcommand status =>
+                (about: "Shows the state of the Supervisors' services")
+                (aliases: &["s", "st", "sta", "stat", "statu"])
+                (@arg SERVICE_GROUP: +required {valid_service_group}
+                    "Service group to ask about (ex: redis.default)")
+                (@arg PEER: -p --peer +takes_value
+                    "A comma-delimited list of one or more Habitat Supervisor HTTP APIs to query \
+                    (default: 127.0.0.1:9631)")
+            )
+        )
         (@subcommand user =>
             (about: "Commands relating to Habitat users")
             (aliases: &["u", "us", "use"])
@@ -259,6 +509,7 @@ pub fn get() -> App<'static, 'static> {
                     (about: "Generates a Habitat user key")
                     (aliases: &["g", "ge", "gen", "gene", "gener", "genera", "generat"])
                     (@arg USER: +required +takes_value "Name of the user key")
+                    (@arg ENCRYPT: -e --encrypt "Encrypt the secret key with a passphrase")
                 )
             )
         )
@@ -303,6 +554,15 @@ fn sub_config_apply() -> App<'static, 'static> {
             "A version number (positive integer) for this configuration (ex: 42)")
         (@arg FILE: {file_exists_or_stdin}
             "Path to local file on disk (ex: /tmp/config.toml, default: <stdin>)")
+        (@arg USER: --user +takes_value
+            "Name of a user key to encrypt the configuration for the service group with, so \
+            only Supervisors holding the service key can read it (ex: jdoe)")
+        (@arg SET: --set +takes_value +multiple number_of_values(1) conflicts_with[FILE]
+            "Sets a single key instead of applying a whole configuration; patches are never \
+            encrypted (ex: --set redis.port=6380)")
+        (@arg UNSET: --unset +takes_value +multiple number_of_values(1) conflicts_with[FILE]
+            "Removes a single key instead of applying a whole configuration \
+            (ex: --unset redis.loglevel)")
         (@arg ORG: --org +takes_value "Name of service organization")
     )
 }
@@ -333,16 +593,57 @@ fn sub_pkg_build() -> App<'static, 'static> {
     }
 }
 
+fn sub_pkg_export() -> App<'static, 'static> {
+    clap_app!(@subcommand export =>
+        (about: "Exports the package to the specified format")
+        (aliases: &["exp"])
+        (@arg FORMAT: +required +takes_value "The export format (ex: docker, aci, oci)")
+        (@arg PKG_IDENT: +required +takes_value
+            "A package identifier (ex: core/redis, core/busybox-static/1.42.2)")
+    )
+        .arg(Arg::with_name("INSTALL_SUP")
+            .help("Installs core/hab-sup from the Depot if it isn't installed (oci format only)")
+            .long("install-supervisor"))
+}
+
+fn sub_pkg_gc() -> App<'static, 'static> {
+    clap_app!(@subcommand gc =>
+        (about: "Removes installed packages which aren't required by any of the given packages \
+            or by a running service")
+        (@arg PKG_IDENT: +required +multiple
+            "One or more package identifiers to keep along with their dependencies, where a \
+            partial identifier keeps its latest installed release (ex: core/redis)")
+    )
+        .arg(Arg::with_name("DRY_RUN")
+            .help("Lists the packages which would be removed without removing them")
+            .short("n")
+            .long("dry-run"))
+}
+
 fn sub_pkg_install() -> App<'static, 'static> {
     clap_app!(@subcommand install =>
         (about: "Installs a Habitat package from a Depot or locally from a Habitat Artifact")
-        (@arg DEPOT_URL: -u --url +takes_value {valid_url} "Use a specific Depot URL")
+        (@arg DEPOT_URL: -u --url +takes_value {valid_depot_url}
+            "Use a specific Depot URL, or a local depot directory (ex: file:///mnt/depot)")
         (@arg PKG_IDENT_OR_ARTIFACT: +required +multiple
             "One or more Habitat package identifiers (ex: acme/redis) and/or filepaths \
             to a Habitat Artifact (ex: /home/acme-redis-3.0.7-21120102031201-x86_64-linux.hart)")
     )
 }
 
+fn sub_pkg_verify_installed() -> App<'static, 'static> {
+    SubCommand::with_name("verify-installed")
+        .about("Verifies the files of an installed package against the hashes it was built with")
+        .arg(Arg::with_name("PKG_IDENT")
+            .help("A package identifier (ex: core/redis, core/busybox-static/1.42.2)")
+            .required_unless("ALL")
+            .conflicts_with("ALL"))
+        .arg(Arg::with_name("ALL")
+            .help("Verifies every installed package")
+            .short("a")
+            .long("all"))
+}
+
 fn file_exists(val: String) -> result::Result<(), String> {
     if Path::new(&val).is_file() {
         Ok(())
@@ -351,6 +652,14 @@ fn file_exists(val: String) -> result::Result<(), String> {
     }
 }
 
+fn dir_exists(val: String) -> result::Result<(), String> {
+    if Path::new(&val).is_dir() {
+        Ok(())
+    } else {
+        Err(format!("Directory: '{}' cannot be found", &val))
+    }
+}
+
 fn file_exists_or_stdin(val: String) -> result::Result<(), String> {
     if val == "-" {
         Ok(())
@@ -359,6 +668,37 @@ fn file_exists_or_stdin(val: String) -> result::Result<(), String> {
     }
 }
 
+fn valid_compression(val: String) -> result::Result<(), String> {
+    match Compression::from_str(&val) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(format!("COMPRESSION: {} is invalid, must be one of (xz, zstd)", &val)),
+    }
+}
+
+fn valid_output_format(val: String) -> result::Result<(), String> {
+    match &val[..] {
+        "text" | "json" | "dot" => Ok(()),
+        _ => {
+            Err(format!("OUTPUT_FORMAT: {} is invalid, must be one of (text, json, dot)",
+                        &val))
+        }
+    }
+}
+
+fn valid_hash_type(val: String) -> result::Result<(), String> {
+    match HashType::from_str(&val) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(format!("HASH_TYPE: {} is invalid, must be one of (blake2b, sha256)", &val)),
+    }
+}
+
+fn valid_numeric(val: String) -> result::Result<(), String> {
+    match val.parse::<usize>() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(format!("'{}' is not a valid number", &val)),
+    }
+}
+
 fn valid_pair_type(val: String) -> result::Result<(), String> {
     match PairType::from_str(&val) {
         Ok(_) => Ok(()),
@@ -378,6 +718,19 @@ fn valid_service_group(val: String) -> result::Result<(), String> {
     }
 }
 
+/// Accepts a depot URL, or a local depot named by a `file://` URL or the path of its directory.
+fn valid_depot_url(val: String) -> result::Result<(), String> {
+    match LocalDepot::path_from_url(&val) {
+        Some(_) => Ok(()),
+        None if Path::new(&val).is_dir() => {
+            Err(format!("Local depot: '{}' has no {} file, write one with `hab pkg index`",
+                        &val,
+                        INDEX_FILE))
+        }
+        None => valid_url(val),
+    }
+}
+
 fn valid_url(val: String) -> result::Result<(), String> {
     match Url::parse(&val) {
         Ok(_) => Ok(()),
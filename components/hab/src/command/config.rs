@@ -12,32 +12,71 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::io::Read;
+
+use hcore::service::ServiceGroup;
+use hyper::status::StatusCode;
+use rustc_serialize::json::Json;
+
+use command::svc;
+use error::{Error, Result};
+
 pub mod apply {
+    use std::fs::File;
     use std::path::Path;
     use std::io::{self, Read};
 
     use ansi_term::Colour::{Blue, Green, Yellow};
-    use hcore::crypto::SymKey;
+    use hcore::crypto::{BoxKeyPair, SymKey};
     use hcore::service::ServiceGroup;
     use common::gossip_file::GossipFile;
 
     use error::Result;
     use gossip::{self, hab_gossip};
 
+    /// Applies a whole configuration. When a user and service key pair are given, the
+    /// configuration is either encrypted for the service, so only the Supervisors holding its
+    /// secret key can read it, or sent as is and signed, so they can tell who applied it.
     pub fn start(peers: &Vec<String>,
                  ring_key: Option<&SymKey>,
                  sg: &ServiceGroup,
                  number: u64,
-                 file_path: Option<&Path>)
+                 file_path: Option<&Path>,
+                 box_pairs: Option<(&BoxKeyPair, &BoxKeyPair)>,
+                 encrypt: bool)
                  -> Result<()> {
         println!("{}",
                  Yellow.bold().paint(format!("» Applying configuration")));
-        let file = match file_path {
-            Some(p) => try!(GossipFile::from_file(sg.clone(), p, number)),
-            None => {
-                let mut body = String::new();
-                try!(io::stdin().read_to_string(&mut body));
-                try!(GossipFile::from_body(sg.clone(), body.into(), number))
+        let file = match box_pairs {
+            Some((user_pair, service_pair)) if encrypt => {
+                let mut body = Vec::new();
+                match file_path {
+                    Some(p) => try!(try!(File::open(p)).read_to_end(&mut body)),
+                    None => try!(io::stdin().read_to_end(&mut body)),
+                };
+                println!("{} configuration for {} with {}",
+                         Green.bold().paint("☛ Encrypting"),
+                         &service_pair.name_with_rev(),
+                         &user_pair.name_with_rev());
+                try!(GossipFile::from_body_encrypt(user_pair, service_pair, body, number))
+            }
+            _ => {
+                let mut file = match file_path {
+                    Some(p) => try!(GossipFile::from_file(sg.clone(), p, number)),
+                    None => {
+                        let mut body = String::new();
+                        try!(io::stdin().read_to_string(&mut body));
+                        try!(GossipFile::from_body(sg.clone(), body.into(), number))
+                    }
+                };
+                if let Some((user_pair, service_pair)) = box_pairs {
+                    println!("{} configuration for {} with {}",
+                             Green.bold().paint("☛ Signing"),
+                             &service_pair.name_with_rev(),
+                             &user_pair.name_with_rev());
+                    try!(file.sign(user_pair, service_pair));
+                }
+                file
             }
         };
         let rumor = hab_gossip::Rumor::gossip_file(file);
@@ -61,3 +100,335 @@ pub mod apply {
         Ok(())
     }
 }
+
+pub mod patch {
+    use ansi_term::Colour::{Blue, Green, Yellow};
+    use hcore::crypto::SymKey;
+    use hcore::service::ServiceGroup;
+    use common::config_patch::{self, ConfigPatch};
+    use common::gossip_file::GossipFile;
+
+    use error::Result;
+    use gossip::{self, hab_gossip};
+
+    pub fn start(peers: &Vec<String>,
+                 ring_key: Option<&SymKey>,
+                 sg: &ServiceGroup,
+                 number: u64,
+                 sets: &[&str],
+                 unsets: &[&str])
+                 -> Result<()> {
+        println!("{}",
+                 Yellow.bold().paint(format!("» Patching configuration")));
+        let mut patch = ConfigPatch::new();
+        for assignment in sets.iter() {
+            let (key_path, value) = try!(config_patch::parse_assignment(assignment));
+            println!("{} {} = {}", Green.bold().paint("+ Setting"), key_path, value);
+            try!(patch.set(&key_path, value, number));
+        }
+        for key_path in unsets.iter() {
+            println!("{} {}", Green.bold().paint("- Unsetting"), key_path);
+            try!(patch.unset(key_path, number));
+        }
+        let file = try!(GossipFile::from_patch(sg.clone(), &patch));
+        let rumor = hab_gossip::Rumor::gossip_file(file);
+
+        let mut list = hab_gossip::RumorList::new();
+        list.add_rumor(rumor);
+
+        if let Some(ring_key) = ring_key {
+            println!("{} communication to \"{}\" ring with {}",
+                     Green.bold().paint("☛ Encrypting"),
+                     &ring_key.name,
+                     &ring_key.name_with_rev());
+
+        }
+        println!("{} configuration patch for {} into ring via {:?}",
+                 Green.bold().paint("↑ Applying"),
+                 &sg,
+                 &peers);
+        try!(gossip::send_rumors_to_peers(&peers, ring_key, &list));
+        println!("{}", Blue.paint(format!("★ Applied configuration patch.")));
+        Ok(())
+    }
+}
+
+pub mod history {
+    //! Lists the configuration versions a Supervisor has applied to its service group.
+
+    use hcore::service::ServiceGroup;
+    use rustc_serialize::json::Json;
+
+    use command::svc;
+    use error::Result;
+    use output;
+
+    pub fn start(supervisor: &str, sg: &ServiceGroup) -> Result<()> {
+        let history = try!(super::fetch_history(supervisor, sg));
+        let revisions = history.find("revisions")
+            .and_then(|r| r.as_array())
+            .cloned()
+            .unwrap_or(vec![]);
+        println!("Configuration history of {}:", sg);
+        let rows: Vec<Vec<String>> = revisions.iter()
+            .map(|revision| {
+                let version = revision.find("version_number")
+                    .and_then(|v| v.as_u64())
+                    .map_or("-".to_string(), |v| v.to_string());
+                let signer = revision.find("signer")
+                    .and_then(|s| s.as_string())
+                    .unwrap_or("unsigned");
+                vec![version,
+                     svc::json_str(revision, "file_name").to_string(),
+                     svc::json_str(revision, "applied_at").to_string(),
+                     signer.to_string(),
+                     svc::json_str(revision, "checksum").to_string()]
+            })
+            .collect();
+        svc::print_table(&["VERSION", "FILE", "APPLIED", "SIGNER", "CHECKSUM"], &rows);
+        output::record("service_group", &sg.to_string());
+        output::record("revisions", &Json::Array(revisions));
+        Ok(())
+    }
+}
+
+pub mod rollback {
+    //! Re-applies a configuration version a Supervisor remembers, under a new version number
+    //! given by the caller so it wins over the versions applied since.
+    //!
+    //! The configuration document in effect at that version is gossiped again, and every key
+    //! patched since is set back to its value at that version. A key patched before the oldest
+    //! version the Supervisor remembers is taken to have been unpatched at that point.
+
+    use std::io::Read;
+    use std::path::Path;
+
+    use ansi_term::Colour::{Blue, Green, Yellow};
+    use common;
+    use common::config_patch::{ConfigPatch, GOSSIP_PATCH_TOML};
+    use common::gossip_file::{GossipFile, GOSSIP_TOML};
+    use hcore::crypto::{BoxKeyPair, SymKey};
+    use hcore::service::ServiceGroup;
+    use http_client::header;
+    use hyper::status::StatusCode;
+    use rustc_serialize::json::Json;
+    use toml;
+
+    use command::svc;
+    use error::{Error, Result};
+    use gossip::{self, hab_gossip};
+    use output;
+
+    /// A configuration document or patch as a Supervisor serves it.
+    struct Document {
+        body: Vec<u8>,
+        checksum: String,
+        encrypted: bool,
+    }
+
+    pub fn start(supervisor: &str,
+                 peers: &Vec<String>,
+                 ring_key: Option<&SymKey>,
+                 sg: &ServiceGroup,
+                 version: u64,
+                 number: u64,
+                 cache_key_path: &Path)
+                 -> Result<()> {
+        println!("{}",
+                 Yellow.bold().paint(format!("» Rolling back configuration of {} to version {}",
+                                             sg,
+                                             version)));
+        let history = try!(super::fetch_history(supervisor, sg));
+        // The version number and file name of every remembered version
+        let revisions: Vec<(u64, String)> = history.find("revisions")
+            .and_then(|r| r.as_array())
+            .map_or(vec![], |r| {
+                r.iter()
+                    .filter_map(|r| {
+                        r.find("version_number")
+                            .and_then(|v| v.as_u64())
+                            .map(|v| (v, svc::json_str(r, "file_name").to_string()))
+                    })
+                    .collect()
+            });
+        let latest = revisions.iter().map(|r| r.0).max().unwrap_or(0);
+        // The Supervisor may lag behind the ring, so this only catches numbers that are surely
+        // too low: anything at or below the ring's current version is discarded.
+        if number <= latest {
+            return Err(Error::ConfigVersionNotNewer(sg.to_string(), number, latest));
+        }
+        if !revisions.iter().any(|r| r.0 == version) {
+            return Err(Error::ConfigVersionNotFound(sg.to_string(), version));
+        }
+
+        let mut list = hab_gossip::RumorList::new();
+        let document = match latest_at(&revisions, GOSSIP_TOML, version) {
+            Some(at) => {
+                let document = try!(fetch_revision(supervisor, sg, at));
+                // An encrypted version is gossiped again exactly as it was, as we may not hold
+                // the service key to read it and it should keep the signer it was encrypted by.
+                let file = if document.encrypted {
+                    GossipFile::from_encrypted_body(sg.clone(),
+                                                    document.body.clone(),
+                                                    document.checksum.clone(),
+                                                    number)
+                } else {
+                    try!(GossipFile::from_body(sg.clone(), document.body.clone(), number))
+                };
+                list.add_rumor(hab_gossip::Rumor::gossip_file(file));
+                Some(document)
+            }
+            None if revisions.iter().any(|r| r.1 == GOSSIP_TOML) => {
+                return Err(Error::ConfigDocumentNotFound(sg.to_string(), version));
+            }
+            // The document was never changed in the remembered history, so the current one
+            // was already in effect.
+            None => try!(fetch(supervisor, &svc::service_path(sg, &file_path(GOSSIP_TOML)))),
+        };
+
+        let mut reverted_keys = vec![];
+        let path = svc::service_path(sg, &file_path(GOSSIP_PATCH_TOML));
+        if let Some(current) = try!(fetch(supervisor, &path)) {
+            let current = try!(parse_patch(&current));
+            if current.entries().values().any(|entry| entry.version > version) {
+                let patch = match latest_at(&revisions, GOSSIP_PATCH_TOML, version) {
+                    Some(at) => try!(parse_patch(&try!(fetch_revision(supervisor, sg, at)))),
+                    None => ConfigPatch::new(),
+                };
+                let table = match document {
+                    Some(ref document) => try!(parse_document(document, cache_key_path)),
+                    None => toml::Table::new(),
+                };
+                let revert = try!(revert_patch(&current, &patch.apply(&table), number));
+                reverted_keys = revert.entries().keys().cloned().collect();
+                let file = try!(GossipFile::from_patch(sg.clone(), &revert));
+                list.add_rumor(hab_gossip::Rumor::gossip_file(file));
+            }
+        }
+
+        if let Some(ring_key) = ring_key {
+            println!("{} communication to \"{}\" ring with {}",
+                     Green.bold().paint("☛ Encrypting"),
+                     &ring_key.name,
+                     &ring_key.name_with_rev());
+
+        }
+        println!("{} version {} of {} as version {} into ring via {:?}",
+                 Green.bold().paint("↑ Applying"),
+                 version,
+                 &sg,
+                 number,
+                 &peers);
+        if !reverted_keys.is_empty() {
+            println!("{} patched keys {}",
+                     Green.bold().paint("↺ Reverting"),
+                     reverted_keys.join(", "));
+        }
+        try!(gossip::send_rumors_to_peers(&peers, ring_key, &list));
+        output::record("service_group", &sg.to_string());
+        output::record("rolled_back_to", &version);
+        output::record("version", &number);
+        output::record("reverted_keys",
+                       &Json::Array(reverted_keys.into_iter().map(Json::String).collect()));
+        println!("{}",
+                 Blue.paint(format!("★ Rolled back configuration to version {}.", version)));
+        Ok(())
+    }
+
+    /// Returns the latest remembered version of a file at or before the given version.
+    fn latest_at(revisions: &[(u64, String)], file_name: &str, version: u64) -> Option<u64> {
+        revisions.iter()
+            .filter(|r| r.1 == file_name && r.0 <= version)
+            .map(|r| r.0)
+            .max()
+    }
+
+    /// Returns a patch setting every key the given patch changes back to its value in the given
+    /// configuration, or unsetting it where the configuration has none.
+    fn revert_patch(patch: &ConfigPatch, config: &toml::Table, number: u64) -> Result<ConfigPatch> {
+        let config = toml::Value::Table(config.clone());
+        let mut revert = ConfigPatch::new();
+        for key_path in patch.entries().keys() {
+            match config.lookup(key_path) {
+                Some(value) => try!(revert.set(key_path, value.clone(), number)),
+                None => try!(revert.unset(key_path, number)),
+            }
+        }
+        Ok(revert)
+    }
+
+    fn parse_patch(document: &Document) -> Result<ConfigPatch> {
+        Ok(try!(ConfigPatch::from_toml_str(&String::from_utf8_lossy(&document.body))))
+    }
+
+    fn parse_document(document: &Document, cache_key_path: &Path) -> Result<toml::Table> {
+        let body = if document.encrypted {
+            try!(BoxKeyPair::decrypt(&document.body, cache_key_path))
+        } else {
+            document.body.clone()
+        };
+        let body = String::from_utf8_lossy(&body);
+        let mut parser = toml::Parser::new(&body);
+        match parser.parse() {
+            Some(table) => Ok(table),
+            None => {
+                let e = common::Error::InvalidTomlError(format!("{:?}", parser.errors));
+                Err(Error::HabitatCommon(e))
+            }
+        }
+    }
+
+    fn file_path(file_name: &str) -> String {
+        format!("/files/{}", file_name)
+    }
+
+    fn fetch_revision(supervisor: &str, sg: &ServiceGroup, version: u64) -> Result<Document> {
+        let path = svc::service_path(sg, &format!("/config/history/{}", version));
+        match try!(fetch(supervisor, &path)) {
+            Some(document) => Ok(document),
+            None => Err(Error::ConfigVersionNotFound(sg.to_string(), version)),
+        }
+    }
+
+    /// Fetches a document from a Supervisor, or returns `None` if it holds none at the path.
+    fn fetch(supervisor: &str, path: &str) -> Result<Option<Document>> {
+        let mut res = try!(svc::request(supervisor, path));
+        match res.status {
+            StatusCode::Ok => (),
+            StatusCode::NotFound => return Ok(None),
+            status => {
+                return Err(Error::SupervisorRequest(supervisor.to_string(), status.to_string()))
+            }
+        }
+        let encrypted = header(&res, "X-Encrypted").map_or(false, |v| v == "true");
+        let checksum = header(&res, "X-Checksum").unwrap_or(String::new());
+        let mut body = Vec::new();
+        try!(res.read_to_end(&mut body)
+            .map_err(|e| Error::SupervisorRequest(supervisor.to_string(), e.to_string())));
+        Ok(Some(Document {
+            body: body,
+            checksum: checksum,
+            encrypted: encrypted,
+        }))
+    }
+}
+
+/// Fetches the configuration history a Supervisor keeps, making sure it is the history of the
+/// service group being asked about.
+fn fetch_history(supervisor: &str, sg: &ServiceGroup) -> Result<Json> {
+    let mut res = try!(svc::request(supervisor, &svc::service_path(sg, "/config/history")));
+    let mut body = String::new();
+    try!(res.read_to_string(&mut body)
+        .map_err(|e| Error::SupervisorRequest(supervisor.to_string(), e.to_string())));
+    if res.status != StatusCode::Ok {
+        return Err(Error::SupervisorRequest(supervisor.to_string(), res.status.to_string()));
+    }
+    let json = try!(Json::from_str(&body)
+        .map_err(|e| Error::SupervisorRequest(supervisor.to_string(), e.to_string())));
+    let held = svc::json_str(&json, "service_group").to_string();
+    if held != sg.to_string() {
+        return Err(Error::SupervisorRequest(supervisor.to_string(),
+                                            format!("it runs {}, not {}", held, sg)));
+    }
+    Ok(json)
+}
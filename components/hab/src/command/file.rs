@@ -12,6 +12,142 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod download {
+    //! Fetches a file gossiped to one of a Supervisor's service groups, decrypting it when it was
+    //! encrypted for a service whose secret key is in the key cache.
+
+    use std::fs::File;
+    use std::io::{Read, Write};
+    use std::path::Path;
+
+    use ansi_term::Colour::{Blue, Green, Yellow};
+    use hcore::crypto::BoxKeyPair;
+    use hcore::service::ServiceGroup;
+    use http_client::header;
+    use hyper::status::StatusCode;
+    use common::gossip_file::sha256sum;
+
+    use command::svc;
+    use error::{Error, Result};
+    use output;
+
+    pub fn start(peer: &str,
+                 sg: &ServiceGroup,
+                 file_name: &str,
+                 dst: Option<&Path>,
+                 cache: &Path)
+                 -> Result<()> {
+        println!("{}",
+                 Yellow.bold().paint(format!("» Downloading file {} of {} from {}",
+                                             file_name,
+                                             sg,
+                                             peer)));
+        let path = svc::service_path(sg, &format!("/files/{}", file_name));
+        let mut res = try!(svc::request(peer, &path));
+        match res.status {
+            StatusCode::Ok => (),
+            StatusCode::NotFound => {
+                return Err(Error::FileNotFound(format!("{} of {} on {}", file_name, sg, peer)))
+            }
+            status => {
+                return Err(Error::SupervisorRequest(peer.to_string(), status.to_string()))
+            }
+        }
+        let version = header(&res, "X-Version-Number").unwrap_or("-".to_string());
+        let checksum = header(&res, "X-Checksum").unwrap_or(String::new());
+        let encrypted = header(&res, "X-Encrypted").map_or(false, |v| v == "true");
+        let mut body = Vec::new();
+        try!(res.read_to_end(&mut body)
+            .map_err(|e| Error::SupervisorRequest(peer.to_string(), e.to_string())));
+
+        let body = if encrypted {
+            println!("{} {}", Green.bold().paint("☛ Decrypting"), file_name);
+            try!(BoxKeyPair::decrypt(&body, cache).map_err(|e| {
+                Error::CryptoCLI(format!("Can't decrypt {}, the secret key of the service it \
+                                          was uploaded for is required: {}",
+                                         file_name,
+                                         e))
+            }))
+        } else {
+            body
+        };
+        if sha256sum(&body) != checksum {
+            return Err(Error::CryptoCLI(format!("Checksum of {} does not match the one gossiped \
+                                                 for it ({})",
+                                                file_name,
+                                                checksum)));
+        }
+
+        let dst = match dst {
+            Some(dst) => dst.to_path_buf(),
+            None => Path::new(file_name).to_path_buf(),
+        };
+        println!("{} {} version {} to {}",
+                 Green.bold().paint("↓ Writing"),
+                 file_name,
+                 version,
+                 dst.display());
+        let mut file = try!(File::create(&dst));
+        try!(file.write_all(&body));
+        output::record("file", file_name);
+        output::record("version", &version);
+        output::record("path", &dst.display().to_string());
+        println!("{}",
+                 Blue.paint(format!("★ Download of {} complete.", file_name)));
+        Ok(())
+    }
+}
+
+pub mod list {
+    //! Lists the files gossiped to one of a Supervisor's service groups.
+
+    use std::io::Read;
+
+    use hcore::service::ServiceGroup;
+    use hyper::status::StatusCode;
+    use rustc_serialize::json::Json;
+
+    use command::svc;
+    use error::{Error, Result};
+    use output;
+
+    pub fn start(peer: &str, sg: &ServiceGroup) -> Result<()> {
+        let mut res = try!(svc::request(peer, &svc::service_path(sg, "/files")));
+        let mut body = String::new();
+        try!(res.read_to_string(&mut body)
+            .map_err(|e| Error::SupervisorRequest(peer.to_string(), e.to_string())));
+        if res.status != StatusCode::Ok {
+            return Err(Error::SupervisorRequest(peer.to_string(), res.status.to_string()));
+        }
+        let json = try!(Json::from_str(&body)
+            .map_err(|e| Error::SupervisorRequest(peer.to_string(), e.to_string())));
+
+        let service_group = svc::json_str(&json, "service_group").to_string();
+        let files = json.find("files").and_then(|f| f.as_array()).cloned().unwrap_or(vec![]);
+        println!("Files gossiped to {}:", service_group);
+        let rows: Vec<Vec<String>> = files.iter()
+            .map(|file| {
+                let version = file.find("version_number")
+                    .and_then(|v| v.as_u64())
+                    .map_or("-".to_string(), |v| v.to_string());
+                let encrypted = if svc::json_bool(file, "encrypted") {
+                    "yes"
+                } else {
+                    "no"
+                };
+                vec![svc::json_str(file, "file_name").to_string(),
+                     version,
+                     svc::json_str(file, "checksum").to_string(),
+                     encrypted.to_string()]
+            })
+            .collect();
+        svc::print_table(&["FILE", "VERSION", "CHECKSUM", "ENCRYPTED"], &rows);
+        output::record("service_group", &service_group);
+        output::record("files", &Json::Array(files));
+        Ok(())
+    }
+}
+
 pub mod upload {
     use std::path::Path;
 
@@ -55,3 +191,174 @@ pub mod upload {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::fs::File;
+    use std::io::Read;
+    use std::path::Path;
+    use std::str::FromStr;
+
+    use common::gossip_file::sha256sum;
+    use hcore::crypto::BoxKeyPair;
+    use hcore::service::ServiceGroup;
+    use http_client::testing;
+    use hyper::server::{Request, Response};
+    use hyper::status::StatusCode;
+    use tempdir::TempDir;
+
+    use error::Error;
+    use super::{download, list};
+
+    const CONFIG: &'static [u8] = b"port = 6379\n";
+
+    /// A gossiped file as a Supervisor serves it.
+    struct Served {
+        body: Vec<u8>,
+        checksum: String,
+        encrypted: bool,
+    }
+
+    /// Serves one file gossiped to redis.default at `/services/redis.default/files/<name>` and a
+    /// listing at `/services/redis.default/files`, returning the address of the fake Supervisor.
+    fn serve(name: &'static str, file: Served, listing: &'static str) -> String {
+        let addr = testing::serve(move |req: Request, mut res: Response| {
+            let path = req.uri.to_string();
+            if path == "/services/redis.default/files" {
+                res.send(listing.as_bytes()).unwrap();
+            } else if path == format!("/services/redis.default/files/{}", name) {
+                res.headers_mut().set_raw("X-Version-Number", vec![b"3".to_vec()]);
+                res.headers_mut()
+                    .set_raw("X-Checksum", vec![file.checksum.clone().into_bytes()]);
+                res.headers_mut()
+                    .set_raw("X-Encrypted", vec![file.encrypted.to_string().into_bytes()]);
+                res.send(&file.body).unwrap();
+            } else {
+                *res.status_mut() = StatusCode::NotFound;
+            }
+        });
+        addr.to_string()
+    }
+
+    fn redis() -> ServiceGroup {
+        ServiceGroup::from_str("redis.default@acme").unwrap()
+    }
+
+    fn plain() -> Served {
+        Served {
+            body: CONFIG.to_vec(),
+            checksum: sha256sum(CONFIG),
+            encrypted: false,
+        }
+    }
+
+    /// Encrypts the config for a service, with the keys to decrypt it in the key cache.
+    fn encrypted(cache: &Path) -> Served {
+        let user_pair = BoxKeyPair::generate_pair_for_user("ruth", cache).unwrap();
+        let service_pair =
+            BoxKeyPair::generate_pair_for_service("acme", "redis.default", cache).unwrap();
+        Served {
+            body: user_pair.encrypt(CONFIG, &service_pair).unwrap(),
+            checksum: sha256sum(CONFIG),
+            encrypted: true,
+        }
+    }
+
+    fn read(path: &Path) -> Vec<u8> {
+        let mut content = Vec::new();
+        File::open(path).unwrap().read_to_end(&mut content).unwrap();
+        content
+    }
+
+    #[test]
+    fn download_writes_a_plain_file() {
+        let dir = TempDir::new("file").unwrap();
+        let peer = serve("redis.toml", plain(), "{}");
+        let dst = dir.path().join("redis.toml");
+        download::start(&peer, &redis(), "redis.toml", Some(dst.as_path()), dir.path()).unwrap();
+        assert_eq!(read(&dst), CONFIG);
+    }
+
+    #[test]
+    fn download_decrypts_an_encrypted_file() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let dir = TempDir::new("file").unwrap();
+        let peer = serve("redis.toml", encrypted(cache.path()), "{}");
+        let dst = dir.path().join("redis.toml");
+        download::start(&peer, &redis(), "redis.toml", Some(dst.as_path()), cache.path()).unwrap();
+        assert_eq!(read(&dst), CONFIG);
+    }
+
+    #[test]
+    fn download_requires_the_service_key_to_decrypt() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let other_cache = TempDir::new("other_key_cache").unwrap();
+        let dir = TempDir::new("file").unwrap();
+        let peer = serve("redis.toml", encrypted(cache.path()), "{}");
+        let dst = dir.path().join("redis.toml");
+        match download::start(&peer,
+                              &redis(),
+                              "redis.toml",
+                              Some(dst.as_path()),
+                              other_cache.path()) {
+            Err(Error::CryptoCLI(_)) => (),
+            other => panic!("Expected a CryptoCLI error, got {:?}", other),
+        }
+        assert!(!dst.exists());
+    }
+
+    #[test]
+    fn download_rejects_a_checksum_mismatch() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let dir = TempDir::new("file").unwrap();
+        let mut file = encrypted(cache.path());
+        file.checksum = sha256sum(b"port = 6380\n");
+        let peer = serve("redis.toml", file, "{}");
+        let dst = dir.path().join("redis.toml");
+        match download::start(&peer, &redis(), "redis.toml", Some(dst.as_path()), cache.path()) {
+            Err(Error::CryptoCLI(ref e)) => assert!(e.contains("Checksum"), "{}", e),
+            other => panic!("Expected a CryptoCLI error, got {:?}", other),
+        }
+        assert!(!dst.exists());
+    }
+
+    #[test]
+    fn download_of_a_file_which_wasnt_gossiped() {
+        let dir = TempDir::new("file").unwrap();
+        let peer = serve("redis.toml", plain(), "{}");
+        match download::start(&peer, &redis(), "nginx.conf", None, dir.path()) {
+            Err(Error::FileNotFound(_)) => (),
+            other => panic!("Expected a FileNotFound error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn download_asks_for_the_service_group() {
+        let dir = TempDir::new("file").unwrap();
+        let peer = serve("redis.toml", plain(), "{}");
+        let nginx = ServiceGroup::from_str("nginx.default").unwrap();
+        match download::start(&peer, &nginx, "redis.toml", None, dir.path()) {
+            Err(Error::FileNotFound(_)) => (),
+            other => panic!("Expected a FileNotFound error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn list_reads_the_gossiped_files() {
+        let peer = serve("redis.toml",
+                         plain(),
+                         "{\"service_group\":\"redis.default\",\"files\":[{\"file_name\":\
+                          \"redis.toml\",\"version_number\":3,\"checksum\":\"abc\",\
+                          \"encrypted\":false}]}");
+        list::start(&peer, &redis()).unwrap();
+    }
+
+    #[test]
+    fn list_rejects_a_listing_which_isnt_json() {
+        let peer = serve("redis.toml", plain(), "not json");
+        match list::start(&peer, &redis()) {
+            Err(Error::SupervisorRequest(ref p, _)) => assert_eq!(p, &peer),
+            other => panic!("Expected a SupervisorRequest error, got {:?}", other),
+        }
+    }
+}
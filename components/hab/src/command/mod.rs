@@ -21,4 +21,5 @@ pub mod ring;
 pub mod service;
 pub mod studio;
 pub mod sup;
+pub mod svc;
 pub mod user;
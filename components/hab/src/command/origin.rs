@@ -54,19 +54,22 @@ pub mod key {
     pub mod download {
         use std::path::Path;
 
-        use ansi_term::Colour::{Blue, Green, Yellow};
+        use ansi_term::Colour::{Blue, Green, Red, Yellow};
         use depot_client::Client;
         use hcore::crypto::SigKeyPair;
 
         use common::command::ProgressBar;
+        use common::command::package::install::fetch_origin_key_revocation;
+        use config;
         use error::Result;
+        use output;
 
         pub fn start(depot: &str,
                      origin: &str,
                      revision: Option<&str>,
                      cache: &Path)
                      -> Result<()> {
-            let depot_client = try!(Client::new(depot, None));
+            let depot_client = try!(Client::new_with_ssl(depot, None, &config::ssl_settings()));
             match revision {
                 Some(revision) => {
                     let nwr = format!("{}-{}", origin, revision);
@@ -113,21 +116,39 @@ pub mod key {
                     println!("{} {}", Green.bold().paint("☑ Cached"), &nwr);
                 }
             }
+            output::append("keys", nwr);
+            if let Some(revocation) = try!(fetch_origin_key_revocation(depot_client,
+                                                                        name,
+                                                                        rev,
+                                                                        cache)) {
+                println!("{} {} as of {}",
+                         Red.bold().paint("✗ Revoked"),
+                         &nwr,
+                         revocation.revoked_at());
+                output::append("revoked", nwr);
+            }
             Ok(())
         }
     }
 
     pub mod export {
-        use std::io;
+        use std::io::{self, Read};
         use std::fs::File;
         use std::path::Path;
 
         use hcore::crypto::SigKeyPair;
-        use hcore::crypto::keys::PairType;
+        use hcore::crypto::keys::{self, PairType};
 
         use error::Result;
+        use output;
+        use passphrase;
 
-        pub fn start(origin: &str, pair_type: PairType, cache: &Path) -> Result<()> {
+        pub fn start(origin: &str,
+                     pair_type: PairType,
+                     encrypt: bool,
+                     decrypt: bool,
+                     cache: &Path)
+                     -> Result<()> {
             let latest = try!(SigKeyPair::get_latest_pair_for(origin, cache));
             let path = match pair_type {
                 PairType::Public => {
@@ -138,11 +159,26 @@ pub mod key {
                 }
             };
             let mut file = try!(File::open(&path));
-            debug!("Streaming file contents of {} {} to standard out",
-                   &pair_type,
-                   &path.display());
-            try!(io::copy(&mut file, &mut io::stdout()));
-            Ok(())
+            output::record("name_with_rev", &latest.name_with_rev());
+            if !encrypt && !decrypt && !output::is_json() {
+                debug!("Streaming file contents of {} {} to standard out",
+                       &pair_type,
+                       &path.display());
+                try!(io::copy(&mut file, &mut io::stdout()));
+                return Ok(());
+            }
+            let mut content = String::new();
+            try!(file.read_to_string(&mut content));
+            let content = if encrypt {
+                try!(keys::encrypt_key_str(&content, &try!(passphrase::new_passphrase())))
+            } else if decrypt && keys::is_encrypted_key_str(&content) {
+                let passphrase =
+                    try!(passphrase::key_passphrase(&path.display().to_string()));
+                try!(keys::decrypt_key_str(&content, &passphrase))
+            } else {
+                content
+            };
+            output::content("key", &content)
         }
     }
 
@@ -153,11 +189,19 @@ pub mod key {
         use hcore::crypto::SigKeyPair;
 
         use error::Result;
+        use output;
+        use passphrase;
 
-        pub fn start(origin: &str, cache: &Path) -> Result<()> {
+        pub fn start(origin: &str, encrypt: bool, cache: &Path) -> Result<()> {
             println!("{}",
                      Yellow.bold().paint(format!("» Generating origin key for {}", &origin)));
-            let pair = try!(SigKeyPair::generate_pair_for_origin(origin, cache));
+            let pair = if encrypt {
+                let passphrase = try!(passphrase::new_passphrase());
+                try!(SigKeyPair::generate_encrypted_pair_for_origin(origin, &passphrase, cache))
+            } else {
+                try!(SigKeyPair::generate_pair_for_origin(origin, cache))
+            };
+            output::record("name_with_rev", &pair.name_with_rev());
             println!("{}",
                      Blue.paint(format!("★ Generated origin key pair {}.",
                                         &pair.name_with_rev())));
@@ -170,13 +214,32 @@ pub mod key {
 
         use ansi_term::Colour::{Blue, Yellow};
         use hcore::crypto::SigKeyPair;
+        use hcore::crypto::keys;
 
         use error::Result;
+        use output;
+        use passphrase;
 
-        pub fn start(content: &str, cache: &Path) -> Result<()> {
+        pub fn start(content: &str, encrypt: bool, cache: &Path) -> Result<()> {
             println!("{}",
                      Yellow.bold().paint(format!("» Importing origin key from standard input")));
-            let (pair, pair_type) = try!(SigKeyPair::write_file_from_str(content, cache));
+            let (content, passphrase) = if keys::is_encrypted_key_str(content) {
+                // The passphrase is needed to check that the imported key can be loaded
+                let passphrase = try!(passphrase::key_passphrase("the imported key"));
+                (content.to_string(), Some(passphrase))
+            } else if encrypt {
+                let passphrase = try!(passphrase::new_passphrase());
+                (try!(keys::encrypt_key_str(content, &passphrase)), Some(passphrase))
+            } else {
+                (content.to_string(), None)
+            };
+            let (pair, pair_type) =
+                try!(SigKeyPair::write_file_from_str_with_passphrase(&content,
+                                                                     passphrase.as_ref()
+                                                                         .map(|p| &p[..]),
+                                                                     cache));
+            output::record("name_with_rev", &pair.name_with_rev());
+            output::record("pair_type", &pair_type.to_string());
             println!("{}",
                      Blue.paint(format!("★ Imported {} origin key {}.",
                                         &pair_type,
@@ -185,6 +248,211 @@ pub mod key {
         }
     }
 
+    pub mod revoke {
+        use std::path::Path;
+
+        use ansi_term::Colour::{Blue, Green, Yellow};
+        use hyper::status::StatusCode::Conflict;
+
+        use common::command::ProgressBar;
+        use depot_client::{self, Client};
+        use hcore::crypto::KeyRevocation;
+
+        use config;
+        use error::{Error, Result};
+        use output;
+        use passphrase;
+
+        pub fn start(depot: &str,
+                     token: &str,
+                     origin: &str,
+                     revision: &str,
+                     cache: &Path)
+                     -> Result<()> {
+            let nwr = format!("{}-{}", origin, revision);
+            println!("{}",
+                     Yellow.bold().paint(format!("» Revoking origin key {}", &nwr)));
+            let pair = try!(passphrase::sig_pair_for(&nwr, cache));
+            let revocation = try!(KeyRevocation::generate_now(&pair));
+            // An earlier revocation already in the key cache is kept in favour of the new one
+            let revocation = try!(KeyRevocation::write_file_from_str(&revocation.to_string(),
+                                                                     cache));
+            let revocation_file = KeyRevocation::get_path(&nwr, cache);
+            output::record("name_with_rev", &nwr);
+            output::record("revoked_at", &revocation.revoked_at());
+            println!("{} {} as of {}",
+                     Green.bold().paint("✓ Revoked"),
+                     &nwr,
+                     revocation.revoked_at());
+
+            let depot_client = try!(Client::new_with_ssl(depot, None, &config::ssl_settings()));
+            println!("{} {}",
+                     Green.bold().paint("↑ Uploading"),
+                     revocation_file.display());
+            let mut progress = ProgressBar::default();
+            match depot_client.put_origin_key_revocation(origin,
+                                                         revision,
+                                                         &revocation_file,
+                                                         token,
+                                                         Some(&mut progress)) {
+                Ok(()) => {
+                    println!("{} {}", Green.bold().paint("✓ Uploaded"), &nwr);
+                }
+                Err(depot_client::Error::HTTP(Conflict)) => {
+                    println!("{} {}",
+                             Yellow.bold()
+                                 .paint("✓ An earlier revocation already exists in the depot"),
+                             &nwr);
+                }
+                Err(e) => {
+                    return Err(Error::DepotClient(e));
+                }
+            };
+            println!("{}",
+                     Blue.paint(format!("★ Revocation of origin key {} complete.", &nwr)));
+            Ok(())
+        }
+    }
+
+    pub mod rotate {
+        //! Rotates an origin key, making a new revision the one used for signing.
+        //!
+        //! # Examples
+        //!
+        //! ```bash
+        //! $ hab origin key rotate acme --secret --resign
+        //! ```
+        //!
+        //! Will generate a new revision of the `acme` origin key, upload its public and secret
+        //! keys to the Depot and mark it as the Depot's current revision, so it is the one
+        //! served as the origin's latest key. As the newest revision in the key cache, it is also
+        //! the one used to sign new artifacts. With `--resign`, the latest release of every
+        //! package in the origin is countersigned with the new key and uploaded again.
+
+        use std::fs;
+        use std::path::Path;
+
+        use ansi_term::Colour::{Blue, Green, Yellow};
+
+        use common::command::ProgressBar;
+        use depot_client::Client;
+        use hcore::crypto::{artifact, SigKeyPair};
+        use hcore::crypto::keys::parse_name_with_rev;
+        use hcore::fs::cache_artifact_path;
+        use hcore::package::PackageIdent;
+
+        use config;
+        use error::Result;
+        use output;
+        use passphrase;
+        use super::upload_latest;
+
+        pub fn start(depot: &str,
+                     token: &str,
+                     origin: &str,
+                     encrypt: bool,
+                     with_secret: bool,
+                     resign: bool,
+                     cache: &Path,
+                     fs_root_path: &Path)
+                     -> Result<()> {
+            println!("{}",
+                     Yellow.bold().paint(format!("» Rotating origin key for {}", &origin)));
+            let pair = if encrypt {
+                let passphrase = try!(passphrase::new_passphrase());
+                try!(SigKeyPair::generate_encrypted_pair_for_origin(origin, &passphrase, cache))
+            } else {
+                try!(SigKeyPair::generate_pair_for_origin(origin, cache))
+            };
+            output::record("name_with_rev", &pair.name_with_rev());
+            println!("{} {}",
+                     Green.bold().paint("✓ Generated"),
+                     &pair.name_with_rev());
+
+            try!(upload_latest::start(depot, token, origin, with_secret, cache));
+
+            let depot_client = try!(Client::new_with_ssl(depot, None, &config::ssl_settings()));
+            try!(depot_client.set_current_origin_key(origin, &pair.rev, token));
+            println!("{} {} as the current key in the depot",
+                     Green.bold().paint("✓ Marked"),
+                     &pair.name_with_rev());
+
+            if resign {
+                try!(resign_latest(&depot_client, token, origin, &pair, cache, fs_root_path));
+            }
+            println!("{}",
+                     Blue.paint(format!("★ Rotated origin key for {} to {}.",
+                                        &origin,
+                                        &pair.name_with_rev())));
+            Ok(())
+        }
+
+        /// Countersigns the latest release of every package in the origin with the new key and
+        /// uploads it again, leaving the countersigned artifact in the artifact cache. The public
+        /// keys of the existing signers are downloaded as needed, so their signatures can be
+        /// verified before anything is countersigned.
+        fn resign_latest(depot_client: &Client,
+                         token: &str,
+                         origin: &str,
+                         pair: &SigKeyPair,
+                         cache: &Path,
+                         fs_root_path: &Path)
+                         -> Result<()> {
+            let mut names: Vec<String> = try!(depot_client.list_origin_packages(origin))
+                .into_iter()
+                .map(|p| p.get_name().to_string())
+                .collect();
+            names.sort();
+            names.dedup();
+            let artifact_cache = cache_artifact_path(Some(fs_root_path));
+            try!(fs::create_dir_all(&artifact_cache));
+            for name in names.iter() {
+                let latest = try!(depot_client.show_package(PackageIdent::new(origin,
+                                                                              name.as_str(),
+                                                                              None,
+                                                                              None)));
+                let ident: PackageIdent = latest.get_ident().clone().into();
+                println!("{} {}", Green.bold().paint("↓ Downloading"), &ident);
+                let mut progress = ProgressBar::default();
+                let archive = try!(depot_client.fetch_package(ident.clone(),
+                                                              &artifact_cache,
+                                                              Some(&mut progress)));
+                if try!(artifact::artifact_signers(&archive.path))
+                    .contains(&pair.name_with_rev()) {
+                    println!("{} {}", Green.paint("→ Already signed"), &ident);
+                    continue;
+                }
+                for nwr in try!(artifact::artifact_signers(&archive.path)) {
+                    if SigKeyPair::get_public_key_path(&nwr, cache).is_err() {
+                        let (name, rev) = try!(parse_name_with_rev(&nwr));
+                        println!("{} {} public origin key",
+                                 Green.bold().paint("↓ Downloading"),
+                                 &nwr);
+                        let mut progress = ProgressBar::default();
+                        try!(depot_client.fetch_origin_key(&name,
+                                                           &rev,
+                                                           cache,
+                                                           Some(&mut progress)));
+                    }
+                }
+                let countersigned = archive.path.with_extension("countersigned");
+                println!("{} {} with {}",
+                         Green.paint("☛ Countersigning"),
+                         &ident,
+                         &pair.name_with_rev());
+                try!(artifact::countersign(&archive.path, &countersigned, pair, cache));
+                try!(fs::rename(&countersigned, &archive.path));
+                println!("{} {}", Green.bold().paint("↑ Uploading"), &ident);
+                let mut archive = archive;
+                let mut progress = ProgressBar::default();
+                try!(depot_client.put_package(&mut archive, token, Some(&mut progress)));
+                output::append("resigned", &ident.to_string());
+                println!("{} {}", Green.bold().paint("✓ Uploaded"), &ident);
+            }
+            Ok(())
+        }
+    }
+
     pub mod upload {
         use std::path::Path;
 
@@ -197,14 +465,16 @@ pub mod key {
         use hcore::crypto::keys::parse_name_with_rev;
         use super::get_name_with_rev;
 
+        use config;
         use error::{Error, Result};
+        use output;
 
         pub fn start(depot: &str,
                      token: &str,
                      public_keyfile: &Path,
                      secret_keyfile: Option<&Path>)
                      -> Result<()> {
-            let depot_client = try!(Client::new(depot, None));
+            let depot_client = try!(Client::new_with_ssl(depot, None, &config::ssl_settings()));
             println!("{}",
                      Yellow.bold()
                          .paint(format!("» Uploading public origin key {}",
@@ -223,6 +493,7 @@ pub mod key {
                                               token,
                                               Some(&mut progress)) {
                 Ok(()) => {
+                    output::append("uploaded", &name_with_rev);
                     println!("{} {}", Green.bold().paint("✓ Uploaded"), &name_with_rev);
                 }
                 Err(e @ depot_client::Error::HTTP(Forbidden)) |
@@ -232,6 +503,7 @@ pub mod key {
 
                 Err(e @ depot_client::Error::HTTP(_)) => {
                     debug!("Error uploading public key {}", e);
+                    output::append("existing", &name_with_rev);
                     println!("{} {}",
                              Yellow.bold()
                                  .paint("✓ Public key revision already exists in the depot"),
@@ -259,7 +531,7 @@ pub mod key {
                                                          token,
                                                          Some(&mut progress)) {
                     Ok(()) => {
-
+                        output::append("uploaded", &name_with_rev);
                         println!("{} {}", Green.bold().paint("✓ Uploaded"), &name_with_rev);
                         println!("{}",
                                  Blue.paint(format!("★ Upload of secret origin key {} complete.",
@@ -287,8 +559,10 @@ pub mod key {
 
         use common::command::ProgressBar;
         use depot_client::{self, Client};
+        use config;
         use error::{Error, Result};
         use hcore::crypto::keys::parse_name_with_rev;
+        use output;
         use hcore::crypto::{PUBLIC_SIG_KEY_VERSION, SECRET_SIG_KEY_VERSION, SigKeyPair};
         use super::get_name_with_rev;
 
@@ -298,7 +572,7 @@ pub mod key {
                      with_secret: bool,
                      cache: &Path)
                      -> Result<()> {
-            let depot_client = try!(Client::new(depot, None));
+            let depot_client = try!(Client::new_with_ssl(depot, None, &config::ssl_settings()));
             let latest = try!(SigKeyPair::get_latest_pair_for(origin, cache));
             let public_keyfile = try!(SigKeyPair::get_public_key_path(&latest.name_with_rev(),
                                                                       cache));
@@ -317,6 +591,7 @@ pub mod key {
 
             match depot_client.put_origin_key(&name, &rev, &public_keyfile, token, Some(&mut progress)) {
                 Ok(()) => {
+                    output::append("uploaded", &name_with_rev);
                     println!("{} {}", Green.bold().paint("✓ Uploaded"), &name_with_rev);
                 }
                 Err(e @ depot_client::Error::HTTP(Forbidden)) |
@@ -325,6 +600,7 @@ pub mod key {
                 }
                 Err(e @ depot_client::Error::HTTP(_)) => {
                     debug!("Error uploading public key {}", e);
+                    output::append("existing", &name_with_rev);
                     println!("{} {}",
                              Yellow.bold()
                                  .paint("✓ Public key revision already exists in the depot"),
@@ -357,7 +633,7 @@ pub mod key {
                                                          token,
                                                          Some(&mut progress)) {
                     Ok(()) => {
-
+                        output::append("uploaded", &name_with_rev);
                         println!("{} {}", Green.bold().paint("✓ Uploaded"), &name_with_rev);
                         println!("{}",
                                  Blue.paint(format!("★ Upload of secret origin key {} complete.",
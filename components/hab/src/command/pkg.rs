@@ -69,6 +69,311 @@ pub mod binlink {
     }
 }
 
+pub mod bundle {
+    //! Bundles of artifacts and origin keys for installing packages on hosts which cannot reach
+    //! a depot.
+    //!
+    //! A bundle is a tar archive holding a `BUNDLE` file, naming the bundled package, followed
+    //! by the artifacts of the package and its transitive dependencies under `artifacts/` and
+    //! the public origin keys (and any key revocations) needed to verify them under `keys/`.
+
+    /// The first entry of a bundle.
+    const BUNDLE_FILE: &'static str = "BUNDLE";
+    /// The first line of the `BUNDLE` file.
+    const BUNDLE_VERSION: &'static str = "HAB-BUNDLE-1";
+    const ARTIFACTS_DIR: &'static str = "artifacts";
+    const KEYS_DIR: &'static str = "keys";
+
+    pub mod create {
+        use std::collections::BTreeSet;
+        use std::fs::File;
+        use std::io::Write;
+        use std::path::{Path, PathBuf};
+
+        use ansi_term::Colour::{Blue, Green, Yellow};
+        use common::command::ProgressBar;
+        use common::command::package::install;
+        use depot_client::Client;
+        use hcore::crypto::{artifact, default_cache_key_path, KeyRevocation, SigKeyPair};
+        use hcore::crypto::keys::parse_name_with_rev;
+        use hcore::fs::cache_artifact_path;
+        use hcore::package::{PackageIdent, PackageInstall};
+
+        use config;
+        use error::Result;
+        use output;
+        use tar::TarBuilder;
+        use super::{ARTIFACTS_DIR, BUNDLE_FILE, BUNDLE_VERSION, KEYS_DIR};
+
+        /// Writes a bundle of a package to `dst_dir`, returning its path. The package is
+        /// installed from the depot first if needed, and any artifact or origin key missing from
+        /// the local caches is downloaded.
+        pub fn start(ident: &PackageIdent,
+                     url: &str,
+                     fs_root_path: &Path,
+                     dst_dir: &Path)
+                     -> Result<PathBuf> {
+            let cache_artifact = cache_artifact_path(Some(fs_root_path));
+            let cache_key = default_cache_key_path(Some(fs_root_path));
+            let ssl = config::ssl_settings();
+            println!("{}",
+                     Yellow.bold().paint(format!("» Bundling {}", ident)));
+            let pkg = match PackageInstall::load(ident, Some(fs_root_path)) {
+                Ok(pkg) => pkg,
+                Err(_) => {
+                    try!(install::from_url(url,
+                                           ident,
+                                           fs_root_path,
+                                           &cache_artifact,
+                                           &cache_key,
+                                           &ssl));
+                    try!(PackageInstall::load(ident, Some(fs_root_path)))
+                }
+            };
+            let depot_client = try!(Client::new_with_ssl(url, Some(fs_root_path), &ssl));
+
+            let mut idents = try!(pkg.tdeps());
+            idents.push(pkg.ident().clone());
+            let mut artifacts = Vec::new();
+            for dep in idents.iter() {
+                output::append("packages", &dep.to_string());
+                let cached = dep.archive_name().map(|name| cache_artifact.join(name));
+                match cached {
+                    Some(ref path) if path.is_file() => artifacts.push(path.clone()),
+                    _ => {
+                        println!("{} {}", Green.bold().paint("↓ Downloading"), dep);
+                        let mut progress = ProgressBar::default();
+                        let archive = try!(depot_client.fetch_package(dep.clone(),
+                                                                      &cache_artifact,
+                                                                      Some(&mut progress)));
+                        artifacts.push(archive.path);
+                    }
+                }
+            }
+
+            let mut keys = BTreeSet::new();
+            for path in artifacts.iter() {
+                for nwr in try!(artifact::artifact_signers(path)) {
+                    let key = match SigKeyPair::get_public_key_path(&nwr, &cache_key) {
+                        Ok(key) => key,
+                        Err(_) => {
+                            let (name, rev) = try!(parse_name_with_rev(&nwr));
+                            println!("{} {} public origin key",
+                                     Green.bold().paint("↓ Downloading"),
+                                     &nwr);
+                            let mut progress = ProgressBar::default();
+                            try!(depot_client.fetch_origin_key(&name,
+                                                               &rev,
+                                                               &cache_key,
+                                                               Some(&mut progress)))
+                        }
+                    };
+                    if keys.insert(key) {
+                        output::append("keys", &nwr);
+                    }
+                    let revocation = KeyRevocation::get_path(&nwr, &cache_key);
+                    if revocation.is_file() {
+                        keys.insert(revocation);
+                    }
+                }
+            }
+
+            let dst = dst_dir.join(bundle_name(pkg.ident()));
+            let mut bundle = TarBuilder::new(try!(File::create(&dst)));
+            let manifest = format!("{}\n{}\n", BUNDLE_VERSION, pkg.ident());
+            try!(bundle.append_data(BUNDLE_FILE, 0o644, manifest.as_bytes()));
+            for path in artifacts.iter() {
+                println!("{} {}", Green.paint("☛ Adding"), path.display());
+                try!(append(&mut bundle, ARTIFACTS_DIR, path));
+            }
+            for path in keys.iter() {
+                println!("{} {}", Green.paint("☛ Adding"), path.display());
+                try!(append(&mut bundle, KEYS_DIR, path));
+            }
+            let mut file = try!(bundle.finish());
+            try!(file.flush());
+            output::record("bundle", &dst.display().to_string());
+            println!("{}",
+                     Blue.paint(format!("★ Bundled {} with {} packages into {}",
+                                        pkg.ident(),
+                                        artifacts.len(),
+                                        dst.display())));
+            Ok(dst)
+        }
+
+        fn bundle_name(ident: &PackageIdent) -> String {
+            format!("{}-{}-{}-{}-bundle.tar",
+                    ident.origin,
+                    ident.name,
+                    ident.version.as_ref().map(|v| &v[..]).unwrap_or("unknown"),
+                    ident.release.as_ref().map(|r| &r[..]).unwrap_or("unknown"))
+        }
+
+        fn append<W: Write>(bundle: &mut TarBuilder<W>, dir: &str, path: &PathBuf) -> Result<()> {
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            let mut file = try!(File::open(path));
+            let size = try!(file.metadata()).len();
+            try!(bundle.append_file(&format!("{}/{}", dir, name), 0o644, size, &mut file));
+            Ok(())
+        }
+    }
+
+    pub mod install {
+        use std::fs::{self, File};
+        use std::io::{self, BufReader, Read};
+        use std::path::Path;
+        use std::str::FromStr;
+
+        use ansi_term::Colour::{Blue, Green, Yellow};
+        use common::command::package::install;
+        use hcore::crypto::{default_cache_key_path, KeyRevocation, SigKeyPair,
+                            PUBLIC_KEY_SUFFIX};
+        use hcore::fs::cache_artifact_path;
+        use hcore::package::{PackageArchive, PackageIdent, PackageInstall};
+
+        use config;
+        use error::{Error, Result};
+        use output;
+        use tar::TarReader;
+        use super::{ARTIFACTS_DIR, BUNDLE_FILE, BUNDLE_VERSION, KEYS_DIR};
+
+        /// Installs a bundle without contacting a depot. The bundled origin keys are imported
+        /// into the key cache and every artifact is verified against them and installed, each
+        /// after the dependencies it requires.
+        pub fn start(src: &Path, url: &str, fs_root_path: &Path) -> Result<()> {
+            let cache_artifact = cache_artifact_path(Some(fs_root_path));
+            let cache_key = default_cache_key_path(Some(fs_root_path));
+            let ssl = config::ssl_settings();
+            println!("{}",
+                     Yellow.bold().paint(format!("» Installing bundle {}", src.display())));
+            try!(fs::create_dir_all(&cache_artifact));
+            try!(fs::create_dir_all(&cache_key));
+
+            let mut reader = TarReader::new(BufReader::new(try!(File::open(src))));
+            let root = try!(read_manifest(&mut reader));
+            let mut artifacts = Vec::new();
+            let mut public_keys = Vec::new();
+            let mut revocations = Vec::new();
+            while let Some(entry) = try!(reader.next_file()) {
+                let (dir, name) = try!(split_entry_name(&entry.name));
+                if dir == ARTIFACTS_DIR {
+                    let path = cache_artifact.join(name);
+                    let tmp = cache_artifact.join(format!("{}.tmp", name));
+                    {
+                        let mut file = try!(File::create(&tmp));
+                        try!(io::copy(&mut reader, &mut file));
+                        try!(file.sync_all());
+                    }
+                    try!(fs::rename(&tmp, &path));
+                    artifacts.push(path);
+                } else if dir == KEYS_DIR {
+                    let mut content = String::new();
+                    try!(reader.read_to_string(&mut content));
+                    if name.ends_with(&format!(".{}", PUBLIC_KEY_SUFFIX)) {
+                        public_keys.push(content);
+                    } else {
+                        revocations.push(content);
+                    }
+                } else {
+                    return Err(Error::BundleMalformed(format!("unexpected entry {}",
+                                                              entry.name)));
+                }
+            }
+
+            for content in public_keys.iter() {
+                let (pair, _) = try!(SigKeyPair::write_file_from_str(content, &cache_key));
+                output::append("keys", &pair.name_with_rev());
+                println!("{} {} public origin key",
+                         Green.bold().paint("☑ Cached"),
+                         pair.name_with_rev());
+            }
+            for content in revocations.iter() {
+                let revocation = try!(KeyRevocation::write_file_from_str(content, &cache_key));
+                output::append("revoked", revocation.name_with_rev());
+                println!("{} {} public origin key is revoked as of {}",
+                         Yellow.bold().paint("✗ Revoked"),
+                         revocation.name_with_rev(),
+                         revocation.revoked_at());
+            }
+
+            let mut pending = Vec::new();
+            for path in artifacts {
+                let mut archive = PackageArchive::new(path.clone());
+                let ident = try!(archive.ident());
+                let tdeps = try!(archive.tdeps());
+                pending.push((ident, tdeps, path));
+            }
+            if !pending.iter().any(|&(ref ident, _, _)| *ident == root) {
+                return Err(Error::BundleMalformed(format!("no artifact for {}", root)));
+            }
+            let count = pending.len();
+            while !pending.is_empty() {
+                let ready = pending.iter().position(|&(_, ref tdeps, _)| {
+                    tdeps.iter().all(|dep| PackageInstall::load(dep, Some(fs_root_path)).is_ok())
+                });
+                match ready {
+                    Some(idx) => {
+                        let (ident, _, path) = pending.remove(idx);
+                        try!(install::from_archive(url,
+                                                   &path,
+                                                   fs_root_path,
+                                                   &cache_artifact,
+                                                   &cache_key,
+                                                   &ssl));
+                        output::append("installed", &ident.to_string());
+                    }
+                    None => {
+                        let idents: Vec<String> =
+                            pending.iter().map(|&(ref ident, _, _)| ident.to_string()).collect();
+                        let msg = format!("dependencies of {} are neither installed nor bundled",
+                                          idents.join(", "));
+                        return Err(Error::BundleMalformed(msg));
+                    }
+                }
+            }
+            output::record("package", &root.to_string());
+            println!("{}",
+                     Blue.paint(format!("★ Install of bundle {} complete with {} packages.",
+                                        root,
+                                        count)));
+            Ok(())
+        }
+
+        fn read_manifest<R: Read>(reader: &mut TarReader<R>) -> Result<PackageIdent> {
+            match try!(reader.next_file()) {
+                Some(ref entry) if entry.name == BUNDLE_FILE => (),
+                _ => return Err(Error::BundleMalformed(format!("missing {} file", BUNDLE_FILE))),
+            }
+            let mut manifest = String::new();
+            try!(reader.read_to_string(&mut manifest));
+            let mut lines = manifest.lines();
+            match lines.next() {
+                Some(version) if version == BUNDLE_VERSION => (),
+                Some(version) => {
+                    return Err(Error::BundleMalformed(format!("unsupported version {}",
+                                                              version)))
+                }
+                None => return Err(Error::BundleMalformed(format!("empty {} file", BUNDLE_FILE))),
+            }
+            match lines.next() {
+                Some(ident) => Ok(try!(PackageIdent::from_str(ident))),
+                None => Err(Error::BundleMalformed("missing package identifier".to_string())),
+            }
+        }
+
+        /// Splits an entry name into its directory and file name, refusing any name which could
+        /// be written outside of the caches.
+        fn split_entry_name(name: &str) -> Result<(&str, &str)> {
+            let mut parts = name.splitn(2, '/');
+            match (parts.next(), parts.next()) {
+                (Some(dir), Some(file)) if !file.is_empty() && !file.contains('/') &&
+                                           !file.starts_with('.') => Ok((dir, file)),
+                _ => Err(Error::BundleMalformed(format!("unexpected entry {}", name))),
+            }
+        }
+    }
+}
+
 pub mod build {
     use std::ffi::OsString;
 
@@ -103,9 +408,610 @@ pub mod build {
     }
 }
 
+pub mod countersign {
+    use std::path::Path;
+
+    use ansi_term::Colour::{Blue, Green, Yellow};
+    use hcore::crypto::{artifact, SigKeyPair};
+
+    use error::{Error, Result};
+
+    pub fn start(origin: &SigKeyPair,
+                 src: &Path,
+                 dst: &Path,
+                 cache_key_path: &Path)
+                 -> Result<()> {
+        if src == dst {
+            return Err(Error::ArgumentError("The countersigned artifact must be written to a \
+                                             different path than the source artifact"));
+        }
+        println!("{}",
+                 Yellow.bold().paint(format!("» Countersigning {}", src.display())));
+        println!("{} {} with {} to create {}",
+                 Green.paint("☛ Countersigning"),
+                 src.display(),
+                 &origin.name_with_rev(),
+                 dst.display());
+        try!(artifact::countersign(src, dst, origin, cache_key_path));
+        println!("{}",
+                 Blue.paint(format!("★ Countersigned artifact {}.", dst.display())));
+        Ok(())
+    }
+}
+
+pub mod deps {
+    //! Prints the dependency tree of an installed package, or the tree of installed packages
+    //! which depend on it.
+    //!
+    //! # Examples
+    //!
+    //! ```bash
+    //! $ hab pkg deps core/redis
+    //! $ hab pkg rdeps core/openssl --format dot | dot -Tsvg > openssl.svg
+    //! ```
+
+    use std::collections::{BTreeMap, BTreeSet, HashMap};
+    use std::iter;
+    use std::path::Path;
+
+    use hcore::package::{PackageIdent, PackageInstall};
+    use rustc_serialize::json::{Json, ToJson};
+
+    use error::{Error, Result};
+    use output;
+
+    pub enum GraphFormat {
+        Text,
+        Json,
+        Dot,
+    }
+
+    pub fn format_for(value: &str) -> Result<GraphFormat> {
+        match value {
+            "text" => Ok(GraphFormat::Text),
+            "json" => Ok(GraphFormat::Json),
+            "dot" => Ok(GraphFormat::Dot),
+            _ => Err(Error::ArgumentError("OUTPUT_FORMAT must be one of (text, json, dot)")),
+        }
+    }
+
+    struct Node {
+        ident: PackageIdent,
+        installed: bool,
+        children: Vec<Node>,
+    }
+
+    /// The tree of dependencies of an installed package or, when reversed, the tree of installed
+    /// packages which depend on it.
+    pub struct Tree {
+        root: Node,
+        reverse: bool,
+    }
+
+    impl Tree {
+        pub fn load(ident: &PackageIdent, reverse: bool, fs_root_path: &Path) -> Result<Self> {
+            let root = try!(PackageInstall::load(ident, Some(fs_root_path))).ident().clone();
+            let (edges, installed) = if reverse {
+                try!(dependents(fs_root_path))
+            } else {
+                try!(dependencies(&root, fs_root_path))
+            };
+            Ok(Tree {
+                root: build_tree(&root, &edges, &installed, &mut Vec::new()),
+                reverse: reverse,
+            })
+        }
+
+        /// Returns the tree as indented lines, one per package.
+        pub fn to_text(&self) -> String {
+            let mut text = String::new();
+            node_text(&self.root, 0, &mut text);
+            text
+        }
+
+        /// Returns the tree as a Graphviz digraph.
+        pub fn to_dot(&self) -> String {
+            let mut pairs = BTreeSet::new();
+            collect_edges(&self.root, self.reverse, &mut pairs);
+            dot(&self.root.ident.to_string(),
+                &[self.root.ident.clone()],
+                &pairs)
+        }
+    }
+
+    impl ToJson for Tree {
+        fn to_json(&self) -> Json {
+            node_json(&self.root, if self.reverse { "rdeps" } else { "deps" })
+        }
+    }
+
+    /// Prints the tree of dependencies of an installed package or, when `reverse` is set, the
+    /// tree of installed packages which depend on it.
+    pub fn start(ident: &PackageIdent,
+                 reverse: bool,
+                 format: &GraphFormat,
+                 fs_root_path: &Path)
+                 -> Result<()> {
+        let tree = try!(Tree::load(ident, reverse, fs_root_path));
+        match *format {
+            GraphFormat::Text => print!("{}", tree.to_text()),
+            GraphFormat::Json => output::record("tree", &tree),
+            GraphFormat::Dot => print!("{}", tree.to_dot()),
+        }
+        Ok(())
+    }
+
+    /// Returns a Graphviz digraph in which each edge points from a package to one of its
+    /// dependencies.
+    pub fn dot(name: &str, nodes: &[PackageIdent], edges: &BTreeSet<(String, String)>) -> String {
+        let mut graph = format!("digraph \"{}\" {{\n", name);
+        for node in nodes.iter() {
+            graph.push_str(&format!("    \"{}\";\n", node));
+        }
+        for &(ref from, ref to) in edges.iter() {
+            graph.push_str(&format!("    \"{}\" -> \"{}\";\n", from, to));
+        }
+        graph.push_str("}\n");
+        graph
+    }
+
+    /// Returns the direct dependencies of the given package and of each of its installed
+    /// transitive dependencies, along with the set of those packages which are installed.
+    fn dependencies(root: &PackageIdent,
+                    fs_root_path: &Path)
+                    -> Result<(HashMap<PackageIdent, Vec<PackageIdent>>, BTreeSet<String>)> {
+        let mut edges = HashMap::new();
+        let mut installed = BTreeSet::new();
+        let mut pending = vec![root.clone()];
+        while let Some(ident) = pending.pop() {
+            if edges.contains_key(&ident) {
+                continue;
+            }
+            let deps = match PackageInstall::load(&ident, Some(fs_root_path)) {
+                Ok(pkg_install) => {
+                    installed.insert(ident.to_string());
+                    try!(pkg_install.deps())
+                }
+                Err(_) => Vec::new(),
+            };
+            pending.extend(deps.iter().cloned());
+            edges.insert(ident, deps);
+        }
+        Ok((edges, installed))
+    }
+
+    /// Returns the installed packages which directly depend on each installed package, along
+    /// with the set of installed packages.
+    fn dependents(fs_root_path: &Path)
+                  -> Result<(HashMap<PackageIdent, Vec<PackageIdent>>, BTreeSet<String>)> {
+        let mut edges: HashMap<PackageIdent, Vec<PackageIdent>> = HashMap::new();
+        let mut installed = BTreeSet::new();
+        for pkg_install in try!(PackageInstall::installed(Some(fs_root_path))).iter() {
+            installed.insert(pkg_install.ident().to_string());
+            for dep in try!(pkg_install.deps()).into_iter() {
+                edges.entry(dep).or_insert(Vec::new()).push(pkg_install.ident().clone());
+            }
+        }
+        Ok((edges, installed))
+    }
+
+    fn build_tree(ident: &PackageIdent,
+                  edges: &HashMap<PackageIdent, Vec<PackageIdent>>,
+                  installed: &BTreeSet<String>,
+                  ancestors: &mut Vec<PackageIdent>)
+                  -> Node {
+        ancestors.push(ident.clone());
+        let mut children = Vec::new();
+        if let Some(next) = edges.get(ident) {
+            for child in next.iter().filter(|c| !ancestors.contains(c)) {
+                children.push(build_tree(child, edges, installed, ancestors));
+            }
+        }
+        ancestors.pop();
+        Node {
+            ident: ident.clone(),
+            installed: installed.contains(&ident.to_string()),
+            children: children,
+        }
+    }
+
+    fn node_text(node: &Node, depth: usize, text: &mut String) {
+        let missing = if node.installed { "" } else { " (not installed)" };
+        let indent: String = iter::repeat("    ").take(depth).collect();
+        text.push_str(&format!("{}{}{}\n", indent, node.ident, missing));
+        for child in node.children.iter() {
+            node_text(child, depth + 1, text);
+        }
+    }
+
+    fn node_json(node: &Node, key: &str) -> Json {
+        let mut m = BTreeMap::new();
+        m.insert("ident".to_string(), node.ident.to_string().to_json());
+        m.insert("installed".to_string(), node.installed.to_json());
+        m.insert(key.to_string(),
+                 Json::Array(node.children.iter().map(|c| node_json(c, key)).collect()));
+        Json::Object(m)
+    }
+
+    fn collect_edges(node: &Node, reverse: bool, pairs: &mut BTreeSet<(String, String)>) {
+        for child in node.children.iter() {
+            if reverse {
+                pairs.insert((child.ident.to_string(), node.ident.to_string()));
+            } else {
+                pairs.insert((node.ident.to_string(), child.ident.to_string()));
+            }
+            collect_edges(child, reverse, pairs);
+        }
+    }
+}
+
+pub mod diff {
+    //! Compares two releases of a package, each either installed or a Habitat Artifact.
+    //!
+    //! # Examples
+    //!
+    //! ```bash
+    //! $ hab pkg diff core/redis/3.0.7/20160614023044 ./core-redis-3.0.7-20160615000000-x86_64-linux.hart
+    //! ```
+    //!
+    //! Will list the files added, removed or changed between the two releases, using the hashes
+    //! in `FILES` for an installed release and the archive's contents for an artifact, along
+    //! with changes to their dependencies, exposed ports and default configuration. With
+    //! `--patch`, a unified diff of each changed text file is shown too.
+
+    use std::cmp;
+    use std::collections::BTreeMap;
+    use std::fs::File;
+    use std::io::Read;
+    use std::path::{Path, PathBuf};
+    use std::str::{self, FromStr};
+
+    use ansi_term::Colour::{Blue, Green, Red, Yellow};
+    use hcore::fs::PKG_PATH;
+    use hcore::package::{ArchiveEntryKind, MetaFile, PackageArchive, PackageIdent,
+                         PackageInstall};
+    use hcore::package::install::read_files_metafile;
+
+    use error::Result;
+    use output;
+
+    /// Lines of unchanged context shown around each change in a unified diff.
+    const CONTEXT: usize = 3;
+    /// The largest product of two files' line counts which is diffed, to bound memory use.
+    const MAX_DIFF_CELLS: usize = 4_000_000;
+
+    enum Release {
+        Installed(PathBuf),
+        Archive(PackageArchive),
+    }
+
+    struct Snapshot {
+        ident: PackageIdent,
+        release: Release,
+        files: BTreeMap<String, String>,
+        deps: Vec<PackageIdent>,
+        tdeps: Vec<PackageIdent>,
+        exposes: Vec<String>,
+        config: Option<String>,
+    }
+
+    impl Snapshot {
+        /// Loads an installed release named by its identifier, or an artifact named by its path.
+        fn load(value: &str, fs_root_path: &Path, cache_key_path: &Path) -> Result<Self> {
+            if Path::new(value).is_file() {
+                let mut archive = PackageArchive::new(PathBuf::from(value));
+                let ident = try!(archive.ident());
+                let prefix = install_prefix(&ident);
+                let mut files = BTreeMap::new();
+                for entry in try!(archive.entries(&cache_key_path)).into_iter() {
+                    if let (ArchiveEntryKind::File, Some(hash)) = (entry.kind, entry.hash) {
+                        if let Some(relative) = relative_path(&prefix, &entry.path) {
+                            files.insert(relative, hash);
+                        }
+                    }
+                }
+                let exposes = try!(archive.exposes()).iter().map(|p| p.to_string()).collect();
+                Ok(Snapshot {
+                    deps: try!(archive.deps()),
+                    tdeps: try!(archive.tdeps()),
+                    exposes: exposes,
+                    config: try!(archive.config()),
+                    ident: ident,
+                    files: files,
+                    release: Release::Archive(archive),
+                })
+            } else {
+                let ident = try!(PackageIdent::from_str(value));
+                let pkg_install = try!(PackageInstall::load(&ident, Some(fs_root_path)));
+                let ident = pkg_install.ident().clone();
+                let prefix = install_prefix(&ident);
+                let installed_path = pkg_install.installed_path();
+                let mut files = BTreeMap::new();
+                let files_path = installed_path.join(MetaFile::Files.to_string());
+                for (hash, file) in try!(read_files_metafile(&files_path)).into_iter() {
+                    if let Some(relative) = relative_path(&prefix, &file) {
+                        files.insert(relative, hash);
+                    }
+                }
+                let config_path = installed_path.join(MetaFile::Config.to_string());
+                let config = if config_path.is_file() {
+                    Some(try!(read_string(&config_path)))
+                } else {
+                    None
+                };
+                Ok(Snapshot {
+                    deps: try!(pkg_install.deps()),
+                    tdeps: try!(pkg_install.tdeps()),
+                    exposes: try!(pkg_install.exposes())
+                        .into_iter()
+                        .filter(|p| !p.is_empty())
+                        .collect(),
+                    config: config,
+                    ident: ident,
+                    files: files,
+                    release: Release::Installed(fs_root_path.to_path_buf()),
+                })
+            }
+        }
+
+        /// Returns the contents of one of the release's files, given relative to its prefix.
+        fn contents(&self, relative: &str, cache_key_path: &Path) -> Result<Vec<u8>> {
+            let absolute = format!("{}{}", install_prefix(&self.ident), relative);
+            let mut buf = Vec::new();
+            match self.release {
+                Release::Installed(ref fs_root_path) => {
+                    let path = fs_root_path.join(absolute.trim_left_matches('/'));
+                    try!(try!(File::open(&path)).read_to_end(&mut buf));
+                }
+                Release::Archive(ref archive) => {
+                    try!(archive.extract_file(&absolute, &mut buf, &cache_key_path));
+                }
+            }
+            Ok(buf)
+        }
+    }
+
+    pub fn start(a: &str,
+                 b: &str,
+                 patch: bool,
+                 fs_root_path: &Path,
+                 cache_key_path: &Path)
+                 -> Result<()> {
+        let old = try!(Snapshot::load(a, fs_root_path, cache_key_path));
+        let new = try!(Snapshot::load(b, fs_root_path, cache_key_path));
+        println!("{}",
+                 Yellow.bold().paint(format!("» Comparing {} with {}", old.ident, new.ident)));
+        output::record("from", &old.ident.to_string());
+        output::record("to", &new.ident.to_string());
+
+        let mut added = Vec::new();
+        let mut removed = Vec::new();
+        let mut changed = Vec::new();
+        for (file, hash) in old.files.iter() {
+            match new.files.get(file) {
+                None => removed.push(file.clone()),
+                Some(other) if other != hash => changed.push(file.clone()),
+                Some(_) => (),
+            }
+        }
+        for file in new.files.keys() {
+            if !old.files.contains_key(file) {
+                added.push(file.clone());
+            }
+        }
+        println!("Files:");
+        print_changes(&added, &removed, &changed);
+        output::record("files_added", &added);
+        output::record("files_removed", &removed);
+        output::record("files_changed", &changed);
+
+        diff_idents("Dependencies", "deps", &old.deps, &new.deps);
+        diff_idents("Transitive Dependencies", "tdeps", &old.tdeps, &new.tdeps);
+
+        let exposes_changed = old.exposes != new.exposes;
+        println!("Exposes: {}",
+                 if exposes_changed {
+                     format!("{} → {}", join_ports(&old.exposes), join_ports(&new.exposes))
+                 } else {
+                     format!("{} (unchanged)", join_ports(&old.exposes))
+                 });
+        output::record("exposes_from", &old.exposes);
+        output::record("exposes_to", &new.exposes);
+
+        let config_changed = old.config != new.config;
+        println!("Default config: {}",
+                 if config_changed { "changed" } else { "unchanged" });
+        output::record("config_changed", &config_changed);
+
+        if patch {
+            let mut patches = BTreeMap::new();
+            if config_changed {
+                let text = unified_diff(&MetaFile::Config.to_string(),
+                                        &MetaFile::Config.to_string(),
+                                        old.config.as_ref().map(|c| c.as_str()).unwrap_or(""),
+                                        new.config.as_ref().map(|c| c.as_str()).unwrap_or(""));
+                print!("{}", text);
+                patches.insert(MetaFile::Config.to_string(), text);
+            }
+            for file in changed.iter() {
+                let before = try!(old.contents(file, cache_key_path));
+                let after = try!(new.contents(file, cache_key_path));
+                let text = match (str::from_utf8(&before), str::from_utf8(&after)) {
+                    (Ok(before), Ok(after)) if !before.contains('\0') &&
+                                               !after.contains('\0') => {
+                        unified_diff(file, file, before, after)
+                    }
+                    _ => format!("Binary files {} differ\n", file),
+                };
+                print!("{}", text);
+                patches.insert(file.clone(), text);
+            }
+            output::record("patches", &patches);
+        }
+
+        println!("{}",
+                 Blue.paint(format!("★ {} files added, {} removed and {} changed.",
+                                    added.len(),
+                                    removed.len(),
+                                    changed.len())));
+        Ok(())
+    }
+
+    fn install_prefix(ident: &PackageIdent) -> String {
+        format!("/{}/{}/", PKG_PATH, ident)
+    }
+
+    /// Returns the path of a file relative to a release's prefix, leaving out `FILES`, which
+    /// isn't listed in itself.
+    fn relative_path(prefix: &str, path: &str) -> Option<String> {
+        if !path.starts_with(prefix) {
+            return None;
+        }
+        let relative = &path[prefix.len()..];
+        if relative == MetaFile::Files.to_string() {
+            None
+        } else {
+            Some(relative.to_string())
+        }
+    }
+
+    fn read_string(path: &Path) -> Result<String> {
+        let mut content = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut content));
+        Ok(content)
+    }
+
+    fn print_changes(added: &[String], removed: &[String], changed: &[String]) {
+        if added.is_empty() && removed.is_empty() && changed.is_empty() {
+            println!("    (unchanged)");
+        }
+        for file in added.iter() {
+            println!("    {} {}", Green.paint("+"), file);
+        }
+        for file in removed.iter() {
+            println!("    {} {}", Red.paint("-"), file);
+        }
+        for file in changed.iter() {
+            println!("    {} {}", Yellow.paint("~"), file);
+        }
+    }
+
+    fn diff_idents(heading: &str, key: &str, old: &[PackageIdent], new: &[PackageIdent]) {
+        let added: Vec<String> =
+            new.iter().filter(|i| !old.contains(i)).map(|i| i.to_string()).collect();
+        let removed: Vec<String> =
+            old.iter().filter(|i| !new.contains(i)).map(|i| i.to_string()).collect();
+        println!("{}:", heading);
+        print_changes(&added, &removed, &[]);
+        output::record(&format!("{}_added", key), &added);
+        output::record(&format!("{}_removed", key), &removed);
+    }
+
+    fn join_ports(ports: &[String]) -> String {
+        if ports.is_empty() {
+            "none".to_string()
+        } else {
+            ports.join(" ")
+        }
+    }
+
+    /// Returns a unified diff of two texts, or an empty string if they are the same.
+    pub fn unified_diff(old_name: &str, new_name: &str, old: &str, new: &str) -> String {
+        let a: Vec<&str> = old.lines().collect();
+        let b: Vec<&str> = new.lines().collect();
+        if (a.len() + 1) * (b.len() + 1) > MAX_DIFF_CELLS {
+            return format!("Files {} differ (too large to show)\n", new_name);
+        }
+        let ops = diff_lines(&a, &b);
+        let changes: Vec<usize> =
+            ops.iter().enumerate().filter(|&(_, op)| op.0 != ' ').map(|(i, _)| i).collect();
+        if changes.is_empty() {
+            return String::new();
+        }
+        // The number of lines of each text which come before each operation
+        let mut a_before = Vec::with_capacity(ops.len());
+        let mut b_before = Vec::with_capacity(ops.len());
+        let (mut a_count, mut b_count) = (0, 0);
+        for op in ops.iter() {
+            a_before.push(a_count);
+            b_before.push(b_count);
+            if op.0 != '+' {
+                a_count += 1;
+            }
+            if op.0 != '-' {
+                b_count += 1;
+            }
+        }
+
+        let mut out = format!("--- a/{}\n+++ b/{}\n", old_name, new_name);
+        let mut k = 0;
+        while k < changes.len() {
+            let start = changes[k].saturating_sub(CONTEXT);
+            let mut last = changes[k];
+            k += 1;
+            // As with diff(1), hunks whose changes are at most twice the context apart are
+            // merged, so that no unchanged line is shown twice or left out between them
+            while k < changes.len() && changes[k] < last + 2 * CONTEXT + 2 {
+                last = changes[k];
+                k += 1;
+            }
+            let end = cmp::min(last + CONTEXT + 1, ops.len());
+            let hunk = &ops[start..end];
+            let a_len = hunk.iter().filter(|op| op.0 != '+').count();
+            let b_len = hunk.iter().filter(|op| op.0 != '-').count();
+            let a_start = if a_len == 0 { a_before[start] } else { a_before[start] + 1 };
+            let b_start = if b_len == 0 { b_before[start] } else { b_before[start] + 1 };
+            out.push_str(&format!("@@ -{},{} +{},{} @@\n", a_start, a_len, b_start, b_len));
+            for op in hunk.iter() {
+                out.push(op.0);
+                out.push_str(op.1);
+                out.push('\n');
+            }
+        }
+        out
+    }
+
+    /// Returns the shortest edit script turning one list of lines into another, as lines
+    /// prefixed with ' ' (kept), '-' (removed) or '+' (added).
+    pub fn diff_lines<'a>(a: &[&'a str], b: &[&'a str]) -> Vec<(char, &'a str)> {
+        let (n, m) = (a.len(), b.len());
+        // lcs[i][j] is the length of the longest common subsequence of a[i..] and b[j..]
+        let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i][j] = if a[i] == b[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    cmp::max(lcs[i + 1][j], lcs[i][j + 1])
+                };
+            }
+        }
+        let mut ops = Vec::with_capacity(n + m);
+        let (mut i, mut j) = (0, 0);
+        while i < n && j < m {
+            if a[i] == b[j] {
+                ops.push((' ', a[i]));
+                i += 1;
+                j += 1;
+            } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+                ops.push(('-', a[i]));
+                i += 1;
+            } else {
+                ops.push(('+', b[j]));
+                j += 1;
+            }
+        }
+        ops.extend(a[i..].iter().map(|l| ('-', *l)));
+        ops.extend(b[j..].iter().map(|l| ('+', *l)));
+        ops
+    }
+}
+
 pub mod exec {
     use std::env;
     use std::ffi::OsString;
+    use std::path::PathBuf;
+    use std::process::Command;
 
     use hcore::package::{PackageIdent, PackageInstall};
     use hcore::fs::find_command;
@@ -114,14 +1020,7 @@ pub mod exec {
     use exec;
 
     pub fn start(ident: &PackageIdent, command: &str, args: Vec<OsString>) -> Result<()> {
-        let pkg_install = try!(PackageInstall::load(&ident, None));
-        let env_path = try!(pkg_install.runtime_path());
-        info!("Setting: PATH='{}'", &env_path);
-        env::set_var("PATH", env_path);
-        let command = match find_command(command) {
-            Some(path) => path,
-            None => return Err(Error::ExecCommandNotFound(command.to_string())),
-        };
+        let command = try!(command_path(ident, command));
         let mut display_args = command.to_string_lossy().into_owned();
         for arg in &args {
             display_args.push(' ');
@@ -130,11 +1029,50 @@ pub mod exec {
         info!("Running: {}", display_args);
         exec::exec_command(command, args)
     }
+
+    /// Runs a command of an installed package as a child process. Unlike `start()`, this returns
+    /// once the command exits, failing unless it exited successfully.
+    pub fn run(ident: &PackageIdent, command: &str, args: Vec<OsString>) -> Result<()> {
+        let command = try!(command_path(ident, command));
+        info!("Running: {} {:?}", command.display(), &args);
+        let status = try!(Command::new(&command).args(&args).status());
+        if status.success() {
+            Ok(())
+        } else {
+            Err(Error::ExecCommandFailed(command.display().to_string(), status))
+        }
+    }
+
+    /// Puts the runtime path of an installed package on `PATH` and finds a command on it.
+    fn command_path(ident: &PackageIdent, command: &str) -> Result<PathBuf> {
+        let pkg_install = try!(PackageInstall::load(&ident, None));
+        let env_path = try!(pkg_install.runtime_path());
+        info!("Setting: PATH='{}'", &env_path);
+        env::set_var("PATH", env_path);
+        match find_command(command) {
+            Some(path) => Ok(path),
+            None => Err(Error::ExecCommandNotFound(command.to_string())),
+        }
+    }
 }
 
 pub mod export {
-    use error::Result;
-    use hcore::package::PackageIdent;
+    use std::env;
+    use std::path::Path;
+    use std::str::FromStr;
+
+    use ansi_term::Colour::{Blue, Green, Yellow};
+    use common::command::package::install;
+    use config;
+    use error::{Error, Result};
+    use hcore::crypto::default_cache_key_path;
+    use hcore::fs::cache_artifact_path;
+    use hcore::package::{PackageIdent, PackageInstall};
+    use hcore::url::default_depot_url;
+    use oci;
+    use output;
+
+    const SUP_PKG_IDENT: &'static str = "core/hab-sup";
 
     #[allow(dead_code)]
     pub struct ExportFormat {
@@ -161,15 +1099,62 @@ pub mod export {
         inner::format_for(value)
     }
 
+    /// Writes an OCI image layout tarball for an installed package to the current directory. The
+    /// image runs the package under the latest installed supervisor, which is only installed from
+    /// the depot when `install_sup` is given. No container daemon is used.
+    ///
+    /// # Failures
+    ///
+    /// * If the supervisor is not installed and `install_sup` is not given
+    pub fn oci(ident: &PackageIdent, fs_root_path: &Path, install_sup: bool) -> Result<()> {
+        let pkg = try!(PackageInstall::load(ident, Some(fs_root_path)));
+        let sup_ident = try!(PackageIdent::from_str(SUP_PKG_IDENT));
+        let sup = match PackageInstall::load(&sup_ident, Some(fs_root_path)) {
+            Ok(sup) => sup,
+            Err(_) if !install_sup => {
+                return Err(Error::SupervisorNotInstalled(sup_ident.to_string()));
+            }
+            Err(_) => {
+                println!("{} is not installed", &sup_ident);
+                println!("Searching for {} in remote {}",
+                         &sup_ident,
+                         &default_depot_url());
+                try!(install::from_url(&default_depot_url(),
+                                       &sup_ident,
+                                       fs_root_path,
+                                       &cache_artifact_path(Some(fs_root_path)),
+                                       &default_cache_key_path(Some(fs_root_path)),
+                                       &config::ssl_settings()));
+                try!(PackageInstall::load(&sup_ident, Some(fs_root_path)))
+            }
+        };
+        let dst = try!(env::current_dir()).join(oci::tarball_name(pkg.ident()));
+        println!("{}",
+                 Yellow.bold().paint(format!("» Exporting {} as an OCI image", pkg.ident())));
+        println!("{} {} with {}",
+                 Green.paint("☛ Writing layers for"),
+                 pkg.ident(),
+                 sup.ident());
+        let digest = try!(oci::export(&pkg, &sup, fs_root_path, &dst));
+        println!("{} {}", Green.paint("☛ Image manifest"), digest);
+        output::record("image", &dst.display().to_string());
+        output::record("digest", &digest);
+        println!("{}",
+                 Blue.paint(format!("★ Exported OCI image layout {}", dst.display())));
+        Ok(())
+    }
+
     #[cfg(target_os = "linux")]
     mod inner {
         use command::pkg::exec;
         use common::command::package::install;
+        use config;
         use error::{Error, Result};
         use hcore::crypto::default_cache_key_path;
         use hcore::fs::{cache_artifact_path, FS_ROOT_PATH};
         use hcore::package::{PackageIdent, PackageInstall};
         use hcore::url::default_depot_url;
+        use output;
         use std::ffi::OsString;
         use std::path::Path;
         use std::str::FromStr;
@@ -215,55 +1200,402 @@ pub mod export {
                                            format_ident,
                                            Path::new(FS_ROOT_PATH),
                                            &cache_artifact_path(None),
-                                           &default_cache_key_path(None)));
+                                           &default_cache_key_path(None),
+                                           &config::ssl_settings()));
                 }
             }
             let pkg_arg = OsString::from(&ident.to_string());
+            if output::is_json() {
+                // An exporter which replaced this process would leave the result document
+                // unwritten, so it is run as a child instead
+                output::record("package", &ident.to_string());
+                output::record("exporter", &format_ident.to_string());
+                return exec::run(&format_ident, &format.cmd(), vec![pkg_arg]);
+            }
             exec::start(&format_ident, &format.cmd(), vec![pkg_arg])
         }
     }
 
-    #[cfg(not(target_os = "linux"))]
-    mod inner {
-        use ansi_term::Colour::Yellow;
-        use error::{Error, Result};
-        use hcore::package::PackageIdent;
-        use std::env;
-        use super::ExportFormat;
+    #[cfg(not(target_os = "linux"))]
+    mod inner {
+        use ansi_term::Colour::Yellow;
+        use error::{Error, Result};
+        use hcore::package::PackageIdent;
+        use std::env;
+        use super::ExportFormat;
+
+        pub fn format_for(value: &str) -> Result<ExportFormat> {
+            let msg = format!("∅ Exporting {} packages from this operating system is not yet \
+                               supported. Try running this command again on a 64-bit Linux \
+                               operating system.\n",
+                              value);
+            println!("{}", Yellow.bold().paint(msg));
+            let e = Error::UnsupportedExportFormat(value.to_string());
+            Err(e)
+        }
+
+        pub fn start(_ident: &PackageIdent, _format: &ExportFormat) -> Result<()> {
+            let subcmd = env::args().nth(1).unwrap_or("<unknown>".to_string());
+            let subsubcmd = env::args().nth(2).unwrap_or("<unknown>".to_string());
+            let msg = format!("∅ Exporting packages from this operating system is not yet \
+                               supported. Try running this command again on a 64-bit Linux \
+                               operating system.\n");
+            println!("{}", Yellow.bold().paint(msg));
+            Err(Error::SubcommandNotSupported(format!("{} {}", subcmd, subsubcmd)))
+
+        }
+    }
+}
+
+pub mod gc {
+    use std::collections::HashSet;
+    use std::path::Path;
+
+    use ansi_term::Colour::{Blue, Green, Yellow};
+    use hcore::package::{PackageIdent, PackageInstall};
+
+    use error::Result;
+    use output;
+    use super::uninstall::in_use_by_services;
+
+    /// Removes every installed package which isn't one of the given roots, a transitive
+    /// dependency of one, or in use by a running service. A root which isn't fully qualified
+    /// keeps only its latest installed release.
+    pub fn start(roots: &[PackageIdent], dry_run: bool, fs_root_path: &Path) -> Result<()> {
+        println!("{}",
+                 Yellow.bold().paint(format!("» Removing packages not required by {}",
+                                             roots.iter()
+                                                 .map(|r| r.to_string())
+                                                 .collect::<Vec<String>>()
+                                                 .join(", "))));
+        let installed = try!(PackageInstall::installed(Some(fs_root_path)));
+        let mut keep: HashSet<PackageIdent> =
+            try!(in_use_by_services(&installed, fs_root_path)).keys().cloned().collect();
+        for root in roots.iter() {
+            let pkg_install = try!(PackageInstall::load(root, Some(fs_root_path)));
+            keep.insert(pkg_install.ident().clone());
+            keep.extend(try!(pkg_install.tdeps()).into_iter());
+        }
+
+        let mut removed = 0;
+        for pkg_install in installed.iter().filter(|p| !keep.contains(p.ident())) {
+            if dry_run {
+                println!("{} {}", Green.paint("» Would remove"), pkg_install.ident());
+            } else {
+                try!(pkg_install.remove());
+                println!("{} {}", Green.bold().paint("✓ Removed"), pkg_install.ident());
+            }
+            output::append("removed", &pkg_install.ident().to_string());
+            removed += 1;
+        }
+        output::record("dry_run", &dry_run);
+        let verb = if dry_run { "Would remove" } else { "Removed" };
+        println!("{}",
+                 Blue.paint(format!("★ {} {} of {} installed packages.",
+                                    verb,
+                                    removed,
+                                    installed.len())));
+        Ok(())
+    }
+}
+
+pub mod hash {
+    use hcore::crypto::hash::{self, HashType};
+
+    use error::Result;
+    use output;
+
+    pub fn start(src: &str, hash_type: HashType) -> Result<()> {
+        let h = try!(hash::hash_file_with_type(&src, hash_type));
+        output::record("hash", &h);
+        println!("{}", h);
+        Ok(())
+    }
+}
+
+pub mod index {
+    use std::collections::BTreeMap;
+    use std::path::Path;
+
+    use ansi_term::Colour::{Blue, Yellow};
+    use common::local_depot::{INDEX_FILE, LocalDepot};
+    use rustc_serialize::json::{Json, ToJson};
+
+    use error::Result;
+    use output;
+
+    pub fn start<P1: AsRef<Path> + ?Sized, P2: AsRef<Path> + ?Sized>(dir: &P1,
+                                                                     cache_key_path: &P2)
+                                                                     -> Result<()> {
+        println!("{}",
+                 Yellow.bold()
+                     .paint(format!("» Indexing local depot {}", dir.as_ref().display())));
+        let count = try!(LocalDepot::generate_index(dir, cache_key_path));
+        if output::is_json() {
+            let depot = try!(LocalDepot::open(dir.as_ref()));
+            for &(ref ident, ref artifact) in depot.packages().iter() {
+                let mut m = BTreeMap::new();
+                m.insert("ident".to_string(), ident.to_string().to_json());
+                m.insert("artifact".to_string(), artifact.display().to_string().to_json());
+                output::append("packages", &Json::Object(m));
+            }
+        }
+        output::record("index", &dir.as_ref().join(INDEX_FILE).display().to_string());
+        println!("{}",
+                 Blue.paint(format!("★ Wrote {} with {} packages",
+                                    dir.as_ref().join(INDEX_FILE).display(),
+                                    count)));
+        Ok(())
+    }
+}
+
+pub mod info {
+    //! Shows the metadata a [Depot](../depot) holds about a package.
+    //!
+    //! # Examples
+    //!
+    //! ```bash
+    //! $ hab pkg info core/redis -u http://localhost:9632
+    //! ```
+    //!
+    //! Will show the dependencies, exposed ports, checksum and views of the latest release of
+    //! `core/redis`, along with every release of it in the Depot.
+
+    use depot_client::Client;
+    use std::fmt;
+
+    use hcore::package::PackageIdent;
+
+    use config;
+    use error::Result;
+    use output;
+
+    pub fn start(url: &str, ident: &PackageIdent) -> Result<()> {
+        let depot_client = try!(Client::new_with_ssl(url, None, &config::ssl_settings()));
+        let package = try!(depot_client.show_package(ident.clone()));
+        let release = package.get_ident();
+        let views = try!(depot_client.package_views(release));
+        let all = PackageIdent::new(release.get_origin(), release.get_name(), None, None);
+        let versions = try!(depot_client.list_package_versions(&all));
+
+        output::record("package", &release.to_string());
+        output::record("checksum", package.get_checksum());
+        output::record("exposes", &package.get_exposes().to_vec());
+        output::record("views", &views);
+        output::record("deps", &to_strings(package.get_deps()));
+        output::record("tdeps", &to_strings(package.get_tdeps()));
+        output::record("versions", &to_strings(&versions));
+        println!("Package: {}", release);
+        println!("Checksum: {}", package.get_checksum());
+        println!("Exposes: {}",
+                 join_or_none(package.get_exposes().iter().map(|p| p.to_string()).collect()));
+        println!("Views: {}", join_or_none(views));
+        print_idents("Dependencies", package.get_deps());
+        print_idents("Transitive Dependencies", package.get_tdeps());
+        print_idents("Available Versions", &versions);
+        Ok(())
+    }
+
+    fn to_strings<T: fmt::Display>(idents: &[T]) -> Vec<String> {
+        idents.iter().map(|i| i.to_string()).collect()
+    }
+
+    fn join_or_none(values: Vec<String>) -> String {
+        if values.is_empty() {
+            "none".to_string()
+        } else {
+            values.join(", ")
+        }
+    }
+
+    fn print_idents<T: fmt::Display>(heading: &str, idents: &[T]) {
+        println!("{}:", heading);
+        if idents.is_empty() {
+            println!("    none");
+        }
+        for ident in idents.iter() {
+            println!("    {}", ident);
+        }
+    }
+}
 
-        pub fn format_for(value: &str) -> Result<ExportFormat> {
-            let msg = format!("∅ Exporting {} packages from this operating system is not yet \
-                               supported. Try running this command again on a 64-bit Linux \
-                               operating system.\n",
-                              value);
-            println!("{}", Yellow.bold().paint(msg));
-            let e = Error::UnsupportedExportFormat(value.to_string());
-            Err(e)
+pub mod inspect {
+    use std::io::{self, Write};
+    use std::path::Path;
+
+    use std::collections::BTreeMap;
+
+    use ansi_term::Colour::{Blue, Green, Yellow};
+    use hcore::package::{ArchiveEntry, ArchiveEntryKind, PackageArchive};
+    use rustc_serialize::json::{Json, ToJson};
+
+    use error::Result;
+    use output;
+
+    pub fn start(src: &Path, file: Option<&str>, cache: &Path) -> Result<()> {
+        let archive = PackageArchive::new(src);
+        output::record("artifact", &src.display().to_string());
+        match file {
+            Some(file) if output::is_json() => {
+                let mut content = Vec::new();
+                try!(archive.extract_file(file, &mut content, &cache));
+                try!(output::content("content", &String::from_utf8_lossy(&content)));
+            }
+            Some(file) => {
+                // Nothing but the file's contents may be written to stdout
+                let stdout = io::stdout();
+                let mut handle = stdout.lock();
+                try!(archive.extract_file(file, &mut handle, &cache));
+                try!(handle.flush());
+            }
+            None => {
+                println!("{}",
+                         Yellow.bold().paint(format!("» Inspecting artifact {}", &src.display())));
+                let entries = try!(archive.entries(&cache));
+                println!("{} {}", Green.bold().paint("✓ Verified"), &src.display());
+                for entry in entries.iter() {
+                    output::append("entries", &entry_json(entry));
+                    println!("{}", format_entry(entry));
+                }
+                println!("{}",
+                         Blue.paint(format!("★ Listed {} entries in {}.",
+                                            entries.len(),
+                                            &src.display())));
+            }
         }
+        Ok(())
+    }
 
-        pub fn start(_ident: &PackageIdent, _format: &ExportFormat) -> Result<()> {
-            let subcmd = env::args().nth(1).unwrap_or("<unknown>".to_string());
-            let subsubcmd = env::args().nth(2).unwrap_or("<unknown>".to_string());
-            let msg = format!("∅ Exporting packages from this operating system is not yet \
-                               supported. Try running this command again on a 64-bit Linux \
-                               operating system.\n");
-            println!("{}", Yellow.bold().paint(msg));
-            Err(Error::SubcommandNotSupported(format!("{} {}", subcmd, subsubcmd)))
+    fn format_entry(entry: &ArchiveEntry) -> String {
+        let (kind, link) = match entry.kind {
+            ArchiveEntryKind::File => ('-', String::new()),
+            ArchiveEntryKind::Directory => ('d', String::new()),
+            ArchiveEntryKind::Symlink(ref target) => ('l', format!(" -> {}", target)),
+            ArchiveEntryKind::Hardlink(ref target) => ('h', format!(" => {}", target)),
+            ArchiveEntryKind::Other => ('?', String::new()),
+        };
+        let hash = entry.hash.as_ref().map(|h| h.as_str()).unwrap_or("-");
+        format!("{}{} {:>10} {:<64} {}{}",
+                kind,
+                format_mode(entry.mode),
+                entry.size,
+                hash,
+                entry.path,
+                link)
+    }
+
+    fn entry_json(entry: &ArchiveEntry) -> Json {
+        let (kind, target) = match entry.kind {
+            ArchiveEntryKind::File => ("file", None),
+            ArchiveEntryKind::Directory => ("directory", None),
+            ArchiveEntryKind::Symlink(ref target) => ("symlink", Some(target.clone())),
+            ArchiveEntryKind::Hardlink(ref target) => ("hardlink", Some(target.clone())),
+            ArchiveEntryKind::Other => ("other", None),
+        };
+        let mut m = BTreeMap::new();
+        m.insert("path".to_string(), entry.path.to_json());
+        m.insert("kind".to_string(), kind.to_json());
+        m.insert("mode".to_string(), entry.mode.to_json());
+        m.insert("size".to_string(), entry.size.to_json());
+        m.insert("hash".to_string(), entry.hash.to_json());
+        m.insert("target".to_string(), target.to_json());
+        Json::Object(m)
+    }
 
+    fn format_mode(mode: u32) -> String {
+        let mut out = String::with_capacity(9);
+        for &(bit, c) in [(0o400, 'r'),
+                          (0o200, 'w'),
+                          (0o100, 'x'),
+                          (0o040, 'r'),
+                          (0o020, 'w'),
+                          (0o010, 'x'),
+                          (0o004, 'r'),
+                          (0o002, 'w'),
+                          (0o001, 'x')]
+            .iter() {
+            out.push(if mode & bit != 0 { c } else { '-' });
         }
+        out
     }
 }
 
-pub mod hash {
-    use hcore::crypto::hash;
+pub mod list {
+    //! Lists installed packages.
+    //!
+    //! # Examples
+    //!
+    //! ```bash
+    //! $ hab pkg list --origin core --name redis
+    //! ```
+
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::path::Path;
+
+    use hcore::package::{Identifiable, PackageInstall};
+    use rustc_serialize::json::{Json, ToJson};
 
     use error::Result;
+    use output;
+    use super::deps::{dot, GraphFormat};
 
-    pub fn start(src: &str) -> Result<()> {
-        let h = try!(hash::hash_file(&src));
-        println!("{}", h);
+    /// Prints the installed packages, optionally only those of an origin and/or name. As a
+    /// Graphviz digraph, each listed package has an edge to each of its direct dependencies.
+    pub fn start(origin: Option<&str>,
+                 name: Option<&str>,
+                 format: &GraphFormat,
+                 fs_root_path: &Path)
+                 -> Result<()> {
+        let installed = try!(installed(origin, name, fs_root_path));
+        match *format {
+            GraphFormat::Text => {
+                for pkg_install in installed.iter() {
+                    println!("{}", pkg_install.ident());
+                }
+            }
+            GraphFormat::Json => output::record("packages", &to_json(&installed)),
+            GraphFormat::Dot => print!("{}", try!(to_dot(&installed))),
+        }
         Ok(())
     }
+
+    /// Returns the installed packages, optionally only those of an origin and/or name.
+    pub fn installed(origin: Option<&str>,
+                     name: Option<&str>,
+                     fs_root_path: &Path)
+                     -> Result<Vec<PackageInstall>> {
+        Ok(try!(PackageInstall::installed(Some(fs_root_path)))
+            .into_iter()
+            .filter(|p| origin.map_or(true, |o| p.ident().origin() == o))
+            .filter(|p| name.map_or(true, |n| p.ident().name() == n))
+            .collect())
+    }
+
+    pub fn to_json(installed: &[PackageInstall]) -> Json {
+        let packages = installed.iter()
+            .map(|p| {
+                let ident = p.ident();
+                let mut m = BTreeMap::new();
+                m.insert("origin".to_string(), ident.origin().to_json());
+                m.insert("name".to_string(), ident.name().to_json());
+                m.insert("version".to_string(), ident.version().to_json());
+                m.insert("release".to_string(), ident.release().to_json());
+                Json::Object(m)
+            })
+            .collect();
+        Json::Array(packages)
+    }
+
+    pub fn to_dot(installed: &[PackageInstall]) -> Result<String> {
+        let mut edges = BTreeSet::new();
+        for pkg_install in installed.iter() {
+            for dep in try!(pkg_install.deps()).iter() {
+                edges.insert((pkg_install.ident().to_string(), dep.to_string()));
+            }
+        }
+        let nodes: Vec<_> = installed.iter().map(|p| p.ident().clone()).collect();
+        Ok(dot("installed", &nodes, &edges))
+    }
 }
 
 pub mod path {
@@ -272,37 +1604,238 @@ pub mod path {
     use hcore::package::{PackageIdent, PackageInstall};
 
     use error::Result;
+    use output;
 
     pub fn start(ident: &PackageIdent, fs_root_path: &Path) -> Result<()> {
         let pkg_install = try!(PackageInstall::load(ident, Some(fs_root_path)));
+        output::record("package", &pkg_install.ident().to_string());
+        output::record("path", &pkg_install.installed_path().display().to_string());
         println!("{}", pkg_install.installed_path().display());
         Ok(())
     }
 }
 
+pub mod search {
+    //! Searches a [Depot](../depot) for packages.
+    //!
+    //! # Examples
+    //!
+    //! ```bash
+    //! $ hab pkg search core/red -u http://localhost:9632
+    //! ```
+    //!
+    //! Will list the packages whose identifiers start with `core/red`, one page at a time.
+
+    use ansi_term::Colour::Blue;
+    use depot_client::Client;
+
+    use config;
+    use error::Result;
+    use output;
+
+    pub fn start(url: &str, query: &str, offset: usize) -> Result<()> {
+        let depot_client = try!(Client::new_with_ssl(url, None, &config::ssl_settings()));
+        let (packages, next) = try!(depot_client.search_package(query, offset));
+        let matches: Vec<String> = packages.iter().map(|p| p.to_string()).collect();
+        output::record("packages", &matches);
+        output::record("next_offset", &next);
+        for package in packages.iter() {
+            println!("{}", package);
+        }
+        match next {
+            Some(next) => {
+                println!("{}",
+                         Blue.paint(format!("★ More results are available; list them with \
+                                             `hab pkg search {} --offset {}`.",
+                                            query,
+                                            next)))
+            }
+            None if packages.is_empty() => {
+                println!("{}",
+                         Blue.paint(format!("★ No packages found matching {}.", query)))
+            }
+            None => (),
+        }
+        Ok(())
+    }
+}
+
 pub mod sign {
+    use std::fs;
     use std::path::Path;
 
     use ansi_term::Colour::{Blue, Green, Yellow};
     use hcore::crypto::{artifact, SigKeyPair};
+    use hcore::crypto::artifact::Compression;
+    use hcore::crypto::hash::HashType;
 
     use error::Result;
+    use output;
 
-    pub fn start(origin: &SigKeyPair, src: &Path, dst: &Path) -> Result<()> {
+    pub fn start(origin: &SigKeyPair,
+                 src: &Path,
+                 dst: &Path,
+                 compression: Option<Compression>,
+                 hash_type: HashType)
+                 -> Result<()> {
         println!("{}",
                  Yellow.bold().paint(format!("» Signing {}", src.display())));
+        let recompressed = match compression {
+            Some(c) if try!(Compression::detect(src)) != Some(c) => {
+                let tmp = dst.with_extension(format!("{}.tmp", c));
+                println!("{} {} with {}",
+                         Green.paint("☛ Compressing"),
+                         src.display(),
+                         c);
+                try!(artifact::recompress(src, &tmp, c));
+                Some(tmp)
+            }
+            _ => None,
+        };
         println!("{} {} with {} to create {}",
                  Green.paint("☛ Signing"),
                  src.display(),
                  &origin.name_with_rev(),
                  dst.display());
-        try!(artifact::sign(src, dst, origin));
+        let result = match recompressed {
+            Some(ref tmp) => artifact::sign_with_hash_type(tmp, dst, origin, hash_type),
+            None => artifact::sign_with_hash_type(src, dst, origin, hash_type),
+        };
+        if let Some(ref tmp) = recompressed {
+            try!(fs::remove_file(tmp));
+        }
+        try!(result);
+        output::record("artifact", &dst.display().to_string());
+        output::record("signer", &origin.name_with_rev());
         println!("{}",
                  Blue.paint(format!("★ Signed artifact {}.", dst.display())));
         Ok(())
     }
 }
 
+pub mod uninstall {
+    use std::collections::HashMap;
+    use std::fs::{self, File};
+    use std::io::Read;
+    use std::path::Path;
+    use std::str::FromStr;
+
+    use ansi_term::Colour::{Blue, Yellow};
+    use hcore::fs::{SVC_IDENT_FILE, SVC_PATH, SVC_PID_FILE};
+    use hcore::package::{PackageIdent, PackageInstall};
+
+    use error::{Error, Result};
+    use output;
+
+    /// Removes an installed package. A package listed in the `TDEPS` of another installed
+    /// package is only removed when `force` is set, and a package in use by a running service
+    /// is always left alone.
+    pub fn start(ident: &PackageIdent, force: bool, fs_root_path: &Path) -> Result<()> {
+        let pkg_install = try!(PackageInstall::load(ident, Some(fs_root_path)));
+        let ident = pkg_install.ident();
+        output::record("package", &ident.to_string());
+        output::record("removed", &false);
+        println!("{}",
+                 Yellow.bold().paint(format!("» Uninstalling {}", ident)));
+        let installed = try!(PackageInstall::installed(Some(fs_root_path)));
+        if let Some(service) = try!(in_use_by_services(&installed, fs_root_path)).get(ident) {
+            output::record("in_use_by", service);
+            println!("{}",
+                     Blue.paint(format!("★ {} is in use by the running {} service, leaving it \
+                                         installed.",
+                                        ident,
+                                        service)));
+            return Ok(());
+        }
+        let dependents = try!(dependents_of(ident, &installed));
+        if !dependents.is_empty() {
+            let dependents: Vec<String> = dependents.iter().map(|d| d.to_string()).collect();
+            if !force {
+                return Err(Error::PackageRequired(ident.to_string(), dependents));
+            }
+            println!("{} {} although it is required by {}",
+                     Yellow.bold().paint("☛ Forcing removal of"),
+                     ident,
+                     dependents.join(", "));
+        }
+        try!(pkg_install.remove());
+        output::record("removed", &true);
+        println!("{}", Blue.paint(format!("★ Uninstalled {}.", ident)));
+        Ok(())
+    }
+
+    /// Returns the installed packages which list the given package in their `TDEPS`.
+    pub fn dependents_of(ident: &PackageIdent,
+                         installed: &[PackageInstall])
+                         -> Result<Vec<PackageIdent>> {
+        let mut dependents = Vec::new();
+        for pkg_install in installed.iter() {
+            if try!(pkg_install.tdeps()).contains(ident) {
+                dependents.push(pkg_install.ident().clone());
+            }
+        }
+        Ok(dependents)
+    }
+
+    /// Returns the installed packages which are run by a Supervisor, or are a transitive
+    /// dependency of one being run, each mapped to the name of a service using it.
+    ///
+    /// A service is running when its PID file names a live process. A service whose package
+    /// identifier wasn't recorded by its Supervisor keeps every installed release of its name.
+    pub fn in_use_by_services(installed: &[PackageInstall],
+                              fs_root_path: &Path)
+                              -> Result<HashMap<PackageIdent, String>> {
+        let mut in_use = HashMap::new();
+        for (service, ident) in try!(running_services(fs_root_path)) {
+            let running = installed.iter().filter(|p| {
+                match ident {
+                    Some(ref ident) => p.ident() == ident,
+                    None => p.ident().name == service,
+                }
+            });
+            for pkg_install in running {
+                in_use.insert(pkg_install.ident().clone(), service.clone());
+                for tdep in try!(pkg_install.tdeps()) {
+                    in_use.insert(tdep, service.clone());
+                }
+            }
+        }
+        Ok(in_use)
+    }
+
+    fn running_services(fs_root_path: &Path) -> Result<Vec<(String, Option<PackageIdent>)>> {
+        let svc_root = fs_root_path.join(SVC_PATH);
+        let mut services = Vec::new();
+        if !svc_root.is_dir() {
+            return Ok(services);
+        }
+        for entry in try!(fs::read_dir(&svc_root)) {
+            let svc_path = try!(entry).path();
+            let pid = match read_file(&svc_path.join(SVC_PID_FILE)) {
+                Some(pid) => pid,
+                None => continue,
+            };
+            if !Path::new("/proc").join(pid.trim()).is_dir() {
+                debug!("Service at {} has a stale PID file", svc_path.display());
+                continue;
+            }
+            let service = svc_path.file_name().unwrap().to_string_lossy().into_owned();
+            let ident = read_file(&svc_path.join(SVC_IDENT_FILE))
+                .and_then(|i| PackageIdent::from_str(i.trim()).ok());
+            services.push((service, ident));
+        }
+        Ok(services)
+    }
+
+    fn read_file(path: &Path) -> Option<String> {
+        let mut content = String::new();
+        match File::open(path).and_then(|mut f| f.read_to_string(&mut content)) {
+            Ok(_) => Some(content),
+            Err(_) => None,
+        }
+    }
+}
+
 pub mod upload {
     //! Uploads a package to a [Depot](../depot).
     //!
@@ -329,7 +1862,9 @@ pub mod upload {
     use depot_client::{self, Client};
     use hyper::status::StatusCode;
 
+    use config;
     use error::{Error, Result};
+    use output;
 
     /// Upload a package from the cache to a Depot. The latest version/release of the package
     /// will be uploaded if not specified.
@@ -343,7 +1878,7 @@ pub mod upload {
         let mut archive = PackageArchive::new(PathBuf::from(archive_path.as_ref()));
         println!("{}",
                  Yellow.bold().paint(format!("» Uploading {}", archive_path.as_ref().display())));
-        let depot_client = try!(Client::new(url, None));
+        let depot_client = try!(Client::new_with_ssl(url, None, &config::ssl_settings()));
         let tdeps = try!(archive.tdeps());
         for dep in tdeps.into_iter() {
             match depot_client.show_package(dep.clone()) {
@@ -360,7 +1895,10 @@ pub mod upload {
         }
         let ident = try!(archive.ident());
         match depot_client.show_package(ident.clone()) {
-            Ok(_) => println!("{} {}", Green.paint("→ Exists"), &ident),
+            Ok(_) => {
+                output::append("existing", &ident.to_string());
+                println!("{} {}", Green.paint("→ Exists"), &ident);
+            }
             Err(_) => {
                 try!(upload_into_depot(&depot_client, token, &ident, &mut archive));
             }
@@ -379,10 +1917,11 @@ pub mod upload {
                  Green.bold().paint("↑ Uploading"),
                  archive.path.display());
         let mut progress = ProgressBar::default();
-        match depot_client.put_package(&mut archive, token, Some(&mut progress)) {
-            Ok(()) => (),
+        let uploaded = match depot_client.put_package(&mut archive, token, Some(&mut progress)) {
+            Ok(()) => true,
             Err(depot_client::Error::HTTP(StatusCode::Conflict)) => {
                 println!("Package already exists on remote; skipping.");
+                false
             }
             Err(depot_client::Error::HTTP(StatusCode::UnprocessableEntity)) => {
                 return Err(Error::PackageArchiveMalformed(format!("{}", archive.path.display())));
@@ -396,6 +1935,11 @@ pub mod upload {
                 return Err(Error::from(e));
             }
         };
+        if uploaded {
+            output::append("uploaded", &ident.to_string());
+        } else {
+            output::append("existing", &ident.to_string());
+        }
         println!("{} {}", Green.bold().paint("✓ Uploaded"), ident);
         Ok(())
     }
@@ -437,20 +1981,559 @@ pub mod verify {
     use std::path::Path;
 
     use ansi_term::Colour::{Blue, Green, Yellow};
-    use hcore::crypto::artifact;
+    use hcore::crypto::artifact::{self, SignaturePolicy};
 
     use error::Result;
+    use output;
 
-    pub fn start(src: &Path, cache: &Path) -> Result<()> {
+    pub fn start(src: &Path, cache: &Path, policy: Option<&SignaturePolicy>) -> Result<()> {
         println!("{}",
                  Yellow.bold().paint(format!("» Verifying artifact {}", &src.display())));
-        let (name_with_rev, hash) = try!(artifact::verify(src, cache));
+        let (signers, hash) = match policy {
+            Some(policy) => try!(artifact::verify_with_policy(src, cache, policy)),
+            None => {
+                let (name_with_rev, hash) = try!(artifact::verify(src, cache));
+                (vec![name_with_rev], hash)
+            }
+        };
+        output::record("checksum", &hash);
+        output::record("signers", &signers);
         println!("{} checksum {} signed with {}",
                  Green.bold().paint("✓ Verifed"),
                  &hash,
-                 &name_with_rev);
+                 signers.join(", "));
         println!("{}",
                  Blue.paint(format!("★ Verified artifact {}.", &src.display())));
         Ok(())
     }
 }
+
+pub mod verify_installed {
+    use std::path::Path;
+
+    use std::collections::BTreeMap;
+
+    use ansi_term::Colour::{Blue, Green, Red, Yellow};
+    use hcore;
+    use hcore::package::{MetaFile, PackageIdent, PackageInstall};
+    use rustc_serialize::json::{Json, ToJson};
+
+    use error::{Error, Result};
+    use output;
+
+    /// Checks the files of an installed package, or of every installed package when no package
+    /// identifier is given, against the hashes in their signed `FILES` metafiles. Packages
+    /// without a `FILES` metafile are skipped when checking every installed package.
+    pub fn start(ident: Option<&PackageIdent>, fs_root_path: &Path, cache: &Path) -> Result<()> {
+        let pkg_installs = match ident {
+            Some(ident) => vec![try!(PackageInstall::load(ident, Some(fs_root_path)))],
+            None => try!(PackageInstall::installed(Some(fs_root_path))),
+        };
+        println!("{}",
+                 Yellow.bold().paint(format!("» Verifying {} installed package(s)",
+                                             pkg_installs.len())));
+        let mut failed = 0;
+        for pkg_install in pkg_installs.iter() {
+            let verification = match pkg_install.verify(cache) {
+                Ok(verification) => verification,
+                Err(hcore::Error::MetaFileNotFound(MetaFile::Files)) if ident.is_none() => {
+                    println!("{} {} which has no {} metafile",
+                             Yellow.bold().paint("☛ Skipping"),
+                             pkg_install.ident(),
+                             MetaFile::Files);
+                    output::append("skipped", &pkg_install.ident().to_string());
+                    continue;
+                }
+                Err(e) => return Err(Error::from(e)),
+            };
+            if verification.is_ok() {
+                println!("{} {}", Green.bold().paint("✓ Verified"), pkg_install.ident());
+                output::append("verified", &pkg_install.ident().to_string());
+                continue;
+            }
+            failed += 1;
+            let mut m = BTreeMap::new();
+            m.insert("package".to_string(), pkg_install.ident().to_string().to_json());
+            m.insert("modified".to_string(), verification.modified.to_json());
+            m.insert("missing".to_string(), verification.missing.to_json());
+            m.insert("extra".to_string(), verification.extra.to_json());
+            output::append("failed", &Json::Object(m));
+            println!("{} {}", Red.bold().paint("✗ Failed"), pkg_install.ident());
+            for file in verification.modified.iter() {
+                println!("    {} {}", Red.paint("modified"), file);
+            }
+            for file in verification.missing.iter() {
+                println!("    {} {}", Red.paint("missing"), file);
+            }
+            for file in verification.extra.iter() {
+                println!("    {} {}", Red.paint("extra"), file);
+            }
+        }
+        if failed > 0 {
+            return Err(Error::InstallVerificationFailed(failed));
+        }
+        println!("{}",
+                 Blue.paint(format!("★ Verified {} installed package(s).",
+                                    pkg_installs.len())));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::str::FromStr;
+
+    use common::local_depot::{LocalDepot, KEYS_DIR};
+    use hcore::crypto::default_cache_key_path;
+    use hcore::fs::{cache_artifact_path, PKG_PATH, SVC_IDENT_FILE, SVC_PATH, SVC_PID_FILE};
+    use hcore::package::{PackageIdent, PackageInstall};
+    use hcore::package::install::INSTALL_COMPLETE_FILE;
+    use libc;
+    use tempdir::TempDir;
+
+    use rustc_serialize::json::ToJson;
+
+    use error::Error;
+    use output;
+    use tar::TarBuilder;
+    use super::{bundle, deps, diff, gc, list, path, uninstall};
+
+    const POSSUMS: &'static str = "happyhumans/possums/8.1.4/20160427165340";
+    const POSSUMS_HART: &'static str = "happyhumans-possums-8.1.4-20160427165340-x86_64-linux.hart";
+    const POSSUMS_KEY: &'static str = "happyhumans-20160424223347.pub";
+
+    fn fake_install(fs_root: &TempDir, ident: &str, tdeps: &[&str]) -> PathBuf {
+        let path = fs_root.path().join(PKG_PATH).join(ident);
+        fs::create_dir_all(&path).unwrap();
+        File::create(path.join(INSTALL_COMPLETE_FILE)).unwrap();
+        File::create(path.join("IDENT")).unwrap().write_all(ident.as_bytes()).unwrap();
+        if !tdeps.is_empty() {
+            File::create(path.join("DEPS")).unwrap().write_all(tdeps[0].as_bytes()).unwrap();
+            File::create(path.join("TDEPS"))
+                .unwrap()
+                .write_all(tdeps.join("\n").as_bytes())
+                .unwrap();
+        }
+        path
+    }
+
+    /// Records a service as being run by this process, so that it counts as running.
+    fn fake_service(fs_root: &TempDir, service: &str, ident: &str) {
+        let path = fs_root.path().join(SVC_PATH).join(service);
+        fs::create_dir_all(&path).unwrap();
+        let pid = unsafe { libc::getpid() };
+        File::create(path.join(SVC_PID_FILE))
+            .unwrap()
+            .write_all(pid.to_string().as_bytes())
+            .unwrap();
+        File::create(path.join(SVC_IDENT_FILE)).unwrap().write_all(ident.as_bytes()).unwrap();
+    }
+
+    fn ident(ident: &str) -> PackageIdent {
+        PackageIdent::from_str(ident).unwrap()
+    }
+
+    fn core_fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("../core/tests/fixtures")
+            .join(name)
+    }
+
+    /// Creates a local depot in `dir` holding the given artifacts and origin keys, returning its
+    /// URL.
+    fn local_depot(dir: &TempDir, artifacts: &[&str], keys: &[&str]) -> String {
+        let keys_path = dir.path().join(KEYS_DIR);
+        fs::create_dir_all(&keys_path).unwrap();
+        for name in artifacts {
+            fs::copy(core_fixture(name), dir.path().join(name)).unwrap();
+        }
+        for name in keys {
+            fs::copy(core_fixture(name), keys_path.join(name)).unwrap();
+        }
+        LocalDepot::generate_index(dir.path(), &keys_path).unwrap();
+        format!("file://{}", dir.path().display())
+    }
+
+    #[test]
+    fn gc_keeps_roots_and_their_dependencies() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        let redis = fake_install(&fs_root, "core/redis/3.0.9/20160101000000", &[]);
+        let app = fake_install(&fs_root,
+                               "acme/app/1.0.0/20160101000000",
+                               &["core/redis/3.0.9/20160101000000"]);
+        let nginx = fake_install(&fs_root, "core/nginx/1.10.1/20160101000000", &[]);
+
+        gc::start(&[ident("acme/app")], false, fs_root.path()).unwrap();
+        assert!(app.is_dir());
+        assert!(redis.is_dir());
+        assert!(!nginx.exists());
+    }
+
+    #[test]
+    fn gc_keeps_only_latest_release_of_unqualified_root() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        let old = fake_install(&fs_root, "acme/app/1.0.0/20160101000000", &[]);
+        let new = fake_install(&fs_root, "acme/app/1.0.1/20160101000000", &[]);
+        let pinned = fake_install(&fs_root, "acme/tool/1.0.0/20160101000000", &[]);
+        let _ = fake_install(&fs_root, "acme/tool/1.0.1/20160101000000", &[]);
+
+        gc::start(&[ident("acme/app"), ident("acme/tool/1.0.0/20160101000000")],
+                  false,
+                  fs_root.path())
+            .unwrap();
+        assert!(!old.exists());
+        assert!(new.is_dir());
+        assert!(pinned.is_dir());
+        assert!(!fs_root.path().join(PKG_PATH).join("acme/tool/1.0.1").exists());
+    }
+
+    #[test]
+    fn gc_keeps_packages_in_use_by_running_services() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        let redis = fake_install(&fs_root, "core/redis/3.0.9/20160101000000", &[]);
+        let nginx = fake_install(&fs_root, "core/nginx/1.10.1/20160101000000", &[]);
+        let app = fake_install(&fs_root, "acme/app/1.0.0/20160101000000", &[]);
+        fake_service(&fs_root, "nginx", "core/nginx/1.10.1/20160101000000");
+
+        gc::start(&[ident("acme/app")], false, fs_root.path()).unwrap();
+        assert!(app.is_dir());
+        assert!(nginx.is_dir());
+        assert!(!redis.exists());
+    }
+
+    #[test]
+    fn gc_dry_run_removes_nothing() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        let app = fake_install(&fs_root, "acme/app/1.0.0/20160101000000", &[]);
+        let nginx = fake_install(&fs_root, "core/nginx/1.10.1/20160101000000", &[]);
+
+        gc::start(&[ident("acme/app")], true, fs_root.path()).unwrap();
+        assert!(app.is_dir());
+        assert!(nginx.is_dir());
+    }
+
+    #[test]
+    fn gc_records_removed_packages() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        fake_install(&fs_root, "acme/app/1.0.0/20160101000000", &[]);
+        fake_install(&fs_root, "core/nginx/1.10.1/20160101000000", &[]);
+
+        output::json_output("pkg gc");
+        gc::start(&[ident("acme/app")], true, fs_root.path()).unwrap();
+        let result = output::recorded();
+        assert_eq!(result.find("removed").unwrap(),
+                   &vec!["core/nginx/1.10.1/20160101000000".to_string()].to_json());
+        assert_eq!(result.find("dry_run").unwrap().as_boolean(), Some(true));
+    }
+
+    #[test]
+    fn path_records_installed_path() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        let redis = fake_install(&fs_root, REDIS, &[]);
+
+        output::json_output("pkg path");
+        path::start(&ident("core/redis"), fs_root.path()).unwrap();
+        let result = output::recorded();
+        assert_eq!(result.find("package").unwrap().as_string(), Some(REDIS));
+        assert_eq!(result.find("path").unwrap().as_string(),
+                   Some(&redis.display().to_string()[..]));
+    }
+
+    #[test]
+    fn uninstall_records_whether_package_was_removed() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        fake_install(&fs_root, REDIS, &[]);
+        fake_install(&fs_root, APP, &[]);
+        fake_service(&fs_root, "app", APP);
+
+        output::json_output("pkg uninstall");
+        uninstall::start(&ident(APP), false, fs_root.path()).unwrap();
+        let result = output::recorded();
+        assert_eq!(result.find("removed").unwrap().as_boolean(), Some(false));
+        assert_eq!(result.find("in_use_by").unwrap().as_string(), Some("app"));
+
+        output::json_output("pkg uninstall");
+        uninstall::start(&ident(REDIS), false, fs_root.path()).unwrap();
+        let result = output::recorded();
+        assert_eq!(result.find("package").unwrap().as_string(), Some(REDIS));
+        assert_eq!(result.find("removed").unwrap().as_boolean(), Some(true));
+    }
+
+    #[test]
+    fn uninstall_removes_package() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        let old = fake_install(&fs_root, "core/redis/3.0.9/20160101000000", &[]);
+        let new = fake_install(&fs_root, "core/redis/3.0.10/20160101000000", &[]);
+
+        uninstall::start(&ident("core/redis/3.0.9/20160101000000"), false, fs_root.path())
+            .unwrap();
+        assert!(!old.exists());
+        assert!(new.is_dir());
+    }
+
+    #[test]
+    fn uninstall_refuses_required_package() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        let redis = fake_install(&fs_root, "core/redis/3.0.9/20160101000000", &[]);
+        let _ = fake_install(&fs_root,
+                             "acme/app/1.0.0/20160101000000",
+                             &["core/redis/3.0.9/20160101000000"]);
+
+        match uninstall::start(&ident("core/redis"), false, fs_root.path()) {
+            Err(Error::PackageRequired(ref pkg, ref dependents)) => {
+                assert_eq!(pkg, "core/redis/3.0.9/20160101000000");
+                assert_eq!(dependents, &vec!["acme/app/1.0.0/20160101000000".to_string()]);
+            }
+            other => panic!("Expected a PackageRequired error, got {:?}", other),
+        }
+        assert!(redis.is_dir());
+    }
+
+    #[test]
+    fn uninstall_forces_removal_of_required_package() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        let redis = fake_install(&fs_root, "core/redis/3.0.9/20160101000000", &[]);
+        let app = fake_install(&fs_root,
+                               "acme/app/1.0.0/20160101000000",
+                               &["core/redis/3.0.9/20160101000000"]);
+
+        uninstall::start(&ident("core/redis"), true, fs_root.path()).unwrap();
+        assert!(!redis.exists());
+        assert!(app.is_dir());
+    }
+
+    #[test]
+    fn uninstall_leaves_package_in_use_by_running_service() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        let redis = fake_install(&fs_root, "core/redis/3.0.9/20160101000000", &[]);
+        let _ = fake_install(&fs_root,
+                             "acme/app/1.0.0/20160101000000",
+                             &["core/redis/3.0.9/20160101000000"]);
+        fake_service(&fs_root, "app", "acme/app/1.0.0/20160101000000");
+
+        uninstall::start(&ident("core/redis"), true, fs_root.path()).unwrap();
+        assert!(redis.is_dir());
+    }
+
+    #[test]
+    fn bundle_round_trip() {
+        let depot = TempDir::new("depot").unwrap();
+        let url = local_depot(&depot, &[POSSUMS_HART], &[POSSUMS_KEY]);
+        let src_root = TempDir::new("src_root").unwrap();
+        let out = TempDir::new("out").unwrap();
+        let bundle_path = bundle::create::start(&ident(POSSUMS), &url, src_root.path(), out.path())
+            .unwrap();
+        assert!(bundle_path.starts_with(out.path()));
+
+        // Installing a bundle must not need anything from the depot
+        let empty = TempDir::new("empty").unwrap();
+        let offline_url = local_depot(&empty, &[], &[]);
+        let dst_root = TempDir::new("dst_root").unwrap();
+        bundle::install::start(&bundle_path, &offline_url, dst_root.path()).unwrap();
+        assert!(PackageInstall::load(&ident(POSSUMS), Some(dst_root.path())).is_ok());
+        assert!(cache_artifact_path(Some(dst_root.path())).join(POSSUMS_HART).is_file());
+        assert!(default_cache_key_path(Some(dst_root.path())).join(POSSUMS_KEY).is_file());
+    }
+
+    #[test]
+    fn bundle_install_refuses_entries_outside_the_caches() {
+        let out = TempDir::new("out").unwrap();
+        let bundle_path = out.path().join("evil-bundle.tar");
+        let mut tar = TarBuilder::new(File::create(&bundle_path).unwrap());
+        tar.append_data("BUNDLE", 0o644, format!("HAB-BUNDLE-1\n{}\n", POSSUMS).as_bytes())
+            .unwrap();
+        tar.append_data("artifacts/../../evil", 0o644, b"evil").unwrap();
+        tar.finish().unwrap();
+
+        let fs_root = TempDir::new("fs_root").unwrap();
+        match bundle::install::start(&bundle_path, "file:///nonexistent", fs_root.path()) {
+            Err(Error::BundleMalformed(_)) => (),
+            r => panic!("Expected BundleMalformed, got {:?}", r),
+        }
+        assert!(!fs_root.path().join("evil").exists());
+    }
+
+    const GLIBC: &'static str = "core/glibc/2.22/20160101000000";
+    const OPENSSL: &'static str = "core/openssl/1.0.2/20160101000000";
+    const REDIS: &'static str = "core/redis/3.0.9/20160101000000";
+    const APP: &'static str = "acme/app/1.0.0/20160101000000";
+
+    /// Installs an app depending on openssl and glibc, and redis depending on glibc.
+    fn fake_graph(fs_root: &TempDir) {
+        fake_install(fs_root, GLIBC, &[]);
+        fake_install(fs_root, OPENSSL, &[GLIBC]);
+        fake_install(fs_root, REDIS, &[GLIBC]);
+        fake_install(fs_root, APP, &[OPENSSL, GLIBC]);
+    }
+
+    #[test]
+    fn deps_tree_as_text() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        fake_graph(&fs_root);
+
+        let tree = deps::Tree::load(&ident("acme/app"), false, fs_root.path()).unwrap();
+        assert_eq!(tree.to_text(),
+                   format!("{}\n    {}\n        {}\n", APP, OPENSSL, GLIBC));
+    }
+
+    #[test]
+    fn deps_tree_marks_missing_dependencies() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        fake_install(&fs_root, APP, &[OPENSSL]);
+
+        let tree = deps::Tree::load(&ident(APP), false, fs_root.path()).unwrap();
+        assert_eq!(tree.to_text(),
+                   format!("{}\n    {} (not installed)\n", APP, OPENSSL));
+        let json = tree.to_json();
+        assert_eq!(json.find("installed").unwrap().as_boolean(), Some(true));
+        let children = json.find("deps").unwrap().as_array().unwrap();
+        assert_eq!(children.len(), 1);
+        assert_eq!(children[0].find("ident").unwrap().as_string(), Some(OPENSSL));
+        assert_eq!(children[0].find("installed").unwrap().as_boolean(), Some(false));
+    }
+
+    #[test]
+    fn deps_reverse_tree_as_dot() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        fake_graph(&fs_root);
+
+        let tree = deps::Tree::load(&ident("core/glibc"), true, fs_root.path()).unwrap();
+        assert_eq!(tree.to_dot(),
+                   format!("digraph \"{glibc}\" {{\n    \"{glibc}\";\n    \"{app}\" -> \
+                            \"{openssl}\";\n    \"{openssl}\" -> \"{glibc}\";\n    \
+                            \"{redis}\" -> \"{glibc}\";\n}}\n",
+                           glibc = GLIBC,
+                           openssl = OPENSSL,
+                           redis = REDIS,
+                           app = APP));
+        let json = tree.to_json();
+        assert_eq!(json.find("rdeps").unwrap().as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn deps_requires_an_installed_package() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        assert!(deps::Tree::load(&ident("core/redis"), false, fs_root.path()).is_err());
+    }
+
+    #[test]
+    fn list_filters_by_origin_and_name() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        fake_graph(&fs_root);
+
+        let names = |installed: Vec<PackageInstall>| {
+            let mut names: Vec<String> =
+                installed.iter().map(|p| p.ident().to_string()).collect();
+            names.sort();
+            names
+        };
+        assert_eq!(names(list::installed(None, None, fs_root.path()).unwrap()),
+                   vec![APP, GLIBC, OPENSSL, REDIS]);
+        assert_eq!(names(list::installed(Some("core"), None, fs_root.path()).unwrap()),
+                   vec![GLIBC, OPENSSL, REDIS]);
+        assert_eq!(names(list::installed(Some("core"), Some("redis"), fs_root.path()).unwrap()),
+                   vec![REDIS]);
+        assert!(list::installed(Some("acme"), Some("redis"), fs_root.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn list_as_json_and_dot() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        fake_install(&fs_root, GLIBC, &[]);
+        fake_install(&fs_root, REDIS, &[GLIBC]);
+        let installed = list::installed(None, Some("redis"), fs_root.path()).unwrap();
+
+        let json = list::to_json(&installed);
+        let packages = json.as_array().unwrap();
+        assert_eq!(packages.len(), 1);
+        assert_eq!(packages[0].find("origin").unwrap().as_string(), Some("core"));
+        assert_eq!(packages[0].find("name").unwrap().as_string(), Some("redis"));
+        assert_eq!(packages[0].find("version").unwrap().as_string(), Some("3.0.9"));
+        assert_eq!(packages[0].find("release").unwrap().as_string(),
+                   Some("20160101000000"));
+
+        assert_eq!(list::to_dot(&installed).unwrap(),
+                   format!("digraph \"installed\" {{\n    \"{redis}\";\n    \
+                            \"{redis}\" -> \"{glibc}\";\n}}\n",
+                           redis = REDIS,
+                           glibc = GLIBC));
+    }
+
+    /// Returns the lines 1 to `count`, with the given lines replaced.
+    fn numbered(count: usize, replaced: &[(usize, &str)]) -> String {
+        (1..count + 1)
+            .map(|n| {
+                replaced.iter()
+                    .find(|r| r.0 == n)
+                    .map(|r| r.1.to_string())
+                    .unwrap_or(n.to_string())
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn diff_lines_keeps_the_longest_common_lines() {
+        let ops = diff::diff_lines(&["a", "b", "c", "d"], &["a", "x", "c", "d", "e"]);
+        assert_eq!(ops,
+                   vec![(' ', "a"), ('-', "b"), ('+', "x"), (' ', "c"), (' ', "d"), ('+', "e")]);
+    }
+
+    #[test]
+    fn diff_lines_of_empty_texts() {
+        assert!(diff::diff_lines(&[], &[]).is_empty());
+        assert_eq!(diff::diff_lines(&[], &["a"]), vec![('+', "a")]);
+        assert_eq!(diff::diff_lines(&["a", "b"], &[]), vec![('-', "a"), ('-', "b")]);
+    }
+
+    #[test]
+    fn unified_diff_of_identical_texts_is_empty() {
+        let text = numbered(10, &[]);
+        assert_eq!(diff::unified_diff("config/redis.conf", "config/redis.conf", &text, &text),
+                   "");
+    }
+
+    #[test]
+    fn unified_diff_shows_context_around_a_change() {
+        let out = diff::unified_diff("a.conf",
+                                     "a.conf",
+                                     &numbered(10, &[]),
+                                     &numbered(10, &[(5, "x")]));
+        assert_eq!(out,
+                   "--- a/a.conf\n+++ b/a.conf\n@@ -2,7 +2,7 @@\n 2\n 3\n 4\n-5\n+x\n 6\n 7\n 8\n");
+    }
+
+    #[test]
+    fn unified_diff_merges_hunks_like_diff() {
+        // Changes 6 unchanged lines apart share a hunk, as with `diff -u`
+        let out = diff::unified_diff("a",
+                                     "a",
+                                     &numbered(20, &[]),
+                                     &numbered(20, &[(2, "x"), (9, "y")]));
+        assert_eq!(out.lines().filter(|l| l.starts_with("@@")).collect::<Vec<_>>(),
+                   vec!["@@ -1,12 +1,12 @@"]);
+        // and changes 7 apart don't
+        let out = diff::unified_diff("a",
+                                     "a",
+                                     &numbered(20, &[]),
+                                     &numbered(20, &[(2, "x"), (10, "y")]));
+        assert_eq!(out.lines().filter(|l| l.starts_with("@@")).collect::<Vec<_>>(),
+                   vec!["@@ -1,5 +1,5 @@", "@@ -7,7 +7,7 @@"]);
+    }
+
+    #[test]
+    fn unified_diff_of_an_added_file() {
+        assert_eq!(diff::unified_diff("hooks/init", "hooks/init", "", "a\nb"),
+                   "--- a/hooks/init\n+++ b/hooks/init\n@@ -0,0 +1,2 @@\n+a\n+b\n");
+    }
+
+    #[test]
+    fn unified_diff_skips_texts_too_large_to_diff() {
+        let text = numbered(2000, &[]);
+        assert_eq!(diff::unified_diff("a", "b", &text, &numbered(2000, &[(1, "x")])),
+                   "Files b differ (too large to show)\n");
+    }
+}
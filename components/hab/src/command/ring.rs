@@ -14,18 +14,25 @@
 
 pub mod key {
     pub mod export {
-        use std::io;
+        use std::io::{self, Read};
         use std::fs::File;
         use std::path::Path;
 
         use hcore::crypto::SymKey;
 
         use error::Result;
+        use output;
 
         pub fn start(ring: &str, cache: &Path) -> Result<()> {
             let latest = try!(SymKey::get_latest_pair_for(ring, cache));
             let path = try!(SymKey::get_secret_key_path(&latest.name_with_rev(), cache));
             let mut file = try!(File::open(&path));
+            output::record("name_with_rev", &latest.name_with_rev());
+            if output::is_json() {
+                let mut content = String::new();
+                try!(file.read_to_string(&mut content));
+                return output::content("key", &content);
+            }
             debug!("Streaming file contents of {} to standard out",
                    &path.display());
             try!(io::copy(&mut file, &mut io::stdout()));
@@ -40,11 +47,13 @@ pub mod key {
         use hcore::crypto::SymKey;
 
         use error::Result;
+        use output;
 
         pub fn start(ring: &str, cache: &Path) -> Result<()> {
             println!("{}",
                      Yellow.bold().paint(format!("» Generating ring key for {}", &ring)));
             let pair = try!(SymKey::generate_pair_for_ring(ring, cache));
+            output::record("name_with_rev", &pair.name_with_rev());
             println!("{}",
                      Blue.paint(format!("★ Generated ring key pair {}.", &pair.name_with_rev())));
             Ok(())
@@ -58,11 +67,14 @@ pub mod key {
         use hcore::crypto::SymKey;
 
         use error::Result;
+        use output;
 
         pub fn start(content: &str, cache: &Path) -> Result<()> {
             println!("{}",
                      Yellow.bold().paint(format!("» Importing ring key from standard input")));
             let (pair, pair_type) = try!(SymKey::write_file_from_str(content, cache));
+            output::record("name_with_rev", &pair.name_with_rev());
+            output::record("pair_type", &pair_type.to_string());
             println!("{}",
                      Blue.paint(format!("★ Imported {} ring key {}.",
                                         &pair_type,
@@ -0,0 +1,649 @@
+// Copyright (c) 2016 Chef Software Inc. and/or applicable contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Queries the HTTP API of one or more running Supervisors.
+//!
+//! Every command takes a list of Supervisors and asks each of them in turn, so a whole ring can
+//! be inspected from one place. As a Supervisor can host many services, every command names the
+//! service group it asks about. A Supervisor which can't be reached is reported alongside the
+//! others and makes the command fail once every Supervisor has been asked.
+
+use std::cmp;
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::iter;
+use std::result;
+use std::time::Duration;
+
+use hcore::service::ServiceGroup;
+use hyper::client::Response;
+use hyper::status::StatusCode;
+use http_client;
+use rustc_serialize::json::{Json, ToJson};
+use url::Url;
+
+use config::ssl_settings;
+use error::{Error, Result};
+use output;
+
+/// The port a Supervisor's HTTP API listens on unless told otherwise.
+pub const SIDECAR_DEFAULT_PORT: u16 = 9631;
+
+/// How long to wait for a Supervisor to answer before giving up on it.
+const READ_TIMEOUT_SECS: u64 = 10;
+
+/// The outcome of asking one Supervisor for one of its endpoints.
+struct Reply {
+    peer: String,
+    result: result::Result<(StatusCode, String), String>,
+}
+
+fn query(peers: &[String], path: &str) -> Vec<Reply> {
+    peers.iter()
+        .map(|peer| {
+            Reply {
+                peer: peer.clone(),
+                result: get(peer, path),
+            }
+        })
+        .collect()
+}
+
+fn get(peer: &str, path: &str) -> result::Result<(StatusCode, String), String> {
+    let mut res = try!(request(peer, path).map_err(|e| e.to_string()));
+    let mut body = String::new();
+    try!(res.read_to_string(&mut body).map_err(|e| e.to_string()));
+    Ok((res.status, body))
+}
+
+/// Sends a GET request for one of the endpoints of a Supervisor's HTTP API.
+pub fn request(peer: &str, path: &str) -> Result<Response> {
+    let failed = |e: String| Error::SupervisorRequest(peer.to_string(), e);
+    let url = try!(Url::parse(&format!("http://{}{}", peer, path))
+        .map_err(|e| failed(e.to_string())));
+    let mut client = try!(http_client::new_hyper_client_with_ssl(Some(&url), None, &ssl_settings())
+        .map_err(|e| failed(e.to_string())));
+    client.set_read_timeout(Some(Duration::from_secs(READ_TIMEOUT_SECS)));
+    debug!("GET {}", &url);
+    client.get(url).send().map_err(|e| failed(e.to_string()))
+}
+
+/// Returns the path of one of the endpoints a Supervisor's HTTP API serves for a service it hosts.
+/// Services are hosted by `service.group`, whatever their organization.
+pub fn service_path(sg: &ServiceGroup, path: &str) -> String {
+    format!("/services/{}.{}{}", sg.service, sg.group, path)
+}
+
+/// Records a Supervisor's entry in the result document, or its error if it couldn't be asked.
+fn record(peer: &str, key: &str, value: result::Result<Json, &str>) {
+    let mut entry = BTreeMap::new();
+    entry.insert("supervisor".to_string(), peer.to_json());
+    match value {
+        Ok(v) => entry.insert(key.to_string(), v),
+        Err(e) => entry.insert("error".to_string(), e.to_json()),
+    };
+    output::append("supervisors", &Json::Object(entry));
+}
+
+/// Returns an error if any Supervisor couldn't be asked.
+fn finish(failures: usize) -> Result<()> {
+    if failures > 0 {
+        Err(Error::SupervisorRequestFailed(failures))
+    } else {
+        Ok(())
+    }
+}
+
+/// Prints rows as a table whose columns are as wide as their widest value.
+pub fn print_table(headings: &[&str], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = headings.iter().map(|h| h.chars().count()).collect();
+    for row in rows.iter() {
+        for (i, value) in row.iter().enumerate() {
+            widths[i] = cmp::max(widths[i], value.chars().count());
+        }
+    }
+    let headings: Vec<String> = headings.iter().map(|h| h.to_string()).collect();
+    print_row(&headings, &widths);
+    for row in rows.iter() {
+        print_row(row, &widths);
+    }
+}
+
+fn print_row(row: &[String], widths: &[usize]) {
+    let mut line = String::new();
+    for (i, value) in row.iter().enumerate() {
+        line.push_str(value);
+        if i + 1 < row.len() {
+            let padding = widths[i] - value.chars().count() + 2;
+            line.extend(iter::repeat(' ').take(padding));
+        }
+    }
+    println!("{}", line);
+}
+
+pub fn json_str<'a>(json: &'a Json, key: &str) -> &'a str {
+    json.find(key).and_then(|v| v.as_string()).unwrap_or("-")
+}
+
+pub fn json_bool(json: &Json, key: &str) -> bool {
+    json.find(key).and_then(|v| v.as_boolean()).unwrap_or(false)
+}
+
+pub mod census {
+    //! Shows every member of every service group known to the Supervisors' census.
+    //!
+    //! # Examples
+    //!
+    //! ```bash
+    //! $ hab svc census redis.default -p 10.0.0.4:9631,10.0.0.5:9631
+    //! ```
+    //!
+    //! Will list each member's service group, host, address, role and gossip state. Members
+    //! reported by more than one Supervisor are only listed once.
+
+    use std::collections::HashSet;
+
+    use hcore::service::ServiceGroup;
+    use hyper::status::StatusCode;
+    use rustc_serialize::json::Json;
+
+    use error::Result;
+    use super::{finish, json_bool, json_str, print_table, query, record, service_path};
+
+    pub fn start(peers: &[String], sg: &ServiceGroup) -> Result<()> {
+        let mut rows = Vec::new();
+        let mut seen = HashSet::new();
+        let mut failures = 0;
+        for reply in query(peers, &service_path(sg, "/census")) {
+            let census = match reply.result {
+                Ok((StatusCode::Ok, body)) => Json::from_str(&body).map_err(|e| e.to_string()),
+                Ok((status, _)) => Err(status.to_string()),
+                Err(e) => Err(e),
+            };
+            let census = match census {
+                Ok(census) => census,
+                Err(e) => {
+                    failures += 1;
+                    println!("{}: {}", &reply.peer, &e);
+                    record(&reply.peer, "census", Err(&e[..]));
+                    continue;
+                }
+            };
+            {
+                let censuses = census.find_path(&["census_list", "censuses"])
+                    .and_then(|c| c.as_object());
+                for (sg, census) in censuses.into_iter().flat_map(|c| c.iter()) {
+                    let population = census.find("population").and_then(|p| p.as_object());
+                    for (id, entry) in population.into_iter().flat_map(|p| p.iter()) {
+                        if !seen.insert(id.clone()) {
+                            continue;
+                        }
+                        rows.push(vec![sg.clone(),
+                                       json_str(entry, "hostname").to_string(),
+                                       json_str(entry, "ip").to_string(),
+                                       json_str(entry, "port").to_string(),
+                                       role(entry).to_string(),
+                                       state(entry).to_string()]);
+                    }
+                }
+            }
+            record(&reply.peer, "census", Ok(census));
+        }
+        rows.sort();
+        print_table(&["SERVICE GROUP", "HOSTNAME", "IP", "PORT", "ROLE", "STATE"],
+                    &rows);
+        finish(failures)
+    }
+
+    fn role(entry: &Json) -> &'static str {
+        if json_bool(entry, "leader") {
+            "leader"
+        } else if json_bool(entry, "follower") {
+            "follower"
+        } else {
+            "-"
+        }
+    }
+
+    fn state(entry: &Json) -> &'static str {
+        if json_bool(entry, "detached") {
+            "detached"
+        } else if json_bool(entry, "confirmed") {
+            "confirmed"
+        } else if json_bool(entry, "suspect") {
+            "suspect"
+        } else if json_bool(entry, "alive") {
+            "alive"
+        } else {
+            "unknown"
+        }
+    }
+}
+
+pub mod config {
+    //! Shows the configuration each Supervisor's service is running with.
+    //!
+    //! # Examples
+    //!
+    //! ```bash
+    //! $ hab svc config redis.default -p 10.0.0.4:9631
+    //! ```
+    //!
+    //! Will print the TOML configuration of the service, preceded by the Supervisor it came from.
+
+    use hcore::service::ServiceGroup;
+    use hyper::status::StatusCode;
+    use rustc_serialize::json::ToJson;
+
+    use error::Result;
+    use super::{finish, query, record, service_path};
+
+    pub fn start(peers: &[String], sg: &ServiceGroup) -> Result<()> {
+        let mut failures = 0;
+        for reply in query(peers, &service_path(sg, "/config")) {
+            match reply.result {
+                Ok((StatusCode::Ok, body)) => {
+                    println!("# {}", &reply.peer);
+                    println!("{}", body.trim_right());
+                    record(&reply.peer, "config", Ok(body.to_json()));
+                }
+                Ok((status, _)) => {
+                    failures += 1;
+                    let e = status.to_string();
+                    println!("# {}: {}", &reply.peer, &e);
+                    record(&reply.peer, "config", Err(&e[..]));
+                }
+                Err(e) => {
+                    failures += 1;
+                    println!("# {}: {}", &reply.peer, &e);
+                    record(&reply.peer, "config", Err(&e[..]));
+                }
+            }
+        }
+        finish(failures)
+    }
+}
+
+pub mod election {
+    //! Shows the leader elections each Supervisor knows about.
+    //!
+    //! # Examples
+    //!
+    //! ```bash
+    //! $ hab svc election redis.default -p 10.0.0.4:9631,10.0.0.5:9631
+    //! ```
+    //!
+    //! Will list, for each Supervisor, every election with its status, leader, term and number
+    //! of votes, so Supervisors which disagree about a leader are easy to spot.
+
+    use hcore::service::ServiceGroup;
+    use hyper::status::StatusCode;
+    use rustc_serialize::json::Json;
+
+    use error::Result;
+    use super::{finish, json_str, print_table, query, record, service_path};
+
+    pub fn start(peers: &[String], sg: &ServiceGroup) -> Result<()> {
+        let mut rows = Vec::new();
+        let mut failures = 0;
+        for reply in query(peers, &service_path(sg, "/election")) {
+            let election = match reply.result {
+                Ok((StatusCode::Ok, body)) => Json::from_str(&body).map_err(|e| e.to_string()),
+                Ok((status, _)) => Err(status.to_string()),
+                Err(e) => Err(e),
+            };
+            let election = match election {
+                Ok(election) => election,
+                Err(e) => {
+                    failures += 1;
+                    rows.push(vec![reply.peer.clone(),
+                                   "-".to_string(),
+                                   e.clone(),
+                                   "-".to_string(),
+                                   "-".to_string(),
+                                   "-".to_string()]);
+                    record(&reply.peer, "election", Err(&e[..]));
+                    continue;
+                }
+            };
+            {
+                let elections = election.find("elections").and_then(|e| e.as_object());
+                for (sg, e) in elections.into_iter().flat_map(|e| e.iter()) {
+                    let term = e.find("term").map(|t| t.to_string());
+                    let votes = e.find("votes").and_then(|v| v.as_array()).map(|v| v.len());
+                    rows.push(vec![reply.peer.clone(),
+                                   sg.clone(),
+                                   json_str(e, "status").to_string(),
+                                   json_str(e, "leader_id").to_string(),
+                                   term.unwrap_or("-".to_string()),
+                                   votes.map(|v| v.to_string()).unwrap_or("-".to_string())]);
+                }
+            }
+            record(&reply.peer, "election", Ok(election));
+        }
+        print_table(&["SUPERVISOR", "SERVICE GROUP", "STATUS", "LEADER", "TERM", "VOTES"],
+                    &rows);
+        finish(failures)
+    }
+}
+
+pub mod health {
+    //! Runs the health check of each Supervisor's service.
+    //!
+    //! # Examples
+    //!
+    //! ```bash
+    //! $ hab svc health redis.default -p 10.0.0.4:9631,10.0.0.5:9631
+    //! ```
+    //!
+    //! Will show whether each service is `OK`, `WARNING`, `CRITICAL` or `UNKNOWN`, along with
+    //! the output of its health check. A Supervisor which doesn't answer with a 200 reports its
+    //! service as `CRITICAL` or `UNKNOWN`. Unhealthy services don't make the command fail, only
+    //! Supervisors which can't be reached do.
+
+    use std::collections::BTreeMap;
+
+    use hcore::service::ServiceGroup;
+    use hyper::status::StatusCode;
+    use rustc_serialize::json::{Json, ToJson};
+
+    use error::Result;
+    use super::{finish, print_table, query, record, service_path};
+
+    pub fn start(peers: &[String], sg: &ServiceGroup) -> Result<()> {
+        let mut rows = Vec::new();
+        let mut failures = 0;
+        for reply in query(peers, &service_path(sg, "/health")) {
+            match reply.result {
+                Ok((code, body)) => {
+                    let (status, out) = parse(code, &body);
+                    let first_line = out.lines().next().unwrap_or("").to_string();
+                    rows.push(vec![reply.peer.clone(), status.clone(), first_line]);
+                    let mut health = BTreeMap::new();
+                    health.insert("status".to_string(), status.to_json());
+                    health.insert("output".to_string(), out.to_json());
+                    record(&reply.peer, "health", Ok(Json::Object(health)));
+                }
+                Err(e) => {
+                    failures += 1;
+                    rows.push(vec![reply.peer.clone(), "-".to_string(), e.clone()]);
+                    record(&reply.peer, "health", Err(&e[..]));
+                }
+            }
+        }
+        print_table(&["SUPERVISOR", "HEALTH", "OUTPUT"], &rows);
+        finish(failures)
+    }
+
+    /// Returns the health status and check output of a `/health` reply, which reads as
+    /// `<status> - <output>`. Only a 200 reply is healthy: any other is `CRITICAL` if the service
+    /// is unavailable and `UNKNOWN` otherwise, whatever its body says.
+    pub fn parse(code: StatusCode, body: &str) -> (String, String) {
+        let body = body.trim();
+        let mut parts = body.splitn(2, " - ");
+        let status = parts.next().unwrap_or("");
+        let out = parts.next().unwrap_or("");
+        let unhealthy = match code {
+            StatusCode::Ok => {
+                let status = if status.is_empty() { "UNKNOWN" } else { status };
+                return (status.to_string(), out.to_string());
+            }
+            StatusCode::ServiceUnavailable => "CRITICAL",
+            _ => "UNKNOWN",
+        };
+        if status == unhealthy {
+            (status.to_string(), out.to_string())
+        } else if body.is_empty() {
+            (unhealthy.to_string(), code.to_string())
+        } else {
+            (unhealthy.to_string(), body.to_string())
+        }
+    }
+}
+
+pub mod status {
+    //! Shows the state of each Supervisor's service.
+    //!
+    //! # Examples
+    //!
+    //! ```bash
+    //! $ hab svc status redis.default -p 10.0.0.4:9631,10.0.0.5:9631
+    //! ```
+    //!
+    //! Will show the package each Supervisor is running, whether its service is up or down, and
+    //! for how long.
+
+    use std::collections::BTreeMap;
+
+    use hcore::service::ServiceGroup;
+    use hyper::status::StatusCode;
+    use rustc_serialize::json::{Json, ToJson};
+
+    use error::Result;
+    use super::{finish, print_table, query, record, service_path};
+
+    pub fn start(peers: &[String], sg: &ServiceGroup) -> Result<()> {
+        let mut rows = Vec::new();
+        let mut failures = 0;
+        for reply in query(peers, &service_path(sg, "/status")) {
+            match reply.result {
+                Ok((StatusCode::Ok, body)) => {
+                    let (package, state, duration) = parse(&body);
+                    rows.push(vec![reply.peer.clone(),
+                                   package.to_string(),
+                                   state.to_string(),
+                                   duration.to_string()]);
+                    let mut status = BTreeMap::new();
+                    status.insert("package".to_string(), package.to_json());
+                    status.insert("state".to_string(), state.to_json());
+                    status.insert("duration".to_string(), duration.to_json());
+                    record(&reply.peer, "status", Ok(Json::Object(status)));
+                }
+                Ok((code, _)) => {
+                    failures += 1;
+                    let e = code.to_string();
+                    rows.push(vec![reply.peer.clone(),
+                                   "-".to_string(),
+                                   e.clone(),
+                                   "-".to_string()]);
+                    record(&reply.peer, "status", Err(&e[..]));
+                }
+                Err(e) => {
+                    failures += 1;
+                    rows.push(vec![reply.peer.clone(),
+                                   "-".to_string(),
+                                   e.clone(),
+                                   "-".to_string()]);
+                    record(&reply.peer, "status", Err(&e[..]));
+                }
+            }
+        }
+        print_table(&["SUPERVISOR", "PACKAGE", "STATE", "FOR"], &rows);
+        finish(failures)
+    }
+
+    /// Returns the package, state and duration of a `/status` reply, which reads as
+    /// `<package>: <state> for <duration>`.
+    pub fn parse(body: &str) -> (&str, &str, &str) {
+        let body = body.trim();
+        let (package, rest) = match body.find(": ") {
+            Some(i) => (&body[..i], &body[i + 2..]),
+            None => ("-", body),
+        };
+        let (state, duration) = match rest.find(" for ") {
+            Some(i) => (&rest[..i], &rest[i + 5..]),
+            None => (rest, "-"),
+        };
+        (package, state, duration)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::TcpListener;
+    use std::str::FromStr;
+
+    use hcore::service::ServiceGroup;
+    use http_client::testing;
+    use hyper::server::{Request, Response};
+    use hyper::status::StatusCode;
+    use rustc_serialize::json::Json;
+
+    use error::Error;
+    use super::{census, election, health, json_bool, json_str, query, service_path, status};
+
+    /// Serves the same reply to every request for redis.default, and a 404 to any other,
+    /// returning the address of the fake Supervisor.
+    fn serve(code: StatusCode, body: &'static str) -> String {
+        let addr = testing::serve(move |req: Request, mut res: Response| {
+            if req.uri.to_string().starts_with("/services/redis.default/") {
+                *res.status_mut() = code;
+                res.send(body.as_bytes()).unwrap();
+            } else {
+                *res.status_mut() = StatusCode::NotFound;
+            }
+        });
+        addr.to_string()
+    }
+
+    /// Returns the address of a Supervisor which isn't listening.
+    fn unreachable() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().to_string()
+    }
+
+    fn redis() -> ServiceGroup {
+        ServiceGroup::from_str("redis.default@acme").unwrap()
+    }
+
+    fn failures(result: ::error::Result<()>) -> usize {
+        match result {
+            Ok(_) => 0,
+            Err(Error::SupervisorRequestFailed(n)) => n,
+            Err(e) => panic!("Unexpected error: {:?}", e),
+        }
+    }
+
+    fn pair(status: &str, out: &str) -> (String, String) {
+        (status.to_string(), out.to_string())
+    }
+
+    #[test]
+    fn health_of_a_200_reply() {
+        assert_eq!(health::parse(StatusCode::Ok, "OK - all good\n"), pair("OK", "all good"));
+        assert_eq!(health::parse(StatusCode::Ok, "WARNING - disk at 85%"),
+                   pair("WARNING", "disk at 85%"));
+        assert_eq!(health::parse(StatusCode::Ok, ""), pair("UNKNOWN", ""));
+    }
+
+    #[test]
+    fn health_of_any_other_reply_is_unhealthy() {
+        assert_eq!(health::parse(StatusCode::ServiceUnavailable, "CRITICAL - no redis"),
+                   pair("CRITICAL", "no redis"));
+        assert_eq!(health::parse(StatusCode::ServiceUnavailable, "OK - all good"),
+                   pair("CRITICAL", "OK - all good"));
+        assert_eq!(health::parse(StatusCode::InternalServerError, "UNKNOWN - hook failed"),
+                   pair("UNKNOWN", "hook failed"));
+        assert_eq!(health::parse(StatusCode::InternalServerError, "Internal Supervisor error"),
+                   pair("UNKNOWN", "Internal Supervisor error"));
+        assert_eq!(health::parse(StatusCode::NotFound, ""),
+                   pair("UNKNOWN", &StatusCode::NotFound.to_string()));
+    }
+
+    #[test]
+    fn status_of_a_running_service() {
+        assert_eq!(status::parse("core/redis/3.0.7/20160614023044: up for 42 seconds\n"),
+                   ("core/redis/3.0.7/20160614023044", "up", "42 seconds"));
+        assert_eq!(status::parse("down"), ("-", "down", "-"));
+    }
+
+    #[test]
+    fn json_values_default_when_missing() {
+        let json = Json::from_str("{\"hostname\":\"redis-1\",\"leader\":true}").unwrap();
+        assert_eq!(json_str(&json, "hostname"), "redis-1");
+        assert_eq!(json_str(&json, "ip"), "-");
+        assert!(json_bool(&json, "leader"));
+        assert!(!json_bool(&json, "follower"));
+    }
+
+    #[test]
+    fn service_paths_name_the_service_group() {
+        assert_eq!(service_path(&redis(), "/health"),
+                   "/services/redis.default/health");
+        assert_eq!(service_path(&ServiceGroup::from_str("nginx.web").unwrap(), "/files/a.conf"),
+                   "/services/nginx.web/files/a.conf");
+    }
+
+    #[test]
+    fn query_asks_every_supervisor() {
+        let peers = vec![serve(StatusCode::ServiceUnavailable, "CRITICAL - no redis"),
+                         unreachable()];
+        let replies = query(&peers, &service_path(&redis(), "/health"));
+        assert_eq!(replies.len(), 2);
+        assert_eq!(replies[0].peer, peers[0]);
+        assert_eq!(replies[0].result,
+                   Ok((StatusCode::ServiceUnavailable, "CRITICAL - no redis".to_string())));
+        assert_eq!(replies[1].peer, peers[1]);
+        assert!(replies[1].result.is_err());
+    }
+
+    #[test]
+    fn unhealthy_services_dont_fail_health() {
+        let peers = vec![serve(StatusCode::ServiceUnavailable, "CRITICAL - no redis")];
+        assert_eq!(failures(health::start(&peers, &redis())), 0);
+    }
+
+    #[test]
+    fn unreachable_supervisors_fail_a_command() {
+        let peers = vec![serve(StatusCode::Ok, "OK - all good"), unreachable(), unreachable()];
+        assert_eq!(failures(health::start(&peers, &redis())), 2);
+    }
+
+    #[test]
+    fn status_fails_on_an_error_reply() {
+        let peers = vec![serve(StatusCode::Ok, "core/redis/3.0.7/20160614023044: up for 1 second"),
+                         serve(StatusCode::NotFound, "")];
+        assert_eq!(failures(status::start(&peers, &redis())), 1);
+    }
+
+    #[test]
+    fn status_fails_for_a_service_group_which_isnt_hosted() {
+        let peers = vec![serve(StatusCode::Ok, "core/redis/3.0.7/20160614023044: up for 1 second")];
+        let nginx = ServiceGroup::from_str("nginx.default").unwrap();
+        assert_eq!(failures(status::start(&peers, &nginx)), 1);
+    }
+
+    #[test]
+    fn census_fails_on_a_reply_which_isnt_json() {
+        let census = "{\"census_list\":{\"censuses\":{\"redis.default\":{\"population\":\
+                      {\"a\":{\"hostname\":\"redis-1\",\"ip\":\"10.0.0.4\",\"port\":\"6379\",\
+                      \"leader\":true,\"alive\":true}}}}}}";
+        let peers = vec![serve(StatusCode::Ok, census), serve(StatusCode::Ok, "not json")];
+        assert_eq!(failures(census::start(&peers, &redis())), 1);
+    }
+
+    #[test]
+    fn census_fails_on_an_error_reply() {
+        let peers = vec![serve(StatusCode::Ok, "{}"),
+                         serve(StatusCode::InternalServerError, "{}")];
+        assert_eq!(failures(census::start(&peers, &redis())), 1);
+    }
+
+    #[test]
+    fn election_fails_on_an_error_reply() {
+        let peers = vec![serve(StatusCode::Ok, "{\"elections\":{}}"),
+                         serve(StatusCode::InternalServerError, "{\"elections\":{}}")];
+        assert_eq!(failures(election::start(&peers, &redis())), 1);
+    }
+}
@@ -20,11 +20,19 @@ pub mod key {
         use hcore::crypto::BoxKeyPair;
 
         use error::Result;
+        use output;
+        use passphrase;
 
-        pub fn start(user: &str, cache: &Path) -> Result<()> {
+        pub fn start(user: &str, encrypt: bool, cache: &Path) -> Result<()> {
             println!("{}",
                      Yellow.bold().paint(format!("» Generating user key for {}", &user)));
-            let pair = try!(BoxKeyPair::generate_pair_for_user(user, cache));
+            let pair = if encrypt {
+                let passphrase = try!(passphrase::new_passphrase());
+                try!(BoxKeyPair::generate_encrypted_pair_for_user(user, &passphrase, cache))
+            } else {
+                try!(BoxKeyPair::generate_pair_for_user(user, cache))
+            };
+            output::record("name_with_rev", &pair.name_with_rev());
             println!("{}",
                      Blue.paint(format!("★ Generated user key pair {}.", &pair.name_with_rev())));
             Ok(())
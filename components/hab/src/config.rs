@@ -19,6 +19,7 @@ use std::path::PathBuf;
 
 use hcore::config::{ConfigFile, ParseInto};
 use hcore::fs::{am_i_root, FS_ROOT_PATH};
+use http_client::SslSettings;
 use toml;
 
 use error::{Error, Result};
@@ -50,6 +51,20 @@ pub fn save(config: &Config) -> Result<()> {
     Ok(())
 }
 
+/// Returns the SSL settings for HTTP clients: those given in the environment, and otherwise the
+/// ones in the CLI config, if it can be loaded. The client certificate and key come together from
+/// whichever of the two gives either of them.
+pub fn ssl_settings() -> SslSettings {
+    let env = SslSettings::from_env();
+    match load() {
+        Ok(config) => env.or(config.ssl_settings()),
+        Err(e) => {
+            debug!("Not using SSL settings from CLI config: {}", e);
+            env
+        }
+    }
+}
+
 fn cli_config_path() -> PathBuf {
     match am_i_root() {
         true => PathBuf::from(FS_ROOT_PATH).join(CLI_CONFIG_PATH),
@@ -66,6 +81,20 @@ fn cli_config_path() -> PathBuf {
 pub struct Config {
     pub auth_token: Option<String>,
     pub origin: Option<String>,
+    pub ssl_ca_bundle: Option<String>,
+    pub ssl_client_cert: Option<String>,
+    pub ssl_client_key: Option<String>,
+}
+
+impl Config {
+    /// Returns the SSL settings given in this config.
+    pub fn ssl_settings(&self) -> SslSettings {
+        SslSettings {
+            ca_bundle: self.ssl_ca_bundle.as_ref().map(PathBuf::from),
+            client_cert: self.ssl_client_cert.as_ref().map(PathBuf::from),
+            client_key: self.ssl_client_key.as_ref().map(PathBuf::from),
+        }
+    }
 }
 
 impl ConfigFile for Config {
@@ -75,6 +104,9 @@ impl ConfigFile for Config {
         let mut cfg = Config::default();
         try!(toml.parse_into("auth_token", &mut cfg.auth_token));
         try!(toml.parse_into("origin", &mut cfg.origin));
+        try!(toml.parse_into("ssl_ca_bundle", &mut cfg.ssl_ca_bundle));
+        try!(toml.parse_into("ssl_client_cert", &mut cfg.ssl_client_cert));
+        try!(toml.parse_into("ssl_client_key", &mut cfg.ssl_client_key));
         Ok(cfg)
     }
 }
@@ -84,6 +116,9 @@ impl Default for Config {
         Config {
             auth_token: None,
             origin: None,
+            ssl_ca_bundle: None,
+            ssl_client_cert: None,
+            ssl_client_key: None,
         }
     }
 }
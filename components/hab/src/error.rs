@@ -17,6 +17,7 @@ use std::ffi;
 use std::fmt;
 use std::io;
 use std::path;
+use std::process;
 use std::result;
 
 use depot_client;
@@ -25,36 +26,145 @@ use hcore;
 
 pub type Result<T> = result::Result<T, Error>;
 
+/// Exit code of a command which succeeded.
+pub const EXIT_OK: i32 = 0;
+/// Exit code of a command which failed for a reason without a more specific exit code.
+pub const EXIT_FAILURE: i32 = 1;
+/// Exit code of a command given invalid arguments.
+pub const EXIT_USAGE: i32 = 2;
+/// Exit code of a command which couldn't find a package, key or file.
+pub const EXIT_NOT_FOUND: i32 = 3;
+/// Exit code of a command which failed to talk to a Depot.
+pub const EXIT_DEPOT: i32 = 4;
+/// Exit code of a command which failed a signature, key or integrity check.
+pub const EXIT_VERIFICATION: i32 = 5;
+/// Exit code of a command which failed to talk to one or more Supervisors.
+pub const EXIT_SUPERVISOR: i32 = 6;
+
 #[derive(Debug)]
 #[allow(dead_code)]
 pub enum Error {
     ArgumentError(&'static str),
+    BundleMalformed(String),
     CommandNotFoundInPkg((String, String)),
+    ConfigDocumentNotFound(String, u64),
+    ConfigVersionNotFound(String, u64),
+    ConfigVersionNotNewer(String, u64, u64),
     CryptoCLI(String),
     DepotClient(depot_client::Error),
+    ExecCommandFailed(String, process::ExitStatus),
     ExecCommandNotFound(String),
     FFINulError(ffi::NulError),
     FileNotFound(String),
     HabitatCommon(common::Error),
     HabitatCore(hcore::Error),
+    InstallVerificationFailed(usize),
     IO(io::Error),
     PackageArchiveMalformed(String),
+    PackageRequired(String, Vec<String>),
     PathPrefixError(path::StripPrefixError),
     SubcommandNotSupported(String),
+    SupervisorNotInstalled(String),
+    SupervisorRequest(String, String),
+    SupervisorRequestFailed(usize),
     UnsupportedExportFormat(String),
 }
 
+impl Error {
+    /// Returns a stable name for the kind of failure, for machine-readable output.
+    pub fn kind(&self) -> &'static str {
+        self.classify().0
+    }
+
+    /// Returns the stable exit code for the kind of failure, used along with JSON output.
+    pub fn exit_code(&self) -> i32 {
+        self.classify().1
+    }
+
+    fn classify(&self) -> (&'static str, i32) {
+        match *self {
+            Error::ArgumentError(_) |
+            Error::ConfigVersionNotNewer(_, _, _) |
+            Error::HabitatCommon(common::Error::InvalidConfigPatch(_)) |
+            Error::SubcommandNotSupported(_) |
+            Error::UnsupportedExportFormat(_) => ("usage", EXIT_USAGE),
+            Error::CommandNotFoundInPkg(_) |
+            Error::ConfigDocumentNotFound(_, _) |
+            Error::ConfigVersionNotFound(_, _) |
+            Error::ExecCommandNotFound(_) |
+            Error::FileNotFound(_) |
+            Error::SupervisorNotInstalled(_) => ("not_found", EXIT_NOT_FOUND),
+            Error::CryptoCLI(_) |
+            Error::InstallVerificationFailed(_) => ("verification", EXIT_VERIFICATION),
+            Error::DepotClient(ref e) |
+            Error::HabitatCommon(common::Error::DepotClient(ref e)) => classify_depot(e),
+            Error::HabitatCore(ref e) |
+            Error::HabitatCommon(common::Error::HabitatCore(ref e)) => classify_core(e),
+            Error::HabitatCommon(common::Error::CryptoKeyError(_)) => {
+                ("verification", EXIT_VERIFICATION)
+            }
+            Error::HabitatCommon(common::Error::LocalDepotPackageNotFound(_)) |
+            Error::HabitatCommon(common::Error::LocalDepotKeyNotFound(_)) => {
+                ("not_found", EXIT_NOT_FOUND)
+            }
+            Error::PackageRequired(_, _) => ("package_required", EXIT_FAILURE),
+            Error::SupervisorRequest(_, _) |
+            Error::SupervisorRequestFailed(_) => ("supervisor", EXIT_SUPERVISOR),
+            _ => ("failure", EXIT_FAILURE),
+        }
+    }
+}
+
+fn classify_depot(err: &depot_client::Error) -> (&'static str, i32) {
+    match *err {
+        depot_client::Error::HabitatCore(ref e) => classify_core(e),
+        depot_client::Error::RemoteOriginKeyNotFound(_) |
+        depot_client::Error::RemotePackageNotFound(_) => ("not_found", EXIT_NOT_FOUND),
+        _ => ("depot", EXIT_DEPOT),
+    }
+}
+
+fn classify_core(err: &hcore::Error) -> (&'static str, i32) {
+    match *err {
+        hcore::Error::FileNotFound(_) |
+        hcore::Error::PackageNotFound(_) => ("not_found", EXIT_NOT_FOUND),
+        hcore::Error::CryptoError(_) => ("verification", EXIT_VERIFICATION),
+        hcore::Error::InvalidPackageIdent(_) |
+        hcore::Error::InvalidServiceGroup(_) => ("usage", EXIT_USAGE),
+        _ => ("failure", EXIT_FAILURE),
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let msg = match *self {
             Error::ArgumentError(ref e) => format!("{}", e),
+            Error::BundleMalformed(ref e) => format!("Bundle is malformed, {}", e),
             Error::CommandNotFoundInPkg((ref p, ref c)) => {
                 format!("`{}' was not found under any 'PATH' directories in the {} package",
                         c,
                         p)
             }
+            Error::ConfigDocumentNotFound(ref sg, ref version) => {
+                format!("The configuration document of {} in effect at version {} is no longer \
+                         in the Supervisor's history",
+                        sg,
+                        version)
+            }
+            Error::ConfigVersionNotFound(ref sg, ref version) => {
+                format!("Configuration version {} of {} is not in the Supervisor's history",
+                        version,
+                        sg)
+            }
+            Error::ConfigVersionNotNewer(ref sg, ref number, ref latest) => {
+                format!("Version {} of {} is not newer than its current version {}",
+                        number,
+                        sg,
+                        latest)
+            }
             Error::CryptoCLI(ref e) => format!("{}", e),
             Error::DepotClient(ref err) => format!("{}", err),
+            Error::ExecCommandFailed(ref c, ref status) => format!("`{}' failed: {}", c, status),
             Error::ExecCommandNotFound(ref c) => {
                 format!("`{}' was not found on the filesystem or in PATH", c)
             }
@@ -62,15 +172,32 @@ impl fmt::Display for Error {
             Error::FileNotFound(ref e) => format!("File not found at: {}", e),
             Error::HabitatCommon(ref e) => format!("{}", e),
             Error::HabitatCore(ref e) => format!("{}", e),
+            Error::InstallVerificationFailed(ref count) => {
+                format!("{} installed package(s) failed verification", count)
+            }
             Error::IO(ref err) => format!("{}", err),
             Error::PackageArchiveMalformed(ref e) => {
                 format!("Package archive was unreadable or contained unexpected contents: {:?}",
                         e)
             }
+            Error::PackageRequired(ref p, ref dependents) => {
+                format!("{} is required by {}, use --force to uninstall it anyway",
+                        p,
+                        dependents.join(", "))
+            }
             Error::PathPrefixError(ref err) => format!("{}", err),
             Error::SubcommandNotSupported(ref e) => {
                 format!("Subcommand `{}' not supported on this operating system", e)
             }
+            Error::SupervisorNotInstalled(ref p) => {
+                format!("{} is not installed, install it or use --install-supervisor", p)
+            }
+            Error::SupervisorRequest(ref peer, ref e) => {
+                format!("Can't query the Supervisor at {}: {}", peer, e)
+            }
+            Error::SupervisorRequestFailed(ref count) => {
+                format!("{} supervisor(s) could not be queried", count)
+            }
             Error::UnsupportedExportFormat(ref e) => format!("Unsupported export format: {}", e),
         };
         write!(f, "{}", msg)
@@ -81,22 +208,38 @@ impl error::Error for Error {
     fn description(&self) -> &str {
         match *self {
             Error::ArgumentError(_) => "There was an error parsing an error or with it's value",
+            Error::BundleMalformed(_) => "Bundle is malformed",
             Error::CommandNotFoundInPkg(_) => {
                 "Command was not found under any 'PATH' directories in the package"
             }
+            Error::ConfigDocumentNotFound(_, _) => {
+                "Configuration document in effect at a version is not in the Supervisor's history"
+            }
+            Error::ConfigVersionNotFound(_, _) => {
+                "Configuration version is not in the Supervisor's history"
+            }
+            Error::ConfigVersionNotNewer(_, _, _) => {
+                "Configuration version is not newer than the current version"
+            }
             Error::CryptoCLI(_) => "A cryptographic error has occurred",
             Error::DepotClient(ref err) => err.description(),
+            Error::ExecCommandFailed(_, _) => "Exec command exited unsuccessfully",
             Error::ExecCommandNotFound(_) => "Exec command was not found on filesystem or in PATH",
             Error::FFINulError(ref err) => err.description(),
             Error::FileNotFound(_) => "File not found",
             Error::HabitatCommon(ref err) => err.description(),
             Error::HabitatCore(ref err) => err.description(),
+            Error::InstallVerificationFailed(_) => "Installed packages failed verification",
             Error::IO(ref err) => err.description(),
             Error::PackageArchiveMalformed(_) => {
                 "Package archive was unreadable or had unexpected contents"
             }
+            Error::PackageRequired(_, _) => "Package is required by other installed packages",
             Error::PathPrefixError(ref err) => err.description(),
             Error::SubcommandNotSupported(_) => "Subcommand not supported on this operating system",
+            Error::SupervisorNotInstalled(_) => "The Supervisor package is not installed",
+            Error::SupervisorRequest(_, _) => "A Supervisor could not be queried",
+            Error::SupervisorRequestFailed(_) => "Supervisors could not be queried",
             Error::UnsupportedExportFormat(_) => "Unsupported export format",
         }
     }
@@ -137,3 +280,91 @@ impl From<path::StripPrefixError> for Error {
         Error::PathPrefixError(err)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::io;
+
+    use hyper::status::StatusCode;
+
+    use depot_client;
+    use common;
+    use hcore;
+    use hcore::package::PackageIdent;
+    use super::*;
+
+    fn redis() -> PackageIdent {
+        PackageIdent::new("core", "redis", None, None)
+    }
+
+    #[test]
+    fn classify_usage_errors() {
+        let errors = vec![Error::ArgumentError("bad"),
+                          Error::UnsupportedExportFormat("rkt".to_string()),
+                          Error::ConfigVersionNotNewer("redis.default".to_string(), 4, 7),
+                          Error::HabitatCommon(common::Error::InvalidConfigPatch("x".to_string())),
+                          Error::HabitatCore(hcore::Error::InvalidServiceGroup("x".to_string()))];
+        for e in errors {
+            assert_eq!(e.classify(), ("usage", EXIT_USAGE), "{:?}", e);
+        }
+    }
+
+    #[test]
+    fn classify_not_found_errors() {
+        let errors = vec![Error::FileNotFound("/tmp/nope".to_string()),
+                          Error::ConfigVersionNotFound("redis.default".to_string(), 3),
+                          Error::ConfigDocumentNotFound("redis.default".to_string(), 3),
+                          Error::HabitatCore(hcore::Error::PackageNotFound(redis())),
+                          Error::HabitatCommon(common::Error::HabitatCore(
+                              hcore::Error::PackageNotFound(redis()))),
+                          Error::DepotClient(depot_client::Error::RemotePackageNotFound(redis())),
+                          Error::HabitatCommon(common::Error::DepotClient(
+                              depot_client::Error::RemoteOriginKeyNotFound("core".to_string())))];
+        for e in errors {
+            assert_eq!(e.classify(), ("not_found", EXIT_NOT_FOUND), "{:?}", e);
+        }
+    }
+
+    #[test]
+    fn classify_depot_errors() {
+        let errors = vec![Error::DepotClient(depot_client::Error::HTTP(StatusCode::BadGateway)),
+                          Error::HabitatCommon(common::Error::DepotClient(
+                              depot_client::Error::NoXFilename))];
+        for e in errors {
+            assert_eq!(e.classify(), ("depot", EXIT_DEPOT), "{:?}", e);
+        }
+    }
+
+    #[test]
+    fn classify_verification_errors() {
+        let errors = vec![Error::InstallVerificationFailed(1),
+                          Error::HabitatCore(hcore::Error::CryptoError("bad".to_string())),
+                          Error::DepotClient(depot_client::Error::HabitatCore(
+                              hcore::Error::CryptoError("bad".to_string()))),
+                          Error::HabitatCommon(common::Error::CryptoKeyError("x".to_string()))];
+        for e in errors {
+            assert_eq!(e.classify(), ("verification", EXIT_VERIFICATION), "{:?}", e);
+        }
+    }
+
+    #[test]
+    fn classify_supervisor_errors() {
+        let errors = vec![Error::SupervisorRequest("10.0.0.1:9631".to_string(),
+                                                  "connection refused".to_string()),
+                          Error::SupervisorRequestFailed(2)];
+        for e in errors {
+            assert_eq!(e.classify(), ("supervisor", EXIT_SUPERVISOR), "{:?}", e);
+        }
+    }
+
+    #[test]
+    fn classify_other_errors_as_failures() {
+        let errors = vec![Error::IO(io::Error::new(io::ErrorKind::Other, "disk")),
+                          Error::HabitatCore(hcore::Error::PermissionFailed)];
+        for e in errors {
+            assert_eq!(e.classify(), ("failure", EXIT_FAILURE), "{:?}", e);
+            assert_eq!(e.kind(), "failure");
+            assert_eq!(e.exit_code(), EXIT_FAILURE);
+        }
+    }
+}
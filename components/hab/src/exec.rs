@@ -27,6 +27,7 @@ use hcore::fs::cache_artifact_path;
 use hcore::package::{PackageIdent, PackageInstall};
 use hcore::url::default_depot_url;
 
+use config;
 use error::{Error, Result};
 
 #[allow(dead_code)] // Currently only used on Linux platforms
@@ -101,7 +102,8 @@ pub fn command_from_pkg(command: &str,
                                                              ident,
                                                              fs_root_path,
                                                              &cache_artifact_path(None),
-                                                             cache_key_path));
+                                                             cache_key_path,
+                                                             &config::ssl_settings()));
             command_from_pkg(&command, &ident, &cache_key_path, retry + 1)
         }
         Err(e) => return Err(Error::from(e)),
@@ -22,11 +22,14 @@ extern crate ansi_term;
 extern crate clap;
 extern crate env_logger;
 extern crate hyper;
+extern crate libc;
 #[macro_use]
 extern crate log;
 extern crate pbr;
 extern crate regex;
 extern crate rustc_serialize;
+#[cfg(test)]
+extern crate tempdir;
 extern crate toml;
 extern crate url;
 // Temporary depdency for gossip/rumor injection code duplication.
@@ -40,24 +43,30 @@ mod config;
 mod error;
 mod exec;
 mod gossip;
+mod oci;
+mod output;
+mod passphrase;
+mod tar;
 
 use std::env;
 use std::ffi::OsString;
 use std::io::{self, Read};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::thread;
 
-use ansi_term::Colour::Red;
+use ansi_term::Colour::{Red, Yellow};
 use clap::ArgMatches;
 
 use error::{Error, Result};
 use hcore::env as henv;
 use hcore::crypto::{init, default_cache_key_path, BoxKeyPair, SigKeyPair, SymKey};
+use hcore::crypto::artifact::{Compression, SignaturePolicy};
+use hcore::crypto::hash::HashType;
 use hcore::crypto::keys::PairType;
 use hcore::fs::{cache_artifact_path, cache_analytics_path, FS_ROOT_PATH};
 use hcore::service::ServiceGroup;
-use hcore::package::PackageIdent;
+use hcore::package::{PackageArchive, PackageIdent, PackageInstall};
 use hcore::url::{DEFAULT_DEPOT_URL, DEPOT_URL_ENVVAR};
 
 use gossip::hab_gossip;
@@ -79,13 +88,22 @@ const DEFAULT_BINLINK_DIR: &'static str = "/bin";
 
 const MAX_FILE_UPLOAD_SIZE_BYTES: u64 = 4096;
 
+/// The commands which print package graphs, and so may also write them in the dot format.
+const GRAPH_COMMANDS: [&'static str; 3] = ["pkg list", "pkg deps", "pkg rdeps"];
+
 fn main() {
     env_logger::init().unwrap();
     thread::spawn(|| analytics::instrument_subcommand());
-    if let Err(e) = start() {
-        println!("{}",
-                 Red.bold().paint(format!("✗✗✗\n✗✗✗ {}\n✗✗✗", e)));
-        std::process::exit(1)
+    let result = start();
+    if let Err(ref e) = result {
+        if !output::is_json() {
+            println!("{}",
+                     Red.bold().paint(format!("✗✗✗\n✗✗✗ {}\n✗✗✗", e)));
+        }
+    }
+    let exit_code = output::finish(&result);
+    if exit_code != error::EXIT_OK {
+        std::process::exit(exit_code)
     }
 }
 
@@ -98,8 +116,18 @@ fn start() -> Result<()> {
     let app_matches =
         cli::get().get_matches_from_safe_borrow(&mut args.iter()).unwrap_or_else(|e| {
             analytics::instrument_clap_error(&e);
+            if json_requested(&args) && e.use_stderr() {
+                std::process::exit(output::usage_error(&e.message));
+            }
             e.exit();
         });
+    let command = command_name(&app_matches);
+    let format = try!(output_format(&app_matches, &command));
+    if format == "dot" && !GRAPH_COMMANDS.contains(&command.as_str()) {
+        return Err(Error::ArgumentError("The dot format is only supported by pkg list, pkg \
+                                         deps and pkg rdeps"));
+    }
+    try!(output::init(format == "json", &command));
     match app_matches.subcommand() {
         ("apply", Some(m)) => try!(sub_config_apply(m)),
         ("cli", Some(matches)) => {
@@ -111,11 +139,15 @@ fn start() -> Result<()> {
         ("config", Some(matches)) => {
             match matches.subcommand() {
                 ("apply", Some(m)) => try!(sub_config_apply(m)),
+                ("history", Some(m)) => try!(sub_config_history(m)),
+                ("rollback", Some(m)) => try!(sub_config_rollback(m)),
                 _ => unreachable!(),
             }
         }
         ("file", Some(matches)) => {
             match matches.subcommand() {
+                ("download", Some(m)) => try!(sub_file_download(m)),
+                ("list", Some(m)) => try!(sub_file_list(m)),
                 ("upload", Some(m)) => try!(sub_file_upload(m)),
                 _ => unreachable!(),
             }
@@ -128,7 +160,9 @@ fn start() -> Result<()> {
                         ("download", Some(sc)) => try!(sub_origin_key_download(sc)),
                         ("export", Some(sc)) => try!(sub_origin_key_export(sc)),
                         ("generate", Some(sc)) => try!(sub_origin_key_generate(sc)),
-                        ("import", Some(_)) => try!(sub_origin_key_import()),
+                        ("import", Some(sc)) => try!(sub_origin_key_import(sc)),
+                        ("revoke", Some(sc)) => try!(sub_origin_key_revoke(sc)),
+                        ("rotate", Some(sc)) => try!(sub_origin_key_rotate(sc)),
                         ("upload", Some(sc)) => try!(sub_origin_key_upload(sc)),
                         _ => unreachable!(),
                     }
@@ -140,14 +174,33 @@ fn start() -> Result<()> {
             match matches.subcommand() {
                 ("binlink", Some(m)) => try!(sub_pkg_binlink(m)),
                 ("build", Some(m)) => try!(sub_pkg_build(m)),
+                ("bundle", Some(m)) => {
+                    match m.subcommand() {
+                        ("create", Some(sc)) => try!(sub_pkg_bundle_create(sc)),
+                        ("install", Some(sc)) => try!(sub_pkg_bundle_install(sc)),
+                        _ => unreachable!(),
+                    }
+                }
+                ("countersign", Some(m)) => try!(sub_pkg_countersign(m)),
+                ("deps", Some(m)) => try!(sub_pkg_deps(m, false, &format)),
+                ("diff", Some(m)) => try!(sub_pkg_diff(m)),
                 ("exec", Some(m)) => try!(sub_pkg_exec(m, remaining_args)),
                 ("export", Some(m)) => try!(sub_pkg_export(m)),
+                ("gc", Some(m)) => try!(sub_pkg_gc(m)),
                 ("hash", Some(m)) => try!(sub_pkg_hash(m)),
+                ("index", Some(m)) => try!(sub_pkg_index(m)),
+                ("info", Some(m)) => try!(sub_pkg_info(m)),
+                ("inspect", Some(m)) => try!(sub_pkg_inspect(m)),
                 ("install", Some(m)) => try!(sub_pkg_install(m)),
+                ("list", Some(m)) => try!(sub_pkg_list(m, &format)),
                 ("path", Some(m)) => try!(sub_pkg_path(m)),
+                ("rdeps", Some(m)) => try!(sub_pkg_deps(m, true, &format)),
+                ("search", Some(m)) => try!(sub_pkg_search(m)),
                 ("sign", Some(m)) => try!(sub_pkg_sign(m)),
+                ("uninstall", Some(m)) => try!(sub_pkg_uninstall(m)),
                 ("upload", Some(m)) => try!(sub_pkg_upload(m)),
                 ("verify", Some(m)) => try!(sub_pkg_verify(m)),
+                ("verify-installed", Some(m)) => try!(sub_pkg_verify_installed(m)),
                 _ => unreachable!(),
             }
         }
@@ -176,6 +229,19 @@ fn start() -> Result<()> {
             }
         }
         ("setup", Some(_)) => try!(sub_cli_setup()),
+        ("svc", Some(matches)) => {
+            let peers = sidecar_peers(&matches);
+            match matches.subcommand() {
+                ("census", Some(m)) => try!(command::svc::census::start(&peers, &try!(svc_sg(m)))),
+                ("config", Some(m)) => try!(command::svc::config::start(&peers, &try!(svc_sg(m)))),
+                ("election", Some(m)) => {
+                    try!(command::svc::election::start(&peers, &try!(svc_sg(m))))
+                }
+                ("health", Some(m)) => try!(command::svc::health::start(&peers, &try!(svc_sg(m)))),
+                ("status", Some(m)) => try!(command::svc::status::start(&peers, &try!(svc_sg(m)))),
+                _ => unreachable!(),
+            }
+        }
         ("user", Some(matches)) => {
             match matches.subcommand() {
                 ("key", Some(m)) => {
@@ -204,14 +270,7 @@ fn sub_cli_setup() -> Result<()> {
 fn sub_config_apply(m: &ArgMatches) -> Result<()> {
     let fs_root = henv::var(FS_ROOT_ENVVAR).unwrap_or(FS_ROOT_PATH.to_string());
     let fs_root_path = Some(Path::new(&fs_root));
-    let peers_str = m.value_of("PEER").unwrap_or("127.0.0.1");
-    let mut peers: Vec<String> = peers_str.split(",").map(|p| p.into()).collect();
-    for p in peers.iter_mut() {
-        if p.find(':').is_none() {
-            p.push(':');
-            p.push_str(&hab_gossip::GOSSIP_DEFAULT_PORT.to_string());
-        }
-    }
+    let peers = gossip_peers(m);
     let number = value_t!(m, "VERSION_NUMBER", u64).unwrap_or_else(|e| e.exit());
     let file_path = match m.value_of("FILE") {
         Some("-") | None => None,
@@ -234,20 +293,136 @@ fn sub_config_apply(m: &ArgMatches) -> Result<()> {
     };
     sg.organization = org;
 
-    command::config::apply::start(&peers, ring_key.as_ref(), &sg, number, file_path)
+    if m.is_present("SET") || m.is_present("UNSET") {
+        let sets: Vec<&str> = m.values_of("SET").map(|v| v.collect()).unwrap_or(vec![]);
+        let unsets: Vec<&str> = m.values_of("UNSET").map(|v| v.collect()).unwrap_or(vec![]);
+        return command::config::patch::start(&peers,
+                                             ring_key.as_ref(),
+                                             &sg,
+                                             number,
+                                             &sets,
+                                             &unsets);
+    }
+    // a configuration is only encrypted for the service group when a user key is asked for on
+    // the command line, so a `$HAB_USER` left in the environment doesn't quietly require the
+    // service key; that user's key only signs it
+    let encrypt = m.is_present("USER");
+    let box_pairs = match m.value_of("USER") {
+        Some(user) => Some(try!(config_box_pairs(user, &sg, &cache))),
+        None => {
+            match env::var(HABITAT_USER_ENVVAR) {
+                Ok(user) => {
+                    match config_box_pairs(&user, &sg, &cache) {
+                        Ok(pairs) => {
+                            println!("{}",
+                                     Yellow.bold().paint(format!("» Applying the configuration \
+                                                                  unencrypted; pass --user {} \
+                                                                  to encrypt it for {}",
+                                                                 user,
+                                                                 sg)));
+                            Some(pairs)
+                        }
+                        Err(e) => {
+                            println!("{}",
+                                     Yellow.bold().paint(format!("» Applying the configuration \
+                                                                  unencrypted and unsigned, as \
+                                                                  {} can't sign it for {} ({})",
+                                                                 user,
+                                                                 sg,
+                                                                 e)));
+                            None
+                        }
+                    }
+                }
+                Err(_) => None,
+            }
+        }
+    };
+    command::config::apply::start(&peers,
+                                  ring_key.as_ref(),
+                                  &sg,
+                                  number,
+                                  file_path,
+                                  box_pairs.as_ref().map(|&(ref u, ref s)| (u, s)),
+                                  encrypt)
+}
+
+/// Returns the latest key pairs of a user and of the service group a configuration is for.
+fn config_box_pairs(user: &str,
+                    sg: &ServiceGroup,
+                    cache: &Path)
+                    -> Result<(BoxKeyPair, BoxKeyPair)> {
+    let user_pair = try!(latest_user_pair(user, cache));
+    let service_pair = try!(BoxKeyPair::get_latest_pair_for(&sg.to_string(), cache));
+    Ok((user_pair, service_pair))
+}
+
+fn sub_config_history(m: &ArgMatches) -> Result<()> {
+    let sg = try!(config_service_group(m));
+    command::config::history::start(&sidecar_addr(m.value_of("SUPERVISOR")), &sg)
+}
+
+fn sub_config_rollback(m: &ArgMatches) -> Result<()> {
+    let fs_root = henv::var(FS_ROOT_ENVVAR).unwrap_or(FS_ROOT_PATH.to_string());
+    let fs_root_path = Some(Path::new(&fs_root));
+    let peers = gossip_peers(m);
+    let version = value_t!(m, "VERSION_NUMBER", u64).unwrap_or_else(|e| e.exit());
+    let number = value_t!(m, "NEW_VERSION_NUMBER", u64).unwrap_or_else(|e| e.exit());
+
+    init();
+    let cache = default_cache_key_path(fs_root_path);
+    let ring_key = match m.value_of("RING") {
+        Some(name) => Some(try!(SymKey::get_latest_pair_for(&name, &cache))),
+        None => None,
+    };
+    let sg = try!(config_service_group(m));
+
+    command::config::rollback::start(&sidecar_addr(m.value_of("SUPERVISOR")),
+                                     &peers,
+                                     ring_key.as_ref(),
+                                     &sg,
+                                     version,
+                                     number,
+                                     &cache)
+}
+
+/// Returns the service group a Supervisor's HTTP API is asked about.
+fn svc_sg(m: &ArgMatches) -> Result<ServiceGroup> {
+    Ok(try!(ServiceGroup::from_str(m.value_of("SERVICE_GROUP").unwrap())))
+}
+
+/// Returns the service group given to a `config` subcommand, in the organization given by
+/// `--org` or `HAB_ORG` if there is one.
+fn config_service_group(m: &ArgMatches) -> Result<ServiceGroup> {
+    let mut sg = try!(ServiceGroup::from_str(m.value_of("SERVICE_GROUP").unwrap()));
+    sg.organization = org_param_or_env(&m).ok();
+    Ok(sg)
+}
+
+fn sub_file_download(m: &ArgMatches) -> Result<()> {
+    let fs_root = henv::var(FS_ROOT_ENVVAR).unwrap_or(FS_ROOT_PATH.to_string());
+    let fs_root_path = Some(Path::new(&fs_root));
+    let peer = sidecar_peer(m);
+    let sg = try!(svc_sg(m));
+    let file_name = m.value_of("FILE").unwrap();
+    let dst = m.value_of("DEST").map(|d| Path::new(d));
+    init();
+
+    command::file::download::start(&peer,
+                                   &sg,
+                                   file_name,
+                                   dst,
+                                   &default_cache_key_path(fs_root_path))
+}
+
+fn sub_file_list(m: &ArgMatches) -> Result<()> {
+    command::file::list::start(&sidecar_peer(m), &try!(svc_sg(m)))
 }
 
 fn sub_file_upload(m: &ArgMatches) -> Result<()> {
     let fs_root = henv::var(FS_ROOT_ENVVAR).unwrap_or(FS_ROOT_PATH.to_string());
     let fs_root_path = Some(Path::new(&fs_root));
-    let peers_str = m.value_of("PEER").unwrap_or("127.0.0.1");
-    let mut peers: Vec<String> = peers_str.split(",").map(|p| p.into()).collect();
-    for p in peers.iter_mut() {
-        if p.find(':').is_none() {
-            p.push(':');
-            p.push_str(&hab_gossip::GOSSIP_DEFAULT_PORT.to_string());
-        }
-    }
+    let peers = gossip_peers(m);
     let number = value_t!(m, "VERSION_NUMBER", u64).unwrap_or_else(|e| e.exit());
     let file_path = Path::new(m.value_of("FILE").unwrap());
     match file_path.metadata() {
@@ -278,7 +453,7 @@ fn sub_file_upload(m: &ArgMatches) -> Result<()> {
     let service_pair = try!(BoxKeyPair::get_latest_pair_for(&sg.to_string(), &cache));
 
     let user = try!(user_param_or_env(&m));
-    let user_pair = try!(BoxKeyPair::get_latest_pair_for(&user, &cache));
+    let user_pair = try!(latest_user_pair(&user, &cache));
 
     command::file::upload::start(&peers,
                                  ring_key.as_ref(),
@@ -307,28 +482,75 @@ fn sub_origin_key_export(m: &ArgMatches) -> Result<()> {
     let fs_root_path = Some(Path::new(&fs_root));
     let origin = m.value_of("ORIGIN").unwrap();
     let pair_type = try!(PairType::from_str(m.value_of("PAIR_TYPE").unwrap()));
+    let encrypt = m.is_present("ENCRYPT");
+    let decrypt = m.is_present("DECRYPT");
     init();
 
-    command::origin::key::export::start(origin, pair_type, &default_cache_key_path(fs_root_path))
+    command::origin::key::export::start(origin,
+                                        pair_type,
+                                        encrypt,
+                                        decrypt,
+                                        &default_cache_key_path(fs_root_path))
 }
 
 fn sub_origin_key_generate(m: &ArgMatches) -> Result<()> {
     let fs_root = henv::var(FS_ROOT_ENVVAR).unwrap_or(FS_ROOT_PATH.to_string());
     let fs_root_path = Some(Path::new(&fs_root));
     let origin = try!(origin_param_or_env(&m));
+    let encrypt = m.is_present("ENCRYPT");
     init();
 
-    command::origin::key::generate::start(&origin, &default_cache_key_path(fs_root_path))
+    command::origin::key::generate::start(&origin, encrypt, &default_cache_key_path(fs_root_path))
 }
 
-fn sub_origin_key_import() -> Result<()> {
+fn sub_origin_key_import(m: &ArgMatches) -> Result<()> {
     let fs_root = henv::var(FS_ROOT_ENVVAR).unwrap_or(FS_ROOT_PATH.to_string());
     let fs_root_path = Some(Path::new(&fs_root));
+    let encrypt = m.is_present("ENCRYPT");
     let mut content = String::new();
     try!(io::stdin().read_to_string(&mut content));
     init();
 
-    command::origin::key::import::start(&content, &default_cache_key_path(fs_root_path))
+    command::origin::key::import::start(&content, encrypt, &default_cache_key_path(fs_root_path))
+}
+
+fn sub_origin_key_revoke(m: &ArgMatches) -> Result<()> {
+    let fs_root = henv::var(FS_ROOT_ENVVAR).unwrap_or(FS_ROOT_PATH.to_string());
+    let fs_root_path = Some(Path::new(&fs_root));
+    let origin = m.value_of("ORIGIN").unwrap();
+    let revision = m.value_of("REVISION").unwrap();
+    let env_or_default = henv::var(DEPOT_URL_ENVVAR).unwrap_or(DEFAULT_DEPOT_URL.to_string());
+    let url = m.value_of("DEPOT_URL").unwrap_or(&env_or_default);
+    let token = try!(auth_token_param_or_env(&m));
+    init();
+
+    command::origin::key::revoke::start(url,
+                                        &token,
+                                        origin,
+                                        revision,
+                                        &default_cache_key_path(fs_root_path))
+}
+
+fn sub_origin_key_rotate(m: &ArgMatches) -> Result<()> {
+    let fs_root = henv::var(FS_ROOT_ENVVAR).unwrap_or(FS_ROOT_PATH.to_string());
+    let fs_root_path = Path::new(&fs_root);
+    let origin = m.value_of("ORIGIN").unwrap();
+    let encrypt = m.is_present("ENCRYPT");
+    let with_secret = m.is_present("WITH_SECRET");
+    let resign = m.is_present("RESIGN");
+    let env_or_default = henv::var(DEPOT_URL_ENVVAR).unwrap_or(DEFAULT_DEPOT_URL.to_string());
+    let url = m.value_of("DEPOT_URL").unwrap_or(&env_or_default);
+    let token = try!(auth_token_param_or_env(&m));
+    init();
+
+    command::origin::key::rotate::start(url,
+                                        &token,
+                                        origin,
+                                        encrypt,
+                                        with_secret,
+                                        resign,
+                                        &default_cache_key_path(Some(fs_root_path)),
+                                        &fs_root_path)
 }
 
 fn sub_origin_key_upload(m: &ArgMatches) -> Result<()> {
@@ -397,6 +619,65 @@ fn sub_pkg_build(m: &ArgMatches) -> Result<()> {
     command::pkg::build::start(plan_context, root, src, keys, reuse)
 }
 
+fn sub_pkg_bundle_create(m: &ArgMatches) -> Result<()> {
+    let fs_root = henv::var(FS_ROOT_ENVVAR).unwrap_or(FS_ROOT_PATH.to_string());
+    let env_or_default = henv::var(DEPOT_URL_ENVVAR).unwrap_or(DEFAULT_DEPOT_URL.to_string());
+    let url = m.value_of("DEPOT_URL").unwrap_or(&env_or_default);
+    let ident = try!(PackageIdent::from_str(m.value_of("PKG_IDENT").unwrap()));
+    init();
+
+    try!(command::pkg::bundle::create::start(&ident,
+                                             url,
+                                             Path::new(&fs_root),
+                                             &try!(env::current_dir())));
+    Ok(())
+}
+
+fn sub_pkg_bundle_install(m: &ArgMatches) -> Result<()> {
+    let fs_root = henv::var(FS_ROOT_ENVVAR).unwrap_or(FS_ROOT_PATH.to_string());
+    let url = henv::var(DEPOT_URL_ENVVAR).unwrap_or(DEFAULT_DEPOT_URL.to_string());
+    let src = Path::new(m.value_of("SOURCE").unwrap());
+    init();
+
+    command::pkg::bundle::install::start(&src, &url, Path::new(&fs_root))
+}
+
+fn sub_pkg_countersign(m: &ArgMatches) -> Result<()> {
+    let fs_root = henv::var(FS_ROOT_ENVVAR).unwrap_or(FS_ROOT_PATH.to_string());
+    let fs_root_path = Some(Path::new(&fs_root));
+    let src = Path::new(m.value_of("SOURCE").unwrap());
+    let dst = Path::new(m.value_of("DEST").unwrap());
+    init();
+    let cache = default_cache_key_path(fs_root_path);
+    let pair = try!(origin_signing_pair(m, &try!(origin_param_or_env(&m)), &cache));
+
+    command::pkg::countersign::start(&pair, &src, &dst, &cache)
+}
+
+fn sub_pkg_deps(m: &ArgMatches, reverse: bool, format: &str) -> Result<()> {
+    let fs_root = henv::var(FS_ROOT_ENVVAR).unwrap_or(FS_ROOT_PATH.to_string());
+    let fs_root_path = Path::new(&fs_root);
+    let ident = try!(PackageIdent::from_str(m.value_of("PKG_IDENT").unwrap()));
+    let format = try!(command::pkg::deps::format_for(format));
+
+    command::pkg::deps::start(&ident, reverse, &format, &fs_root_path)
+}
+
+fn sub_pkg_diff(m: &ArgMatches) -> Result<()> {
+    let fs_root = henv::var(FS_ROOT_ENVVAR).unwrap_or(FS_ROOT_PATH.to_string());
+    let fs_root_path = Path::new(&fs_root);
+    let a = m.value_of("PKG_A").unwrap();
+    let b = m.value_of("PKG_B").unwrap();
+    let patch = m.is_present("PATCH");
+    init();
+
+    command::pkg::diff::start(&a,
+                              &b,
+                              patch,
+                              &fs_root_path,
+                              &default_cache_key_path(Some(fs_root_path)))
+}
+
 fn sub_pkg_exec(m: &ArgMatches, cmd_args: Vec<OsString>) -> Result<()> {
     let ident = try!(PackageIdent::from_str(m.value_of("PKG_IDENT").unwrap()));
     let cmd = m.value_of("CMD").unwrap();
@@ -407,15 +688,65 @@ fn sub_pkg_exec(m: &ArgMatches, cmd_args: Vec<OsString>) -> Result<()> {
 fn sub_pkg_export(m: &ArgMatches) -> Result<()> {
     let ident = try!(PackageIdent::from_str(m.value_of("PKG_IDENT").unwrap()));
     let format = &m.value_of("FORMAT").unwrap();
+    if *format == "oci" {
+        let fs_root = henv::var(FS_ROOT_ENVVAR).unwrap_or(FS_ROOT_PATH.to_string());
+        init();
+        return command::pkg::export::oci(&ident,
+                                         Path::new(&fs_root),
+                                         m.is_present("INSTALL_SUP"));
+    }
     let export_fmt = try!(command::pkg::export::format_for(&format));
     command::pkg::export::start(&ident, &export_fmt)
 }
 
+fn sub_pkg_gc(m: &ArgMatches) -> Result<()> {
+    let fs_root = henv::var(FS_ROOT_ENVVAR).unwrap_or(FS_ROOT_PATH.to_string());
+    let fs_root_path = Path::new(&fs_root);
+    let mut roots = Vec::new();
+    for ident in m.values_of("PKG_IDENT").unwrap() {
+        roots.push(try!(PackageIdent::from_str(ident)));
+    }
+    let dry_run = m.is_present("DRY_RUN");
+
+    command::pkg::gc::start(&roots, dry_run, &fs_root_path)
+}
+
 fn sub_pkg_hash(m: &ArgMatches) -> Result<()> {
     let source = m.value_of("SOURCE").unwrap();
+    let hash_type = match m.value_of("HASH_TYPE") {
+        Some(h) => try!(HashType::from_str(h)),
+        None => HashType::default(),
+    };
+
+    init();
+    command::pkg::hash::start(&source, hash_type)
+}
 
+fn sub_pkg_index(m: &ArgMatches) -> Result<()> {
+    let fs_root = henv::var(FS_ROOT_ENVVAR).unwrap_or(FS_ROOT_PATH.to_string());
+    let fs_root_path = Some(Path::new(&fs_root));
+    let dir = Path::new(m.value_of("DIR").unwrap());
     init();
-    command::pkg::hash::start(&source)
+
+    command::pkg::index::start(&dir, &default_cache_key_path(fs_root_path))
+}
+
+fn sub_pkg_info(m: &ArgMatches) -> Result<()> {
+    let env_or_default = henv::var(DEPOT_URL_ENVVAR).unwrap_or(DEFAULT_DEPOT_URL.to_string());
+    let url = m.value_of("DEPOT_URL").unwrap_or(&env_or_default);
+    let ident = try!(PackageIdent::from_str(m.value_of("PKG_IDENT").unwrap()));
+
+    command::pkg::info::start(&url, &ident)
+}
+
+fn sub_pkg_inspect(m: &ArgMatches) -> Result<()> {
+    let fs_root = henv::var(FS_ROOT_ENVVAR).unwrap_or(FS_ROOT_PATH.to_string());
+    let fs_root_path = Some(Path::new(&fs_root));
+    let src = Path::new(m.value_of("SOURCE").unwrap());
+    let file = m.value_of("FILE");
+    init();
+
+    command::pkg::inspect::start(&src, file, &default_cache_key_path(fs_root_path))
 }
 
 fn sub_pkg_install(m: &ArgMatches) -> Result<()> {
@@ -424,6 +755,7 @@ fn sub_pkg_install(m: &ArgMatches) -> Result<()> {
     let env_or_default = henv::var(DEPOT_URL_ENVVAR).unwrap_or(DEFAULT_DEPOT_URL.to_string());
     let url = m.value_of("DEPOT_URL").unwrap_or(&env_or_default);
     let ident_or_artifacts = m.values_of("PKG_IDENT_OR_ARTIFACT").unwrap();
+    let ssl = config::ssl_settings();
     init();
 
     for ident_or_artifact in ident_or_artifacts {
@@ -431,11 +763,32 @@ fn sub_pkg_install(m: &ArgMatches) -> Result<()> {
                                                       ident_or_artifact,
                                                       Path::new(&fs_root),
                                                       &cache_artifact_path(fs_root_path),
-                                                      &default_cache_key_path(fs_root_path)));
+                                                      &default_cache_key_path(fs_root_path),
+                                                      &ssl));
+        if output::is_json() {
+            let installed = if Path::new(ident_or_artifact).is_file() {
+                try!(PackageArchive::new(PathBuf::from(ident_or_artifact)).ident())
+            } else {
+                let ident = try!(PackageIdent::from_str(ident_or_artifact));
+                try!(PackageInstall::load(&ident, fs_root_path)).ident().clone()
+            };
+            output::append("installed", &installed.to_string());
+        }
     }
     Ok(())
 }
 
+fn sub_pkg_list(m: &ArgMatches, format: &str) -> Result<()> {
+    let fs_root = henv::var(FS_ROOT_ENVVAR).unwrap_or(FS_ROOT_PATH.to_string());
+    let fs_root_path = Path::new(&fs_root);
+    let format = try!(command::pkg::deps::format_for(format));
+
+    command::pkg::list::start(m.value_of("ORIGIN"),
+                              m.value_of("NAME"),
+                              &format,
+                              &fs_root_path)
+}
+
 fn sub_pkg_path(m: &ArgMatches) -> Result<()> {
     let fs_root = henv::var(FS_ROOT_ENVVAR).unwrap_or(FS_ROOT_PATH.to_string());
     let fs_root_path = Path::new(&fs_root);
@@ -444,16 +797,43 @@ fn sub_pkg_path(m: &ArgMatches) -> Result<()> {
     command::pkg::path::start(&ident, &fs_root_path)
 }
 
+fn sub_pkg_search(m: &ArgMatches) -> Result<()> {
+    let env_or_default = henv::var(DEPOT_URL_ENVVAR).unwrap_or(DEFAULT_DEPOT_URL.to_string());
+    let url = m.value_of("DEPOT_URL").unwrap_or(&env_or_default);
+    let query = m.value_of("SEARCH_TERM").unwrap();
+    let offset = m.value_of("OFFSET").map(|o| o.parse::<usize>().unwrap()).unwrap_or(0);
+
+    command::pkg::search::start(&url, query, offset)
+}
+
 fn sub_pkg_sign(m: &ArgMatches) -> Result<()> {
     let fs_root = henv::var(FS_ROOT_ENVVAR).unwrap_or(FS_ROOT_PATH.to_string());
     let fs_root_path = Some(Path::new(&fs_root));
     let src = Path::new(m.value_of("SOURCE").unwrap());
     let dst = Path::new(m.value_of("DEST").unwrap());
+    let compression = match m.value_of("COMPRESSION") {
+        Some(c) => Some(try!(Compression::from_str(c))),
+        None => None,
+    };
+    let hash_type = match m.value_of("HASH_TYPE") {
+        Some(h) => try!(HashType::from_str(h)),
+        None => HashType::default(),
+    };
     init();
-    let pair = try!(SigKeyPair::get_latest_pair_for(&try!(origin_param_or_env(&m)),
-                                                    &default_cache_key_path(fs_root_path)));
+    let pair = try!(origin_signing_pair(m,
+                                        &try!(origin_param_or_env(&m)),
+                                        &default_cache_key_path(fs_root_path)));
+
+    command::pkg::sign::start(&pair, &src, &dst, compression, hash_type)
+}
+
+fn sub_pkg_uninstall(m: &ArgMatches) -> Result<()> {
+    let fs_root = henv::var(FS_ROOT_ENVVAR).unwrap_or(FS_ROOT_PATH.to_string());
+    let fs_root_path = Path::new(&fs_root);
+    let ident = try!(PackageIdent::from_str(m.value_of("PKG_IDENT").unwrap()));
+    let force = m.is_present("FORCE");
 
-    command::pkg::sign::start(&pair, &src, &dst)
+    command::pkg::uninstall::start(&ident, force, &fs_root_path)
 }
 
 fn sub_pkg_upload(m: &ArgMatches) -> Result<()> {
@@ -471,9 +851,33 @@ fn sub_pkg_verify(m: &ArgMatches) -> Result<()> {
     let fs_root = henv::var(FS_ROOT_ENVVAR).unwrap_or(FS_ROOT_PATH.to_string());
     let fs_root_path = Some(Path::new(&fs_root));
     let src = Path::new(m.value_of("SOURCE").unwrap());
+    let policy = match m.values_of("REQUIRE") {
+        Some(requirements) => {
+            let mut policy = SignaturePolicy::new();
+            for keys in requirements {
+                policy = policy.require_one_of(keys.split(',').map(|k| k.trim()));
+            }
+            Some(policy)
+        }
+        None => None,
+    };
     init();
 
-    command::pkg::verify::start(&src, &default_cache_key_path(fs_root_path))
+    command::pkg::verify::start(&src, &default_cache_key_path(fs_root_path), policy.as_ref())
+}
+
+fn sub_pkg_verify_installed(m: &ArgMatches) -> Result<()> {
+    let fs_root = henv::var(FS_ROOT_ENVVAR).unwrap_or(FS_ROOT_PATH.to_string());
+    let fs_root_path = Path::new(&fs_root);
+    let ident = match m.value_of("PKG_IDENT") {
+        Some(ident) => Some(try!(PackageIdent::from_str(ident))),
+        None => None,
+    };
+    init();
+
+    command::pkg::verify_installed::start(ident.as_ref(),
+                                          &fs_root_path,
+                                          &default_cache_key_path(Some(fs_root_path)))
 }
 
 fn sub_ring_key_export(m: &ArgMatches) -> Result<()> {
@@ -520,9 +924,10 @@ fn sub_user_key_generate(m: &ArgMatches) -> Result<()> {
     let fs_root = henv::var(FS_ROOT_ENVVAR).unwrap_or(FS_ROOT_PATH.to_string());
     let fs_root_path = Some(Path::new(&fs_root));
     let user = m.value_of("USER").unwrap(); // clap required
+    let encrypt = m.is_present("ENCRYPT");
     init();
 
-    command::user::key::generate::start(user, &default_cache_key_path(fs_root_path))
+    command::user::key::generate::start(user, encrypt, &default_cache_key_path(fs_root_path))
 }
 
 fn exec_subcommand_if_called() -> Result<()> {
@@ -555,6 +960,101 @@ fn raw_parse_args() -> (Vec<OsString>, Vec<OsString>) {
     }
 }
 
+/// Returns the value of an argument given to a command or to any of its parent commands, as
+/// global arguments may be given at any level.
+fn global_value<'a>(m: &'a ArgMatches, name: &str) -> Option<&'a str> {
+    m.value_of(name).or_else(|| {
+        match m.subcommand() {
+            (_, Some(sc)) => global_value(sc, name),
+            _ => None,
+        }
+    })
+}
+
+/// Returns the output format given with the global `--format`, or with the `-f` shorthand which
+/// only `pkg list`, `pkg deps` and `pkg rdeps` take.
+fn output_format(m: &ArgMatches, command: &str) -> Result<String> {
+    let global = global_value(m, "OUTPUT_FORMAT");
+    let alias = if GRAPH_COMMANDS.contains(&command) {
+        global_value(m, "FORMAT")
+    } else {
+        None
+    };
+    match (global, alias) {
+        (Some(g), Some(a)) if g != a => {
+            Err(Error::ArgumentError("--format and -f were given different output formats"))
+        }
+        (g, a) => Ok(g.or(a).unwrap_or("text").to_string()),
+    }
+}
+
+/// Returns the full name of the subcommand being run (ex: "pkg install").
+fn command_name(m: &ArgMatches) -> String {
+    match m.subcommand() {
+        (name, Some(sc)) => {
+            let rest = command_name(sc);
+            if rest.is_empty() {
+                name.to_string()
+            } else {
+                format!("{} {}", name, rest)
+            }
+        }
+        _ => String::new(),
+    }
+}
+
+/// Returns true if JSON output was asked for in arguments which may not have parsed.
+fn json_requested(args: &[OsString]) -> bool {
+    args.iter().any(|a| *a == *"--format=json") ||
+    args.windows(2).any(|w| (w[0] == *"--format" || w[0] == *"-f") && w[1] == *"json")
+}
+
+/// Returns the Supervisor HTTP API given to a subcommand, adding the default port if it was given
+/// without one.
+fn sidecar_peer(m: &ArgMatches) -> String {
+    sidecar_addr(m.value_of("PEER"))
+}
+
+fn sidecar_addr(addr: Option<&str>) -> String {
+    let mut addr = addr.unwrap_or("127.0.0.1").to_string();
+    if addr.find(':').is_none() {
+        addr.push(':');
+        addr.push_str(&command::svc::SIDECAR_DEFAULT_PORT.to_string());
+    }
+    addr
+}
+
+/// Returns the gossip peers given to a subcommand, adding the default port to any given without
+/// one.
+fn gossip_peers(m: &ArgMatches) -> Vec<String> {
+    let peers_str = m.value_of("PEER").unwrap_or("127.0.0.1");
+    let mut peers: Vec<String> = peers_str.split(",").map(|p| p.into()).collect();
+    for p in peers.iter_mut() {
+        if p.find(':').is_none() {
+            p.push(':');
+            p.push_str(&hab_gossip::GOSSIP_DEFAULT_PORT.to_string());
+        }
+    }
+    peers
+}
+
+/// Returns the Supervisor HTTP APIs given to a `svc` subcommand, adding the default port to any
+/// given without one.
+fn sidecar_peers(m: &ArgMatches) -> Vec<String> {
+    let peers_str = match m.subcommand() {
+        (_, Some(sc)) => sc.value_of("PEER").unwrap_or("127.0.0.1"),
+        _ => "127.0.0.1",
+    };
+    let mut peers: Vec<String> = peers_str.split(",").map(|p| p.into()).collect();
+    for p in peers.iter_mut() {
+        if p.find(':').is_none() {
+            p.push(':');
+            p.push_str(&command::svc::SIDECAR_DEFAULT_PORT.to_string());
+        }
+    }
+    peers
+}
+
 /// Check to see if the user has passed in an AUTH_TOKEN param. If not, check the
 /// HABITAT_AUTH_TOKEN env var. If not, check the CLI config to see if there is a default auth
 /// token set. If that's empty too, then error.
@@ -576,6 +1076,43 @@ fn auth_token_param_or_env(m: &ArgMatches) -> Result<String> {
     }
 }
 
+/// Load the origin key pair for signing: the revision the Depot holds as current, or the latest
+/// one in the key cache if the origin has no key in the Depot or the Depot can't be reached. If
+/// its secret key is encrypted, the passphrase is taken from the environment or prompted for
+/// before the pair is loaded.
+fn origin_signing_pair(m: &ArgMatches, origin: &str, cache: &Path) -> Result<SigKeyPair> {
+    let env_or_default = henv::var(DEPOT_URL_ENVVAR).unwrap_or(DEFAULT_DEPOT_URL.to_string());
+    let url = m.value_of("DEPOT_URL").unwrap_or(&env_or_default);
+    let current = depot_client::Client::new_with_ssl(url, None, &config::ssl_settings())
+        .and_then(|client| client.current_origin_key_revision(origin));
+    let name_with_rev = match current {
+        Ok(revision) => format!("{}-{}", origin, revision),
+        Err(e) => {
+            let latest = try!(SigKeyPair::get_latest_pair_for(origin, cache));
+            if let depot_client::Error::RemoteOriginKeyNotFound(_) = e {
+                debug!("{} has no origin key in {}", origin, url);
+            } else {
+                println!("{}",
+                         Yellow.bold().paint(format!("» Could not find the current {} key in \
+                                                      {} ({}), signing with {}",
+                                                     origin,
+                                                     url,
+                                                     e,
+                                                     latest.name_with_rev())));
+            }
+            latest.name_with_rev()
+        }
+    };
+    passphrase::sig_pair_for(&name_with_rev, cache)
+}
+
+/// Load the latest key pair of a user. If its secret key is encrypted, the passphrase is taken
+/// from the environment or prompted for before the pair is loaded.
+fn latest_user_pair(user: &str, cache: &Path) -> Result<BoxKeyPair> {
+    let latest = try!(BoxKeyPair::get_latest_pair_for(user, cache));
+    passphrase::box_pair_for(&latest.name_with_rev(), cache)
+}
+
 /// Check to see if the user has passed in an ORIGIN param.  If not, check the HABITAT_ORIGIN env
 /// var. If not, check the CLI config to see if there is a default origin set. If that's empty too,
 /// then error.
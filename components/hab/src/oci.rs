@@ -0,0 +1,500 @@
+// Copyright (c) 2016 Chef Software Inc. and/or applicable contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Builds OCI image layout tarballs from installed packages.
+//!
+//! No container daemon is involved: the image is assembled by writing tar layers straight from
+//! the package install directories on disk. The resulting tarball holds an [OCI image
+//! layout](https://github.com/opencontainers/image-spec/blob/master/image-layout.md) with a single
+//! image whose layers are:
+//!
+//! * a base layer with a minimal `/etc` and `/tmp`
+//! * one layer for each package, being the supervisor, the exported package and all of their
+//!   transitive dependencies
+//!
+//! Layers are written with fixed ownership and timestamps and with their entries in sorted order,
+//! so the same package release always produces the same layer digest. Registries and container
+//! runtimes can therefore reuse a layer shared by several images, such as the one for
+//! `core/glibc`.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use hcore::crypto::hash::{HashType, Hasher};
+use hcore::fs::{PKG_PATH, SVC_PATH};
+use hcore::package::{PackageIdent, PackageInstall};
+use rustc_serialize::json::Json;
+
+use error::Result;
+use tar::TarBuilder;
+
+/// The port of the supervisor's HTTP API, which is always exposed.
+const SUP_HTTP_PORT: &'static str = "9631";
+const OCI_ARCHITECTURE: &'static str = "amd64";
+const OCI_OS: &'static str = "linux";
+const MEDIA_TYPE_CONFIG: &'static str = "application/vnd.oci.image.config.v1+json";
+const MEDIA_TYPE_LAYER: &'static str = "application/vnd.oci.image.layer.v1.tar";
+const MEDIA_TYPE_MANIFEST: &'static str = "application/vnd.oci.image.manifest.v1+json";
+const REF_NAME_ANNOTATION: &'static str = "org.opencontainers.image.ref.name";
+
+const ETC_PASSWD: &'static str = "root:x:0:0:root:/:/bin/sh\nhab:x:42:42:root:/:/bin/sh\n";
+const ETC_GROUP: &'static str = "root:x:0:\nhab:x:42:hab\n";
+const ETC_NSSWITCH: &'static str = "passwd:     files\ngroup:      files\nshadow:     files\n\n\
+                                    hosts:      files dns\nnetworks:   files\n\n\
+                                    rpc:        files\nservices:   files\n";
+
+/// Returns the file name of the image layout tarball for the given package.
+pub fn tarball_name(ident: &PackageIdent) -> String {
+    format!("{}-{}-{}-{}-oci.tar",
+            ident.origin,
+            ident.name,
+            ident.version.as_ref().map(|v| &v[..]).unwrap_or("unknown"),
+            ident.release.as_ref().map(|r| &r[..]).unwrap_or("unknown"))
+}
+
+/// Writes an OCI image layout tarball to `dst` for an image running `pkg` under the supervisor
+/// package `sup`. Both packages and all of their transitive dependencies must be installed
+/// under `fs_root_path`.
+///
+/// Returns the digest of the image manifest.
+///
+/// # Failures
+///
+/// * If a package or one of its dependencies is not installed
+/// * If a package's files cannot be read
+/// * If the tarball cannot be written
+pub fn export(pkg: &PackageInstall,
+              sup: &PackageInstall,
+              fs_root_path: &Path,
+              dst: &Path)
+              -> Result<String> {
+    let layer_tmp = dst.with_extension("layer.tmp");
+    let result = write_image(pkg, sup, fs_root_path, dst, &layer_tmp);
+    if layer_tmp.exists() {
+        try!(fs::remove_file(&layer_tmp));
+    }
+    result
+}
+
+fn write_image(pkg: &PackageInstall,
+               sup: &PackageInstall,
+               fs_root_path: &Path,
+               dst: &Path,
+               layer_tmp: &Path)
+               -> Result<String> {
+    let mut layout = TarBuilder::new(try!(File::create(dst)));
+    let mut layers = Vec::new();
+    let mut history = Vec::new();
+
+    debug!("Writing base layer");
+    let base = try!(write_layer(layer_tmp, |tar| append_base(tar)));
+    try!(append_blob_file(&mut layout, &base, layer_tmp));
+    layers.push(base);
+    history.push("base filesystem".to_string());
+
+    for ident in try!(layer_idents(pkg, sup)) {
+        let install = try!(PackageInstall::load(&ident, Some(fs_root_path)));
+        debug!("Writing layer for {}", &ident);
+        let layer = try!(write_layer(layer_tmp, |tar| append_package(tar, &install)));
+        try!(append_blob_file(&mut layout, &layer, layer_tmp));
+        layers.push(layer);
+        history.push(ident.to_string());
+    }
+
+    let config_json = try!(image_config(pkg, sup, &layers, &history)).to_string();
+    let config = Descriptor::for_bytes(MEDIA_TYPE_CONFIG, config_json.as_bytes());
+    try!(append_blob(&mut layout, &config, config_json.as_bytes()));
+
+    let manifest = image_manifest(&config, &layers).to_string();
+    let manifest_desc = Descriptor::for_bytes(MEDIA_TYPE_MANIFEST, manifest.as_bytes());
+    try!(append_blob(&mut layout, &manifest_desc, manifest.as_bytes()));
+
+    let ident = pkg.ident();
+    let ref_name = format!("{}-{}",
+                           ident.version.as_ref().map(|v| &v[..]).unwrap_or("unknown"),
+                           ident.release.as_ref().map(|r| &r[..]).unwrap_or("unknown"));
+    let mut index_desc = manifest_desc.to_json();
+    if let Json::Object(ref mut obj) = index_desc {
+        let mut annotations = BTreeMap::new();
+        annotations.insert(REF_NAME_ANNOTATION.to_string(), Json::String(ref_name));
+        obj.insert("annotations".to_string(), Json::Object(annotations));
+    }
+    let mut index = BTreeMap::new();
+    index.insert("schemaVersion".to_string(), Json::U64(2));
+    index.insert("manifests".to_string(), Json::Array(vec![index_desc]));
+    let index = Json::Object(index).to_string();
+    try!(layout.append_data("index.json", 0o644, index.as_bytes()));
+    try!(layout.append_data("oci-layout",
+                            0o644,
+                            b"{\"imageLayoutVersion\":\"1.0.0\"}"));
+    let mut file = try!(layout.finish());
+    try!(file.flush());
+    Ok(manifest_desc.digest)
+}
+
+/// The packages to write as layers, in order: the supervisor's dependencies and the supervisor
+/// first as they are shared by every exported image, then the package's dependencies and the
+/// package itself.
+fn layer_idents(pkg: &PackageInstall, sup: &PackageInstall) -> Result<Vec<PackageIdent>> {
+    let mut seen = HashSet::new();
+    let mut idents = Vec::new();
+    let mut candidates = try!(sup.tdeps());
+    candidates.push(sup.ident().clone());
+    candidates.extend(try!(pkg.tdeps()));
+    candidates.push(pkg.ident().clone());
+    for ident in candidates {
+        if seen.insert(ident.clone()) {
+            idents.push(ident);
+        }
+    }
+    Ok(idents)
+}
+
+fn image_config(pkg: &PackageInstall,
+                sup: &PackageInstall,
+                layers: &[Descriptor],
+                history: &[String])
+                -> Result<Json> {
+    let ident = pkg.ident();
+    let mut path = Vec::new();
+    for run_path in vec![try!(pkg.runtime_path()), try!(sup.runtime_path())] {
+        for entry in run_path.split(':') {
+            if !entry.is_empty() && !path.contains(&entry.to_string()) {
+                path.push(entry.to_string());
+            }
+        }
+    }
+    let sup_bin = Path::new("/")
+        .join(PKG_PATH)
+        .join(sup.ident().to_string())
+        .join("bin/hab-sup");
+
+    let mut ports = BTreeMap::new();
+    ports.insert(format!("{}/tcp", SUP_HTTP_PORT), Json::Object(BTreeMap::new()));
+    for port in try!(pkg.exposes()) {
+        if !port.is_empty() {
+            ports.insert(format!("{}/tcp", port), Json::Object(BTreeMap::new()));
+        }
+    }
+    let mut volumes = BTreeMap::new();
+    for dir in vec!["data", "config"] {
+        let volume = Path::new("/").join(SVC_PATH).join(&ident.name).join(dir);
+        volumes.insert(volume.to_string_lossy().into_owned(),
+                       Json::Object(BTreeMap::new()));
+    }
+
+    let mut config = BTreeMap::new();
+    config.insert("Env".to_string(),
+                  Json::Array(vec![Json::String(format!("PATH={}", path.join(":")))]));
+    config.insert("Entrypoint".to_string(),
+                  Json::Array(vec![Json::String(sup_bin.to_string_lossy().into_owned())]));
+    config.insert("Cmd".to_string(),
+                  Json::Array(vec![Json::String("start".to_string()),
+                                   Json::String(ident.to_string())]));
+    config.insert("ExposedPorts".to_string(), Json::Object(ports));
+    config.insert("Volumes".to_string(), Json::Object(volumes));
+    config.insert("WorkingDir".to_string(), Json::String("/".to_string()));
+
+    let mut rootfs = BTreeMap::new();
+    rootfs.insert("type".to_string(), Json::String("layers".to_string()));
+    rootfs.insert("diff_ids".to_string(),
+                  Json::Array(layers.iter().map(|l| Json::String(l.digest.clone())).collect()));
+
+    let history = history.iter()
+        .map(|h| {
+            let mut entry = BTreeMap::new();
+            entry.insert("created_by".to_string(),
+                         Json::String(format!("hab pkg export oci: {}", h)));
+            Json::Object(entry)
+        })
+        .collect();
+
+    let mut image = BTreeMap::new();
+    image.insert("architecture".to_string(),
+                 Json::String(OCI_ARCHITECTURE.to_string()));
+    image.insert("os".to_string(), Json::String(OCI_OS.to_string()));
+    image.insert("config".to_string(), Json::Object(config));
+    image.insert("rootfs".to_string(), Json::Object(rootfs));
+    image.insert("history".to_string(), Json::Array(history));
+    Ok(Json::Object(image))
+}
+
+fn image_manifest(config: &Descriptor, layers: &[Descriptor]) -> Json {
+    let mut manifest = BTreeMap::new();
+    manifest.insert("schemaVersion".to_string(), Json::U64(2));
+    manifest.insert("mediaType".to_string(),
+                    Json::String(MEDIA_TYPE_MANIFEST.to_string()));
+    manifest.insert("config".to_string(), config.to_json());
+    manifest.insert("layers".to_string(),
+                    Json::Array(layers.iter().map(|l| l.to_json()).collect()));
+    Json::Object(manifest)
+}
+
+/// A content descriptor, referencing a blob in the image layout by its digest.
+struct Descriptor {
+    media_type: &'static str,
+    digest: String,
+    size: u64,
+}
+
+impl Descriptor {
+    fn for_bytes(media_type: &'static str, data: &[u8]) -> Self {
+        let mut hasher = Hasher::new(HashType::Sha256);
+        hasher.update(data);
+        Descriptor {
+            media_type: media_type,
+            digest: format!("sha256:{}", hasher.finish()),
+            size: data.len() as u64,
+        }
+    }
+
+    fn blob_path(&self) -> String {
+        format!("blobs/{}", self.digest.replace(':', "/"))
+    }
+
+    fn to_json(&self) -> Json {
+        let mut obj = BTreeMap::new();
+        obj.insert("mediaType".to_string(),
+                   Json::String(self.media_type.to_string()));
+        obj.insert("digest".to_string(), Json::String(self.digest.clone()));
+        obj.insert("size".to_string(), Json::U64(self.size));
+        Json::Object(obj)
+    }
+}
+
+fn append_blob<W: Write>(layout: &mut TarBuilder<W>, desc: &Descriptor, data: &[u8]) -> Result<()> {
+    try!(layout.append_data(&desc.blob_path(), 0o644, data));
+    Ok(())
+}
+
+fn append_blob_file<W: Write>(layout: &mut TarBuilder<W>,
+                              desc: &Descriptor,
+                              path: &Path)
+                              -> Result<()> {
+    let mut file = try!(File::open(path));
+    try!(layout.append_file(&desc.blob_path(), 0o644, desc.size, &mut file));
+    Ok(())
+}
+
+/// Writes a layer tarball to `path` with the given function, returning its descriptor.
+fn write_layer<F>(path: &Path, f: F) -> Result<Descriptor>
+    where F: FnOnce(&mut TarBuilder<HashingWriter<File>>) -> io::Result<()>
+{
+    let mut tar = TarBuilder::new(HashingWriter::new(try!(File::create(path))));
+    try!(f(&mut tar));
+    let writer = try!(tar.finish());
+    let (mut file, digest, size) = writer.into_parts();
+    try!(file.flush());
+    Ok(Descriptor {
+        media_type: MEDIA_TYPE_LAYER,
+        digest: format!("sha256:{}", digest),
+        size: size,
+    })
+}
+
+fn append_base<W: Write>(tar: &mut TarBuilder<W>) -> io::Result<()> {
+    try!(tar.append_dir("etc/", 0o755));
+    try!(tar.append_data("etc/group", 0o644, ETC_GROUP.as_bytes()));
+    try!(tar.append_data("etc/nsswitch.conf", 0o644, ETC_NSSWITCH.as_bytes()));
+    try!(tar.append_data("etc/passwd", 0o644, ETC_PASSWD.as_bytes()));
+    try!(tar.append_dir("tmp/", 0o1777));
+    Ok(())
+}
+
+fn append_package<W: Write>(tar: &mut TarBuilder<W>, install: &PackageInstall) -> io::Result<()> {
+    let pkg_path = PathBuf::from(PKG_PATH).join(install.ident().to_string());
+    let mut parent = PathBuf::new();
+    for component in pkg_path.components() {
+        parent.push(component.as_os_str());
+        if parent != pkg_path {
+            try!(tar.append_dir(&format!("{}/", parent.display()), 0o755));
+        }
+    }
+    append_tree(tar, install.installed_path(), &pkg_path)
+}
+
+/// Appends a directory and everything beneath it, in sorted order, naming each entry by its path
+/// relative to `src` joined onto `name`.
+fn append_tree<W: Write>(tar: &mut TarBuilder<W>, src: &Path, name: &Path) -> io::Result<()> {
+    let meta = try!(fs::symlink_metadata(src));
+    let entry_name = name.to_string_lossy().into_owned();
+    let mode = meta.permissions().mode() & 0o7777;
+    if meta.file_type().is_symlink() {
+        let target = try!(fs::read_link(src));
+        tar.append_symlink(&entry_name, &target.to_string_lossy())
+    } else if meta.is_dir() {
+        try!(tar.append_dir(&format!("{}/", entry_name), mode));
+        let mut children = Vec::new();
+        for entry in try!(fs::read_dir(src)) {
+            children.push(try!(entry).file_name());
+        }
+        children.sort();
+        for child in children {
+            try!(append_tree(tar, &src.join(&child), &name.join(&child)));
+        }
+        Ok(())
+    } else {
+        let mut file = try!(File::open(src));
+        tar.append_file(&entry_name, mode, meta.len(), &mut file)
+    }
+}
+
+/// A writer which computes the SHA-256 digest and size of everything written through it.
+struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: Hasher,
+    size: u64,
+}
+
+impl<W: Write> HashingWriter<W> {
+    fn new(inner: W) -> Self {
+        HashingWriter {
+            inner: inner,
+            hasher: Hasher::new(HashType::Sha256),
+            size: 0,
+        }
+    }
+
+    fn into_parts(self) -> (W, String, u64) {
+        (self.inner, self.hasher.finish(), self.size)
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = try!(self.inner.write(buf));
+        self.hasher.update(&buf[..n]);
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::fs::{self, File};
+    use std::io::{Read, Write};
+    use std::str::FromStr;
+
+    use hcore::crypto::hash::{HashType, Hasher};
+    use hcore::fs::PKG_PATH;
+    use hcore::package::{PackageIdent, PackageInstall};
+    use hcore::package::install::INSTALL_COMPLETE_FILE;
+    use rustc_serialize::json::Json;
+    use tempdir::TempDir;
+
+    use tar::TarReader;
+    use super::{export, Descriptor, MEDIA_TYPE_CONFIG};
+
+    const SUP: &'static str = "core/hab-sup/0.7.0/20160101000000";
+    const APP: &'static str = "acme/app/1.0.0/20160101000000";
+
+    fn fake_install(fs_root: &TempDir, ident: &str) -> PackageInstall {
+        let path = fs_root.path().join(PKG_PATH).join(ident);
+        fs::create_dir_all(path.join("bin")).unwrap();
+        File::create(path.join(INSTALL_COMPLETE_FILE)).unwrap();
+        File::create(path.join("IDENT")).unwrap().write_all(ident.as_bytes()).unwrap();
+        File::create(path.join("bin").join("run")).unwrap().write_all(ident.as_bytes()).unwrap();
+        PackageInstall::load(&PackageIdent::from_str(ident).unwrap(),
+                             Some(fs_root.path()))
+            .unwrap()
+    }
+
+    fn sha256(data: &[u8]) -> String {
+        let mut hasher = Hasher::new(HashType::Sha256);
+        hasher.update(data);
+        format!("sha256:{}", hasher.finish())
+    }
+
+    /// Exports the fake packages, returning the manifest digest and the files of the layout.
+    fn export_layout(fs_root: &TempDir) -> (String, HashMap<String, Vec<u8>>) {
+        let sup = fake_install(fs_root, SUP);
+        let pkg = fake_install(fs_root, APP);
+        let dst = fs_root.path().join("image.tar");
+        let digest = export(&pkg, &sup, fs_root.path(), &dst).unwrap();
+        let mut reader = TarReader::new(File::open(&dst).unwrap());
+        let mut files = HashMap::new();
+        while let Some(entry) = reader.next_file().unwrap() {
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data).unwrap();
+            files.insert(entry.name, data);
+        }
+        (digest, files)
+    }
+
+    /// Returns the blob a descriptor references, checking its digest and size.
+    fn blob<'a>(files: &'a HashMap<String, Vec<u8>>, desc: &Json) -> &'a [u8] {
+        let digest = desc.find("digest").unwrap().as_string().unwrap();
+        let data = files.get(&format!("blobs/{}", digest.replace(':', "/"))).unwrap();
+        assert_eq!(sha256(data), digest);
+        assert_eq!(data.len() as u64, desc.find("size").unwrap().as_u64().unwrap());
+        data
+    }
+
+    fn json(data: &[u8]) -> Json {
+        Json::from_str(&String::from_utf8(data.to_vec()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn descriptor_digest_is_sha256_of_data() {
+        let desc = Descriptor::for_bytes(MEDIA_TYPE_CONFIG, b"hello");
+        assert_eq!(desc.digest,
+                   "sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824");
+        assert_eq!(desc.size, 5);
+        assert_eq!(desc.blob_path(),
+                   "blobs/sha256/2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824");
+    }
+
+    #[test]
+    fn export_blobs_match_their_digests() {
+        let fs_root = TempDir::new("fs_root").unwrap();
+        let (digest, files) = export_layout(&fs_root);
+
+        let index = json(&files["index.json"]);
+        let manifests = index.find("manifests").unwrap().as_array().unwrap();
+        assert_eq!(manifests.len(), 1);
+        assert_eq!(manifests[0].find("digest").unwrap().as_string().unwrap(),
+                   digest);
+
+        let manifest = json(blob(&files, &manifests[0]));
+        let config = json(blob(&files, manifest.find("config").unwrap()));
+        let layers = manifest.find("layers").unwrap().as_array().unwrap();
+        // The base layer, then the supervisor and the package
+        assert_eq!(layers.len(), 3);
+        for layer in layers.iter() {
+            blob(&files, layer);
+        }
+        let diff_ids = config.find_path(&["rootfs", "diff_ids"]).unwrap().as_array().unwrap();
+        assert_eq!(diff_ids.len(), layers.len());
+        for (diff_id, layer) in diff_ids.iter().zip(layers.iter()) {
+            assert_eq!(diff_id, layer.find("digest").unwrap());
+        }
+    }
+
+    #[test]
+    fn export_is_reproducible() {
+        let first = TempDir::new("first").unwrap();
+        let second = TempDir::new("second").unwrap();
+        let (first_digest, _) = export_layout(&first);
+        let (second_digest, _) = export_layout(&second);
+        assert_eq!(first_digest, second_digest);
+    }
+}
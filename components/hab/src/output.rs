@@ -0,0 +1,316 @@
+// Copyright (c) 2016 Chef Software Inc. and/or applicable contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Machine-readable output for `hab` commands.
+//!
+//! With `--format json`, everything a command would normally write to standard out (progress,
+//! prose, progress bars) is sent to standard error instead, and once the command finishes a
+//! single JSON document describing its outcome is written to standard out:
+//!
+//! ```json
+//! {"command":"pkg install","ok":true,"result":{"installed":["core/redis/3.0.7/20160614023044"]}}
+//! {"command":"pkg install","error":{"exit_code":3,"kind":"not_found","message":"..."},"ok":false}
+//! ```
+//!
+//! Commands add to the `result` object with `record()` and `append()`. Both do nothing unless
+//! JSON output was asked for, so a command never needs to check the output format itself, and
+//! every command gets the document, errors and exit codes without any further work. The exit
+//! codes in `error` tell failures apart only with JSON output; text output exits with 1 on any
+//! error, as it always has.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::unix::io::{FromRawFd, RawFd};
+
+use libc;
+use rustc_serialize::json::{Json, ToJson};
+
+use error::{Result, EXIT_FAILURE, EXIT_OK, EXIT_USAGE};
+
+struct State {
+    json: bool,
+    stdout: Option<RawFd>,
+    command: String,
+    result: BTreeMap<String, Json>,
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::new(State {
+        json: false,
+        stdout: None,
+        command: String::new(),
+        result: BTreeMap::new(),
+    });
+}
+
+/// Sets up the output of the given command. With JSON output, standard out is redirected to
+/// standard error until `finish()` writes the result document.
+pub fn init(json: bool, command: &str) -> Result<()> {
+    let stdout = if json {
+        try!(io::stdout().flush());
+        Some(try!(redirect(libc::STDOUT_FILENO, libc::STDERR_FILENO)))
+    } else {
+        None
+    };
+    STATE.with(|s| {
+        let mut state = s.borrow_mut();
+        state.json = json;
+        state.stdout = stdout;
+        state.command = command.to_string();
+    });
+    Ok(())
+}
+
+/// Returns true if a JSON document was asked for rather than text.
+pub fn is_json() -> bool {
+    STATE.with(|s| s.borrow().json)
+}
+
+/// Sets a field of the result document.
+pub fn record<T: ToJson + ?Sized>(key: &str, value: &T) {
+    STATE.with(|s| {
+        let mut state = s.borrow_mut();
+        if state.json {
+            state.result.insert(key.to_string(), value.to_json());
+        }
+    })
+}
+
+/// Adds a value to an array field of the result document.
+pub fn append<T: ToJson + ?Sized>(key: &str, value: &T) {
+    STATE.with(|s| {
+        let mut state = s.borrow_mut();
+        if state.json {
+            let entry = state.result.entry(key.to_string()).or_insert(Json::Array(Vec::new()));
+            if let Json::Array(ref mut values) = *entry {
+                values.push(value.to_json());
+            }
+        }
+    })
+}
+
+/// Writes raw content, such as an exported key, to standard out. With JSON output the content is
+/// recorded as a field of the result document instead.
+pub fn content(key: &str, content: &str) -> Result<()> {
+    if is_json() {
+        record(key, content);
+    } else {
+        try!(io::stdout().write_all(content.as_bytes()));
+    }
+    Ok(())
+}
+
+/// Finishes the command, writing the result document when JSON output was asked for, and
+/// returns the exit code for the process. Text output keeps exiting with 1 on any error; the
+/// more specific exit codes are only used along with the JSON document.
+pub fn finish(result: &Result<()>) -> i32 {
+    let doc = match document(result) {
+        Some(doc) => doc,
+        None => {
+            return match *result {
+                Ok(_) => EXIT_OK,
+                Err(_) => EXIT_FAILURE,
+            }
+        }
+    };
+    let _ = io::stdout().flush();
+    match STATE.with(|s| s.borrow_mut().stdout.take()) {
+        Some(saved) => {
+            let _ = restore(libc::STDOUT_FILENO, saved, &doc);
+        }
+        None => println!("{}", doc),
+    }
+    match *result {
+        Ok(_) => EXIT_OK,
+        Err(ref e) => e.exit_code(),
+    }
+}
+
+/// Points `fd` at the file `target` is open to, returning a duplicate of the file `fd` was open
+/// to so that `restore()` can point it back.
+fn redirect(fd: RawFd, target: RawFd) -> io::Result<RawFd> {
+    let saved = unsafe { libc::dup(fd) };
+    if saved < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if unsafe { libc::dup2(target, fd) } < 0 {
+        let e = io::Error::last_os_error();
+        unsafe { libc::close(saved) };
+        return Err(e);
+    }
+    Ok(saved)
+}
+
+/// Writes the result document to the file `saved` is open to, and points `fd` back at it.
+fn restore(fd: RawFd, saved: RawFd, doc: &Json) -> io::Result<()> {
+    // Closes `saved` once it is done with
+    let mut file = unsafe { File::from_raw_fd(saved) };
+    try!(writeln!(file, "{}", doc));
+    if unsafe { libc::dup2(saved, fd) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Returns the result document of the command, or `None` unless JSON output was asked for.
+fn document(result: &Result<()>) -> Option<Json> {
+    STATE.with(|s| {
+        let state = s.borrow();
+        if !state.json {
+            return None;
+        }
+        let mut doc = BTreeMap::new();
+        doc.insert("command".to_string(), state.command.to_json());
+        doc.insert("ok".to_string(), result.is_ok().to_json());
+        doc.insert("result".to_string(), Json::Object(state.result.clone()));
+        if let Err(ref e) = *result {
+            doc.insert("error".to_string(),
+                       error_json(e.kind(), &e.to_string(), e.exit_code()));
+        }
+        Some(Json::Object(doc))
+    })
+}
+
+/// Asks for JSON output without redirecting standard out, as `init()` would, so that tests of
+/// commands can check what they record.
+#[cfg(test)]
+pub fn json_output(command: &str) {
+    STATE.with(|s| {
+        let mut state = s.borrow_mut();
+        state.json = true;
+        state.command = command.to_string();
+        state.result.clear();
+    })
+}
+
+/// Returns the `result` object recorded so far.
+#[cfg(test)]
+pub fn recorded() -> Json {
+    STATE.with(|s| Json::Object(s.borrow().result.clone()))
+}
+
+/// Writes the result document for command line arguments which couldn't be parsed, returning
+/// the exit code for the process.
+pub fn usage_error(message: &str) -> i32 {
+    let mut doc = BTreeMap::new();
+    doc.insert("ok".to_string(), false.to_json());
+    doc.insert("error".to_string(), error_json("usage", message, EXIT_USAGE));
+    println!("{}", Json::Object(doc));
+    EXIT_USAGE
+}
+
+fn error_json(kind: &str, message: &str, exit_code: i32) -> Json {
+    let mut m = BTreeMap::new();
+    m.insert("kind".to_string(), kind.to_json());
+    m.insert("message".to_string(), message.to_json());
+    m.insert("exit_code".to_string(), exit_code.to_json());
+    Json::Object(m)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::File;
+    use std::io::{Read, Write};
+    use std::os::unix::io::AsRawFd;
+    use std::path::Path;
+    use std::thread;
+
+    use rustc_serialize::json::Json;
+    use tempdir::TempDir;
+
+    use super::{append, document, finish, init, is_json, json_output, record, redirect,
+                restore};
+    use error::{Error, EXIT_FAILURE, EXIT_NOT_FOUND, EXIT_OK};
+
+    fn read(path: &Path) -> String {
+        let mut content = String::new();
+        File::open(path).unwrap().read_to_string(&mut content).unwrap();
+        content
+    }
+
+    #[test]
+    fn text_output_records_nothing() {
+        init(false, "pkg list").unwrap();
+        record("count", &1);
+        append("installed", "core/redis");
+        assert!(!is_json());
+        assert!(document(&Ok(())).is_none());
+    }
+
+    #[test]
+    fn text_output_exits_with_failure_on_any_error() {
+        init(false, "pkg path").unwrap();
+        assert_eq!(finish(&Ok(())), EXIT_OK);
+        assert_eq!(finish(&Err(Error::FileNotFound("core/redis".to_string()))),
+                   EXIT_FAILURE);
+    }
+
+    #[test]
+    fn json_output_collects_the_result() {
+        json_output("pkg install");
+        record("count", &2);
+        append("installed", "core/glibc");
+        append("installed", "core/redis");
+        let doc = document(&Ok(())).unwrap();
+        assert_eq!(doc,
+                   Json::from_str("{\"command\":\"pkg install\",\"ok\":true,\"result\":\
+                                   {\"count\":2,\"installed\":[\"core/glibc\",\"core/redis\"]}}")
+                       .unwrap());
+    }
+
+    #[test]
+    fn json_output_describes_the_error() {
+        json_output("pkg path");
+        let result = Err(Error::FileNotFound("core/redis".to_string()));
+        let doc = document(&result).unwrap();
+        let error = doc.find("error").unwrap();
+        assert_eq!(doc.find("ok").unwrap(), &Json::Boolean(false));
+        assert_eq!(error.find("kind").unwrap().as_string(), Some("not_found"));
+        assert_eq!(error.find("exit_code").unwrap().as_i64(),
+                   Some(EXIT_NOT_FOUND as i64));
+        assert_eq!(finish(&result), EXIT_NOT_FOUND);
+    }
+
+    #[test]
+    fn output_state_is_per_thread() {
+        json_output("pkg list");
+        record("count", &1);
+        thread::spawn(|| {
+                assert!(!is_json());
+                record("count", &2);
+                assert!(document(&Ok(())).is_none());
+            })
+            .join()
+            .unwrap();
+        let doc = document(&Ok(())).unwrap();
+        assert_eq!(doc.find_path(&["result", "count"]).unwrap().as_i64(), Some(1));
+    }
+
+    #[test]
+    fn json_output_moves_stdout_to_stderr_until_finished() {
+        let dir = TempDir::new("output").unwrap();
+        let mut stdout = File::create(dir.path().join("stdout")).unwrap();
+        let stderr = File::create(dir.path().join("stderr")).unwrap();
+
+        let saved = redirect(stdout.as_raw_fd(), stderr.as_raw_fd()).unwrap();
+        stdout.write_all(b"progress\n").unwrap();
+        restore(stdout.as_raw_fd(), saved, &Json::Boolean(true)).unwrap();
+        stdout.write_all(b"done\n").unwrap();
+
+        assert_eq!(read(&dir.path().join("stdout")), "true\ndone\n");
+        assert_eq!(read(&dir.path().join("stderr")), "progress\n");
+    }
+}
@@ -0,0 +1,127 @@
+// Copyright (c) 2016 Chef Software Inc. and/or applicable contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Passphrases for encrypted secret keys.
+//!
+//! A passphrase is taken from `HAB_KEY_PASSPHRASE` when it is set and otherwise read from the
+//! controlling terminal with echo turned off, so that it still works when standard input is
+//! being used for key contents. A passphrase read from the terminal is only ever handed to the
+//! key-loading calls which need it, so it never reaches the environment of a child process.
+
+use std::fs::OpenOptions;
+use std::io::{self, BufRead, BufReader, Write};
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use hcore::crypto::{BoxKeyPair, SigKeyPair, SECRET_KEY_PASSPHRASE_ENV_VAR};
+use hcore::crypto::keys;
+use libc;
+
+use error::{Error, Result};
+
+/// Returns the passphrase of an existing encrypted key, prompting for it if it isn't set in the
+/// environment.
+pub fn key_passphrase(key_description: &str) -> Result<String> {
+    if let Some(passphrase) = keys::secret_key_passphrase() {
+        return Ok(passphrase);
+    }
+    read_passphrase(&format!("Passphrase for {}: ", key_description))
+}
+
+/// Returns the passphrase for a key which is about to be encrypted, prompting for it twice if it
+/// isn't set in the environment.
+pub fn new_passphrase() -> Result<String> {
+    if let Some(passphrase) = keys::secret_key_passphrase() {
+        return Ok(passphrase);
+    }
+    let passphrase = try!(read_passphrase("New passphrase: "));
+    if passphrase.is_empty() {
+        return Err(Error::ArgumentError("The passphrase cannot be empty"));
+    }
+    if passphrase != try!(read_passphrase("Confirm passphrase: ")) {
+        return Err(Error::ArgumentError("The passphrases do not match"));
+    }
+    Ok(passphrase)
+}
+
+/// Makes sure a secret key file can be loaded, prompting for its passphrase if it is encrypted.
+/// Returns the passphrase of an encrypted secret key file and `None` for an unencrypted one.
+///
+/// # Failures
+///
+/// * If the passphrase doesn't decrypt the secret key
+/// * If there is no terminal to prompt on and the passphrase isn't set in the environment
+pub fn unlock_secret_key(keyfile: &Path) -> Result<Option<String>> {
+    if !try!(keys::is_encrypted_key_file(keyfile)) {
+        return Ok(None);
+    }
+    let passphrase = try!(key_passphrase(&keyfile.display().to_string()));
+    try!(keys::check_key_passphrase(keyfile, &passphrase));
+    Ok(Some(passphrase))
+}
+
+/// Loads an origin key pair, unlocking its secret key first if it is encrypted.
+pub fn sig_pair_for(name_with_rev: &str, cache: &Path) -> Result<SigKeyPair> {
+    let passphrase = match SigKeyPair::get_secret_key_path(name_with_rev, cache) {
+        Ok(secret_keyfile) => try!(unlock_secret_key(&secret_keyfile)),
+        Err(_) => None,
+    };
+    Ok(try!(SigKeyPair::get_pair_for_with_passphrase(name_with_rev,
+                                                     passphrase.as_ref().map(|p| &p[..]),
+                                                     cache)))
+}
+
+/// Loads a user or service key pair, unlocking its secret key first if it is encrypted.
+pub fn box_pair_for(name_with_rev: &str, cache: &Path) -> Result<BoxKeyPair> {
+    let passphrase = match BoxKeyPair::get_secret_key_path(name_with_rev, cache) {
+        Ok(secret_keyfile) => try!(unlock_secret_key(&secret_keyfile)),
+        Err(_) => None,
+    };
+    Ok(try!(BoxKeyPair::get_pair_for_with_passphrase(name_with_rev,
+                                                     passphrase.as_ref().map(|p| &p[..]),
+                                                     cache)))
+}
+
+fn read_passphrase(prompt: &str) -> Result<String> {
+    let tty = match OpenOptions::new().read(true).write(true).open("/dev/tty") {
+        Ok(tty) => tty,
+        Err(e) => {
+            debug!("Can't open /dev/tty: {}", e);
+            return Err(Error::CryptoCLI(format!("A passphrase is required, set {} to provide \
+                                                 one without a terminal",
+                                                SECRET_KEY_PASSPHRASE_ENV_VAR)));
+        }
+    };
+    let fd = tty.as_raw_fd();
+    let mut term: libc::termios = unsafe { mem::zeroed() };
+    if unsafe { libc::tcgetattr(fd, &mut term) } != 0 {
+        return Err(Error::IO(io::Error::last_os_error()));
+    }
+    let original = term;
+    term.c_lflag &= !libc::ECHO;
+    term.c_lflag |= libc::ECHONL;
+
+    try!((&tty).write_all(prompt.as_bytes()));
+    try!((&tty).flush());
+    if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &term) } != 0 {
+        return Err(Error::IO(io::Error::last_os_error()));
+    }
+    let mut line = String::new();
+    let read = BufReader::new(&tty).read_line(&mut line);
+    // Always restore echo, even if reading failed
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &original) };
+    try!(read);
+    Ok(line.trim_right_matches(|c| c == '\r' || c == '\n').to_string())
+}
@@ -0,0 +1,438 @@
+// Copyright (c) 2016 Chef Software Inc. and/or applicable contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Minimal reading and writing of tar archives, as used for OCI image layouts and package
+//! bundles.
+
+use std::io::{self, Read, Write};
+
+const TAR_BLOCK_SIZE: usize = 512;
+
+/// A minimal writer of POSIX ustar archives. Entries are owned by root and carry a zero
+/// modification time so that the archive only depends on the content and modes of its entries.
+/// Names and link targets too long for the ustar header use GNU long name entries.
+pub struct TarBuilder<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> TarBuilder<W> {
+    pub fn new(inner: W) -> Self {
+        TarBuilder { inner: inner }
+    }
+
+    pub fn append_dir(&mut self, name: &str, mode: u32) -> io::Result<()> {
+        self.append_header(name, mode, 0, b'5', "")
+    }
+
+    pub fn append_symlink(&mut self, name: &str, target: &str) -> io::Result<()> {
+        self.append_header(name, 0o777, 0, b'2', target)
+    }
+
+    pub fn append_data(&mut self, name: &str, mode: u32, data: &[u8]) -> io::Result<()> {
+        try!(self.append_header(name, mode, data.len() as u64, b'0', ""));
+        try!(self.inner.write_all(data));
+        self.pad(data.len() as u64)
+    }
+
+    pub fn append_file(&mut self,
+                       name: &str,
+                       mode: u32,
+                       size: u64,
+                       reader: &mut Read)
+                       -> io::Result<()> {
+        try!(self.append_header(name, mode, size, b'0', ""));
+        let copied = try!(io::copy(&mut reader.take(size), &mut self.inner));
+        if copied != size {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                      format!("{} changed size while being archived", name)));
+        }
+        self.pad(size)
+    }
+
+    /// Writes the end of archive marker and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        try!(self.inner.write_all(&[0; TAR_BLOCK_SIZE * 2]));
+        Ok(self.inner)
+    }
+
+    fn append_header(&mut self,
+                     name: &str,
+                     mode: u32,
+                     size: u64,
+                     kind: u8,
+                     link: &str)
+                     -> io::Result<()> {
+        if link.len() > 100 {
+            try!(self.append_data_entry("././@LongLink", b'K', link.as_bytes()));
+        }
+        let (prefix, short_name) = match split_name(name) {
+            Some(parts) => parts,
+            None => {
+                try!(self.append_data_entry("././@LongLink", b'L', name.as_bytes()));
+                ("", truncate(name, 100))
+            }
+        };
+        let link = truncate(link, 100);
+
+        let mut header = [0u8; TAR_BLOCK_SIZE];
+        header[..short_name.len()].copy_from_slice(short_name.as_bytes());
+        try!(octal(&mut header[100..108], mode as u64));
+        try!(octal(&mut header[108..116], 0));
+        try!(octal(&mut header[116..124], 0));
+        try!(octal(&mut header[124..136], size));
+        try!(octal(&mut header[136..148], 0));
+        header[156] = kind;
+        header[157..157 + link.len()].copy_from_slice(link.as_bytes());
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        header[265..269].copy_from_slice(b"root");
+        header[297..301].copy_from_slice(b"root");
+        header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+
+        for b in header[148..156].iter_mut() {
+            *b = b' ';
+        }
+        let checksum: u32 = header.iter().map(|b| *b as u32).sum();
+        let checksum = format!("{:06o}\0 ", checksum);
+        header[148..156].copy_from_slice(checksum.as_bytes());
+        self.inner.write_all(&header)
+    }
+
+    /// Writes a GNU extension entry whose data is `data` followed by a NUL.
+    fn append_data_entry(&mut self, name: &str, kind: u8, data: &[u8]) -> io::Result<()> {
+        let size = data.len() as u64 + 1;
+        try!(self.append_header(name, 0o644, size, kind, ""));
+        try!(self.inner.write_all(data));
+        try!(self.inner.write_all(&[0]));
+        self.pad(size)
+    }
+
+    fn pad(&mut self, size: u64) -> io::Result<()> {
+        let padding = padding_for(size) as usize;
+        self.inner.write_all(&[0; TAR_BLOCK_SIZE][..padding])
+    }
+}
+
+/// A file entry of an archive read by `TarReader`.
+pub struct TarEntry {
+    pub name: String,
+    pub size: u64,
+}
+
+/// A minimal reader of ustar archives, such as those written by `TarBuilder`. Only regular file
+/// entries are returned, with the data of the current entry read through the reader itself.
+/// Directories, links and other entries are skipped.
+pub struct TarReader<R: Read> {
+    inner: R,
+    remaining: u64,
+    padding: u64,
+}
+
+impl<R: Read> TarReader<R> {
+    pub fn new(inner: R) -> Self {
+        TarReader {
+            inner: inner,
+            remaining: 0,
+            padding: 0,
+        }
+    }
+
+    /// Advances to the next regular file entry, skipping any unread data of the current one.
+    /// Returns `None` at the end of the archive.
+    pub fn next_file(&mut self) -> io::Result<Option<TarEntry>> {
+        let unread = self.remaining + self.padding;
+        try!(self.skip(unread));
+        self.remaining = 0;
+        self.padding = 0;
+        let mut long_name = None;
+        loop {
+            let mut header = [0u8; TAR_BLOCK_SIZE];
+            if !try!(self.read_block(&mut header)) || header.iter().all(|b| *b == 0) {
+                return Ok(None);
+            }
+            try!(verify_checksum(&header));
+            let size = try!(parse_octal(&header[124..136]));
+            let padding = padding_for(size);
+            let name = match long_name.take() {
+                Some(name) => name,
+                None => header_name(&header),
+            };
+            match header[156] {
+                b'0' | 0 => {
+                    self.remaining = size;
+                    self.padding = padding;
+                    return Ok(Some(TarEntry {
+                        name: name,
+                        size: size,
+                    }));
+                }
+                b'L' => {
+                    let mut data = Vec::new();
+                    try!((&mut self.inner).take(size).read_to_end(&mut data));
+                    if data.len() as u64 != size {
+                        return Err(unexpected_eof());
+                    }
+                    try!(self.skip(padding));
+                    let end = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+                    long_name = Some(String::from_utf8_lossy(&data[..end]).into_owned());
+                }
+                _ => try!(self.skip(size + padding)),
+            }
+        }
+    }
+
+    /// Reads one block, returning `false` if the archive ends before it starts.
+    fn read_block(&mut self, block: &mut [u8]) -> io::Result<bool> {
+        let mut read = 0;
+        while read < block.len() {
+            match try!(self.inner.read(&mut block[read..])) {
+                0 if read == 0 => return Ok(false),
+                0 => return Err(unexpected_eof()),
+                n => read += n,
+            }
+        }
+        Ok(true)
+    }
+
+    fn skip(&mut self, len: u64) -> io::Result<()> {
+        let skipped = try!(io::copy(&mut (&mut self.inner).take(len), &mut io::sink()));
+        if skipped != len {
+            return Err(unexpected_eof());
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for TarReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 {
+            return Ok(0);
+        }
+        let max = if (buf.len() as u64) < self.remaining {
+            buf.len()
+        } else {
+            self.remaining as usize
+        };
+        let n = try!(self.inner.read(&mut buf[..max]));
+        if n == 0 {
+            return Err(unexpected_eof());
+        }
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+fn header_name(header: &[u8]) -> String {
+    let field = |bytes: &[u8]| {
+        let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+        String::from_utf8_lossy(&bytes[..end]).into_owned()
+    };
+    let name = field(&header[0..100]);
+    let prefix = if &header[257..262] == b"ustar" {
+        field(&header[345..500])
+    } else {
+        String::new()
+    };
+    if prefix.is_empty() {
+        name
+    } else {
+        format!("{}/{}", prefix, name)
+    }
+}
+
+fn verify_checksum(header: &[u8]) -> io::Result<()> {
+    let expected = try!(parse_octal(&header[148..156]));
+    let actual: u64 = header.iter()
+        .enumerate()
+        .map(|(i, b)| if i >= 148 && i < 156 { b' ' as u64 } else { *b as u64 })
+        .sum();
+    if expected != actual {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                  "Invalid tar header checksum"));
+    }
+    Ok(())
+}
+
+fn parse_octal(field: &[u8]) -> io::Result<u64> {
+    let digits: String = field.iter()
+        .take_while(|b| **b != 0)
+        .map(|b| *b as char)
+        .collect();
+    let digits = digits.trim();
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid tar header number"))
+}
+
+fn padding_for(size: u64) -> u64 {
+    match size % TAR_BLOCK_SIZE as u64 {
+        0 => 0,
+        remainder => TAR_BLOCK_SIZE as u64 - remainder,
+    }
+}
+
+fn unexpected_eof() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "Unexpected end of tar archive")
+}
+
+/// Splits a name into the ustar prefix and name fields, if it fits in them.
+fn split_name(name: &str) -> Option<(&str, &str)> {
+    if name.len() <= 100 {
+        return Some(("", name));
+    }
+    for (idx, _) in name.match_indices('/') {
+        let (prefix, rest) = (&name[..idx], &name[idx + 1..]);
+        if prefix.len() <= 155 && !rest.is_empty() && rest.len() <= 100 {
+            return Some((prefix, rest));
+        }
+    }
+    None
+}
+
+/// Returns the longest prefix of `value` which is at most `max` bytes long.
+fn truncate(value: &str, max: usize) -> &str {
+    let mut end = if value.len() > max { max } else { value.len() };
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    &value[..end]
+}
+
+/// Writes `value` as a NUL terminated octal number filling the field.
+fn octal(field: &mut [u8], value: u64) -> io::Result<()> {
+    let digits = format!("{:01$o}", value, field.len() - 1);
+    if digits.len() > field.len() - 1 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  format!("{} is too large for a tar header", value)));
+    }
+    field[..digits.len()].copy_from_slice(digits.as_bytes());
+    field[digits.len()] = 0;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Read};
+    use std::iter;
+
+    use super::{TarBuilder, TarReader, TAR_BLOCK_SIZE};
+
+    fn repeat(c: char, n: usize) -> String {
+        iter::repeat(c).take(n).collect()
+    }
+
+    /// Reads every file entry of an archive as its name and data.
+    fn read_all(archive: Vec<u8>) -> Vec<(String, Vec<u8>)> {
+        let mut reader = TarReader::new(Cursor::new(archive));
+        let mut files = Vec::new();
+        while let Some(entry) = reader.next_file().unwrap() {
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data).unwrap();
+            assert_eq!(data.len() as u64, entry.size);
+            files.push((entry.name, data));
+        }
+        files
+    }
+
+    #[test]
+    fn round_trip_files() {
+        let mut tar = TarBuilder::new(Vec::new());
+        tar.append_data("BUNDLE", 0o644, b"hello\n").unwrap();
+        let block = vec![7u8; TAR_BLOCK_SIZE];
+        tar.append_file("artifacts/block", 0o644, block.len() as u64, &mut &block[..])
+            .unwrap();
+        tar.append_data("empty", 0o600, b"").unwrap();
+        let archive = tar.finish().unwrap();
+        assert_eq!(archive.len() % TAR_BLOCK_SIZE, 0);
+
+        assert_eq!(read_all(archive),
+                   vec![("BUNDLE".to_string(), b"hello\n".to_vec()),
+                        ("artifacts/block".to_string(), block),
+                        ("empty".to_string(), Vec::new())]);
+    }
+
+    #[test]
+    fn round_trip_skips_directories_and_symlinks() {
+        let mut tar = TarBuilder::new(Vec::new());
+        tar.append_dir("etc/", 0o755).unwrap();
+        tar.append_symlink("etc/link", &repeat('t', 150)).unwrap();
+        tar.append_data("etc/passwd", 0o644, b"root").unwrap();
+        let archive = tar.finish().unwrap();
+
+        assert_eq!(read_all(archive),
+                   vec![("etc/passwd".to_string(), b"root".to_vec())]);
+    }
+
+    #[test]
+    fn round_trip_long_names() {
+        // Fits the ustar prefix and name fields
+        let split = format!("{}/{}", repeat('d', 120), repeat('f', 90));
+        // Fits neither, so it needs a GNU long name entry
+        let long = format!("{}/{}", repeat('d', 10), repeat('f', 200));
+        let mut tar = TarBuilder::new(Vec::new());
+        tar.append_data(&split, 0o644, b"split").unwrap();
+        tar.append_data(&long, 0o644, b"long").unwrap();
+        let archive = tar.finish().unwrap();
+
+        assert_eq!(read_all(archive),
+                   vec![(split, b"split".to_vec()), (long, b"long".to_vec())]);
+    }
+
+    #[test]
+    fn next_file_skips_unread_data() {
+        let mut tar = TarBuilder::new(Vec::new());
+        tar.append_data("first", 0o644, &[1u8; 700]).unwrap();
+        tar.append_data("second", 0o644, b"2").unwrap();
+        let archive = tar.finish().unwrap();
+
+        let mut reader = TarReader::new(Cursor::new(archive));
+        assert_eq!(reader.next_file().unwrap().unwrap().name, "first");
+        let mut partial = [0u8; 10];
+        reader.read_exact(&mut partial).unwrap();
+        assert_eq!(reader.next_file().unwrap().unwrap().name, "second");
+        assert!(reader.next_file().unwrap().is_none());
+    }
+
+    #[test]
+    fn append_file_refuses_a_short_reader() {
+        let mut tar = TarBuilder::new(Vec::new());
+        assert!(tar.append_file("short", 0o644, 10, &mut &b"abc"[..]).is_err());
+    }
+
+    #[test]
+    fn next_file_refuses_a_corrupt_header() {
+        let mut tar = TarBuilder::new(Vec::new());
+        tar.append_data("file", 0o644, b"data").unwrap();
+        let mut archive = tar.finish().unwrap();
+        archive[0] = b'g';
+
+        let mut reader = TarReader::new(Cursor::new(archive));
+        assert!(reader.next_file().is_err());
+    }
+
+    #[test]
+    fn next_file_refuses_a_truncated_archive() {
+        let mut tar = TarBuilder::new(Vec::new());
+        tar.append_data("file", 0o644, &[1u8; 700]).unwrap();
+        let mut archive = tar.finish().unwrap();
+        archive.truncate(TAR_BLOCK_SIZE + 100);
+
+        let mut reader = TarReader::new(Cursor::new(archive));
+        reader.next_file().unwrap().unwrap();
+        let mut data = Vec::new();
+        assert!(reader.read_to_end(&mut data).is_err());
+    }
+}
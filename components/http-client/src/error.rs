@@ -23,10 +23,12 @@ use openssl::ssl;
 
 #[derive(Debug)]
 pub enum Error {
+    ClientCertificateIncomplete(&'static str),
     HabitatCore(hab_core::Error),
     HyperError(hyper::error::Error),
     IO(io::Error),
     SslError(ssl::error::SslError),
+    SslFileNotFound(&'static str, String),
 }
 
 pub type Result<T> = result::Result<T, Error>;
@@ -34,10 +36,15 @@ pub type Result<T> = result::Result<T, Error>;
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let msg = match *self {
+            Error::ClientCertificateIncomplete(ref e) => {
+                format!("A client certificate and key must be given together, {} is not set",
+                        e)
+            }
             Error::HabitatCore(ref e) => format!("{}", e),
             Error::HyperError(ref err) => format!("{}", err),
             Error::IO(ref e) => format!("{}", e),
             Error::SslError(ref e) => format!("{}", e),
+            Error::SslFileNotFound(ref k, ref p) => format!("File {} from {} not found", p, k),
         };
         write!(f, "{}", msg)
     }
@@ -46,10 +53,14 @@ impl fmt::Display for Error {
 impl error::Error for Error {
     fn description(&self) -> &str {
         match *self {
+            Error::ClientCertificateIncomplete(_) => {
+                "A client certificate and key must be given together"
+            }
             Error::HabitatCore(ref err) => err.description(),
             Error::HyperError(ref err) => err.description(),
             Error::IO(ref err) => err.description(),
             Error::SslError(ref err) => err.description(),
+            Error::SslFileNotFound(_, _) => "SSL certificate or key file not found",
         }
     }
 }
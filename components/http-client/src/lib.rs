@@ -20,20 +20,77 @@ extern crate log;
 extern crate openssl;
 
 pub mod error;
+pub mod proxy;
+pub mod testing;
 
+use std::str;
 use std::sync::Arc;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
+use hab_core::env;
 use hab_core::util::sys;
-use hyper::client::Client;
+use hyper::client::{Client, Response};
 use hyper::client::pool::{Config, Pool};
 use hyper::header::UserAgent;
 use hyper::http::h1::Http11Protocol;
 use hyper::net::{HttpsConnector, Openssl};
 use hyper::Url;
 use openssl::ssl::{SslContext, SslMethod, SSL_OP_NO_SSLV2, SSL_OP_NO_SSLV3, SSL_OP_NO_COMPRESSION};
+use openssl::x509::X509FileType;
 
 pub use error::{Error, Result};
+pub use proxy::ProxyHttpsConnector;
+
+/// Environment variable naming an additional PEM file of CA certificates to trust, such as the
+/// root of an internal PKI.
+pub const SSL_CA_BUNDLE_ENVVAR: &'static str = "HAB_SSL_CA_BUNDLE";
+/// Environment variable naming a PEM file holding a client certificate (and optionally its
+/// chain) presented for mutual TLS.
+pub const SSL_CLIENT_CERT_ENVVAR: &'static str = "HAB_SSL_CLIENT_CERT";
+/// Environment variable naming the PEM file holding the private key of the client certificate.
+pub const SSL_CLIENT_KEY_ENVVAR: &'static str = "HAB_SSL_CLIENT_KEY";
+
+/// SSL settings for the clients built by `new_hyper_client_with_ssl`, which add to the CA
+/// certificates located as described for `new_hyper_client`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SslSettings {
+    /// A PEM file of additional CA certificates to trust, such as the root of an internal PKI.
+    pub ca_bundle: Option<PathBuf>,
+    /// A PEM file holding a client certificate (and optionally its chain) presented for mutual
+    /// TLS.
+    pub client_cert: Option<PathBuf>,
+    /// The PEM file holding the private key of the client certificate.
+    pub client_key: Option<PathBuf>,
+}
+
+impl SslSettings {
+    /// Returns the settings given in the `HAB_SSL_CA_BUNDLE`, `HAB_SSL_CLIENT_CERT`, and
+    /// `HAB_SSL_CLIENT_KEY` environment variables.
+    pub fn from_env() -> Self {
+        SslSettings {
+            ca_bundle: env::var(SSL_CA_BUNDLE_ENVVAR).ok().map(PathBuf::from),
+            client_cert: env::var(SSL_CLIENT_CERT_ENVVAR).ok().map(PathBuf::from),
+            client_key: env::var(SSL_CLIENT_KEY_ENVVAR).ok().map(PathBuf::from),
+        }
+    }
+
+    /// Returns these settings, taking any setting which isn't given from `other`. The client
+    /// certificate and its key are taken as a pair: from these settings if either is given here,
+    /// and otherwise from `other`, so a certificate is never presented with another's key.
+    pub fn or(self, other: SslSettings) -> Self {
+        let (client_cert, client_key) = if self.client_cert.is_some() ||
+                                           self.client_key.is_some() {
+            (self.client_cert, self.client_key)
+        } else {
+            (other.client_cert, other.client_key)
+        };
+        SslSettings {
+            ca_bundle: self.ca_bundle.or(other.ca_bundle),
+            client_cert: client_cert,
+            client_key: client_key,
+        }
+    }
+}
 
 /// Builds a new hyper HTTP client with appropriate SSL configuration and HTTP/HTTPS proxy support.
 ///
@@ -65,11 +122,59 @@ pub use error::{Error, Result};
 /// library will default to using this on the Mac. Therefore the behavior on the Mac remains
 /// unchanged and will use the system's certificates.
 ///
-pub fn new_hyper_client(_for_domain: Option<&Url>, fs_root_path: Option<&Path>) -> Result<Client> {
-    let ctx = try!(ssl_ctx(fs_root_path));
-    let connector = HttpsConnector::new(Openssl { context: Arc::new(ctx) });
-    let pool = Pool::with_connector(Config::default(), connector);
-    Ok(Client::with_protocol(Http11Protocol::with_connector(pool)))
+/// ## Additional CA Certificates and Client Certificates
+///
+/// On all platforms, if the `HAB_SSL_CA_BUNDLE` environment variable is set then the certificates
+/// in that PEM file are trusted in addition to the ones located above. If the
+/// `HAB_SSL_CLIENT_CERT` and `HAB_SSL_CLIENT_KEY` environment variables are set then that
+/// certificate and private key are presented to servers which request a client certificate. Both
+/// must be set together. Use `new_hyper_client_with_ssl` to give these settings directly.
+///
+/// ## Proxies
+///
+/// When a URL is given, the `https_proxy` (for `https` URLs) or `http_proxy` (for all other URLs)
+/// environment variable is consulted, honoring any matching entry in `no_proxy`. If a proxy
+/// applies, every connection is tunnelled through it with a `CONNECT` request, authenticating
+/// with any credentials present in the proxy URL. See the `habitat_core::env` module for the
+/// supported proxy and `no_proxy` values.
+pub fn new_hyper_client(for_domain: Option<&Url>, fs_root_path: Option<&Path>) -> Result<Client> {
+    new_hyper_client_with_ssl(for_domain, fs_root_path, &SslSettings::from_env())
+}
+
+/// Builds a new hyper HTTP client like `new_hyper_client`, using the given SSL settings for
+/// additional CA certificates and the client certificate instead of the environment.
+pub fn new_hyper_client_with_ssl(for_domain: Option<&Url>,
+                                 fs_root_path: Option<&Path>,
+                                 settings: &SslSettings)
+                                 -> Result<Client> {
+    let ctx = try!(ssl_ctx(fs_root_path, settings));
+    let ssl = Openssl { context: Arc::new(ctx) };
+    let proxy = match for_domain {
+        Some(url) => {
+            match (url.scheme(), url.host_str()) {
+                ("https", Some(host)) => try!(env::https_proxy_unless_domain_exempted(host)),
+                (_, Some(host)) => try!(env::http_proxy_unless_domain_exempted(host)),
+                (_, None) => None,
+            }
+        }
+        None => None,
+    };
+    match proxy {
+        Some(proxy) => {
+            debug!("Using proxy {}:{} for {}",
+                   &proxy.host,
+                   proxy.port,
+                   for_domain.unwrap());
+            let connector = ProxyHttpsConnector::new(proxy, ssl);
+            let pool = Pool::with_connector(Config::default(), connector);
+            Ok(Client::with_protocol(Http11Protocol::with_connector(pool)))
+        }
+        None => {
+            let connector = HttpsConnector::new(ssl);
+            let pool = Pool::with_connector(Config::default(), connector);
+            Ok(Client::with_protocol(Http11Protocol::with_connector(pool)))
+        }
+    }
 }
 
 /// Returns an HTTP User-Agent string type for use by Hyper when making HTTP requests.
@@ -108,16 +213,69 @@ pub fn user_agent(product: &str, version: &str) -> Result<UserAgent> {
     Ok(UserAgent(ua))
 }
 
-fn ssl_ctx(fs_root_path: Option<&Path>) -> Result<SslContext> {
+/// Returns the first value of a response header, if it is present and valid UTF-8.
+pub fn header(res: &Response, name: &str) -> Option<String> {
+    res.headers
+        .get_raw(name)
+        .and_then(|values| values.first())
+        .and_then(|value| str::from_utf8(value).ok())
+        .map(|value| value.to_string())
+}
+
+fn ssl_ctx(fs_root_path: Option<&Path>, settings: &SslSettings) -> Result<SslContext> {
     // The spirit of this implementation is directly from Hyper's default OpensslClient function:
     // https://github.com/hyperium/hyper/blob/v0.9.5/src/net.rs#L653-L661
     let mut ctx = try!(SslContext::new(SslMethod::Sslv23));
     try!(ssl::set_ca(&mut ctx, fs_root_path));
+    try!(set_extra_ca(&mut ctx, settings));
+    try!(set_client_cert(&mut ctx, settings));
     ctx.set_options(SSL_OP_NO_SSLV2 | SSL_OP_NO_SSLV3 | SSL_OP_NO_COMPRESSION);
     try!(ctx.set_cipher_list("ALL!EXPORT!EXPORT40!EXPORT56!aNULL!LOW!RC4@STRENGTH"));
     Ok(ctx)
 }
 
+fn set_extra_ca(ctx: &mut SslContext, settings: &SslSettings) -> Result<()> {
+    if let Some(bundle) = try!(ssl_file(SSL_CA_BUNDLE_ENVVAR, settings.ca_bundle.as_ref())) {
+        debug!("Adding CA file to SSL context: {}", bundle.display());
+        try!(ctx.set_CA_file(bundle));
+    }
+    Ok(())
+}
+
+fn set_client_cert(ctx: &mut SslContext, settings: &SslSettings) -> Result<()> {
+    let cert = try!(ssl_file(SSL_CLIENT_CERT_ENVVAR, settings.client_cert.as_ref()));
+    let key = try!(ssl_file(SSL_CLIENT_KEY_ENVVAR, settings.client_key.as_ref()));
+    match (cert, key) {
+        (Some(cert), Some(key)) => {
+            debug!("Setting client certificate for SSL context to: {}, key: {}",
+                   cert.display(),
+                   key.display());
+            try!(ctx.set_certificate_chain_file(&cert, X509FileType::PEM));
+            try!(ctx.set_private_key_file(&key, X509FileType::PEM));
+            try!(ctx.check_private_key());
+            Ok(())
+        }
+        (None, None) => Ok(()),
+        (Some(_), None) => Err(Error::ClientCertificateIncomplete(SSL_CLIENT_KEY_ENVVAR)),
+        (None, Some(_)) => Err(Error::ClientCertificateIncomplete(SSL_CLIENT_CERT_ENVVAR)),
+    }
+}
+
+/// Checks that a file given for an SSL setting exists. The setting is named by its environment
+/// variable.
+fn ssl_file(key: &'static str, path: Option<&PathBuf>) -> Result<Option<PathBuf>> {
+    match path {
+        Some(path) => {
+            if path.is_file() {
+                Ok(Some(path.clone()))
+            } else {
+                Err(Error::SslFileNotFound(key, path.display().to_string()))
+            }
+        }
+        None => Ok(None),
+    }
+}
+
 #[cfg(target_os = "linux")]
 mod ssl {
     use std::fs::{self, File};
@@ -176,3 +334,172 @@ mod ssl {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use hyper::client::Client;
+    use hyper::server::{Request, Response};
+    use openssl::ssl::{SslContext, SslMethod};
+
+    use error::Error;
+    use testing;
+    use super::{header, set_client_cert, set_extra_ca, ssl_file, SslSettings, SSL_CA_BUNDLE_ENVVAR,
+                SSL_CLIENT_CERT_ENVVAR, SSL_CLIENT_KEY_ENVVAR};
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join(name)
+    }
+
+    fn ctx() -> SslContext {
+        SslContext::new(SslMethod::Sslv23).unwrap()
+    }
+
+    #[test]
+    fn ssl_file_absent_setting() {
+        assert_eq!(ssl_file(SSL_CA_BUNDLE_ENVVAR, None).unwrap(), None);
+    }
+
+    #[test]
+    fn ssl_file_existing_file() {
+        let path = fixture("client.crt");
+        assert_eq!(ssl_file(SSL_CA_BUNDLE_ENVVAR, Some(&path)).unwrap(),
+                   Some(path));
+    }
+
+    #[test]
+    fn ssl_file_missing_file() {
+        let path = fixture("nope.pem");
+        match ssl_file(SSL_CA_BUNDLE_ENVVAR, Some(&path)) {
+            Err(Error::SslFileNotFound(key, p)) => {
+                assert_eq!(key, SSL_CA_BUNDLE_ENVVAR);
+                assert_eq!(p, path.display().to_string());
+            }
+            r => panic!("Expected SslFileNotFound, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn settings_or_prefers_own_values() {
+        let own = SslSettings {
+            ca_bundle: Some(PathBuf::from("/own/ca.pem")),
+            ..Default::default()
+        };
+        let other = SslSettings {
+            ca_bundle: Some(PathBuf::from("/other/ca.pem")),
+            client_cert: Some(PathBuf::from("/other/client.crt")),
+            client_key: None,
+        };
+        let merged = own.or(other);
+        assert_eq!(merged.ca_bundle, Some(PathBuf::from("/own/ca.pem")));
+        assert_eq!(merged.client_cert, Some(PathBuf::from("/other/client.crt")));
+        assert_eq!(merged.client_key, None);
+    }
+
+    #[test]
+    fn settings_or_takes_client_cert_and_key_as_a_pair() {
+        let own = SslSettings {
+            client_cert: Some(PathBuf::from("/own/client.crt")),
+            ..Default::default()
+        };
+        let other = SslSettings {
+            client_cert: Some(PathBuf::from("/other/client.crt")),
+            client_key: Some(PathBuf::from("/other/client.key")),
+            ..Default::default()
+        };
+        let merged = own.or(other.clone());
+        assert_eq!(merged.client_cert, Some(PathBuf::from("/own/client.crt")));
+        assert_eq!(merged.client_key, None);
+
+        let merged = SslSettings::default().or(other);
+        assert_eq!(merged.client_cert, Some(PathBuf::from("/other/client.crt")));
+        assert_eq!(merged.client_key, Some(PathBuf::from("/other/client.key")));
+    }
+
+    #[test]
+    fn set_extra_ca_without_bundle() {
+        assert!(set_extra_ca(&mut ctx(), &SslSettings::default()).is_ok());
+    }
+
+    #[test]
+    fn set_extra_ca_with_bundle() {
+        let settings = SslSettings { ca_bundle: Some(fixture("client.crt")), ..Default::default() };
+        assert!(set_extra_ca(&mut ctx(), &settings).is_ok());
+    }
+
+    #[test]
+    fn set_extra_ca_with_missing_bundle() {
+        let settings = SslSettings { ca_bundle: Some(fixture("nope.pem")), ..Default::default() };
+        match set_extra_ca(&mut ctx(), &settings) {
+            Err(Error::SslFileNotFound(key, _)) => assert_eq!(key, SSL_CA_BUNDLE_ENVVAR),
+            r => panic!("Expected SslFileNotFound, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn set_client_cert_without_cert() {
+        assert!(set_client_cert(&mut ctx(), &SslSettings::default()).is_ok());
+    }
+
+    #[test]
+    fn set_client_cert_with_matching_key() {
+        let settings = SslSettings {
+            client_cert: Some(fixture("client.crt")),
+            client_key: Some(fixture("client.key")),
+            ..Default::default()
+        };
+        assert!(set_client_cert(&mut ctx(), &settings).is_ok());
+    }
+
+    #[test]
+    fn set_client_cert_with_mismatched_key() {
+        let settings = SslSettings {
+            client_cert: Some(fixture("client.crt")),
+            client_key: Some(fixture("other.key")),
+            ..Default::default()
+        };
+        assert!(set_client_cert(&mut ctx(), &settings).is_err());
+    }
+
+    #[test]
+    fn set_client_cert_without_key() {
+        let settings = SslSettings {
+            client_cert: Some(fixture("client.crt")),
+            ..Default::default()
+        };
+        match set_client_cert(&mut ctx(), &settings) {
+            Err(Error::ClientCertificateIncomplete(key)) => {
+                assert_eq!(key, SSL_CLIENT_KEY_ENVVAR)
+            }
+            r => panic!("Expected ClientCertificateIncomplete, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn set_client_cert_without_cert_file() {
+        let settings = SslSettings {
+            client_key: Some(fixture("client.key")),
+            ..Default::default()
+        };
+        match set_client_cert(&mut ctx(), &settings) {
+            Err(Error::ClientCertificateIncomplete(key)) => {
+                assert_eq!(key, SSL_CLIENT_CERT_ENVVAR)
+            }
+            r => panic!("Expected ClientCertificateIncomplete, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn header_values() {
+        let addr = testing::serve(|_: Request, mut res: Response| {
+            res.headers_mut().set_raw("X-Checksum", vec![b"abc".to_vec()]);
+            res.headers_mut().set_raw("X-Garbled", vec![vec![0xff, 0xfe]]);
+            res.send(b"").unwrap();
+        });
+        let res = Client::new().get(&format!("http://{}/", addr)[..]).send().unwrap();
+        assert_eq!(header(&res, "X-Checksum"), Some("abc".to_string()));
+        assert_eq!(header(&res, "X-Garbled"), None);
+        assert_eq!(header(&res, "X-Missing"), None);
+    }
+}
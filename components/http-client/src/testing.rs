@@ -0,0 +1,35 @@
+// Copyright (c) 2016 Chef Software Inc. and/or applicable contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Fake HTTP servers for the tests of the crates which talk to a Depot or a Supervisor.
+
+use std::mem;
+use std::net::SocketAddr;
+
+use hyper::server::{Handler, Server};
+
+/// Serves every request with the given handler on a free local port, returning the address it
+/// listens on. The server keeps running for the rest of the test process.
+pub fn serve<H: Handler + 'static>(handler: H) -> SocketAddr {
+    let listening = Server::http("127.0.0.1:0").unwrap().handle(handler).unwrap();
+    let addr = listening.socket;
+    leave_running(listening);
+    addr
+}
+
+/// Leaves a server running for the rest of the test process rather than joining it when its
+/// handle is dropped.
+pub fn leave_running<L>(listening: L) {
+    mem::forget(listening);
+}
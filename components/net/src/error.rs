@@ -18,6 +18,7 @@ use std::fmt;
 use std::io;
 use std::result;
 
+use http_client;
 use hyper;
 use protobuf;
 use rustc_serialize::json;
@@ -29,6 +30,7 @@ use oauth;
 pub enum Error {
     Auth(oauth::github::AuthErr),
     GitHubAPI(HashMap<String, String>),
+    HttpClient(http_client::Error),
     IO(io::Error),
     HyperError(hyper::error::Error),
     JsonDecode(json::DecoderError),
@@ -47,6 +49,7 @@ impl fmt::Display for Error {
         let msg = match *self {
             Error::Auth(ref e) => format!("GitHub Authentication error, {}", e),
             Error::GitHubAPI(ref e) => format!("GitHub API error, {:?}", e),
+            Error::HttpClient(ref e) => format!("{}", e),
             Error::IO(ref e) => format!("{}", e),
             Error::HyperError(ref e) => format!("{}", e),
             Error::JsonDecode(ref e) => format!("JSON decoding error, {}", e),
@@ -66,6 +69,7 @@ impl error::Error for Error {
         match *self {
             Error::Auth(_) => "GitHub authorization error.",
             Error::GitHubAPI(_) => "GitHub API error.",
+            Error::HttpClient(ref err) => err.description(),
             Error::IO(ref err) => err.description(),
             Error::HyperError(ref err) => err.description(),
             Error::HTTP(_) => "Non-200 HTTP response.",
@@ -85,6 +89,12 @@ impl From<hyper::error::Error> for Error {
     }
 }
 
+impl From<http_client::Error> for Error {
+    fn from(err: http_client::Error) -> Self {
+        Error::HttpClient(err)
+    }
+}
+
 impl From<io::Error> for Error {
     fn from(err: io::Error) -> Error {
         Error::IO(err)
@@ -14,6 +14,7 @@
 
 extern crate fnv;
 extern crate habitat_builder_protocol as protocol;
+extern crate habitat_http_client as http_client;
 extern crate hyper;
 extern crate libc;
 #[macro_use]
@@ -20,6 +20,7 @@ use hyper::{self, Url};
 use hyper::status::StatusCode;
 use hyper::header::{Authorization, Accept, Bearer, UserAgent, qitem};
 use hyper::mime::{Mime, TopLevel, SubLevel};
+use http_client::new_hyper_client;
 use protocol::sessionsrv;
 use rustc_serialize::json;
 
@@ -176,8 +177,8 @@ pub enum AuthResp {
 }
 
 fn http_get(url: Url, token: &str) -> Result<hyper::client::response::Response> {
-    hyper::Client::new()
-        .get(url)
+    let client = try!(new_hyper_client(Some(&url), None));
+    client.get(url)
         .header(Accept(vec![qitem(Mime(TopLevel::Application, SubLevel::Json, vec![]))]))
         .header(Authorization(Bearer { token: token.to_owned() }))
         .header(UserAgent(USER_AGENT.to_string()))
@@ -186,8 +187,8 @@ fn http_get(url: Url, token: &str) -> Result<hyper::client::response::Response>
 }
 
 fn http_post(url: Url) -> Result<hyper::client::response::Response> {
-    hyper::Client::new()
-        .post(url)
+    let client = try!(new_hyper_client(Some(&url), None));
+    client.post(url)
         .header(Accept(vec![qitem(Mime(TopLevel::Application, SubLevel::Json, vec![]))]))
         .send()
         .map_err(|e| Error::from(e))
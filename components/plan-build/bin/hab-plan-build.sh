@@ -1862,7 +1862,9 @@ _build_metadata() {
     | while read file; do _b2sum $file; done > ${pkg_name}_blake2bsums
 
   build_line "Generating signed metadata FILES"
-  $HAB_BIN pkg sign --origin $pkg_origin ${pkg_name}_blake2bsums $pkg_prefix/FILES
+  # Signed payloads must be compressed
+  xz --compress --stdout ${pkg_name}_blake2bsums > ${pkg_name}_blake2bsums.xz
+  $HAB_BIN pkg sign --origin $pkg_origin ${pkg_name}_blake2bsums.xz $pkg_prefix/FILES
   popd > /dev/null
   return 0
 }
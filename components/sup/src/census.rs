@@ -42,6 +42,7 @@ use uuid::Uuid;
 
 use gossip::member::{MemberId, MemberList, Health};
 use gossip::lamport_clock::LamportClock;
+use gossip::server::ServiceRegistry;
 use error::{Error, Result};
 use util;
 
@@ -222,6 +223,19 @@ impl CensusEntry {
         self.needs_write = Some(true);
     }
 
+    /// Mark this entry as gone for good, because its service was unloaded from a Supervisor that
+    /// is still running. Unlike a detached entry, a departed entry is never revived by the health
+    /// of its member.
+    pub fn set_departed(&mut self) {
+        self.keep_me = false;
+        self.set_detached();
+    }
+
+    /// Has this entry's service been unloaded from its Supervisor?
+    pub fn departed(&self) -> bool {
+        !self.keep_me
+    }
+
     /// Return the string we use for this CensusEntry when it is a candidate in an election.
     pub fn candidate_string(&self) -> String {
         format!("{}", self.id)
@@ -779,17 +793,20 @@ impl DerefMut for CensusList {
     }
 }
 
-pub fn start_health_adjuster(census_list: Arc<RwLock<CensusList>>,
-                             member_list: Arc<RwLock<MemberList>>) {
+/// Keeps the health of every census entry, in the census list of every service we host, in step
+/// with the health of its member.
+pub fn start_health_adjuster(services: ServiceRegistry, member_list: Arc<RwLock<MemberList>>) {
     outputln!("Starting census health adjuster");
-    let cl1 = census_list.clone();
     let ml1 = member_list.clone();
     let _t = thread::Builder::new().name("health_adjuster".to_string()).spawn(move || {
         loop {
-            {
-                let mut cl = cl1.write().unwrap();
+            for (_service, lists) in services.read().unwrap().iter() {
+                let mut cl = lists.census_list.write().unwrap();
                 for (_service_group, mut census) in cl.iter_mut() {
                     for (_census_entry_id, mut census_entry) in census.iter_mut() {
+                        if census_entry.departed() {
+                            continue;
+                        }
                         let ml = ml1.read().unwrap();
                         if let Some(member) = ml.get(&census_entry.member_id) {
                             match member.health {
@@ -906,5 +923,21 @@ mod test {
             fail_the_leader(&mut census);
             assert_eq!(census.has_leader(), false);
         }
+
+        #[test]
+        fn departed_entries_are_not_alive() {
+            let mut census = generate_census();
+            add_entries(&mut census, 2);
+            let me = census.me.clone();
+            assert_eq!(census.alive_population(), 3);
+            {
+                let ce = census.get_mut(&me).unwrap();
+                let incarnation = ce.incarnation.clone();
+                ce.set_departed();
+                assert!(ce.departed() && ce.detached && !ce.alive);
+                assert!(ce.incarnation > incarnation);
+            }
+            assert_eq!(census.alive_population(), 2);
+        }
     }
 }
@@ -0,0 +1,48 @@
+// Copyright (c) 2016 Chef Software Inc. and/or applicable contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Loads a service into the Supervisor running on this host.
+//!
+//! # Examples
+//!
+//! ```bash
+//! $ hab-sup load acme/redis -g production -t leader
+//! ```
+//!
+//! Will write a spec for the `redis` service in the `production` group, using the `leader`
+//! topology. The Supervisor watches for specs, and starts the service alongside the ones it
+//! already hosts, sharing their gossip membership and HTTP API. Loading a service again with
+//! different options restarts it with them.
+//!
+//! See the [manager](../../manager) for how specs are picked up.
+
+use error::Result;
+use config::Config;
+use manager::{specs_path, ServiceSpec};
+
+static LOGKEY: &'static str = "CL";
+
+/// Writes the spec for the service described by `config`.
+///
+/// # Failures
+///
+/// * Fails if the spec cannot be written
+pub fn service(config: &Config) -> Result<()> {
+    let spec = ServiceSpec::from_config(config);
+    let path = try!(spec.write(&specs_path()));
+    outputln!("Loaded {}; its spec is at {}",
+              spec.service_group(),
+              path.display());
+    Ok(())
+}
@@ -19,5 +19,7 @@
 //! CLI.
 
 pub mod start;
+pub mod load;
+pub mod unload;
 pub mod configure;
 pub mod shell;
@@ -55,34 +55,49 @@
 //!
 //! See the [documentation on topologies](../topology) for a deeper discussion of how they function.
 //!
+//! The Supervisor started here can host more services alongside this one; see the
+//! [load](../load) command.
+//!
 
-use std::env;
 use std::path::Path;
 
 use ansi_term::Colour::Yellow;
 use common::command::ProgressBar;
 use common::command::package::install;
-use depot_client::Client;
+use depot_client::{Client, SslSettings};
 use hcore::crypto::default_cache_key_path;
 use hcore::fs::{cache_artifact_path, FS_ROOT_PATH};
 use hcore::package::PackageIdent;
 
 use error::{Error, Result};
 use config::{Config, UpdateStrategy};
+use manager::{Host, Manager, ServiceSpec};
 use package::Package;
 use topology::{self, Topology};
 
 static LOGKEY: &'static str = "CS";
 
+/// Starts a Supervisor, hosting the package given on the command line, and any service loaded
+/// into it later, until it is told to stop or the package's service finishes.
+///
+/// # Failures
+///
+/// * Fails if the Supervisor cannot join the gossip ring
+/// * Fails if the package given on the command line fails to start or run
+pub fn package(config: &Config) -> Result<()> {
+    let mut manager = try!(Manager::new(config));
+    manager.run(ServiceSpec::from_config(config))
+}
+
 /// Creates a [Package](../../pkg/struct.Package.html), then passes it to the run method of the
-/// selected [topology](../../topology).
+/// selected [topology](../../topology). Runs on a thread of its own for each service a Supervisor
+/// hosts.
 ///
 /// # Failures
 ///
 /// * Fails if it cannot find a package with the given name
 /// * Fails if the `run` method for the topology fails
-/// * Fails if an unknown topology was specified on the command line
-pub fn package(config: &Config) -> Result<()> {
+pub fn service(config: &Config, host: Host) -> Result<()> {
     match Package::load(config.package(), None) {
         Ok(package) => {
             let update_strategy = config.update_strategy();
@@ -116,7 +131,7 @@ pub fn package(config: &Config) -> Result<()> {
                     }
                 }
             }
-            start_package(package, config)
+            start_package(package, config, host)
         }
         Err(_) => {
             outputln!("{} is not installed",
@@ -130,10 +145,11 @@ pub fn package(config: &Config) -> Result<()> {
                                                               config.package(),
                                                               Path::new(FS_ROOT_PATH),
                                                               &cache_artifact_path(None),
-                                                              &default_cache_key_path(None)));
+                                                              &default_cache_key_path(None),
+                                                              &SslSettings::from_env()));
                     let package = try!(Package::load(&new_pkg_data.get_ident().clone().into(),
                                                      None));
-                    start_package(package, config)
+                    start_package(package, config, host)
                 }
                 None => Err(sup_error!(Error::PackageNotFound(config.package().clone()))),
             }
@@ -141,13 +157,10 @@ pub fn package(config: &Config) -> Result<()> {
     }
 }
 
-fn start_package(package: Package, config: &Config) -> Result<()> {
-    let run_path = try!(package.run_path());
-    debug!("Setting the PATH to {}", run_path);
-    env::set_var("PATH", &run_path);
+fn start_package(package: Package, config: &Config, host: Host) -> Result<()> {
     match *config.topology() {
-        Topology::Standalone => topology::standalone::run(package, config),
-        Topology::Leader => topology::leader::run(package, config),
-        Topology::Initializer => topology::initializer::run(package, config),
+        Topology::Standalone => topology::standalone::run(package, config, host),
+        Topology::Leader => topology::leader::run(package, config, host),
+        Topology::Initializer => topology::initializer::run(package, config, host),
     }
 }
@@ -0,0 +1,50 @@
+// Copyright (c) 2016 Chef Software Inc. and/or applicable contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Unloads a service from the Supervisor running on this host.
+//!
+//! # Examples
+//!
+//! ```bash
+//! $ hab-sup unload acme/redis -g production
+//! ```
+//!
+//! Will remove the spec for the `redis` service in the `production` group. The Supervisor stops
+//! the service, and the rest of the ring sees it depart. A service given to `hab-sup start` has no
+//! spec, and runs for as long as its Supervisor does.
+
+use std::fs;
+
+use error::{Error, Result};
+use config::Config;
+use manager::{specs_path, spec_file_name};
+
+static LOGKEY: &'static str = "CU";
+
+/// Removes the spec for the service described by `config`.
+///
+/// # Failures
+///
+/// * Fails if no service is loaded in the service group
+/// * Fails if the spec cannot be removed
+pub fn service(config: &Config) -> Result<()> {
+    let service_group = format!("{}.{}", config.package().name, config.group());
+    let path = specs_path().join(spec_file_name(&service_group));
+    if !path.is_file() {
+        return Err(sup_error!(Error::ServiceNotLoaded(service_group)));
+    }
+    try!(fs::remove_file(&path));
+    outputln!("Unloaded {}", service_group);
+    Ok(())
+}
@@ -35,6 +35,8 @@ static LOGKEY: &'static str = "CFG";
 pub enum Command {
     Config,
     Start,
+    Load,
+    Unload,
     ShellBash,
     ShellSh,
 }
@@ -68,6 +70,8 @@ impl FromStr for Command {
             "bash" => Ok(Command::ShellBash),
             "sh" => Ok(Command::ShellSh),
             "start" => Ok(Command::Start),
+            "load" => Ok(Command::Load),
+            "unload" => Ok(Command::Unload),
             _ => Err(sup_error!(Error::CommandNotImplemented)),
         }
     }
@@ -81,7 +85,7 @@ impl Default for Command {
 }
 
 /// Holds our configuration options.
-#[derive(Default, Debug, PartialEq, Eq)]
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
 pub struct Config {
     command: Command,
     package: PackageIdent,
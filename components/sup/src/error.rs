@@ -116,6 +116,7 @@ pub enum Error {
     InvalidKeyParameter(String),
     InvalidPidFile,
     InvalidServiceGroupString(String),
+    InvalidServiceSpec(String),
     Io(io::Error),
     IPFailed,
     JsonDecode(json::DecoderError),
@@ -128,6 +129,8 @@ pub enum Error {
     PackageArchiveMalformed(String),
     PackageNotFound(package::PackageIdent),
     RemotePackageNotFound(package::PackageIdent),
+    ServiceNotLoaded(String),
+    ServicePanicked(String),
     SignalFailed,
     SignalNotifierStarted,
     StrFromUtf8Error(str::Utf8Error),
@@ -176,6 +179,7 @@ impl fmt::Display for SupError {
             Error::InvalidServiceGroupString(ref e) => {
                 format!("Invalid service group string: {}", e)
             }
+            Error::InvalidServiceSpec(ref e) => format!("Invalid service spec: {}", e),
             Error::Io(ref err) => format!("{}", err),
             Error::IPFailed => format!("Failed to discover this hosts outbound IP address"),
             Error::JsonDecode(ref e) => format!("JSON decoding error: {}", e),
@@ -206,6 +210,8 @@ impl fmt::Display for SupError {
                     format!("Cannot find a release of package in any sources: {}", pkg)
                 }
             }
+            Error::ServiceNotLoaded(ref sg) => format!("No service is loaded as {}", sg),
+            Error::ServicePanicked(ref sg) => format!("The {} service crashed", sg),
             Error::SignalFailed => format!("Failed to send a signal to the child process"),
             Error::SignalNotifierStarted => {
                 format!("Only one instance of a Signal Notifier may be running")
@@ -257,6 +263,7 @@ impl error::Error for SupError {
             Error::InvalidServiceGroupString(_) => {
                 "Service group strings must be in service.group format (example: redis.default)"
             }
+            Error::InvalidServiceSpec(_) => "A service spec was unreadable or incomplete",
             Error::Io(ref err) => err.description(),
             Error::IPFailed => "Failed to discover the outbound IP address",
             Error::JsonDecode(_) => "JSON decoding error: {:?}",
@@ -276,6 +283,8 @@ impl error::Error for SupError {
             }
             Error::PackageNotFound(_) => "Cannot find a package",
             Error::RemotePackageNotFound(_) => "Cannot find a package in any sources",
+            Error::ServiceNotLoaded(_) => "No service is loaded for the service group",
+            Error::ServicePanicked(_) => "A service crashed",
             Error::SignalFailed => "Failed to send a signal to the child process",
             Error::SignalNotifierStarted => "Only one instance of a Signal Notifier may be running",
             Error::StrFromUtf8Error(_) => "Failed to convert a str from a &[u8] as UTF-8",
@@ -23,6 +23,7 @@
 
 use threadpool::ThreadPool;
 
+use std::collections::BTreeMap;
 use std::thread;
 use std::ops::Deref;
 use std::time::Duration;
@@ -53,7 +54,24 @@ static INBOUND_MAX_THREADS: usize = 5;
 /// The default port for the Gossip protocol
 pub static GOSSIP_DEFAULT_PORT: usize = 9634;
 
+/// The gossip state kept for each service a Supervisor hosts. Membership and rumors are shared by
+/// every service; censuses, elections and gossiped files are seen from one service's point of
+/// view, so each service keeps its own.
+#[derive(Clone)]
+pub struct ServiceLists {
+    /// Our list of censuses
+    pub census_list: Arc<RwLock<CensusList>>,
+    /// The list of elections
+    pub election_list: Arc<RwLock<ElectionList>>,
+    /// The list of gossip files
+    pub gossip_file_list: Arc<RwLock<GossipFileList>>,
+}
+
+/// The services a Supervisor hosts, by `service.group`.
+pub type ServiceRegistry = Arc<RwLock<BTreeMap<String, ServiceLists>>>;
+
 /// A gossip server
+#[derive(Clone)]
 pub struct Server {
     /// The port to listen for inbound connections on
     pub listen: String,
@@ -61,14 +79,10 @@ pub struct Server {
     pub member_list: Arc<RwLock<MemberList>>,
     /// Our list of rumors to share
     pub rumor_list: Arc<RwLock<RumorList>>,
-    /// Our list of censuses
-    pub census_list: Arc<RwLock<CensusList>>,
     /// The failure detector
     pub detector: Arc<RwLock<Detector>>,
-    /// The list of elections
-    pub election_list: Arc<RwLock<ElectionList>>,
-    /// The list of gossip files
-    pub gossip_file_list: Arc<RwLock<GossipFileList>>,
+    /// The services we host
+    pub services: ServiceRegistry,
     /// Our 'peer' entry, used to generate SWIM protocol messages.
     pub peer: Peer,
     /// An optional ring key used to encrypt messages with peers
@@ -76,17 +90,12 @@ pub struct Server {
 }
 
 impl Server {
-    /// Creates a new Server. Creates our own entry in the census and membership lists, and writes
-    /// a rumor that this server is alive.
+    /// Creates a new Server. Creates our own entry in the membership list, and writes a rumor
+    /// that this server is alive. Services join with `add_service`.
     pub fn new(listen_ip: String,
                listen_port: u16,
                permanent: bool,
-               ring_name_with_rev: Option<String>,
-               service: String,
-               group: String,
-               organization: Option<String>,
-               exposes: Option<Vec<String>>,
-               port: Option<String>)
+               ring_name_with_rev: Option<String>)
                -> Server {
 
         let hostname = util::sys::hostname(None).unwrap_or(String::from("unknown"));
@@ -95,17 +104,8 @@ impl Server {
         let peer_listen2 = peer_listen.clone();
 
         let member = Member::new(hostname, listen_ip, peer_listen2, permanent);
-
-        let service_group = format!("{}.{}", service, group);
-        let mut ce = CensusEntry::new(service.clone(), group.clone(), member.id.clone());
-        ce.exposes = exposes;
-        ce.port = port;
         let my_id = member.id.clone();
-        let leader_id = member.id.clone();
         outputln!("Supervisor {}", member);
-        outputln!("Census {}", ce);
-
-        let census_list = CensusList::new(Census::new(ce.clone()));
 
         let ring_key = match ring_name_with_rev {
             Some(rnwr) => Some(SymKey::get_pair_for(&rnwr, &default_cache_key_path(None)).unwrap()),
@@ -116,14 +116,9 @@ impl Server {
             listen: listen,
             member_list: Arc::new(RwLock::new(MemberList::new(member.clone()))),
             rumor_list: Arc::new(RwLock::new(RumorList::new())),
-            census_list: Arc::new(RwLock::new(census_list)),
             peer: Peer::new(my_id, peer_listen),
             detector: Arc::new(RwLock::new(Detector::new())),
-            election_list: Arc::new(RwLock::new(ElectionList::new(service_group, leader_id))),
-            gossip_file_list:
-                Arc::new(RwLock::new(GossipFileList::new(ServiceGroup::new(service,
-                                                                           group,
-                                                                           organization)))),
+            services: Arc::new(RwLock::new(BTreeMap::new())),
             ring_key: Arc::new(ring_key),
         };
 
@@ -134,14 +129,107 @@ impl Server {
             rl.add_rumor(rumor);
         }
 
+        server
+    }
+
+    /// Adds a service to this Supervisor. Creates its entry in the census, writes a rumor for
+    /// it, and catches its lists up with the rumors we already hold. The service is registered
+    /// before it catches up, so a rumor arriving meanwhile reaches its lists one way or the
+    /// other.
+    pub fn add_service(&self,
+                       service: String,
+                       group: String,
+                       organization: Option<String>,
+                       exposes: Option<Vec<String>>,
+                       port: Option<String>)
+                       -> ServiceLists {
+        let service_group = format!("{}.{}", service, group);
+        let my_id = self.peer.member_id.clone();
+        let mut ce = CensusEntry::new(service.clone(), group.clone(), my_id.clone());
+        ce.exposes = exposes;
+        ce.port = port;
+        outputln!("Census {}", ce);
+
+        let lists = ServiceLists {
+            census_list: Arc::new(RwLock::new(CensusList::new(Census::new(ce.clone())))),
+            election_list: Arc::new(RwLock::new(ElectionList::new(service_group.clone(),
+                                                                  my_id))),
+            gossip_file_list:
+                Arc::new(RwLock::new(GossipFileList::new(ServiceGroup::new(service,
+                                                                           group,
+                                                                           organization)))),
+        };
+
+        {
+            let mut services = self.services.write().unwrap();
+            services.insert(service_group.clone(), lists.clone());
+        }
+
+        // Catch up on what the ring, and our other services, already know
+        {
+            let rl = self.rumor_list.read().unwrap();
+            for rumor in rl.rumors.values() {
+                match rumor.payload {
+                    Message::CensusEntry(ref remote_ce) => {
+                        let mut cl = lists.census_list.write().unwrap();
+                        cl.process(remote_ce.clone());
+                    }
+                    Message::Election(ref election) => {
+                        if election.service_group() == service_group {
+                            let mut el = lists.election_list.write().unwrap();
+                            el.process(election.clone());
+                        }
+                    }
+                    Message::GossipFile(ref gossip_file) => {
+                        if service_key(&gossip_file.service_group) == service_group {
+                            let mut gfl = lists.gossip_file_list.write().unwrap();
+                            gfl.process(gossip_file.clone());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        self.share_census_entry(&ce);
+
         // Write our Census Entry Rumor
         {
             let rumor = Rumor::census_entry(ce);
-            let mut rl = server.rumor_list.write().unwrap();
+            let mut rl = self.rumor_list.write().unwrap();
             rl.add_rumor(rumor);
         }
+        lists
+    }
 
-        server
+    /// Removes a service from this Supervisor, and tells the ring it has departed.
+    pub fn remove_service(&self, service_group: &str) {
+        let lists = {
+            let mut services = self.services.write().unwrap();
+            services.remove(service_group)
+        };
+        if let Some(lists) = lists {
+            let me = {
+                let mut cl = lists.census_list.write().unwrap();
+                cl.me_mut().set_departed();
+                cl.me().clone()
+            };
+            outputln!("Census {} departed", me);
+            self.share_census_entry(&me);
+            let mut rl = self.rumor_list.write().unwrap();
+            rl.update_rumor(Rumor::census_entry(me));
+        }
+    }
+
+    /// Hands the census entry of one of our services to every other service we host. The ring
+    /// would tell them eventually, but only once the rumor had come back around to us.
+    pub fn share_census_entry(&self, ce: &CensusEntry) {
+        let services = self.services.read().unwrap();
+        for (service_group, lists) in services.iter() {
+            if *service_group != ce.service_group() {
+                let mut cl = lists.census_list.write().unwrap();
+                cl.process(ce.clone());
+            }
+        }
     }
 
     /// Starts the inbound gossip listener.
@@ -154,15 +242,13 @@ impl Server {
         let key = self.ring_key.clone();
         let ml = self.member_list.clone();
         let rl = self.rumor_list.clone();
-        let cl = self.census_list.clone();
         let my_peer = self.peer.clone();
         let detector = self.detector.clone();
-        let el = self.election_list.clone();
-        let gfl = self.gossip_file_list.clone();
+        let services = self.services.clone();
         let listener = try!(UtpListener::bind(&self.listen[..]));
         let _t = thread::Builder::new()
             .name("inbound".to_string())
-            .spawn(move || inbound(listener, key, my_peer, ml, rl, cl, detector, el, gfl));
+            .spawn(move || inbound(listener, key, my_peer, ml, rl, detector, services));
         Ok(())
     }
 
@@ -258,10 +344,8 @@ pub fn inbound(listener: UtpListener,
                my_peer: Peer,
                member_list: Arc<RwLock<MemberList>>,
                rumor_list: Arc<RwLock<RumorList>>,
-               census_list: Arc<RwLock<CensusList>>,
                detector: Arc<RwLock<Detector>>,
-               election_list: Arc<RwLock<ElectionList>>,
-               gossip_file_list: Arc<RwLock<GossipFileList>>) {
+               services: ServiceRegistry) {
     let pool = ThreadPool::new(INBOUND_MAX_THREADS);
     for connection in listener.incoming() {
         loop {
@@ -285,12 +369,10 @@ pub fn inbound(listener: UtpListener,
                 let my_peer = my_peer.clone();
                 let ml = member_list.clone();
                 let rl = rumor_list.clone();
-                let cl = census_list.clone();
                 let d1 = detector.clone();
-                let el = election_list.clone();
-                let gfl = gossip_file_list.clone();
+                let services = services.clone();
 
-                pool.execute(move || receive(socket, src, key, my_peer, ml, rl, cl, d1, el, gfl));
+                pool.execute(move || receive(socket, src, key, my_peer, ml, rl, d1, services));
             }
             _ => {}
         }
@@ -321,10 +403,8 @@ fn receive(socket: UtpSocket,
            my_peer: Peer,
            member_list: Arc<RwLock<MemberList>>,
            rumor_list: Arc<RwLock<RumorList>>,
-           census_list: Arc<RwLock<CensusList>>,
            detector: Arc<RwLock<Detector>>,
-           election_list: Arc<RwLock<ElectionList>>,
-           gossip_file_list: Arc<RwLock<GossipFileList>>) {
+           services: ServiceRegistry) {
     let mut client = Client::from_socket(socket, ring_key.deref().as_ref());
     let msg = match client.recv_message() {
         Ok(msg) => msg,
@@ -396,9 +476,7 @@ fn receive(socket: UtpSocket,
             process_rumors(remote_rumor_list,
                            rumor_list,
                            member_list,
-                           census_list,
-                           election_list,
-                           gossip_file_list);
+                           services);
         }
         Protocol::Ack(mut from_peer, remote_rumor_list) => {
             // If this is a proxy ack, forward the results on
@@ -430,9 +508,7 @@ fn receive(socket: UtpSocket,
                 process_rumors(remote_rumor_list,
                                rumor_list,
                                member_list,
-                               census_list,
-                               election_list,
-                               gossip_file_list);
+                               services);
             }
         }
         Protocol::PingReq(from_peer, remote_rumor_list) => {
@@ -468,19 +544,19 @@ fn receive(socket: UtpSocket,
             process_rumors(remote_rumor_list,
                            rumor_list,
                            member_list,
-                           census_list,
-                           election_list,
-                           gossip_file_list);
+                           services);
         }
     }
 }
 
+/// Processes a list of rumors. Membership is shared by every service we host. Census entries are
+/// handed to every service, as any of them may bind to any service group. Elections and gossiped
+/// files are handed to the service they are for, or if we don't host it, to the first service we
+/// do, so that we keep passing them along.
 pub fn process_rumors(remote_rumors: RumorList,
                       rumor_list: Arc<RwLock<RumorList>>,
                       member_list: Arc<RwLock<MemberList>>,
-                      census_list: Arc<RwLock<CensusList>>,
-                      election_list: Arc<RwLock<ElectionList>>,
-                      gossip_file_list: Arc<RwLock<GossipFileList>>) {
+                      services: ServiceRegistry) {
     for (id, remote_rumor) in remote_rumors.rumors.into_iter() {
         match remote_rumor.payload {
             Message::Member(m) => {
@@ -507,8 +583,13 @@ pub fn process_rumors(remote_rumors: RumorList,
             Message::CensusEntry(ce) => {
                 debug!("Processing Census Entry {:#?}", ce);
                 let processed = {
-                    let mut cl = census_list.write().unwrap();
-                    cl.process(ce.clone())
+                    let services = services.read().unwrap();
+                    let mut processed = false;
+                    for lists in services.values() {
+                        let mut cl = lists.census_list.write().unwrap();
+                        processed |= cl.process(ce.clone());
+                    }
+                    processed
                 };
                 if processed {
                     let mut rl = rumor_list.write().unwrap();
@@ -520,6 +601,10 @@ pub fn process_rumors(remote_rumors: RumorList,
             // destroy the un-neccessary rumors
             Message::Election(election) => {
                 debug!("Processing Election {}", election);
+                let election_list = match lists_for(&services, &election.service_group()) {
+                    Some(lists) => lists.election_list,
+                    None => continue,
+                };
                 let processed = {
                     let mut el = election_list.write().unwrap();
                     el.process(election.clone())
@@ -539,14 +624,25 @@ pub fn process_rumors(remote_rumors: RumorList,
             }
             Message::GossipFile(gossip_file) => {
                 debug!("Processing Gossip File {}", gossip_file);
+                let gossip_file_list =
+                    match lists_for(&services, &service_key(&gossip_file.service_group)) {
+                        Some(lists) => lists.gossip_file_list,
+                        None => continue,
+                    };
+                // Gossip on what we now hold rather than what we were sent, as a configuration
+                // patch is merged into ours instead of replacing it.
                 let processed = {
                     let mut gf = gossip_file_list.write().unwrap();
-                    gf.process(gossip_file.clone())
+                    if gf.process(gossip_file.clone()) {
+                        gf.get_for(&gossip_file.service_group, &gossip_file.file_name).cloned()
+                    } else {
+                        None
+                    }
                 };
-                if processed {
+                if let Some(current) = processed {
                     let mut rl = rumor_list.write().unwrap();
-                    rl.prune_gossip_files_for(&gossip_file);
-                    rl.add_rumor(Rumor::gossip_file(gossip_file));
+                    rl.prune_gossip_files_for(&current);
+                    rl.add_rumor(Rumor::gossip_file(current));
                 }
             }
             Message::Blank => {}
@@ -554,6 +650,20 @@ pub fn process_rumors(remote_rumors: RumorList,
     }
 }
 
+/// The lists of the service we host for `service_group`, or failing that, of the first service we
+/// host.
+fn lists_for(services: &ServiceRegistry, service_group: &str) -> Option<ServiceLists> {
+    let services = services.read().unwrap();
+    services.get(service_group)
+        .or(services.values().next())
+        .cloned()
+}
+
+/// The `service.group` a gossiped file's service group is hosted under.
+fn service_key(service_group: &ServiceGroup) -> String {
+    format!("{}.{}", service_group.service, service_group.group)
+}
+
 
 /// The outbound distributor. Every OUTBOUND_INTERVAL in milliseconds, it spawns a new connection
 /// to the next member.
@@ -811,3 +921,72 @@ pub fn failure_detector(ring_key: Arc<Option<SymKey>>,
         thread::sleep(Duration::from_millis(100));
     }
 }
+
+#[cfg(test)]
+mod test {
+    use gossip::server::{lists_for, Server};
+
+    fn server() -> Server {
+        Server::new("127.0.0.1".to_string(), 0, false, None)
+    }
+
+    fn add(server: &Server, service: &str) {
+        server.add_service(service.to_string(), "default".to_string(), None, None, None);
+    }
+
+    #[test]
+    fn lists_for_falls_back_to_first_service() {
+        let server = server();
+        assert!(lists_for(&server.services, "redis.default").is_none());
+
+        add(&server, "redis");
+        add(&server, "nginx");
+        let lists = lists_for(&server.services, "redis.default").unwrap();
+        assert_eq!(lists.census_list.read().unwrap().me().service_group(),
+                   "redis.default");
+        // Services are kept by service group, so the first one is nginx.default.
+        let lists = lists_for(&server.services, "postgres.default").unwrap();
+        assert_eq!(lists.census_list.read().unwrap().me().service_group(),
+                   "nginx.default");
+    }
+
+    #[test]
+    fn add_service_shares_census_entries() {
+        let server = server();
+        add(&server, "redis");
+        add(&server, "nginx");
+        let redis = lists_for(&server.services, "redis.default").unwrap();
+        let nginx = lists_for(&server.services, "nginx.default").unwrap();
+        let nginx_id = nginx.census_list.read().unwrap().me().id.clone();
+        assert!(redis.census_list
+            .write()
+            .unwrap()
+            .get_mut(&nginx_id, "nginx.default")
+            .is_some());
+    }
+
+    #[test]
+    fn remove_service_marks_census_entry_departed() {
+        let server = server();
+        add(&server, "redis");
+        add(&server, "nginx");
+        let redis = lists_for(&server.services, "redis.default").unwrap();
+        let nginx = lists_for(&server.services, "nginx.default").unwrap();
+        let redis_id = redis.census_list.read().unwrap().me().id.clone();
+
+        server.remove_service("redis.default");
+        assert!(!server.services.read().unwrap().contains_key("redis.default"));
+        assert!(redis.census_list.read().unwrap().me().departed());
+        // The services we still host hear of it straight away.
+        assert!(nginx.census_list
+            .write()
+            .unwrap()
+            .get_mut(&redis_id, "redis.default")
+            .unwrap()
+            .departed());
+
+        // Removing a service we don't host is a no-op.
+        server.remove_service("redis.default");
+        assert_eq!(server.services.read().unwrap().len(), 1);
+    }
+}
@@ -42,6 +42,8 @@
 extern crate habitat_core as hcore;
 extern crate habitat_common as common;
 extern crate habitat_depot_client as depot_client;
+#[cfg(test)]
+extern crate habitat_http_client as http_client;
 extern crate handlebars;
 #[macro_use]
 extern crate hyper;
@@ -271,6 +273,7 @@ pub mod package;
 pub mod topology;
 pub mod state_machine;
 pub mod sidecar;
+pub mod manager;
 pub mod health_check;
 pub mod config;
 pub mod service_config;
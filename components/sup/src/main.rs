@@ -38,7 +38,7 @@ use hcore::package::PackageIdent;
 use hcore::url::{DEFAULT_DEPOT_URL, DEPOT_URL_ENVVAR};
 
 use sup::config::{Command, Config, UpdateStrategy};
-use sup::error::{Error, Result, SupError};
+use sup::error::{Result, SupError};
 use sup::command::*;
 use sup::topology::Topology;
 use sup::util::parse_ip_port_with_defaults;
@@ -97,18 +97,7 @@ fn config_from_args(args: &ArgMatches, subcommand: &str, sub_args: &ArgMatches)
         config.set_outfile(outfile.to_string());
     }
     if let Some(topology) = sub_args.value_of("topology") {
-        match topology.as_ref() {
-            "standalone" => {
-                config.set_topology(Topology::Standalone);
-            }
-            "leader" => {
-                config.set_topology(Topology::Leader);
-            }
-            "initializer" => {
-                config.set_topology(Topology::Initializer);
-            }
-            t => return Err(sup_error!(Error::UnknownTopology(String::from(t)))),
-        }
+        config.set_topology(try!(Topology::from_str(topology)));
     }
     if sub_args.value_of("expire-days").is_some() {
         let ed = value_t!(sub_args.value_of("expire-days"), u16).unwrap_or_else(|e| e.exit());
@@ -298,6 +287,33 @@ fn main() {
             .short("I")
             .long("permanent-peer")
             .help("If this service is a permanent peer"));
+    let sub_load = SubCommand::with_name("load")
+        .about("Load a service into the Supervisor running on this host")
+        .arg(Arg::with_name("package")
+            .index(1)
+            .required(true)
+            .help("Name of package to load"))
+        .arg(arg_url())
+        .arg(arg_group())
+        .arg(arg_org())
+        .arg(arg_strategy())
+        .arg(Arg::with_name("topology")
+            .short("t")
+            .long("topology")
+            .value_name("topology")
+            .help("Service topology"))
+        .arg(Arg::with_name("bind")
+            .long("bind")
+            .value_name("bind")
+            .multiple(true)
+            .help("One or more service groups to bind to a configuration"));
+    let sub_unload = SubCommand::with_name("unload")
+        .about("Unload a service from the Supervisor running on this host")
+        .arg(Arg::with_name("package")
+            .index(1)
+            .required(true)
+            .help("Name of package to unload"))
+        .arg(arg_group());
     let sub_bash = SubCommand::with_name("bash")
         .about("Start an interactive shell (bash)")
         .aliases(&["b", "ba", "bas"]);
@@ -322,6 +338,8 @@ fn main() {
             .global(true)
             .help("Turn ANSI color off :("))
         .subcommand(sub_start)
+        .subcommand(sub_load)
+        .subcommand(sub_unload)
         .subcommand(sub_bash)
         .subcommand(sub_sh)
         .subcommand(sub_config);
@@ -342,6 +360,8 @@ fn main() {
         Command::ShellSh => shell_sh(&config),
         Command::Config => configure(&config),
         Command::Start => start(&config),
+        Command::Load => load(&config),
+        Command::Unload => unload(&config),
     };
 
     match result {
@@ -386,3 +406,15 @@ fn start(config: &Config) -> Result<()> {
               Yellow.bold().paint(config.package().to_string()));
     Ok(())
 }
+
+/// Load a service into the running Supervisor
+#[allow(dead_code)]
+fn load(config: &Config) -> Result<()> {
+    load::service(config)
+}
+
+/// Unload a service from the running Supervisor
+#[allow(dead_code)]
+fn unload(config: &Config) -> Result<()> {
+    unload::service(config)
+}
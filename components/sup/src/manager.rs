@@ -0,0 +1,574 @@
+// Copyright (c) 2016 Chef Software Inc. and/or applicable contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The service manager.
+//!
+//! A Supervisor hosts one or more services. They share a single gossip membership and a single
+//! HTTP API, while each service runs its own [topology](../topology), package updater and hooks on
+//! a thread of its own.
+//!
+//! The service given to `hab-sup start` is started first, and the Supervisor runs for as long as
+//! it does. Further services are loaded and unloaded at runtime through *service specs*: small
+//! TOML files in the specs directory, written by `hab-sup load` and removed by `hab-sup unload`.
+//! The manager watches the directory, starting a service when its spec appears, stopping it when
+//! its spec goes away, and restarting it when its spec changes.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::net::SocketAddrV4;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use hcore::fs as hfs;
+use hcore::package::PackageIdent;
+use toml;
+use wonder;
+
+use census;
+use command::start;
+use config::{Config, UpdateStrategy};
+use error::{Error, Result};
+use gossip::server::Server;
+use sidecar::{self, SidecarActor, SidecarServices};
+use topology::Topology;
+use util::signals::{self, SignalNotifier};
+use PROGRAM_NAME;
+
+static LOGKEY: &'static str = "MR";
+/// How often we check for signals, finished services and changed specs, in milliseconds
+const LOOP_INTERVAL_MS: u64 = 500;
+/// The extension of a service spec file
+pub const SPEC_FILE_EXT: &'static str = "spec";
+
+/// Returns the directory the Supervisor watches for service specs.
+pub fn specs_path() -> PathBuf {
+    hfs::svc_path(PROGRAM_NAME.as_str()).join("specs")
+}
+
+/// A command from the manager to one of its services.
+#[derive(Debug)]
+pub enum ServiceCommand {
+    /// Forward a signal on to the supervised process
+    Signal(signals::Signal),
+    /// Stop the supervised process, and the service with it
+    Stop,
+}
+
+/// What a service is given by the Supervisor hosting it.
+pub struct Host {
+    /// The gossip server every service shares
+    pub gossip_server: Server,
+    /// The services the sidecar answers for
+    pub sidecar_services: Arc<RwLock<SidecarServices>>,
+    /// Commands from the manager
+    pub commands: Receiver<ServiceCommand>,
+}
+
+/// How to run a service: which package, in which group, and with which topology.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceSpec {
+    pub ident: PackageIdent,
+    pub group: String,
+    pub topology: Topology,
+    pub update_strategy: UpdateStrategy,
+    pub bind: Vec<String>,
+    /// The service's organization; defaults to the Supervisor's
+    pub organization: Option<String>,
+    /// The depot to install and update the package from; defaults to the Supervisor's
+    pub url: Option<String>,
+}
+
+impl ServiceSpec {
+    /// The spec for the service described by the command line.
+    pub fn from_config(config: &Config) -> ServiceSpec {
+        ServiceSpec {
+            ident: config.package().clone(),
+            group: config.group().to_string(),
+            topology: config.topology().clone(),
+            update_strategy: config.update_strategy(),
+            bind: config.bind(),
+            organization: config.organization().clone(),
+            url: config.url().clone(),
+        }
+    }
+
+    /// The service group the service runs in, as `service.group`.
+    pub fn service_group(&self) -> String {
+        format!("{}.{}", self.ident.name, self.group)
+    }
+
+    /// The configuration to run the service with: the Supervisor's own, with the service's
+    /// settings from this spec.
+    pub fn config_for(&self, supervisor: &Config) -> Config {
+        let mut config = supervisor.clone();
+        config.set_package(self.ident.clone())
+            .set_group(self.group.clone())
+            .set_topology(self.topology.clone())
+            .set_update_strategy(self.update_strategy.clone())
+            .set_bind(self.bind.clone());
+        if let Some(ref organization) = self.organization {
+            config.set_organization(organization.clone());
+        }
+        if let Some(ref url) = self.url {
+            config.set_url(url.clone());
+        }
+        config
+    }
+
+    /// Parses a spec from the TOML document produced by `to_toml_string`.
+    pub fn from_toml_str(body: &str) -> Result<ServiceSpec> {
+        let mut toml_parser = toml::Parser::new(body);
+        let table = try!(toml_parser.parse()
+            .ok_or(sup_error!(Error::TomlParser(toml_parser.errors))));
+        let ident = match table.get("ident").and_then(|v| v.as_str()) {
+            Some(ident) => try!(PackageIdent::from_str(ident)),
+            None => return Err(sup_error!(Error::InvalidServiceSpec("no ident".to_string()))),
+        };
+        let group = match table.get("group").and_then(|v| v.as_str()) {
+            Some(group) => group.to_string(),
+            None => return Err(sup_error!(Error::InvalidServiceSpec("no group".to_string()))),
+        };
+        let topology = match table.get("topology").and_then(|v| v.as_str()) {
+            Some(topology) => try!(Topology::from_str(topology)),
+            None => Topology::default(),
+        };
+        let update_strategy = match table.get("update_strategy").and_then(|v| v.as_str()) {
+            Some("none") | None => UpdateStrategy::None,
+            Some("at-once") => UpdateStrategy::AtOnce,
+            Some(s) => {
+                return Err(sup_error!(Error::InvalidServiceSpec(format!("unknown update \
+                                                                         strategy {}",
+                                                                        s))))
+            }
+        };
+        let bind = match table.get("bind").and_then(|v| v.as_slice()) {
+            Some(bind) => bind.iter().filter_map(|b| b.as_str()).map(|b| b.to_string()).collect(),
+            None => vec![],
+        };
+        Ok(ServiceSpec {
+            ident: ident,
+            group: group,
+            topology: topology,
+            update_strategy: update_strategy,
+            bind: bind,
+            organization: table.get("organization")
+                .and_then(|v| v.as_str())
+                .map(|o| o.to_string()),
+            url: table.get("url").and_then(|v| v.as_str()).map(|u| u.to_string()),
+        })
+    }
+
+    pub fn to_toml_string(&self) -> String {
+        let mut table = toml::Table::new();
+        table.insert("ident".to_string(),
+                     toml::Value::String(self.ident.to_string()));
+        table.insert("group".to_string(), toml::Value::String(self.group.clone()));
+        table.insert("topology".to_string(),
+                     toml::Value::String(self.topology.to_string()));
+        let update_strategy = match self.update_strategy {
+            UpdateStrategy::None => "none",
+            UpdateStrategy::AtOnce => "at-once",
+        };
+        table.insert("update_strategy".to_string(),
+                     toml::Value::String(update_strategy.to_string()));
+        table.insert("bind".to_string(),
+                     toml::Value::Array(self.bind
+                         .iter()
+                         .map(|b| toml::Value::String(b.clone()))
+                         .collect()));
+        if let Some(ref organization) = self.organization {
+            table.insert("organization".to_string(),
+                         toml::Value::String(organization.clone()));
+        }
+        if let Some(ref url) = self.url {
+            table.insert("url".to_string(), toml::Value::String(url.clone()));
+        }
+        toml::Value::Table(table).to_string()
+    }
+
+    /// The name of the file the spec is kept in.
+    pub fn file_name(&self) -> String {
+        spec_file_name(&self.service_group())
+    }
+
+    /// Writes the spec into `dir`. The file is written aside and then renamed into place, so the
+    /// manager never reads half a spec.
+    pub fn write(&self, dir: &Path) -> Result<PathBuf> {
+        try!(fs::create_dir_all(dir));
+        let path = dir.join(self.file_name());
+        let tmp_path = dir.join(format!(".{}.tmp", self.file_name()));
+        {
+            let mut file = try!(File::create(&tmp_path));
+            try!(file.write_all(self.to_toml_string().as_bytes()));
+        }
+        try!(fs::rename(&tmp_path, &path));
+        Ok(path)
+    }
+}
+
+/// The name of the spec file for the service running in `service_group`.
+pub fn spec_file_name(service_group: &str) -> String {
+    format!("{}.{}", service_group, SPEC_FILE_EXT)
+}
+
+/// A service the manager has started.
+struct RunningService {
+    spec: ServiceSpec,
+    /// True if the service was loaded from a spec, rather than given to `hab-sup start`
+    from_spec: bool,
+    /// True once the service has been told to stop; it is left alone until it has finished
+    stopping: bool,
+    commands: Sender<ServiceCommand>,
+    thread: JoinHandle<Result<()>>,
+}
+
+/// Tells the manager a service's thread has finished, even if it panicked.
+struct ExitNotice {
+    service_group: String,
+    exits: Sender<String>,
+}
+
+impl Drop for ExitNotice {
+    fn drop(&mut self) {
+        let _ = self.exits.send(self.service_group.clone());
+    }
+}
+
+/// The manager; owns what the services of a Supervisor share, and runs each of them.
+pub struct Manager {
+    /// The Supervisor's configuration; each service's is derived from it
+    pub config: Config,
+    /// The Gossip Server; listens for inbound gossip traffic
+    pub gossip_server: Server,
+    /// Our Sidecar Actor; exposes a restful HTTP interface to the outside world
+    pub sidecar_actor: SidecarActor,
+    pub sidecar_services: Arc<RwLock<SidecarServices>>,
+    /// The directory we watch for service specs
+    pub specs_path: PathBuf,
+    services: HashMap<String, RunningService>,
+    /// Specs which failed to run, by service group. They are not retried until they change.
+    failed: HashMap<String, ServiceSpec>,
+    exits_tx: Sender<String>,
+    exits: Receiver<String>,
+}
+
+impl Manager {
+    /// Creates a new manager. Joins the gossip ring and starts the sidecar; no services are
+    /// running until `run` is called.
+    pub fn new(config: &Config) -> Result<Manager> {
+        let gossip_server = Server::new(String::from(config.gossip_listen_ip()),
+                                        config.gossip_listen_port(),
+                                        config.gossip_permanent(),
+                                        config.ring().clone());
+        try!(gossip_server.start_inbound());
+        try!(gossip_server.initial_peers(config.gossip_peer()));
+        gossip_server.start_outbound();
+        gossip_server.start_failure_detector();
+        census::start_health_adjuster(gossip_server.services.clone(),
+                                      gossip_server.member_list.clone());
+
+        let sidecar_services = Arc::new(RwLock::new(SidecarServices::new()));
+        let sidecar_listen = try!(SocketAddrV4::from_str(&format!("{}:{}",
+                                                                  &config.http_listen_ip(),
+                                                                  config.http_listen_port())));
+        let sidecar_actor = sidecar::Sidecar::start(sidecar_listen,
+                                                    gossip_server.member_list.clone(),
+                                                    gossip_server.rumor_list.clone(),
+                                                    gossip_server.detector.clone(),
+                                                    sidecar_services.clone());
+        let (exits_tx, exits) = mpsc::channel();
+        Ok(Manager {
+            config: config.clone(),
+            gossip_server: gossip_server,
+            sidecar_actor: sidecar_actor,
+            sidecar_services: sidecar_services,
+            specs_path: specs_path(),
+            services: HashMap::new(),
+            failed: HashMap::new(),
+            exits_tx: exits_tx,
+            exits: exits,
+        })
+    }
+
+    /// Runs `primary`, and any service with a spec, until we are told to stop or `primary`
+    /// finishes. Every service is stopped before we return.
+    ///
+    /// # Failures
+    ///
+    /// * `primary` fails
+    pub fn run(&mut self, primary: ServiceSpec) -> Result<()> {
+        let handler = wonder::actor::Builder::new(SignalNotifier)
+            .name("signal-handler".to_string())
+            .start(())
+            .unwrap();
+        let result = match self.start_service(primary, false) {
+            Ok(()) => self.watch(&handler),
+            Err(e) => Err(e),
+        };
+        self.stop_all();
+        result
+    }
+
+    fn watch(&mut self, handler: &wonder::actor::Actor<signals::Message>) -> Result<()> {
+        loop {
+            match handler.receiver.try_recv() {
+                Ok(wonder::actor::Message::Cast(signals::Message::Signal(sig))) => {
+                    debug!("SIG = {:?}", sig);
+                    match sig {
+                        signals::Signal::SIGINT | signals::Signal::SIGTERM => return Ok(()),
+                        _ => {
+                            for service in self.services.values() {
+                                let _ = service.commands.send(ServiceCommand::Signal(sig.clone()));
+                            }
+                        }
+                    }
+                }
+                Ok(_) => {}
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => {
+                    panic!("signal handler crashed!");
+                }
+            }
+
+            loop {
+                let service_group = match self.exits.try_recv() {
+                    Ok(service_group) => service_group,
+                    Err(_) => break,
+                };
+                if let Some((spec, from_spec, result)) = self.join(&service_group) {
+                    if !from_spec {
+                        return result;
+                    }
+                    if let Err(e) = result {
+                        outputln!("{} failed: {}", service_group, e);
+                        outputln!("Not restarting {} until its spec changes", service_group);
+                        self.failed.insert(service_group, spec);
+                    }
+                }
+            }
+
+            self.load_specs();
+            thread::sleep(Duration::from_millis(LOOP_INTERVAL_MS));
+        }
+    }
+
+    /// Starts, stops and restarts services to match the specs in our specs directory.
+    fn load_specs(&mut self) {
+        let specs = self.read_specs();
+
+        let mut stopping = vec![];
+        for (service_group, service) in self.services.iter() {
+            if !service.from_spec || service.stopping {
+                continue;
+            }
+            match specs.get(service_group) {
+                Some(spec) if *spec == service.spec => {}
+                Some(_) => {
+                    outputln!("The spec for {} changed; restarting it", service_group);
+                    stopping.push(service_group.clone());
+                }
+                None => {
+                    outputln!("Unloading {}", service_group);
+                    stopping.push(service_group.clone());
+                }
+            }
+        }
+        for service_group in stopping.iter() {
+            self.stop_service(service_group);
+        }
+
+        let retry: Vec<String> = self.failed
+            .iter()
+            .filter(|&(sg, spec)| specs.get(sg) != Some(spec))
+            .map(|(sg, _)| sg.clone())
+            .collect();
+        for service_group in retry.iter() {
+            self.failed.remove(service_group);
+        }
+
+        for (service_group, spec) in specs.into_iter() {
+            if self.services.contains_key(&service_group) ||
+               self.failed.contains_key(&service_group) {
+                continue;
+            }
+            outputln!("Loading {}", service_group);
+            if let Err(e) = self.start_service(spec.clone(), true) {
+                outputln!("Failed to load {}: {}", service_group, e);
+                self.failed.insert(service_group, spec);
+            }
+        }
+    }
+
+    /// Reads every spec in our specs directory, by service group. A spec we can't read is left
+    /// out, and so unloads its service should it be running.
+    fn read_specs(&self) -> HashMap<String, ServiceSpec> {
+        let mut specs = HashMap::new();
+        let entries = match fs::read_dir(&self.specs_path) {
+            Ok(entries) => entries,
+            Err(_) => return specs,
+        };
+        for entry in entries {
+            let path = match entry {
+                Ok(entry) => entry.path(),
+                Err(_) => continue,
+            };
+            if path.extension().and_then(|e| e.to_str()) != Some(SPEC_FILE_EXT) {
+                continue;
+            }
+            match read_spec(&path) {
+                Ok(spec) => {
+                    specs.insert(spec.service_group(), spec);
+                }
+                Err(e) => debug!("Skipping the spec at {}: {}", path.display(), e),
+            }
+        }
+        specs
+    }
+
+    /// Starts a service on a thread of its own.
+    fn start_service(&mut self, spec: ServiceSpec, from_spec: bool) -> Result<()> {
+        let service_group = spec.service_group();
+        let config = spec.config_for(&self.config);
+        let (commands_tx, commands) = mpsc::channel();
+        let host = Host {
+            gossip_server: self.gossip_server.clone(),
+            sidecar_services: self.sidecar_services.clone(),
+            commands: commands,
+        };
+        let notice = ExitNotice {
+            service_group: service_group.clone(),
+            exits: self.exits_tx.clone(),
+        };
+        let thread = try!(thread::Builder::new()
+            .name(format!("service-{}", service_group))
+            .spawn(move || -> Result<()> {
+                let _notice = notice;
+                start::service(&config, host)
+            }));
+        self.services.insert(service_group,
+                             RunningService {
+                                 spec: spec,
+                                 from_spec: from_spec,
+                                 stopping: false,
+                                 commands: commands_tx,
+                                 thread: thread,
+                             });
+        Ok(())
+    }
+
+    /// Tells a service to stop; it is joined once it has.
+    fn stop_service(&mut self, service_group: &str) {
+        if let Some(service) = self.services.get_mut(service_group) {
+            let _ = service.commands.send(ServiceCommand::Stop);
+            service.stopping = true;
+        }
+    }
+
+    /// Joins the thread of a finished service, returning its spec, whether it was loaded from a
+    /// spec, and how it finished.
+    fn join(&mut self, service_group: &str) -> Option<(ServiceSpec, bool, Result<()>)> {
+        let service = match self.services.remove(service_group) {
+            Some(service) => service,
+            None => return None,
+        };
+        let result = match service.thread.join() {
+            Ok(result) => result,
+            Err(_) => Err(sup_error!(Error::ServicePanicked(service_group.to_string()))),
+        };
+        Some((service.spec, service.from_spec, result))
+    }
+
+    /// Stops every service, and waits for them to finish.
+    fn stop_all(&mut self) {
+        for service in self.services.values() {
+            let _ = service.commands.send(ServiceCommand::Stop);
+        }
+        let service_groups: Vec<String> = self.services.keys().cloned().collect();
+        for service_group in service_groups.iter() {
+            if let Some((_, _, Err(e))) = self.join(service_group) {
+                outputln!("{} failed while stopping: {}", service_group, e);
+            }
+        }
+    }
+}
+
+/// Reads the spec at `path`.
+pub fn read_spec(path: &Path) -> Result<ServiceSpec> {
+    let mut file = try!(File::open(path));
+    let mut body = String::new();
+    try!(file.read_to_string(&mut body));
+    ServiceSpec::from_toml_str(&body)
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use hcore::package::PackageIdent;
+
+    use config::{Config, UpdateStrategy};
+    use topology::Topology;
+    use super::ServiceSpec;
+
+    fn spec() -> ServiceSpec {
+        ServiceSpec {
+            ident: PackageIdent::from_str("core/redis").unwrap(),
+            group: "production".to_string(),
+            topology: Topology::Leader,
+            update_strategy: UpdateStrategy::AtOnce,
+            bind: vec!["db:postgres.default".to_string()],
+            organization: None,
+            url: Some("http://depot.example.com/v1/depot".to_string()),
+        }
+    }
+
+    #[test]
+    fn spec_round_trips_through_toml() {
+        let spec = spec();
+        assert_eq!(ServiceSpec::from_toml_str(&spec.to_toml_string()).unwrap(), spec);
+        assert_eq!(spec.service_group(), "redis.production");
+        assert_eq!(spec.file_name(), "redis.production.spec");
+    }
+
+    #[test]
+    fn spec_needs_an_ident_and_group() {
+        assert!(ServiceSpec::from_toml_str("group = \"default\"").is_err());
+        assert!(ServiceSpec::from_toml_str("ident = \"core/redis\"").is_err());
+        let spec = ServiceSpec::from_toml_str("ident = \"core/redis\"\ngroup = \"default\"")
+            .unwrap();
+        assert_eq!(spec.topology, Topology::Standalone);
+        assert_eq!(spec.update_strategy, UpdateStrategy::None);
+        assert!(spec.bind.is_empty());
+    }
+
+    #[test]
+    fn config_for_keeps_the_supervisors_settings() {
+        let mut supervisor = Config::new();
+        supervisor.set_gossip_listen_port(9634)
+            .set_http_listen_port(9631)
+            .set_organization("acme".to_string());
+        let config = spec().config_for(&supervisor);
+        assert_eq!(config.gossip_listen_port(), 9634);
+        assert_eq!(config.http_listen_port(), 9631);
+        assert_eq!(config.package().name, "redis");
+        assert_eq!(config.group(), "production");
+        assert_eq!(*config.topology(), Topology::Leader);
+        assert_eq!(config.organization().as_ref().unwrap(), "acme");
+    }
+}
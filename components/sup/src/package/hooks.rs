@@ -54,21 +54,32 @@ pub struct Hook {
     pub htype: HookType,
     pub template: PathBuf,
     pub path: PathBuf,
+    /// The PATH the hook runs with; the package's run path, as every service a Supervisor hosts
+    /// needs its own.
+    pub run_path: Option<String>,
 }
 
 impl Hook {
-    pub fn new(htype: HookType, template: PathBuf, path: PathBuf) -> Self {
+    pub fn new(htype: HookType,
+               template: PathBuf,
+               path: PathBuf,
+               run_path: Option<String>)
+               -> Self {
         Hook {
             htype: htype,
             template: template,
             path: path,
+            run_path: run_path,
         }
     }
 
     pub fn run(&self, context: Option<&ServiceConfig>) -> Result<String> {
         try!(self.compile(context));
-        let mut child = try!(Command::new(&self.path)
-            .stdin(Stdio::null())
+        let mut command = Command::new(&self.path);
+        if let Some(ref run_path) = self.run_path {
+            command.env("PATH", run_path);
+        }
+        let mut child = try!(command.stdin(Stdio::null())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .spawn());
@@ -164,11 +175,12 @@ impl<'a> HookTable<'a> {
         match fs::metadata(path) {
             Ok(meta) => {
                 if meta.is_dir() {
-                    self.init_hook = self.load_hook(HookType::Init);
-                    self.file_updated_hook = self.load_hook(HookType::FileUpdated);
-                    self.reconfigure_hook = self.load_hook(HookType::Reconfigure);
-                    self.health_check_hook = self.load_hook(HookType::HealthCheck);
-                    self.run_hook = self.load_hook(HookType::Run);
+                    let run_path = self.package.run_path().ok();
+                    self.init_hook = self.load_hook(HookType::Init, &run_path);
+                    self.file_updated_hook = self.load_hook(HookType::FileUpdated, &run_path);
+                    self.reconfigure_hook = self.load_hook(HookType::Reconfigure, &run_path);
+                    self.health_check_hook = self.load_hook(HookType::HealthCheck, &run_path);
+                    self.run_hook = self.load_hook(HookType::Run, &run_path);
                 }
             }
             Err(_) => {}
@@ -176,11 +188,11 @@ impl<'a> HookTable<'a> {
         self
     }
 
-    fn load_hook(&self, hook_type: HookType) -> Option<Hook> {
+    fn load_hook(&self, hook_type: HookType, run_path: &Option<String>) -> Option<Hook> {
         let template = self.package.hook_template_path(&hook_type);
         let concrete = self.package.hook_path(&hook_type);
         match fs::metadata(&template) {
-            Ok(_) => Some(Hook::new(hook_type, template, concrete)),
+            Ok(_) => Some(Hook::new(hook_type, template, concrete, run_path.clone())),
             Err(_) => None,
         }
     }
@@ -21,7 +21,7 @@ use hcore::crypto::default_cache_key_path;
 use hcore::fs::{CACHE_ARTIFACT_PATH, FS_ROOT_PATH};
 use hcore::package::PackageIdent;
 use wonder;
-use wonder::actor::{GenServer, InitResult, HandleResult, ActorSender, ActorResult};
+use wonder::actor::{GenServer, InitResult, HandleResult, ActorSender, ActorResult, StopReason};
 
 use error::SupError;
 use package::Package;
@@ -47,6 +47,11 @@ impl PackageUpdater {
     pub fn run(actor: &PackageUpdaterActor) -> ActorResult<()> {
         actor.cast(UpdaterMessage::Run)
     }
+
+    /// Stop a package updater for good, as the service it updates is being unloaded.
+    pub fn stop(actor: &PackageUpdaterActor) -> ActorResult<()> {
+        actor.cast(UpdaterMessage::Stop)
+    }
 }
 
 pub struct UpdaterState {
@@ -150,6 +155,7 @@ impl GenServer for PackageUpdater {
                    -> HandleResult<Self::T> {
         match msg {
             UpdaterMessage::Run => HandleResult::NoReply(Some(TIMEOUT_MS)),
+            UpdaterMessage::Stop => HandleResult::Stop(StopReason::Normal, None),
             _ => {
                 match state.status {
                     UpdaterStatus::Running => HandleResult::NoReply(Some(TIMEOUT_MS)),
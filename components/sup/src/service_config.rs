@@ -25,6 +25,7 @@ use rustc_serialize::Encodable;
 use toml;
 use handlebars::{Handlebars, JsonRender};
 
+use common::config_patch::{ConfigPatch, GOSSIP_PATCH_TOML};
 use common::gossip_file::GOSSIP_TOML;
 use census::{Census, CensusList};
 use config::Config;
@@ -332,6 +333,7 @@ impl Cfg {
         try!(cfg.load_default(pkg));
         try!(cfg.load_user(pkg));
         try!(cfg.load_gossip(pkg));
+        try!(cfg.load_gossip_patch(pkg));
         try!(cfg.load_environment(pkg));
         Ok(cfg)
     }
@@ -429,6 +431,33 @@ impl Cfg {
         Ok(())
     }
 
+    // Configuration patches are applied on top of the gossiped configuration, so a key set by a
+    // patch survives a later `hab config apply` of the whole document until it is unset.
+    fn load_gossip_patch(&mut self, pkg: &Package) -> Result<()> {
+        let mut file = match File::open(pkg.svc_path().join(GOSSIP_PATCH_TOML)) {
+            Ok(file) => file,
+            Err(e) => {
+                debug!("Failed to open {}: {}", GOSSIP_PATCH_TOML, e);
+                return Ok(());
+            }
+        };
+        let mut config = String::new();
+        match file.read_to_string(&mut config) {
+            Ok(_) => {
+                let patch = try!(ConfigPatch::from_toml_str(&config));
+                let gossip = match self.gossip {
+                    Some(toml::Value::Table(ref table)) => patch.apply(table),
+                    _ => patch.apply(&toml::Table::new()),
+                };
+                self.gossip = Some(toml::Value::Table(gossip));
+            }
+            Err(e) => {
+                outputln!("Failed to load {}: {}", GOSSIP_PATCH_TOML, e);
+            }
+        }
+        Ok(())
+    }
+
     fn load_environment(&mut self, pkg: &Package) -> Result<()> {
         let var_name = format!("{}_{}", ENV_VAR_PREFIX, pkg.name)
             .to_ascii_uppercase()
@@ -713,4 +742,5 @@ mod test {
             assert_eq!(version, VERSION);
         }
     }
+
 }
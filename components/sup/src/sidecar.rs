@@ -18,10 +18,19 @@
 //! Supports:
 //!
 //! * /config: Returns the current configuration of the service
+//! * /config/history: Returns the configuration versions applied to the service group
+//! * /config/history/:version: Returns the document of an applied configuration version
+//! * /files: Returns the gossiped files held for the service group
+//! * /files/:file: Returns the contents of a gossiped file, encrypted if it was uploaded encrypted
 //! * /health: Returns the current health of the service
 //! * /status: Returns the current status of the service, from the supervisors point of view
+//! * /services: Returns the service groups of the services this Supervisor hosts
+//!
+//! A Supervisor can host many services. Each endpoint above answers for the first service it
+//! started, and for any other service when prefixed with `/services/:service_group` (ex:
+//! `/services/redis.default/health`).
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::net::SocketAddrV4;
 use std::sync::{Arc, RwLock};
 
@@ -40,6 +49,7 @@ use service_config::ServiceConfig;
 use gossip::member::{MemberList, MemberId};
 use gossip::rumor::RumorList;
 use gossip::detector::Detector;
+use gossip::server::ServiceLists;
 use census::{CensusList, CensusEntry, CensusEntryId, Census};
 use election::{Election, ElectionList};
 use supervisor::Supervisor;
@@ -47,29 +57,80 @@ use supervisor::Supervisor;
 static LOGKEY: &'static str = "SI";
 const GET_HEALTH: &'static str = "/health";
 const GET_CONFIG: &'static str = "/config";
+const GET_CONFIG_HISTORY: &'static str = "/config/history";
+const GET_CONFIG_REVISION: &'static str = "/config/history/:version";
 const GET_STATUS: &'static str = "/status";
 const GET_GOSSIP: &'static str = "/gossip";
 const GET_CENSUS: &'static str = "/census";
 const GET_ELECTION: &'static str = "/election";
+const GET_FILES: &'static str = "/files";
+const GET_FILE: &'static str = "/files/:file";
+const GET_SERVICES: &'static str = "/services";
+const SERVICE_PREFIX: &'static str = "/services/:service_group";
 
 pub type SidecarActor = wonder::actor::Actor<SidecarMessage>;
 
 pub struct Sidecar;
 
-pub struct SidecarState {
-    /// The IP:Port where the sidecar listens
-    pub listen: SocketAddrV4,
+/// What the sidecar needs to answer for one of the services we host.
+#[derive(Clone)]
+pub struct SidecarService {
     /// The package this sidecar is helping out
     pub package: Arc<RwLock<Package>>,
     /// The configuration of the supervised service
     pub config: Arc<RwLock<ServiceConfig>>,
+    pub supervisor: Arc<RwLock<Supervisor>>,
+    pub lists: ServiceLists,
+}
+
+/// The services the sidecar answers for, by `service.group`.
+pub struct SidecarServices {
+    /// The service answering requests which don't name a service group
+    primary: Option<String>,
+    services: BTreeMap<String, SidecarService>,
+}
+
+impl SidecarServices {
+    pub fn new() -> SidecarServices {
+        SidecarServices {
+            primary: None,
+            services: BTreeMap::new(),
+        }
+    }
+
+    /// Adds a service. The first service added answers requests which don't name a service
+    /// group.
+    pub fn insert(&mut self, service_group: String, service: SidecarService) {
+        if self.primary.is_none() {
+            self.primary = Some(service_group.clone());
+        }
+        self.services.insert(service_group, service);
+    }
+
+    pub fn remove(&mut self, service_group: &str) {
+        self.services.remove(service_group);
+        if self.primary.as_ref().map(|p| &p[..]) == Some(service_group) {
+            self.primary = self.services.keys().next().cloned();
+        }
+    }
+
+    /// The service for `service_group`, or the primary service if none is named.
+    pub fn get(&self, service_group: Option<&str>) -> Option<&SidecarService> {
+        match service_group.or(self.primary.as_ref().map(|p| &p[..])) {
+            Some(sg) => self.services.get(sg),
+            None => None,
+        }
+    }
+}
+
+pub struct SidecarState {
+    /// The IP:Port where the sidecar listens
+    pub listen: SocketAddrV4,
     pub member_list: Arc<RwLock<MemberList>>,
     pub rumor_list: Arc<RwLock<RumorList>>,
-    pub census_list: Arc<RwLock<CensusList>>,
     pub detector: Arc<RwLock<Detector>>,
-    pub election_list: Arc<RwLock<ElectionList>>,
-    pub supervisor: Arc<RwLock<Supervisor>>,
-    pub gossip_file_list: Arc<RwLock<GossipFileList>>,
+    /// The services we host
+    pub services: Arc<RwLock<SidecarServices>>,
 }
 
 #[derive(Debug)]
@@ -80,27 +141,17 @@ pub enum SidecarMessage {
 
 impl SidecarState {
     pub fn new(listen: SocketAddrV4,
-               package: Arc<RwLock<Package>>,
-               config: Arc<RwLock<ServiceConfig>>,
                member_list: Arc<RwLock<MemberList>>,
                rumor_list: Arc<RwLock<RumorList>>,
-               census_list: Arc<RwLock<CensusList>>,
                detector: Arc<RwLock<Detector>>,
-               election_list: Arc<RwLock<ElectionList>>,
-               supervisor: Arc<RwLock<Supervisor>>,
-               gossip_file_list: Arc<RwLock<GossipFileList>>)
+               services: Arc<RwLock<SidecarServices>>)
                -> Self {
         SidecarState {
             listen: listen,
-            package: package,
-            config: config,
             member_list: member_list,
             rumor_list: rumor_list,
-            census_list: census_list,
             detector: detector,
-            election_list: election_list,
-            supervisor: supervisor,
-            gossip_file_list: gossip_file_list,
+            services: services,
         }
     }
 }
@@ -108,26 +159,12 @@ impl SidecarState {
 impl Sidecar {
     /// Start the sidecar.
     pub fn start(listen: SocketAddrV4,
-                 package: Arc<RwLock<Package>>,
-                 config: Arc<RwLock<ServiceConfig>>,
                  member_list: Arc<RwLock<MemberList>>,
                  rumor_list: Arc<RwLock<RumorList>>,
-                 census_list: Arc<RwLock<CensusList>>,
                  detector: Arc<RwLock<Detector>>,
-                 election_list: Arc<RwLock<ElectionList>>,
-                 supervisor: Arc<RwLock<Supervisor>>,
-                 gossip_file_list: Arc<RwLock<GossipFileList>>)
+                 services: Arc<RwLock<SidecarServices>>)
                  -> SidecarActor {
-        let state = SidecarState::new(listen,
-                                      package,
-                                      config,
-                                      member_list,
-                                      rumor_list,
-                                      census_list,
-                                      detector,
-                                      election_list,
-                                      supervisor,
-                                      gossip_file_list);
+        let state = SidecarState::new(listen, member_list, rumor_list, detector, services);
         wonder::actor::Builder::new(Sidecar).name("sidecar".to_string()).start(state).unwrap()
     }
 }
@@ -147,18 +184,21 @@ impl GenServer for Sidecar {
                       state: &mut Self::S)
                       -> HandleResult<Self::T> {
         let mut router = Router::new();
-        let package_1 = state.package.clone();
-        let package_2 = state.package.clone();
-        let config_1 = state.config.clone();
-
-        router.get(GET_CONFIG, move |r: &mut Request| config(&package_1, r));
 
-        let supervisor_1 = state.supervisor.clone();
-        router.get(GET_STATUS, move |r: &mut Request| status(&supervisor_1, r));
-
-        let supervisor_2 = state.supervisor.clone();
-        router.get(GET_HEALTH,
-                   move |r: &mut Request| health(&package_2, &config_1, &supervisor_2, r));
+        route(&mut router,
+              GET_CONFIG,
+              &state.services,
+              |s: &SidecarService, r: &mut Request| config(&s.package, r));
+        route(&mut router,
+              GET_STATUS,
+              &state.services,
+              |s: &SidecarService, r: &mut Request| status(&s.supervisor, r));
+        route(&mut router,
+              GET_HEALTH,
+              &state.services,
+              |s: &SidecarService, r: &mut Request| {
+                  health(&s.package, &s.config, &s.supervisor, r)
+              });
 
         let ml = state.member_list.clone();
         let rl = state.rumor_list.clone();
@@ -166,16 +206,44 @@ impl GenServer for Sidecar {
         let id = {
             Arc::new(ml.read().unwrap().my_id.clone())
         };
-        let gfl = state.gossip_file_list.clone();
-
-        router.get(GET_GOSSIP,
-                   move |r: &mut Request| gossip(&ml, &rl, &gfl, &detector, &id, r));
-
-        let cl1 = state.census_list.clone();
-        router.get(GET_CENSUS, move |r: &mut Request| census(&cl1, r));
-
-        let el = state.election_list.clone();
-        router.get(GET_ELECTION, move |r: &mut Request| election(&el, r));
+        route(&mut router,
+              GET_GOSSIP,
+              &state.services,
+              move |s: &SidecarService, r: &mut Request| {
+                  gossip(&ml, &rl, &s.lists.gossip_file_list, &detector, &id, r)
+              });
+
+        route(&mut router,
+              GET_CENSUS,
+              &state.services,
+              |s: &SidecarService, r: &mut Request| census(&s.lists.census_list, r));
+        route(&mut router,
+              GET_ELECTION,
+              &state.services,
+              |s: &SidecarService, r: &mut Request| election(&s.lists.election_list, r));
+        route(&mut router,
+              GET_FILES,
+              &state.services,
+              |s: &SidecarService, r: &mut Request| files(&s.lists.gossip_file_list, r));
+        route(&mut router,
+              GET_FILE,
+              &state.services,
+              |s: &SidecarService, r: &mut Request| file(&s.lists.gossip_file_list, r));
+        route(&mut router,
+              GET_CONFIG_HISTORY,
+              &state.services,
+              |s: &SidecarService, r: &mut Request| {
+                  config_history(&s.lists.gossip_file_list, r)
+              });
+        route(&mut router,
+              GET_CONFIG_REVISION,
+              &state.services,
+              |s: &SidecarService, r: &mut Request| {
+                  config_revision(&s.lists.gossip_file_list, r)
+              });
+
+        let services = state.services.clone();
+        router.get(GET_SERVICES, move |r: &mut Request| list_services(&services, r));
 
         match Iron::new(router).http(state.listen) {
             Ok(_) => HandleResult::NoReply(None),
@@ -186,6 +254,74 @@ impl GenServer for Sidecar {
     }
 }
 
+/// Routes `path`, and `path` under `/services/:service_group`, to `handler` with the service the
+/// request is for.
+fn route<F>(router: &mut Router,
+            path: &str,
+            services: &Arc<RwLock<SidecarServices>>,
+            handler: F)
+    where F: Fn(&SidecarService, &mut Request) -> IronResult<Response> + Send + Sync + 'static
+{
+    let handler = Arc::new(handler);
+    let handler_1 = handler.clone();
+    let services_1 = services.clone();
+    router.get(path, move |r: &mut Request| dispatch(&services_1, &*handler_1, r));
+    let services_2 = services.clone();
+    let scoped_path = format!("{}{}", SERVICE_PREFIX, path);
+    router.get(&scoped_path[..],
+               move |r: &mut Request| dispatch(&services_2, &*handler, r));
+}
+
+/// Finds the service a request is for, and hands the request to `handler`. Responds with a 404 if
+/// we don't host the service.
+fn dispatch<F>(services: &Arc<RwLock<SidecarServices>>,
+               handler: &F,
+               req: &mut Request)
+               -> IronResult<Response>
+    where F: Fn(&SidecarService, &mut Request) -> IronResult<Response>
+{
+    let service = {
+        let service_group = req.extensions
+            .get::<Router>()
+            .unwrap()
+            .find("service_group")
+            .map(|sg| sg.to_string());
+        let services = services.read().unwrap();
+        services.get(service_group.as_ref().map(|sg| &sg[..])).cloned()
+    };
+    match service {
+        Some(service) => handler(&service, req),
+        None => Ok(Response::with(status::NotFound)),
+    }
+}
+
+#[derive(Debug, RustcEncodable)]
+struct ServicesResponse<'a> {
+    primary: Option<&'a str>,
+    services: Vec<&'a str>,
+}
+
+/// The /services callback.
+///
+/// Returns the service group of each service we host, and which of them answers requests that
+/// don't name one.
+fn list_services(services: &Arc<RwLock<SidecarServices>>,
+                 _req: &mut Request)
+                 -> IronResult<Response> {
+    let services = services.read().unwrap();
+    let response = ServicesResponse {
+        primary: services.primary.as_ref().map(|p| &p[..]),
+        services: services.services.keys().map(|sg| &sg[..]).collect(),
+    };
+
+    let json_response = match json::encode(&response) {
+        Ok(json_response) => json_response,
+        Err(e) => return Err(IronError::from(sup_error!(Error::JsonEncode(e)))),
+    };
+
+    Ok(Response::with((status::Ok, json_response)))
+}
+
 #[derive(Debug, RustcEncodable)]
 struct ElectionResponse<'a> {
     elections: &'a HashMap<String, Election>,
@@ -281,6 +417,154 @@ fn census(census_list: &Arc<RwLock<CensusList>>, _req: &mut Request) -> IronResu
     Ok(Response::with((status::Ok, json_response)))
 }
 
+#[derive(Debug, RustcEncodable)]
+struct FileResponse<'a> {
+    file_name: &'a str,
+    version_number: u64,
+    checksum: &'a str,
+    encrypted: bool,
+}
+
+#[derive(Debug, RustcEncodable)]
+struct FilesResponse<'a> {
+    service_group: String,
+    files: Vec<FileResponse<'a>>,
+}
+
+/// The /files callback.
+///
+/// Returns the name, version, checksum and encryption of each gossiped file held for the service
+/// group, without their contents.
+fn files(gossip_file_list: &Arc<RwLock<GossipFileList>>,
+         _req: &mut Request)
+         -> IronResult<Response> {
+    let gfl = gossip_file_list.read().unwrap();
+    let response = FilesResponse {
+        service_group: gfl.service_group().to_string(),
+        files: gfl.files()
+            .into_iter()
+            .map(|gf| {
+                FileResponse {
+                    file_name: &gf.file_name,
+                    version_number: gf.version_number(),
+                    checksum: gf.checksum(),
+                    encrypted: gf.is_encrypted(),
+                }
+            })
+            .collect(),
+    };
+
+    let json_response = match json::encode(&response) {
+        Ok(json_response) => json_response,
+        Err(e) => return Err(IronError::from(sup_error!(Error::JsonEncode(e)))),
+    };
+
+    Ok(Response::with((status::Ok, json_response)))
+}
+
+/// The /files/:file callback.
+///
+/// Returns the contents of a gossiped file held for the service group, as they were gossiped, so
+/// an encrypted file stays encrypted. Its version, checksum and encryption are given in the
+/// `X-Version-Number`, `X-Checksum` and `X-Encrypted` headers.
+fn file(gossip_file_list: &Arc<RwLock<GossipFileList>>,
+        req: &mut Request)
+        -> IronResult<Response> {
+    let file_name = match req.extensions.get::<Router>().unwrap().find("file") {
+        Some(file_name) => file_name.to_string(),
+        None => return Ok(Response::with(status::BadRequest)),
+    };
+    let gfl = gossip_file_list.read().unwrap();
+    let gf = match gfl.get(&file_name) {
+        Some(gf) => gf,
+        None => return Ok(Response::with(status::NotFound)),
+    };
+    let mut response = Response::with((status::Ok, gf.body().to_vec()));
+    response.headers.set_raw("X-Version-Number",
+                             vec![gf.version_number().to_string().into_bytes()]);
+    response.headers.set_raw("X-Checksum", vec![gf.checksum().to_string().into_bytes()]);
+    response.headers.set_raw("X-Encrypted", vec![gf.is_encrypted().to_string().into_bytes()]);
+    Ok(response)
+}
+
+#[derive(Debug, RustcEncodable)]
+struct RevisionResponse<'a> {
+    version_number: u64,
+    file_name: &'a str,
+    checksum: &'a str,
+    signer: Option<&'a str>,
+    applied_at: &'a str,
+    encrypted: bool,
+}
+
+#[derive(Debug, RustcEncodable)]
+struct HistoryResponse<'a> {
+    service_group: String,
+    revisions: Vec<RevisionResponse<'a>>,
+}
+
+/// The /config/history callback.
+///
+/// Returns the version, file name, checksum, signer and time applied of each configuration
+/// version this Supervisor remembers for the service group, newest first, without their
+/// documents.
+fn config_history(gossip_file_list: &Arc<RwLock<GossipFileList>>,
+                  _req: &mut Request)
+                  -> IronResult<Response> {
+    let gfl = gossip_file_list.read().unwrap();
+    let response = HistoryResponse {
+        service_group: gfl.service_group().to_string(),
+        revisions: gfl.history()
+            .into_iter()
+            .map(|r| {
+                RevisionResponse {
+                    version_number: r.version_number,
+                    file_name: &r.file_name,
+                    checksum: &r.checksum,
+                    signer: r.signer.as_ref().map(|s| &s[..]),
+                    applied_at: &r.applied_at,
+                    encrypted: r.encrypted,
+                }
+            })
+            .collect(),
+    };
+
+    let json_response = match json::encode(&response) {
+        Ok(json_response) => json_response,
+        Err(e) => return Err(IronError::from(sup_error!(Error::JsonEncode(e)))),
+    };
+
+    Ok(Response::with((status::Ok, json_response)))
+}
+
+/// The /config/history/:version callback.
+///
+/// Returns the document of a configuration version as it was gossiped, with its file name,
+/// checksum and encryption in the `X-File-Name`, `X-Checksum` and `X-Encrypted` headers.
+fn config_revision(gossip_file_list: &Arc<RwLock<GossipFileList>>,
+                   req: &mut Request)
+                   -> IronResult<Response> {
+    let version = match req.extensions
+        .get::<Router>()
+        .unwrap()
+        .find("version")
+        .and_then(|v| v.parse::<u64>().ok()) {
+        Some(version) => version,
+        None => return Ok(Response::with(status::BadRequest)),
+    };
+    let gfl = gossip_file_list.read().unwrap();
+    let revision = match gfl.revision(version) {
+        Some(revision) => revision,
+        None => return Ok(Response::with(status::NotFound)),
+    };
+    let mut response = Response::with((status::Ok, revision.body.clone()));
+    response.headers.set_raw("X-File-Name", vec![revision.file_name.clone().into_bytes()]);
+    response.headers.set_raw("X-Checksum", vec![revision.checksum.clone().into_bytes()]);
+    response.headers.set_raw("X-Encrypted",
+                             vec![revision.encrypted.to_string().into_bytes()]);
+    Ok(response)
+}
+
 /// The /config callback.
 ///
 /// Returns the current running configuration.
@@ -348,3 +632,229 @@ impl From<SupError> for IronError {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::fs::File;
+    use std::io::{Read, Write};
+    use std::path::{Path, PathBuf};
+    use std::str::{self, FromStr};
+    use std::sync::{Arc, RwLock};
+
+    use common::gossip_file::{sha256sum, GossipFile, GossipFileList};
+    use hcore::crypto::BoxKeyPair;
+    use hcore::package::{PackageIdent, PackageInstall};
+    use hcore::service::ServiceGroup;
+    use http_client::{header, testing};
+    use hyper::client::{Client, Response};
+    use hyper::status::StatusCode;
+    use iron::{Iron, Request};
+    use router::Router;
+    use rustc_serialize::json::Json;
+    use tempdir::TempDir;
+
+    use census::{Census, CensusEntry, CensusList};
+    use config::Config;
+    use election::ElectionList;
+    use gossip::member::MemberId;
+    use gossip::server::ServiceLists;
+    use package::Package;
+    use service_config::ServiceConfig;
+    use supervisor::Supervisor;
+    use super::{file, files, route, SidecarService, SidecarServices, GET_FILE, GET_FILES};
+
+    const CONFIG: &'static [u8] = b"port = 6379\n";
+
+    /// Returns the files gossiped to redis.default@acme: a plain configuration and a file
+    /// encrypted for the service with keys in the key cache, along with a configuration
+    /// gossiped to another service group.
+    fn gossip_file_list(cache: &Path) -> GossipFileList {
+        let sg = ServiceGroup::from_str("redis.default@acme").unwrap();
+        let mut gfl = GossipFileList::new(sg.clone());
+        gfl.process(GossipFile::from_body(sg, CONFIG.to_vec(), 2).unwrap());
+        let other = ServiceGroup::from_str("nginx.default").unwrap();
+        gfl.process(GossipFile::from_body(other, b"workers = 2\n".to_vec(), 7).unwrap());
+
+        let user_pair = BoxKeyPair::generate_pair_for_user("ruth", cache).unwrap();
+        let service_pair = BoxKeyPair::generate_pair_for_service("acme", "redis.default", cache)
+            .unwrap();
+        let path = cache.join("redis.conf");
+        File::create(&path).unwrap().write_all(CONFIG).unwrap();
+        gfl.process(GossipFile::from_file_encrypt(&user_pair, &service_pair, &path, 5).unwrap());
+        gfl
+    }
+
+    /// Serves the files endpoints of a sidecar, returning its URL.
+    fn serve(gfl: GossipFileList) -> String {
+        let gfl = Arc::new(RwLock::new(gfl));
+        let gfl_1 = gfl.clone();
+        let mut router = Router::new();
+        router.get(GET_FILES, move |r: &mut Request| files(&gfl, r));
+        router.get(GET_FILE, move |r: &mut Request| file(&gfl_1, r));
+        let listening = Iron::new(router).http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listening.socket);
+        testing::leave_running(listening);
+        url
+    }
+
+    /// Returns a service for `service.group`, whose gossip file list names its service group.
+    fn service(service_group: &str) -> SidecarService {
+        let sg = ServiceGroup::from_str(service_group).unwrap();
+        let ident = PackageIdent::from_str(&format!("core/{}/1.0.0/20160816000000", sg.service))
+            .unwrap();
+        let package = Package {
+            origin: ident.origin.clone(),
+            name: sg.service.clone(),
+            version: String::from("1.0.0"),
+            release: String::from("20160816000000"),
+            deps: Vec::new(),
+            tdeps: Vec::new(),
+            pkg_install: PackageInstall::new_from_parts(ident.clone(),
+                                                        PathBuf::from("/"),
+                                                        PathBuf::from("/fakeo"),
+                                                        PathBuf::from("/fakeo/here")),
+        };
+        let member_id = MemberId::new_v4();
+        let ce = CensusEntry::new(sg.service.clone(), sg.group.clone(), member_id.clone());
+        let cl = CensusList::new(Census::new(ce));
+        let config = ServiceConfig::new(&Config::default(), &package, &cl, Vec::new()).unwrap();
+        SidecarService {
+            package: Arc::new(RwLock::new(package)),
+            config: Arc::new(RwLock::new(config)),
+            supervisor: Arc::new(RwLock::new(Supervisor::new(ident, String::from("/tmp")))),
+            lists: ServiceLists {
+                census_list: Arc::new(RwLock::new(cl)),
+                election_list: Arc::new(RwLock::new(ElectionList::new(service_group.to_string(),
+                                                                      member_id))),
+                gossip_file_list: Arc::new(RwLock::new(GossipFileList::new(sg))),
+            },
+        }
+    }
+
+    fn service_group_of(service: Option<&SidecarService>) -> Option<String> {
+        service.map(|s| s.lists.gossip_file_list.read().unwrap().service_group().to_string())
+    }
+
+    fn get(url: &str, path: &str) -> (Response, Vec<u8>) {
+        let mut res = Client::new().get(&format!("{}{}", url, path)[..]).send().unwrap();
+        let mut body = Vec::new();
+        res.read_to_end(&mut body).unwrap();
+        (res, body)
+    }
+
+    #[test]
+    fn files_lists_the_files_of_the_service_group() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let url = serve(gossip_file_list(cache.path()));
+        let (res, body) = get(&url, "/files");
+        assert_eq!(res.status, StatusCode::Ok);
+        let json = Json::from_str(str::from_utf8(&body).unwrap()).unwrap();
+        assert_eq!(json.find("service_group").unwrap().as_string(),
+                   Some("redis.default@acme"));
+        let files = json.find("files").unwrap().as_array().unwrap();
+        let summary: Vec<(&str, u64, bool)> = files.iter()
+            .map(|f| {
+                (f.find("file_name").unwrap().as_string().unwrap(),
+                 f.find("version_number").unwrap().as_u64().unwrap(),
+                 f.find("encrypted").unwrap().as_boolean().unwrap())
+            })
+            .collect();
+        assert_eq!(summary,
+                   vec![("gossip.toml", 2, false), ("redis.conf", 5, true)]);
+        for f in files.iter() {
+            assert_eq!(f.find("checksum").unwrap().as_string(),
+                       Some(&sha256sum(CONFIG)[..]));
+            assert!(f.find("body").is_none());
+        }
+    }
+
+    #[test]
+    fn file_serves_a_plain_file() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let url = serve(gossip_file_list(cache.path()));
+        let (res, body) = get(&url, "/files/gossip.toml");
+        assert_eq!(res.status, StatusCode::Ok);
+        assert_eq!(body, CONFIG);
+        assert_eq!(header(&res, "X-Version-Number").unwrap(), "2");
+        assert_eq!(header(&res, "X-Checksum").unwrap(), sha256sum(CONFIG));
+        assert_eq!(header(&res, "X-Encrypted").unwrap(), "false");
+    }
+
+    #[test]
+    fn file_serves_an_encrypted_file_as_gossiped() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let url = serve(gossip_file_list(cache.path()));
+        let (res, body) = get(&url, "/files/redis.conf");
+        assert_eq!(res.status, StatusCode::Ok);
+        assert!(body != CONFIG);
+        assert_eq!(header(&res, "X-Version-Number").unwrap(), "5");
+        assert_eq!(header(&res, "X-Checksum").unwrap(), sha256sum(CONFIG));
+        assert_eq!(header(&res, "X-Encrypted").unwrap(), "true");
+        assert_eq!(BoxKeyPair::decrypt(&body, cache.path()).unwrap(), CONFIG);
+    }
+
+    #[test]
+    fn file_which_wasnt_gossiped_to_the_service_group() {
+        let cache = TempDir::new("key_cache").unwrap();
+        let url = serve(gossip_file_list(cache.path()));
+        let (res, _) = get(&url, "/files/nginx.conf");
+        assert_eq!(res.status, StatusCode::NotFound);
+    }
+
+    #[test]
+    fn sidecar_services_hand_primary_over_on_remove() {
+        let mut services = SidecarServices::new();
+        assert!(services.get(None).is_none());
+        services.insert("redis.default".to_string(), service("redis.default"));
+        services.insert("postgres.default".to_string(), service("postgres.default"));
+        services.insert("nginx.default".to_string(), service("nginx.default"));
+        assert_eq!(service_group_of(services.get(None)),
+                   Some("redis.default".to_string()));
+        assert_eq!(service_group_of(services.get(Some("nginx.default"))),
+                   Some("nginx.default".to_string()));
+
+        // Removing another service leaves the primary alone.
+        services.remove("postgres.default");
+        assert_eq!(service_group_of(services.get(None)),
+                   Some("redis.default".to_string()));
+
+        // Removing the primary hands over to the first service we still host.
+        services.remove("redis.default");
+        assert!(services.get(Some("redis.default")).is_none());
+        assert_eq!(service_group_of(services.get(None)),
+                   Some("nginx.default".to_string()));
+
+        services.remove("nginx.default");
+        assert!(services.get(None).is_none());
+    }
+
+    #[test]
+    fn dispatch_finds_the_service_a_request_is_for() {
+        let mut services = SidecarServices::new();
+        services.insert("redis.default".to_string(), service("redis.default"));
+        services.insert("nginx.default".to_string(), service("nginx.default"));
+        let services = Arc::new(RwLock::new(services));
+        let mut router = Router::new();
+        route(&mut router,
+              GET_FILES,
+              &services,
+              |s: &SidecarService, r: &mut Request| files(&s.lists.gossip_file_list, r));
+        let listening = Iron::new(router).http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listening.socket);
+        testing::leave_running(listening);
+
+        for &(path, service_group) in [("/files", "redis.default"),
+                                       ("/services/redis.default/files", "redis.default"),
+                                       ("/services/nginx.default/files", "nginx.default")]
+            .iter() {
+            let (res, body) = get(&url, path);
+            assert_eq!(res.status, StatusCode::Ok, "{}", path);
+            let json = Json::from_str(str::from_utf8(&body).unwrap()).unwrap();
+            assert_eq!(json.find("service_group").unwrap().as_string(),
+                       Some(service_group));
+        }
+
+        let (res, _) = get(&url, "/services/postgres.default/files");
+        assert_eq!(res.status, StatusCode::NotFound);
+    }
+}
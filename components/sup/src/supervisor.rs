@@ -34,7 +34,6 @@ use time::{Duration, SteadyTime};
 use error::{Result, Error};
 use util::signals;
 
-const PIDFILE_NAME: &'static str = "PID";
 static LOGKEY: &'static str = "SV";
 
 // Functions from POSIX libc.
@@ -122,16 +121,19 @@ impl fmt::Display for ProcessState {
 pub struct Supervisor {
     pub pid: Option<Pid>,
     pub package_ident: PackageIdent,
+    /// The PATH the service runs with; see `Package::run_path`
+    pub run_path: String,
     pub state: ProcessState,
     pub state_entered: SteadyTime,
     pub has_started: bool,
 }
 
 impl Supervisor {
-    pub fn new(package_ident: PackageIdent) -> Supervisor {
+    pub fn new(package_ident: PackageIdent, run_path: String) -> Supervisor {
         Supervisor {
             pid: None,
             package_ident: package_ident,
+            run_path: run_path,
             state: ProcessState::Down,
             state_entered: SteadyTime::now(),
             has_started: false,
@@ -160,6 +162,7 @@ impl Supervisor {
             outputln!(preamble & self.package_ident.name, "Starting");
             self.enter_state(ProcessState::Start);
             let mut child = try!(Command::new(self.run_cmd())
+                .env("PATH", &self.run_path)
                 .stdin(Stdio::null())
                 .stdout(Stdio::piped())
                 .stderr(Stdio::piped())
@@ -319,15 +322,23 @@ impl Supervisor {
     }
 
     pub fn pid_file(&self) -> PathBuf {
-        self.service_dir().join(PIDFILE_NAME)
+        self.service_dir().join(hcore::fs::SVC_PID_FILE)
     }
 
-    /// Create a pid file for a package
+    pub fn ident_file(&self) -> PathBuf {
+        self.service_dir().join(hcore::fs::SVC_IDENT_FILE)
+    }
+
+    /// Create a pid file for a package, along with a file containing the identifier of the
+    /// package being run so that `hab pkg uninstall` can tell which packages are in use.
     /// The existence of this file does not guarantee that a
     /// process exists at the PID contained within.
     pub fn create_pidfile(&self) -> Result<()> {
         match self.pid {
             Some(ref pid) => {
+                let ident_file = self.ident_file();
+                let mut f = try!(File::create(ident_file));
+                try!(write!(f, "{}", self.package_ident));
                 let pid_file = self.pid_file();
                 debug!("Creating PID file for child {} -> {:?}",
                        pid_file.display(),
@@ -353,6 +364,7 @@ impl Supervisor {
                 debug!("Error removing pidfile: {}, continuing", e);
             }
         };
+        let _ = fs::remove_file(self.ident_file());
     }
 
     /// attempt to read the pidfile for this package.
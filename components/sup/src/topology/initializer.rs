@@ -21,6 +21,7 @@
 //! followers attempt to run thier initialization sequences.
 
 use config::Config;
+use manager::Host;
 use error::{Result, SupError};
 use state_machine::StateMachine;
 use topology::{self, standalone, State, Worker};
@@ -34,8 +35,8 @@ enum InitGate {
     Done,
 }
 
-pub fn run(package: Package, config: &Config) -> Result<()> {
-    let mut worker = try!(Worker::new(package, String::from("initializer"), config));
+pub fn run(package: Package, config: &Config, host: Host) -> Result<()> {
+    let mut worker = try!(Worker::new(package, String::from("initializer"), config, host));
     let mut sm: StateMachine<State, Worker, SupError> =
         StateMachine::new(State::DetermineViability);
     sm.add_dispatch(State::DetermineViability, state_determine_viability);
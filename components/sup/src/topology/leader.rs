@@ -17,13 +17,14 @@ use state_machine::StateMachine;
 use error::{Result, SupError};
 use package::Package;
 use config::Config;
+use manager::Host;
 use census::MIN_QUORUM;
 use gossip::server;
 
 static LOGKEY: &'static str = "TL";
 
-pub fn run(package: Package, config: &Config) -> Result<()> {
-    let mut worker = try!(Worker::new(package, String::from("leader"), config));
+pub fn run(package: Package, config: &Config, host: Host) -> Result<()> {
+    let mut worker = try!(Worker::new(package, String::from("leader"), config, host));
     let mut sm: StateMachine<State, Worker, SupError> = StateMachine::new(State::Init);
     sm.add_dispatch(State::Init, state_init);
     sm.add_dispatch(State::MinimumQuorum, state_minimum_quorum);
@@ -162,9 +163,7 @@ pub fn state_start_election(worker: &mut Worker) -> Result<(State, u64)> {
     server::process_rumors(rumor_list,
                            worker.rumor_list.clone(),
                            worker.member_list.clone(),
-                           worker.census_list.clone(),
-                           worker.election_list.clone(),
-                           worker.gossip_file_list.clone());
+                           worker.gossip_server.services.clone());
     Ok((State::Election, 200))
 }
 
@@ -208,9 +207,7 @@ pub fn state_election(worker: &mut Worker) -> Result<(State, u64)> {
         server::process_rumors(rumor_list,
                                worker.rumor_list.clone(),
                                worker.member_list.clone(),
-                               worker.census_list.clone(),
-                               worker.election_list.clone(),
-                               worker.gossip_file_list.clone());
+                               worker.gossip_server.services.clone());
     }
 
     Ok((State::Election, 200))
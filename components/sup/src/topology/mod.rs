@@ -20,45 +20,45 @@
 //! 1. Processing the main event loop
 //! 1. Registering callbacks with the [discovery](../discovery) system
 //!
-//! Typically, topologies are created from the [start](../command/start) command.
+//! Typically, topologies are started by the [manager](../manager), one for each service the
+//! Supervisor hosts.
 
 pub mod standalone;
 pub mod leader;
 pub mod initializer;
 
+use std::fmt;
 use std::mem;
-use std::net::SocketAddrV4;
 use std::ops::DerefMut;
 use std::str::FromStr;
 use std::sync::{Arc, RwLock};
-use std::sync::mpsc::TryRecvError;
+use std::sync::mpsc::{Receiver, TryRecvError};
 use std::thread;
 use std::time::Duration;
 
 use wonder;
 
 use state_machine::StateMachine;
-use census::{self, CensusList};
+use census::CensusList;
 use common::gossip_file::GossipFileList;
 use package::{self, Package, PackageUpdaterActor};
-use util::signals::SignalNotifier;
-use error::{Result, SupError};
+use error::{Error, Result, SupError};
 use config::Config;
+use manager::{Host, ServiceCommand};
 use service_config::ServiceConfig;
-use sidecar;
+use sidecar::{SidecarService, SidecarServices};
 use supervisor::Supervisor;
 use gossip;
 use gossip::rumor::{Rumor, RumorList};
 use gossip::member::MemberList;
 use election::ElectionList;
 use time::SteadyTime;
-use util::signals;
 use config::UpdateStrategy;
 
 static LOGKEY: &'static str = "TP";
 static MINIMUM_LOOP_TIME_MS: i64 = 200;
 
-#[derive(PartialEq, Eq, Debug, RustcEncodable)]
+#[derive(PartialEq, Eq, Debug, Clone, RustcEncodable)]
 pub enum Topology {
     Standalone,
     Leader,
@@ -71,6 +71,30 @@ impl Default for Topology {
     }
 }
 
+impl FromStr for Topology {
+    type Err = SupError;
+
+    fn from_str(topology: &str) -> Result<Topology> {
+        match topology {
+            "standalone" => Ok(Topology::Standalone),
+            "leader" => Ok(Topology::Leader),
+            "initializer" => Ok(Topology::Initializer),
+            t => Err(sup_error!(Error::UnknownTopology(String::from(t)))),
+        }
+    }
+}
+
+impl fmt::Display for Topology {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let topology = match *self {
+            Topology::Standalone => "standalone",
+            Topology::Leader => "leader",
+            Topology::Initializer => "initializer",
+        };
+        write!(f, "{}", topology)
+    }
+}
+
 /// Viable states for the topologies. Not every topology will implement every state.
 #[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
 pub enum State {
@@ -99,21 +123,25 @@ pub struct Worker<'a> {
     pub package: Arc<RwLock<Package>>,
     /// Name of the package being supervised
     pub package_name: String,
+    /// The service group we are running in, as `service.group`
+    pub service_group: String,
     /// A pointer to our current Config
     pub config: &'a Config,
     /// The topology we are running
     pub topology: String,
     /// Our Service Configuration; manages changes to our configuration,
     pub service_config: Arc<RwLock<ServiceConfig>>,
-    /// The Gossip Server; listens for inbound gossip traffic
+    /// The Gossip Server; shared by every service this Supervisor hosts
     pub gossip_server: gossip::server::Server,
     pub census_list: Arc<RwLock<CensusList>>,
     pub rumor_list: Arc<RwLock<RumorList>>,
     pub election_list: Arc<RwLock<ElectionList>>,
     pub member_list: Arc<RwLock<MemberList>>,
     pub gossip_file_list: Arc<RwLock<GossipFileList>>,
-    /// Our Sidecar Actor; exposes a restful HTTP interface to the outside world
-    pub sidecar_actor: sidecar::SidecarActor,
+    /// The services our Sidecar answers for; we are in it for as long as we run
+    pub sidecar_services: Arc<RwLock<SidecarServices>>,
+    /// Commands from the Supervisor hosting us
+    pub commands: Receiver<ServiceCommand>,
     /// Watches a package Depot for updates and signals the main thread when an update is available. Optionally
     /// started if a value is passed for the url option on startup.
     pub pkg_updater: Option<PackageUpdaterActor>,
@@ -125,16 +153,45 @@ pub struct Worker<'a> {
 impl<'a> Worker<'a> {
     /// Create a new worker
     ///
-    /// Automatically sets the backend to Etcd.
-    pub fn new(package: Package, topology: String, config: &'a Config) -> Result<Worker<'a>> {
+    /// Joins the gossip ring and the sidecar of the Supervisor hosting us.
+    pub fn new(package: Package,
+               topology: String,
+               config: &'a Config,
+               host: Host)
+               -> Result<Worker<'a>> {
         let mut pkg_updater = None;
         let package_name = package.name.clone();
         let package_exposes = package.exposes().clone();
         let package_port = package_exposes.first().map(|e| e.clone());
         let package_ident = package.ident().clone();
+        let run_path = try!(package.run_path());
+        debug!("Running {} with the PATH {}", package_ident, run_path);
         let pkg_lock = Arc::new(RwLock::new(package));
-        let pkg_lock_1 = pkg_lock.clone();
 
+        let gossip_server = host.gossip_server;
+        let service_group = format!("{}.{}", package_name, config.group());
+        let lists = gossip_server.add_service(package_name.clone(),
+                                              config.group().to_string(),
+                                              config.organization().clone(),
+                                              Some(package_exposes),
+                                              package_port);
+
+        // Setup the Service Configuration
+        let service_config = {
+            let cl = lists.census_list.read().unwrap();
+            let pkg = pkg_lock.read().unwrap();
+            ServiceConfig::new(&config, &pkg, &cl, config.bind())
+        };
+        let service_config = match service_config {
+            Ok(service_config) => service_config,
+            Err(e) => {
+                gossip_server.remove_service(&service_group);
+                return Err(e);
+            }
+        };
+        let service_config_lock = Arc::new(RwLock::new(service_config));
+
+        let supervisor = Arc::new(RwLock::new(Supervisor::new(package_ident, run_path)));
 
         match config.update_strategy() {
             UpdateStrategy::None => {},
@@ -146,67 +203,32 @@ impl<'a> Worker<'a> {
             }
         }
 
-        let gossip_server = gossip::server::Server::new(String::from(config.gossip_listen_ip()),
-                                                        config.gossip_listen_port(),
-                                                        config.gossip_permanent(),
-                                                        config.ring().clone(),
-                                                        package_name.clone(),
-                                                        config.group().to_string(),
-                                                        config.organization().clone(),
-                                                        Some(package_exposes),
-                                                        package_port);
-
-        try!(gossip_server.start_inbound());
-        try!(gossip_server.initial_peers(config.gossip_peer()));
-        gossip_server.start_outbound();
-        gossip_server.start_failure_detector();
-        census::start_health_adjuster(gossip_server.census_list.clone(),
-                                      gossip_server.member_list.clone());
+        {
+            let mut sidecar_services = host.sidecar_services.write().unwrap();
+            sidecar_services.insert(service_group.clone(),
+                                    SidecarService {
+                                        package: pkg_lock.clone(),
+                                        config: service_config_lock.clone(),
+                                        supervisor: supervisor.clone(),
+                                        lists: lists.clone(),
+                                    });
+        }
 
-        // Setup the Service Configuration
-        let service_config = {
-            let cl = gossip_server.census_list.read().unwrap();
-            let pkg = pkg_lock.read().unwrap();
-            let sc = try!(ServiceConfig::new(&config, &pkg, &cl, config.bind()));
-            sc
-        };
-        let service_config_lock = Arc::new(RwLock::new(service_config));
-        let service_config_lock_1 = service_config_lock.clone();
-
-        let supervisor = Arc::new(RwLock::new(Supervisor::new(package_ident)));
-
-        let sidecar_ml = gossip_server.member_list.clone();
-        let sidecar_rl = gossip_server.rumor_list.clone();
-        let sidecar_cl = gossip_server.census_list.clone();
-        let sidecar_gfl = gossip_server.gossip_file_list.clone();
-        let sidecar_detector = gossip_server.detector.clone();
-        let sidecar_el = gossip_server.election_list.clone();
-        let sidecar_sup = supervisor.clone();
-        let sidecar_listen = try!(SocketAddrV4::from_str(&format!("{}:{}",
-                                                                  &config.http_listen_ip(),
-                                                                  config.http_listen_port())));
         Ok(Worker {
             package: pkg_lock,
             package_name: package_name,
+            service_group: service_group,
             topology: topology,
             config: config,
-            census_list: gossip_server.census_list.clone(),
+            census_list: lists.census_list,
             rumor_list: gossip_server.rumor_list.clone(),
-            election_list: gossip_server.election_list.clone(),
-            gossip_file_list: gossip_server.gossip_file_list.clone(),
+            election_list: lists.election_list,
+            gossip_file_list: lists.gossip_file_list,
             member_list: gossip_server.member_list.clone(),
             gossip_server: gossip_server,
             service_config: service_config_lock,
-            sidecar_actor: sidecar::Sidecar::start(sidecar_listen,
-                                                   pkg_lock_1,
-                                                   service_config_lock_1,
-                                                   sidecar_ml,
-                                                   sidecar_rl,
-                                                   sidecar_cl,
-                                                   sidecar_detector,
-                                                   sidecar_el,
-                                                   sidecar_sup,
-                                                   sidecar_gfl),
+            sidecar_services: host.sidecar_services,
+            commands: host.commands,
             supervisor: supervisor,
             pkg_updater: pkg_updater,
             return_state: None,
@@ -216,6 +238,12 @@ impl<'a> Worker<'a> {
     /// update a package, but does NOT restart the service
     pub fn update_package(&self, updated: Package) -> Result<()> {
         let service_config = self.service_config.read().unwrap();
+        let run_path = try!(updated.run_path());
+        {
+            let mut supervisor = self.supervisor.write().unwrap();
+            supervisor.package_ident = updated.ident().clone();
+            supervisor.run_path = run_path;
+        }
         {
             let mut package = self.package.write().unwrap();
             mem::replace(package.deref_mut(), updated);
@@ -226,10 +254,24 @@ impl<'a> Worker<'a> {
     }
 }
 
+/// Leaves the sidecar and the gossip ring of the Supervisor hosting us, however we stopped.
+impl<'a> Drop for Worker<'a> {
+    fn drop(&mut self) {
+        if let Some(ref updater) = self.pkg_updater {
+            let _ = package::PackageUpdater::stop(updater);
+        }
+        {
+            let mut sidecar_services = self.sidecar_services.write().unwrap();
+            sidecar_services.remove(&self.service_group);
+        }
+        self.gossip_server.remove_service(&self.service_group);
+    }
+}
+
 /// The main loop of a topology.
 ///
 /// 1. Loops forever
-/// 1. Checks if we have caught a signal; if so, acts on the signal. (May exit entirely)
+/// 1. Checks for a command from the Supervisor hosting us; forwards signals on, or stops
 /// 1. Checks the current `state` of our [StateMachine](../state_machine)
 /// 1. If it is running, we run a non-blocking `waitpid`, and inspect why the child died;
 ///    depending on the circumstances, we may exit with an error here
@@ -252,33 +294,21 @@ fn run_internal<'a>(sm: &mut StateMachine<State, Worker<'a>, SupError>,
         try!(package.create_svc_path());
         try!(package.copy_run(&service_config));
     }
-    let handler = wonder::actor::Builder::new(SignalNotifier)
-        .name("signal-handler".to_string())
-        .start(())
-        .unwrap();
     loop {
         let start_time = SteadyTime::now();
-        match handler.receiver.try_recv() {
-            Ok(wonder::actor::Message::Cast(signals::Message::Signal(sig))) => {
-                debug!("SIG = {:?}", sig);
-                match sig {
-                    signals::Signal::SIGINT | signals::Signal::SIGTERM => {
-                        let mut supervisor = worker.supervisor.write().unwrap();
-                        try!(supervisor.down());
-                        break;
-                    }
-                    _ => {
-                        outputln!("Forwarding {:?} on to the supervised process", sig);
-                        let supervisor = worker.supervisor.write().unwrap();
-                        try!(supervisor.send_unix_signal(sig.clone()));
-                    }
-                };
-            }
-            Ok(_) => {}
-            Err(TryRecvError::Empty) => {}
+        match worker.commands.try_recv() {
+            Ok(ServiceCommand::Stop) |
             Err(TryRecvError::Disconnected) => {
-                panic!("signal handler crashed!");
+                let mut supervisor = worker.supervisor.write().unwrap();
+                try!(supervisor.down());
+                break;
+            }
+            Ok(ServiceCommand::Signal(sig)) => {
+                outputln!("Forwarding {:?} on to the supervised process", sig);
+                let supervisor = worker.supervisor.write().unwrap();
+                try!(supervisor.send_unix_signal(sig));
             }
+            Err(TryRecvError::Empty) => {}
         }
 
         {
@@ -312,8 +342,11 @@ fn run_internal<'a>(sm: &mut StateMachine<State, Worker<'a>, SupError>,
                 }
                 if write_rumor {
                     outputln!("Writing our census rumor: {:#?}", me_clone);
-                    let mut rl = worker.rumor_list.write().unwrap();
-                    rl.add_rumor(Rumor::census_entry(me_clone));
+                    {
+                        let mut rl = worker.rumor_list.write().unwrap();
+                        rl.add_rumor(Rumor::census_entry(me_clone.clone()));
+                    }
+                    worker.gossip_server.share_census_entry(&me_clone);
                 }
                 let mut cl = worker.census_list.write().unwrap();
                 cl.written();